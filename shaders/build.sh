@@ -0,0 +1,31 @@
+#!/bin/sh
+# Собирает шейдеры в SPIR-V в src/spv, откуда они подключаются через include_bytes!.
+# Компилятор — naga (cargo install naga-cli), тот же для GLSL и WGSL. Вычислительный шейдер cull написан
+# на WGSL, потому что GLSL-фронтенд naga не поддерживает атомарные операции. Push-константы в WGSL
+# объявляются расширением naga var<immediate>.
+#
+# Запускается из корня репозитория: sh shaders/build.sh
+set -e
+
+compile() {
+    naga --keep-coordinate-space "$@"
+}
+
+glsl() {
+    compile --input-kind glsl --shader-stage "$2" "shaders/$1" "src/spv/$3"
+}
+
+glsl shader.vert vert vert.spv
+glsl shader.frag frag frag.spv
+glsl ui.vert vert ui_vert.spv
+glsl ui.frag frag ui_frag.spv
+glsl text.vert vert text_vert.spv
+glsl text.frag frag text_frag.spv
+glsl sprite.vert vert sprite_vert.spv
+glsl sprite.frag frag sprite_frag.spv
+glsl instanced.vert vert instanced_vert.spv
+glsl instanced.frag frag instanced_frag.spv
+glsl scene.vert vert scene_vert.spv
+glsl scene_skinned.vert vert scene_skinned_vert.spv
+glsl scene.frag frag scene_frag.spv
+compile shaders/cull.wgsl src/spv/cull_comp.spv
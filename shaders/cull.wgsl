@@ -0,0 +1,44 @@
+// Отсечение объектов по пирамиде видимости. Для каждого видимого объекта в буфер команд добавляется
+// команда отрисовки с одним экземпляром — самим объектом, — а draw_count считает добавленные команды.
+// Шейдер написан на WGSL: шейдеры собираются naga (см. shaders/build.sh), а его GLSL-фронтенд не поддерживает
+// атомарные операции. var<immediate> — объявление push-констант в WGSL naga.
+
+// Раскладка совпадает с VkDrawIndexedIndirectCommand.
+struct DrawCommand {
+    index_count: u32,
+    instance_count: u32,
+    first_index: u32,
+    vertex_offset: i32,
+    first_instance: u32,
+}
+
+struct PushConstants {
+    // Плоскости пирамиды видимости в мировых координатах, нормали смотрят внутрь.
+    planes: array<vec4<f32>, 6>,
+    object_count: u32,
+    index_count: u32,
+}
+
+// Ограничивающие сферы объектов: центр в xyz, радиус в w.
+@group(0) @binding(0) var<storage, read> bounds: array<vec4<f32>>;
+@group(0) @binding(1) var<storage, read_write> commands: array<DrawCommand>;
+@group(0) @binding(2) var<storage, read_write> draw_count: atomic<u32>;
+
+var<immediate> push_constants: PushConstants;
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let object = id.x;
+    if object >= push_constants.object_count {
+        return;
+    }
+    let sphere = bounds[object];
+    for (var i = 0; i < 6; i++) {
+        let plane = push_constants.planes[i];
+        if dot(plane.xyz, sphere.xyz) + plane.w < -sphere.w {
+            return;
+        }
+    }
+    let slot = atomicAdd(&draw_count, 1u);
+    commands[slot] = DrawCommand(push_constants.index_count, 1u, 0u, 0, object);
+}
@@ -0,0 +1,13 @@
+#version 450
+
+layout(location = 0) in vec3 in_normal;
+layout(location = 1) in vec4 in_color;
+
+layout(location = 0) out vec4 out_color;
+
+void main() {
+    // Простое освещение по Ламберту от одного направленного источника и немного фонового света.
+    vec3 light_direction = normalize(vec3(0.4, 1.0, 0.6));
+    float diffuse = max(dot(normalize(in_normal), light_direction), 0.0);
+    out_color = vec4(in_color.rgb * (0.3 + 0.7 * diffuse), in_color.a);
+}
@@ -0,0 +1,26 @@
+#version 450
+
+// Вершины сетки: позиция и нормаль в пространстве модели.
+layout(location = 0) in vec3 in_position;
+layout(location = 1) in vec3 in_normal;
+// Атрибуты экземпляра. Матрица модели передается четырьмя столбцами, по одной позиции на столбец.
+layout(location = 2) in vec4 in_model_0;
+layout(location = 3) in vec4 in_model_1;
+layout(location = 4) in vec4 in_model_2;
+layout(location = 5) in vec4 in_model_3;
+layout(location = 6) in vec4 in_color;
+
+layout(push_constant) uniform PushConstants {
+    mat4 view_projection;
+} push_constants;
+
+layout(location = 0) out vec3 out_normal;
+layout(location = 1) out vec4 out_color;
+
+void main() {
+    mat4 model = mat4(in_model_0, in_model_1, in_model_2, in_model_3);
+    gl_Position = push_constants.view_projection * model * vec4(in_position, 1.0);
+    // Масштаб экземпляров одинаков по всем осям, поэтому нормаль можно повернуть самой матрицей модели.
+    out_normal = mat3(model) * in_normal;
+    out_color = in_color;
+}
@@ -0,0 +1,89 @@
+#version 450
+
+layout(location = 0) in vec3 in_normal;
+layout(location = 1) in vec2 in_uv;
+layout(location = 2) in vec3 in_world_position;
+
+layout(set = 0, binding = 0) uniform FrameUniforms {
+    mat4 view_projection;
+    vec4 camera_position;
+} frame;
+
+const uint LIGHT_DIRECTIONAL = 0;
+const uint LIGHT_POINT = 1;
+const uint LIGHT_SPOT = 2;
+
+// Раскладка совпадает с GpuLight в scene_renderer.rs.
+struct Light {
+    vec3 position;
+    float range;
+    vec3 direction;
+    uint kind;
+    vec3 color;
+    float inner_cone_cos;
+    float outer_cone_cos;
+};
+
+// Источники света всех проходов кадра. Источники прохода начинаются с light_offset.
+layout(set = 0, binding = 2) readonly buffer Lights {
+    Light lights[];
+} scene_lights;
+
+// Текстура материала. У материалов без текстуры здесь белая текстура.
+layout(set = 1, binding = 0) uniform texture2D base_color_texture;
+layout(set = 1, binding = 1) uniform sampler base_color_sampler;
+
+layout(push_constant) uniform PushConstants {
+    mat4 model;
+    vec4 base_color;
+    uint joint_offset;
+    float metallic;
+    float roughness;
+    uint light_offset;
+    uint light_count;
+} push_constants;
+
+layout(location = 0) out vec4 out_color;
+
+// Рассеянный свет окружения, чтобы неосвещенные стороны не были черными.
+const float AMBIENT = 0.2;
+
+void main() {
+    vec4 albedo = push_constants.base_color * texture(sampler2D(base_color_texture, base_color_sampler), in_uv);
+    vec3 normal = normalize(in_normal);
+    vec3 view_direction = normalize(frame.camera_position.xyz - in_world_position);
+    // Металл не рассеивает свет, а отражает его своим цветом. У остальных материалов блик слабый и белый.
+    vec3 diffuse_color = albedo.rgb * (1.0 - push_constants.metallic);
+    vec3 specular_color = mix(vec3(0.04), albedo.rgb, push_constants.metallic);
+    // Гладкая поверхность дает узкий яркий блик, шероховатая — широкий и тусклый.
+    float shininess = exp2(10.0 * (1.0 - push_constants.roughness) + 1.0);
+    float specular_scale = (shininess + 8.0) / 8.0;
+
+    vec3 color = AMBIENT * albedo.rgb;
+    for (uint index = 0; index < push_constants.light_count; index++) {
+        Light light = scene_lights.lights[push_constants.light_offset + index];
+        vec3 light_direction = -light.direction;
+        float attenuation = 1.0;
+        if (light.kind != LIGHT_DIRECTIONAL) {
+            vec3 to_light = light.position - in_world_position;
+            float distance_squared = max(dot(to_light, to_light), 0.0001);
+            light_direction = to_light * inversesqrt(distance_squared);
+            attenuation = 1.0 / distance_squared;
+            // Свет плавно гаснет к границе range, как в KHR_lights_punctual.
+            if (light.range > 0.0) {
+                float ratio = distance_squared / (light.range * light.range);
+                float window = clamp(1.0 - ratio * ratio, 0.0, 1.0);
+                attenuation *= window * window;
+            }
+            if (light.kind == LIGHT_SPOT) {
+                float cone_cos = dot(light.direction, -light_direction);
+                attenuation *= smoothstep(light.outer_cone_cos, light.inner_cone_cos, cone_cos);
+            }
+        }
+        float n_dot_l = max(dot(normal, light_direction), 0.0);
+        vec3 half_vector = normalize(light_direction + view_direction);
+        float specular = specular_scale * pow(max(dot(normal, half_vector), 0.0), shininess);
+        color += (diffuse_color + specular_color * specular) * light.color * n_dot_l * attenuation;
+    }
+    out_color = vec4(color, albedo.a);
+}
@@ -0,0 +1,35 @@
+#version 450
+
+layout(location = 0) in vec3 in_position;
+layout(location = 1) in vec3 in_normal;
+layout(location = 2) in vec2 in_uv;
+
+// Данные кадра, общие для всех отрисовок сцены.
+layout(set = 0, binding = 0) uniform FrameUniforms {
+    mat4 view_projection;
+    vec4 camera_position;
+} frame;
+
+// Данные одной отрисовки: мировая матрица узла, материал и диапазоны палитры суставов и источников света.
+layout(push_constant) uniform PushConstants {
+    mat4 model;
+    vec4 base_color;
+    uint joint_offset;
+    float metallic;
+    float roughness;
+    uint light_offset;
+    uint light_count;
+} push_constants;
+
+layout(location = 0) out vec3 out_normal;
+layout(location = 1) out vec2 out_uv;
+layout(location = 2) out vec3 out_world_position;
+
+void main() {
+    vec4 world_position = push_constants.model * vec4(in_position, 1.0);
+    gl_Position = frame.view_projection * world_position;
+    // Для неравномерного масштаба нормаль преобразуется обратной транспонированной матрицей.
+    out_normal = transpose(inverse(mat3(push_constants.model))) * in_normal;
+    out_uv = in_uv;
+    out_world_position = world_position.xyz;
+}
@@ -0,0 +1,47 @@
+#version 450
+
+layout(location = 0) in vec3 in_position;
+layout(location = 1) in vec3 in_normal;
+layout(location = 2) in vec2 in_uv;
+// Четыре сустава, влияющих на вершину, и их веса.
+layout(location = 3) in uvec4 in_joints;
+layout(location = 4) in vec4 in_weights;
+
+layout(set = 0, binding = 0) uniform FrameUniforms {
+    mat4 view_projection;
+    vec4 camera_position;
+} frame;
+
+// Матрицы суставов всех скелетных отрисовок кадра. Палитра отрисовки начинается с joint_offset.
+layout(set = 0, binding = 1) readonly buffer JointMatrices {
+    mat4 matrices[];
+} joints;
+
+layout(push_constant) uniform PushConstants {
+    mat4 model;
+    vec4 base_color;
+    uint joint_offset;
+    float metallic;
+    float roughness;
+    uint light_offset;
+    uint light_count;
+} push_constants;
+
+layout(location = 0) out vec3 out_normal;
+layout(location = 1) out vec2 out_uv;
+layout(location = 2) out vec3 out_world_position;
+
+void main() {
+    // Вершина деформируется взвешенной суммой матриц своих суставов, а дальше рисуется как обычная.
+    mat4 skin =
+        in_weights.x * joints.matrices[push_constants.joint_offset + in_joints.x] +
+        in_weights.y * joints.matrices[push_constants.joint_offset + in_joints.y] +
+        in_weights.z * joints.matrices[push_constants.joint_offset + in_joints.z] +
+        in_weights.w * joints.matrices[push_constants.joint_offset + in_joints.w];
+    mat4 model = push_constants.model * skin;
+    vec4 world_position = model * vec4(in_position, 1.0);
+    gl_Position = frame.view_projection * world_position;
+    out_normal = transpose(inverse(mat3(model))) * in_normal;
+    out_uv = in_uv;
+    out_world_position = world_position.xyz;
+}
@@ -4,6 +4,15 @@ layout(location = 0) in vec3 fragColor;
 
 layout(location = 0) out vec4 outColor;
 
+layout(push_constant) uniform PushConstants {
+    mat4 model_view_projection;
+    vec4 overlay_color;
+    float point_size;
+    float time;
+} push_constants;
+
 void main() {
-    outColor = vec4(fragColor, 1.0);
-}
\ No newline at end of file
+    // При нулевой альфе цвета каркаса остается цвет вершин, при единичной фрагмент закрашивается цветом каркаса.
+    vec4 overlay = push_constants.overlay_color;
+    outColor = vec4(mix(fragColor, overlay.rgb, overlay.a), 1.0);
+}
@@ -1,10 +1,20 @@
 #version 450
 layout(location = 0) out vec3 fragColor;
 
+// Преобразование треугольника в координаты отсечения, цвет каркаса поверх заливки, размер точек в режиме точек
+// и время сцены в секундах. Раскладка совпадает со ScenePushConstants в main.rs.
+layout(push_constant) uniform PushConstants {
+    mat4 model_view_projection;
+    vec4 overlay_color;
+    float point_size;
+    float time;
+} push_constants;
+
+// Треугольник задан в координатах модели, ось y направлена вверх.
 vec2 positions[3] = vec2[](
-    vec2(0.0, -0.5),
-    vec2(0.5, 0.5),
-    vec2(-0.5, 0.5)
+    vec2(0.0, 0.5),
+    vec2(0.5, -0.5),
+    vec2(-0.5, -0.5)
 );
 
 vec3 colors[3] = vec3[](
@@ -14,6 +24,10 @@ vec3 colors[3] = vec3[](
 );
 
 void main() {
-    gl_Position = vec4(positions[gl_VertexIndex], 0.0, 1.0);
-    fragColor = colors[gl_VertexIndex];
-}
\ No newline at end of file
+    gl_Position = push_constants.model_view_projection * vec4(positions[gl_VertexIndex], 0.0, 1.0);
+    // Размер учитывается, только когда треугольник растеризуется точками.
+    gl_PointSize = push_constants.point_size;
+    // Яркость вершин пульсирует со временем, каждая вершина со своим сдвигом фазы.
+    float pulse = 0.8 + 0.2 * sin(push_constants.time * 3.0 + float(gl_VertexIndex) * 2.094);
+    fragColor = colors[gl_VertexIndex] * pulse;
+}
@@ -0,0 +1,13 @@
+#version 450
+
+layout(location = 0) in vec4 in_color;
+layout(location = 1) in vec2 in_uv;
+
+layout(set = 0, binding = 0) uniform texture2D sprite_texture;
+layout(set = 0, binding = 1) uniform sampler sprite_sampler;
+
+layout(location = 0) out vec4 out_color;
+
+void main() {
+    out_color = in_color * texture(sampler2D(sprite_texture, sprite_sampler), in_uv);
+}
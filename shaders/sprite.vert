@@ -0,0 +1,20 @@
+#version 450
+
+// Вершины спрайтов: позиция в пикселях (начало координат в левом верхнем углу), текстурные координаты и линейный цвет оттенка.
+layout(location = 0) in vec2 in_position;
+layout(location = 1) in vec2 in_uv;
+layout(location = 2) in vec4 in_color;
+
+layout(push_constant) uniform PushConstants {
+    // Ортографическая проекция размером с кадр.
+    mat4 projection;
+} push_constants;
+
+layout(location = 0) out vec4 out_color;
+layout(location = 1) out vec2 out_uv;
+
+void main() {
+    gl_Position = push_constants.projection * vec4(in_position, 0.0, 1.0);
+    out_color = in_color;
+    out_uv = in_uv;
+}
@@ -0,0 +1,21 @@
+#version 450
+
+layout(location = 0) in vec2 in_uv;
+layout(location = 1) in vec4 in_color;
+layout(location = 2) flat in float in_sdf;
+
+// В атласе хранится одна компонента: покрытие пикселя для растровых символов
+// или расстояние до контура для SDF, где 0.5 — сам контур.
+layout(set = 0, binding = 0) uniform texture2D glyph_atlas;
+layout(set = 0, binding = 1) uniform sampler glyph_sampler;
+
+layout(location = 0) out vec4 out_color;
+
+void main() {
+    float value = texture(sampler2D(glyph_atlas, glyph_sampler), in_uv).r;
+    // Ширина перехода в один пиксель экрана при любом масштабе: так края остаются четкими и сглаженными.
+    float width = fwidth(value);
+    float sdf_alpha = smoothstep(0.5 - width, 0.5 + width, value);
+    float alpha = mix(value, sdf_alpha, in_sdf);
+    out_color = vec4(in_color.rgb, in_color.a * alpha);
+}
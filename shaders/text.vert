@@ -0,0 +1,23 @@
+#version 450
+
+// Вершина символа: позиция в пикселях кадра, координаты в атласе, линейный цвет и способ выборки.
+layout(location = 0) in vec2 in_position;
+layout(location = 1) in vec2 in_uv;
+layout(location = 2) in vec4 in_color;
+layout(location = 3) in float in_sdf;
+
+layout(push_constant) uniform PushConstants {
+    // Размер кадра в пикселях.
+    vec2 screen_size;
+} push_constants;
+
+layout(location = 0) out vec2 out_uv;
+layout(location = 1) out vec4 out_color;
+layout(location = 2) flat out float out_sdf;
+
+void main() {
+    gl_Position = vec4(2.0 * in_position / push_constants.screen_size - 1.0, 0.0, 1.0);
+    out_uv = in_uv;
+    out_color = in_color;
+    out_sdf = in_sdf;
+}
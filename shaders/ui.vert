@@ -0,0 +1,27 @@
+#version 450
+
+// Вершины интерфейса egui: позиция в точках (логических пикселях), текстурные координаты и цвет sRGB.
+layout(location = 0) in vec2 in_position;
+layout(location = 1) in vec2 in_uv;
+layout(location = 2) in vec4 in_color;
+
+layout(push_constant) uniform PushConstants {
+    // Размер экрана в точках.
+    vec2 screen_size;
+} push_constants;
+
+layout(location = 0) out vec4 out_color;
+layout(location = 1) out vec2 out_uv;
+
+// Цепочка обмена в формате sRGB сама переводит результат обратно, поэтому смешивание идет в линейном пространстве.
+vec3 linear_from_srgb(vec3 srgb) {
+    vec3 lower = srgb / 12.92;
+    vec3 higher = pow((srgb + 0.055) / 1.055, vec3(2.4));
+    return mix(higher, lower, vec3(lessThan(srgb, vec3(0.04045))));
+}
+
+void main() {
+    gl_Position = vec4(2.0 * in_position / push_constants.screen_size - 1.0, 0.0, 1.0);
+    out_color = vec4(linear_from_srgb(in_color.rgb), in_color.a);
+    out_uv = in_uv;
+}
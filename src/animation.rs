@@ -0,0 +1,390 @@
+use glam::{Mat4, Quat, Vec3};
+
+use std::collections::HashMap;
+use std::ops::{Add, Mul};
+
+use crate::scene::{NodeId, Scene, SkinHandle, Transform};
+
+// Скелетная анимация поверх графа сцены. Суставы скелета — обычные узлы сцены, и клип анимации меняет
+// их локальные преобразования по ключевым кадрам. Поза — локальные преобразования набора узлов:
+// клипы пишут в позу, позы смешиваются, а готовая поза переносится в сцену.
+//
+// Скин связывает суставы со скелетной сеткой. После пересчета мировых матриц сцены он дает палитру
+// матриц суставов, которая загружается в SceneRenderer::set_joint_matrices, а вершины сетки
+// деформируются ею в вершинном шейдере.
+
+// Как значение меняется между ключевыми кадрами.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    // Значение ключа держится до следующего ключа.
+    Step,
+    // Линейная интерполяция, для поворотов — сферическая.
+    Linear,
+    // Кубический сплайн Эрмита. У каждого ключа три значения: входящая касательная, значение и исходящая касательная.
+    CubicSpline,
+}
+
+// Значения ключевых кадров одного свойства узла.
+#[derive(Debug, Clone)]
+pub enum Keyframes {
+    Translation(Vec<Vec3>),
+    Rotation(Vec<Quat>),
+    Scale(Vec<Vec3>),
+}
+
+impl Keyframes {
+    fn len(&self) -> usize {
+        match self {
+            Keyframes::Translation(values) | Keyframes::Scale(values) => values.len(),
+            Keyframes::Rotation(values) => values.len(),
+        }
+    }
+}
+
+// Анимация одного свойства одного узла.
+#[derive(Debug, Clone)]
+pub struct Channel {
+    pub node: NodeId,
+    pub interpolation: Interpolation,
+    // Время ключей в секундах по возрастанию.
+    pub times: Vec<f32>,
+    pub keyframes: Keyframes,
+}
+
+impl Channel {
+    // Записывает значение свойства в момент time в преобразование узла.
+    fn sample(&self, time: f32, transform: &mut Transform) {
+        match &self.keyframes {
+            Keyframes::Translation(values) => {
+                transform.translation =
+                    sample(&self.times, values, self.interpolation, time, Vec3::lerp)
+            }
+            Keyframes::Rotation(values) => {
+                transform.rotation =
+                    sample(&self.times, values, self.interpolation, time, Quat::slerp).normalize()
+            }
+            Keyframes::Scale(values) => {
+                transform.scale = sample(&self.times, values, self.interpolation, time, Vec3::lerp)
+            }
+        }
+    }
+}
+
+// Проверяет, что значений столько, сколько нужно для ключей, а время ключей не убывает.
+pub fn validate_keyframes(
+    interpolation: Interpolation,
+    times: &[f32],
+    keyframes: &Keyframes,
+) -> Result<(), String> {
+    let per_key = match interpolation {
+        Interpolation::CubicSpline => 3,
+        _ => 1,
+    };
+    if times.is_empty() {
+        return Err("channel has no keyframes".to_owned());
+    }
+    if keyframes.len() != times.len() * per_key {
+        return Err(format!(
+            "channel has {} values for {} keyframes",
+            keyframes.len(),
+            times.len()
+        ));
+    }
+    if times.windows(2).any(|pair| pair[1] < pair[0]) {
+        return Err("channel keyframe times are not increasing".to_owned());
+    }
+    Ok(())
+}
+
+// Значение в момент time. До первого ключа и после последнего держится крайнее значение.
+fn sample<T>(
+    times: &[f32],
+    values: &[T],
+    interpolation: Interpolation,
+    time: f32,
+    lerp: fn(T, T, f32) -> T,
+) -> T
+where
+    T: Copy + Add<Output = T> + Mul<f32, Output = T>,
+{
+    // У сплайна значение ключа — среднее из трех.
+    let value = |key: usize| match interpolation {
+        Interpolation::CubicSpline => values[key * 3 + 1],
+        _ => values[key],
+    };
+    let next = times.partition_point(|&key_time| key_time <= time);
+    if next == 0 {
+        return value(0);
+    }
+    if next == times.len() {
+        return value(times.len() - 1);
+    }
+    let previous = next - 1;
+    let duration = times[next] - times[previous];
+    let t = (time - times[previous]) / duration;
+    match interpolation {
+        Interpolation::Step => value(previous),
+        Interpolation::Linear => lerp(value(previous), value(next), t),
+        Interpolation::CubicSpline => {
+            let (t2, t3) = (t * t, t * t * t);
+            let out_tangent = values[previous * 3 + 2] * duration;
+            let in_tangent = values[next * 3] * duration;
+            value(previous) * (2.0 * t3 - 3.0 * t2 + 1.0)
+                + out_tangent * (t3 - 2.0 * t2 + t)
+                + value(next) * (-2.0 * t3 + 3.0 * t2)
+                + in_tangent * (t3 - t2)
+        }
+    }
+}
+
+// Набор каналов, которые проигрываются вместе: одно движение персонажа.
+#[derive(Debug, Clone, Default)]
+pub struct AnimationClip {
+    pub name: String,
+    pub channels: Vec<Channel>,
+}
+
+impl AnimationClip {
+    // Время последнего ключа среди всех каналов.
+    pub fn duration(&self) -> f32 {
+        self.channels
+            .iter()
+            .filter_map(|channel| channel.times.last().copied())
+            .fold(0.0, f32::max)
+    }
+
+    // Записывает в позу значения всех каналов в момент time. Узлы, которых нет в позе, пропускаются,
+    // а свойства, которые клип не анимирует, остаются в позе прежними.
+    pub fn sample(&self, time: f32, pose: &mut Pose) {
+        for channel in &self.channels {
+            if let Some(transform) = pose.transforms.get_mut(&channel.node) {
+                channel.sample(time, transform);
+            }
+        }
+    }
+
+    // Узлы, которые анимирует клип.
+    pub fn nodes(&self) -> impl Iterator<Item = NodeId> + '_ {
+        self.channels.iter().map(|channel| channel.node)
+    }
+}
+
+// Локальные преобразования набора узлов.
+#[derive(Debug, Clone, Default)]
+pub struct Pose {
+    transforms: HashMap<NodeId, Transform>,
+}
+
+impl Pose {
+    // Текущие локальные преобразования узлов сцены. Поза привязки снимается так до начала анимации.
+    pub fn capture(scene: &Scene, nodes: impl IntoIterator<Item = NodeId>) -> Self {
+        Pose {
+            transforms: nodes
+                .into_iter()
+                .map(|node| (node, scene.local_transform(node)))
+                .collect(),
+        }
+    }
+
+    // Смешивает две позы: при weight 0 получается self, при 1 — other. Узлы, которых нет в other, берутся из self.
+    pub fn blend(&self, other: &Pose, weight: f32) -> Pose {
+        Pose {
+            transforms: self
+                .transforms
+                .iter()
+                .map(|(&node, &a)| {
+                    let transform = match other.transforms.get(&node) {
+                        Some(b) => Transform {
+                            translation: a.translation.lerp(b.translation, weight),
+                            rotation: a.rotation.slerp(b.rotation, weight).normalize(),
+                            scale: a.scale.lerp(b.scale, weight),
+                        },
+                        None => a,
+                    };
+                    (node, transform)
+                })
+                .collect(),
+        }
+    }
+
+    // Переносит позу в локальные преобразования узлов сцены.
+    pub fn apply(&self, scene: &mut Scene) {
+        for (&node, &transform) in &self.transforms {
+            scene.set_local_transform(node, transform);
+        }
+    }
+}
+
+// Привязка скелетной сетки к суставам.
+#[derive(Debug, Clone)]
+pub struct Skin {
+    // Узлы суставов в порядке номеров суставов в вершинах сетки.
+    pub joints: Vec<NodeId>,
+    // Переводят вершину из координат сетки в координаты сустава в позе привязки.
+    pub inverse_bind_matrices: Vec<Mat4>,
+    // Палитра в SceneRenderer, в которую загружаются матрицы суставов.
+    pub handle: SkinHandle,
+}
+
+impl Skin {
+    // Матрицы суставов для сетки узла mesh_node. Вершина переводится матрицей сустава в координаты
+    // этого узла, а дальше, как и у обычной сетки, его мировой матрицей. Вызывается после update_world_matrices.
+    pub fn joint_matrices(&self, scene: &Scene, mesh_node: NodeId) -> Vec<Mat4> {
+        let inverse_mesh_world = scene.world_matrix(mesh_node).inverse();
+        self.joints
+            .iter()
+            .zip(&self.inverse_bind_matrices)
+            .map(|(&joint, inverse_bind)| {
+                inverse_mesh_world * scene.world_matrix(joint) * *inverse_bind
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_vec3(
+        times: &[f32],
+        values: &[Vec3],
+        interpolation: Interpolation,
+        time: f32,
+    ) -> Vec3 {
+        sample(times, values, interpolation, time, Vec3::lerp)
+    }
+
+    #[test]
+    fn step_holds_previous_key() {
+        let values = [Vec3::ZERO, Vec3::X, Vec3::Y];
+        let times = [0.0, 1.0, 2.0];
+        assert_eq!(
+            sample_vec3(&times, &values, Interpolation::Step, 0.9),
+            Vec3::ZERO
+        );
+        assert_eq!(
+            sample_vec3(&times, &values, Interpolation::Step, 1.0),
+            Vec3::X
+        );
+        assert_eq!(
+            sample_vec3(&times, &values, Interpolation::Step, 1.5),
+            Vec3::X
+        );
+    }
+
+    #[test]
+    fn linear_interpolates_between_keys() {
+        let values = [Vec3::ZERO, Vec3::new(2.0, 4.0, 0.0)];
+        let times = [1.0, 3.0];
+        assert_eq!(
+            sample_vec3(&times, &values, Interpolation::Linear, 1.5),
+            Vec3::new(0.5, 1.0, 0.0)
+        );
+        assert_eq!(
+            sample_vec3(&times, &values, Interpolation::Linear, 2.0),
+            Vec3::new(1.0, 2.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn cubic_spline_uses_tangents() {
+        let times = [0.0, 2.0];
+        // Без касательных середина сплайна — середина отрезка.
+        let flat = [
+            Vec3::ZERO,
+            Vec3::ZERO,
+            Vec3::ZERO,
+            Vec3::ZERO,
+            Vec3::X,
+            Vec3::ZERO,
+        ];
+        let middle = sample_vec3(&times, &flat, Interpolation::CubicSpline, 1.0);
+        assert!(middle.abs_diff_eq(Vec3::X * 0.5, 1e-6));
+        // Исходящая касательная первого ключа умножается на длину отрезка: 2 * (t³ - 2t² + t) при t = 0.5.
+        let bump = [
+            Vec3::ZERO,
+            Vec3::ZERO,
+            Vec3::X,
+            Vec3::ZERO,
+            Vec3::ZERO,
+            Vec3::ZERO,
+        ];
+        let middle = sample_vec3(&times, &bump, Interpolation::CubicSpline, 1.0);
+        assert!(middle.abs_diff_eq(Vec3::X * 0.25, 1e-6));
+        assert_eq!(
+            sample_vec3(&times, &flat, Interpolation::CubicSpline, 2.0),
+            Vec3::X
+        );
+    }
+
+    #[test]
+    fn clamps_outside_keys() {
+        let times = [1.0, 2.0];
+        let values = [Vec3::X, Vec3::Y];
+        let spline = [Vec3::Z, Vec3::X, Vec3::Z, Vec3::Z, Vec3::Y, Vec3::Z];
+        for interpolation in [Interpolation::Step, Interpolation::Linear] {
+            assert_eq!(sample_vec3(&times, &values, interpolation, 0.0), Vec3::X);
+            assert_eq!(sample_vec3(&times, &values, interpolation, 5.0), Vec3::Y);
+        }
+        assert_eq!(
+            sample_vec3(&times, &spline, Interpolation::CubicSpline, -1.0),
+            Vec3::X
+        );
+        assert_eq!(
+            sample_vec3(&times, &spline, Interpolation::CubicSpline, 5.0),
+            Vec3::Y
+        );
+    }
+
+    #[test]
+    fn validates_keyframes() {
+        let two = Keyframes::Translation(vec![Vec3::ZERO; 2]);
+        let six = Keyframes::Scale(vec![Vec3::ONE; 6]);
+        assert!(validate_keyframes(Interpolation::Linear, &[0.0, 1.0], &two).is_ok());
+        assert!(validate_keyframes(Interpolation::CubicSpline, &[0.0, 1.0], &six).is_ok());
+        assert!(validate_keyframes(Interpolation::CubicSpline, &[0.0, 1.0], &two).is_err());
+        assert!(validate_keyframes(Interpolation::Step, &[0.0], &two).is_err());
+        assert!(
+            validate_keyframes(Interpolation::Step, &[], &Keyframes::Rotation(vec![])).is_err()
+        );
+        assert!(validate_keyframes(Interpolation::Linear, &[1.0, 0.0], &two).is_err());
+    }
+
+    #[test]
+    fn blend_weights_select_poses() {
+        let mut scene = Scene::new();
+        let joint = scene.add_node("joint", None, Transform::IDENTITY);
+        let rest = Pose::capture(&scene, [joint]);
+        let clip = AnimationClip {
+            name: "raise".to_owned(),
+            channels: vec![
+                Channel {
+                    node: joint,
+                    interpolation: Interpolation::Linear,
+                    times: vec![0.0, 1.0],
+                    keyframes: Keyframes::Translation(vec![Vec3::ZERO, Vec3::Y * 2.0]),
+                },
+                Channel {
+                    node: joint,
+                    interpolation: Interpolation::Step,
+                    times: vec![0.0],
+                    keyframes: Keyframes::Rotation(vec![Quat::from_rotation_z(1.0)]),
+                },
+            ],
+        };
+        assert_eq!(clip.duration(), 1.0);
+        let mut raised = rest.clone();
+        clip.sample(1.0, &mut raised);
+
+        let transform = |pose: &Pose| pose.transforms[&joint];
+        assert_eq!(transform(&rest.blend(&raised, 0.0)), transform(&rest));
+        let full = transform(&rest.blend(&raised, 1.0));
+        assert!(full.translation.abs_diff_eq(Vec3::Y * 2.0, 1e-6));
+        assert!(full.rotation.abs_diff_eq(Quat::from_rotation_z(1.0), 1e-6));
+        let half = transform(&rest.blend(&raised, 0.5));
+        assert!(half.translation.abs_diff_eq(Vec3::Y, 1e-6));
+
+        raised.apply(&mut scene);
+        assert_eq!(scene.local_transform(joint), transform(&raised));
+    }
+}
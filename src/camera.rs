@@ -0,0 +1,237 @@
+use glam::{Mat4, Vec3};
+
+use std::f32::consts::FRAC_PI_2;
+
+use crate::input::Input;
+
+// Камера не ограничивает угол наклона ровно 90 градусами, иначе направление взгляда совпадет с осью y
+// и матрица вида выродится.
+const MAX_PITCH: f32 = FRAC_PI_2 - 0.01;
+
+// Направление взгляда по углам поворота (вокруг вертикали) и наклона. При нулевых углах камера смотрит вдоль -z.
+fn direction(yaw: f32, pitch: f32) -> Vec3 {
+    Vec3::new(
+        yaw.sin() * pitch.cos(),
+        pitch.sin(),
+        -yaw.cos() * pitch.cos(),
+    )
+}
+
+// Обратная к direction: углы поворота и наклона по направлению взгляда.
+fn angles(direction: Vec3) -> (f32, f32) {
+    let direction = direction.normalize();
+    (
+        direction.x.atan2(-direction.z),
+        direction.y.clamp(-1.0, 1.0).asin(),
+    )
+}
+
+// Свободная камера от первого лица: действия move_* двигают ее, move_fast ускоряет.
+// Пока активно действие look, мышь поворачивает взгляд, а курсор захватывается окном.
+// Колесо меняет скорость движения.
+#[derive(Debug, Clone, Copy)]
+pub struct FlyCamera {
+    pub position: Vec3,
+    pub yaw: f32,
+    pub pitch: f32,
+    // Скорость в единицах мира в секунду.
+    pub speed: f32,
+    // Радиан на пиксель сдвига мыши.
+    pub sensitivity: f32,
+}
+
+impl FlyCamera {
+    pub fn new(position: Vec3, target: Vec3) -> Self {
+        let (yaw, pitch) = angles(target - position);
+        FlyCamera {
+            position,
+            yaw,
+            pitch,
+            speed: 2.0,
+            sensitivity: 0.003,
+        }
+    }
+
+    pub fn forward(&self) -> Vec3 {
+        direction(self.yaw, self.pitch)
+    }
+
+    // Захвачен ли курсор для поворота взгляда.
+    pub fn is_looking(&self, input: &Input) -> bool {
+        input.action_held("look")
+    }
+
+    // delta_time — время кадра в секундах. Движение умножается на него, поэтому скорость не зависит от частоты кадров.
+    // Сдвиг мыши уже является расстоянием за кадр, его на время умножать не нужно.
+    pub fn update(&mut self, input: &Input, delta_time: f32) {
+        if self.is_looking(input) {
+            let delta = input.mouse_delta() * self.sensitivity;
+            self.yaw += delta.x;
+            self.pitch = (self.pitch - delta.y).clamp(-MAX_PITCH, MAX_PITCH);
+        }
+        if input.scroll() != 0.0 {
+            self.speed = (self.speed * 1.1f32.powf(input.scroll())).clamp(0.1, 100.0);
+        }
+
+        let forward = self.forward();
+        let right = forward.cross(Vec3::Y).normalize();
+        let movement = forward * input.action_axis("move_back", "move_forward")
+            + right * input.action_axis("move_left", "move_right")
+            + Vec3::Y * input.action_axis("move_down", "move_up");
+        let boost = if input.action_held("move_fast") {
+            4.0
+        } else {
+            1.0
+        };
+        self.position += movement.normalize_or_zero() * self.speed * boost * delta_time;
+    }
+
+    pub fn view(&self) -> Mat4 {
+        Mat4::look_to_rh(self.position, self.forward(), Vec3::Y)
+    }
+}
+
+// Камера, вращающаяся вокруг точки: мышь при действии orbit_rotate вращает, при orbit_pan сдвигает точку
+// в плоскости экрана, колесо приближает и отдаляет.
+#[derive(Debug, Clone, Copy)]
+pub struct OrbitCamera {
+    pub target: Vec3,
+    pub distance: f32,
+    pub yaw: f32,
+    pub pitch: f32,
+    // Радиан на пиксель сдвига мыши.
+    pub sensitivity: f32,
+}
+
+impl OrbitCamera {
+    pub fn new(target: Vec3, position: Vec3) -> Self {
+        let (yaw, pitch) = angles(target - position);
+        OrbitCamera {
+            target,
+            distance: (target - position).length(),
+            yaw,
+            pitch,
+            sensitivity: 0.005,
+        }
+    }
+
+    pub fn forward(&self) -> Vec3 {
+        direction(self.yaw, self.pitch)
+    }
+
+    pub fn position(&self) -> Vec3 {
+        self.target - self.forward() * self.distance
+    }
+
+    // Камера управляется только мышью, а сдвиг мыши — уже расстояние за кадр, поэтому время кадра не нужно.
+    pub fn update(&mut self, input: &Input) {
+        let delta = input.mouse_delta();
+        if input.action_held("orbit_rotate") {
+            // Сцена поворачивается вслед за мышью, то есть камера — в обратную сторону.
+            self.yaw -= delta.x * self.sensitivity;
+            self.pitch = (self.pitch + delta.y * self.sensitivity).clamp(-MAX_PITCH, MAX_PITCH);
+        }
+        if input.action_held("orbit_pan") {
+            // Сдвиг пропорционален расстоянию, чтобы точка под курсором двигалась примерно вместе с мышью.
+            let forward = self.forward();
+            let right = forward.cross(Vec3::Y).normalize();
+            let up = right.cross(forward);
+            let scale = self.distance * 0.002;
+            self.target += (up * delta.y - right * delta.x) * scale;
+        }
+        if input.scroll() != 0.0 {
+            self.distance = (self.distance * 0.9f32.powf(input.scroll())).clamp(0.05, 1000.0);
+        }
+    }
+
+    pub fn view(&self) -> Mat4 {
+        Mat4::look_at_rh(self.position(), self.target, Vec3::Y)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CameraMode {
+    Fly,
+    Orbit,
+}
+
+// Камера сцены с двумя способами управления. При переключении новая камера продолжает с того же места и направления.
+#[derive(Debug, Clone, Copy)]
+pub struct Camera {
+    pub mode: CameraMode,
+    pub fly: FlyCamera,
+    pub orbit: OrbitCamera,
+    // Вертикальный угол обзора в радианах.
+    pub fov_y: f32,
+    pub near: f32,
+    pub far: f32,
+}
+
+impl Camera {
+    pub fn new(mode: CameraMode, position: Vec3, target: Vec3) -> Self {
+        Camera {
+            mode,
+            fly: FlyCamera::new(position, target),
+            orbit: OrbitCamera::new(target, position),
+            fov_y: 60f32.to_radians(),
+            near: 0.01,
+            far: 1000.0,
+        }
+    }
+
+    pub fn toggle_mode(&mut self) {
+        match self.mode {
+            CameraMode::Fly => {
+                // Точка вращения ставится перед камерой на прежнем расстоянии.
+                self.orbit.yaw = self.fly.yaw;
+                self.orbit.pitch = self.fly.pitch;
+                self.orbit.target = self.fly.position + self.fly.forward() * self.orbit.distance;
+                self.mode = CameraMode::Orbit;
+            }
+            CameraMode::Orbit => {
+                self.fly.position = self.orbit.position();
+                self.fly.yaw = self.orbit.yaw;
+                self.fly.pitch = self.orbit.pitch;
+                self.mode = CameraMode::Fly;
+            }
+        }
+    }
+
+    // Нужно ли захватить курсор окном: мышь поворачивает взгляд свободной камеры.
+    pub fn wants_cursor_grab(&self, input: &Input) -> bool {
+        self.mode == CameraMode::Fly && self.fly.is_looking(input)
+    }
+
+    pub fn update(&mut self, input: &Input, delta_time: f32) {
+        match self.mode {
+            CameraMode::Fly => self.fly.update(input, delta_time),
+            CameraMode::Orbit => self.orbit.update(input),
+        }
+    }
+
+    pub fn position(&self) -> Vec3 {
+        match self.mode {
+            CameraMode::Fly => self.fly.position,
+            CameraMode::Orbit => self.orbit.position(),
+        }
+    }
+
+    pub fn view(&self) -> Mat4 {
+        match self.mode {
+            CameraMode::Fly => self.fly.view(),
+            CameraMode::Orbit => self.orbit.view(),
+        }
+    }
+
+    // Перспективная проекция для Vulkan: глубина от 0 до 1, а ось y в координатах отсечения направлена вниз,
+    // поэтому ось y проекции переворачивается.
+    pub fn projection(&self, aspect_ratio: f32) -> Mat4 {
+        let mut projection = Mat4::perspective_rh(self.fov_y, aspect_ratio, self.near, self.far);
+        projection.y_axis.y = -projection.y_axis.y;
+        projection
+    }
+
+    pub fn view_projection(&self, aspect_ratio: f32) -> Mat4 {
+        self.projection(aspect_ratio) * self.view()
+    }
+}
@@ -0,0 +1,148 @@
+use ash::vk;
+
+use std::ffi::CStr;
+
+use crate::draw_mode::DrawMode;
+use crate::frame_stats::FrameStats;
+use crate::window_mode::WindowMode;
+
+// Сведения об устройстве, которые не меняются во время работы.
+pub struct DeviceInfo {
+    pub name: String,
+    pub device_type: vk::PhysicalDeviceType,
+    pub api_version: u32,
+    pub driver_version: u32,
+    // Как рисуются проходы графа: динамическим рендерингом или проходами рендеринга.
+    pub rendering: String,
+}
+
+impl DeviceInfo {
+    pub fn query(instance: &ash::Instance, p_device: vk::PhysicalDevice, rendering: &str) -> Self {
+        let properties = unsafe { instance.get_physical_device_properties(p_device) };
+        let name = unsafe { CStr::from_ptr(properties.device_name.as_ptr()) }
+            .to_string_lossy()
+            .into_owned();
+        DeviceInfo {
+            name,
+            device_type: properties.device_type,
+            api_version: properties.api_version,
+            driver_version: properties.driver_version,
+            rendering: rendering.to_owned(),
+        }
+    }
+}
+
+// Встроенная панель отладки. Переключатели только меняют поля, применяет их вызывающий код.
+pub struct DebugPanel {
+    pub visible: bool,
+    pub draw_mode: DrawMode,
+    // Доступны ли режимы, кроме заливки. Недоступные показываются, но выбрать их нельзя.
+    pub non_solid_supported: bool,
+    pub vsync: bool,
+    pub window_mode: WindowMode,
+}
+
+impl DebugPanel {
+    pub fn new(vsync: bool, non_solid_supported: bool) -> Self {
+        DebugPanel {
+            visible: true,
+            draw_mode: DrawMode::Fill,
+            non_solid_supported,
+            vsync,
+            window_mode: WindowMode::Windowed,
+        }
+    }
+
+    // Без frame_stats, например при воспроизведении записи, настоящие времена кадров не показываются:
+    // они отличаются от записанных, и кадры воспроизведения иначе не совпадали бы с кадрами записи.
+    pub fn show(
+        &mut self,
+        context: &egui::Context,
+        device_info: &DeviceInfo,
+        frame_stats: Option<&FrameStats>,
+        present_mode: vk::PresentModeKHR,
+    ) {
+        if !self.visible {
+            return;
+        }
+        egui::Window::new("Debug")
+            .default_pos(egui::pos2(8.0, 8.0))
+            .resizable(false)
+            .show(context, |ui| {
+                egui::CollapsingHeader::new("Device")
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        egui::Grid::new("device").num_columns(2).show(ui, |ui| {
+                            ui.label("Name");
+                            ui.label(&device_info.name);
+                            ui.end_row();
+                            ui.label("Type");
+                            ui.label(format!("{:?}", device_info.device_type));
+                            ui.end_row();
+                            ui.label("Vulkan");
+                            ui.label(format!(
+                                "{}.{}.{}",
+                                vk::api_version_major(device_info.api_version),
+                                vk::api_version_minor(device_info.api_version),
+                                vk::api_version_patch(device_info.api_version)
+                            ));
+                            ui.end_row();
+                            ui.label("Driver");
+                            ui.label(format!("{:#x}", device_info.driver_version));
+                            ui.end_row();
+                            ui.label("Rendering");
+                            ui.label(&device_info.rendering);
+                            ui.end_row();
+                            ui.label("Present mode");
+                            ui.label(format!("{:?}", present_mode));
+                            ui.end_row();
+                        });
+                    });
+
+                egui::CollapsingHeader::new("Frame timings")
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        let frame_stats = match frame_stats {
+                            Some(frame_stats) => frame_stats,
+                            None => {
+                                ui.label("Not measured during replay");
+                                return;
+                            }
+                        };
+                        ui.label(format!("{:.1} FPS", frame_stats.fps()));
+                        egui::Grid::new("timings")
+                            .num_columns(4)
+                            .striped(true)
+                            .show(ui, |ui| {
+                                ui.label("ms");
+                                ui.label("avg");
+                                ui.label("p95");
+                                ui.label("max");
+                                ui.end_row();
+                                for (name, stats) in frame_stats.metrics() {
+                                    ui.label(name);
+                                    ui.label(format!("{:.3}", stats.average()));
+                                    ui.label(format!("{:.3}", stats.percentile(95.0)));
+                                    ui.label(format!("{:.3}", stats.max()));
+                                    ui.end_row();
+                                }
+                            });
+                    });
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    for mode in DrawMode::ALL {
+                        ui.add_enabled_ui(mode.is_supported(self.non_solid_supported), |ui| {
+                            ui.radio_value(&mut self.draw_mode, mode, mode.name())
+                        });
+                    }
+                });
+                ui.checkbox(&mut self.vsync, "VSync");
+                ui.horizontal(|ui| {
+                    ui.radio_value(&mut self.window_mode, WindowMode::Windowed, "Windowed");
+                    ui.radio_value(&mut self.window_mode, WindowMode::Borderless, "Borderless");
+                    ui.radio_value(&mut self.window_mode, WindowMode::Exclusive, "Exclusive");
+                });
+            });
+    }
+}
@@ -0,0 +1,107 @@
+use ash::extensions::khr;
+use ash::vk;
+
+use std::ffi::CStr;
+
+// Непрямая отрисовка с числом команд из буфера: сколько команд выполнить, решает видеокарта, а не процессор.
+// В Vulkan 1.2 она входит в ядро (функция drawIndirectCount), а раньше была расширением VK_KHR_draw_indirect_count.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrawIndirectCountSupport {
+    Core,
+    Extension,
+    Unsupported,
+}
+
+impl DrawIndirectCountSupport {
+    pub fn query(instance: &ash::Instance, p_device: vk::PhysicalDevice) -> Self {
+        let properties = unsafe { instance.get_physical_device_properties(p_device) };
+        if properties.api_version >= vk::API_VERSION_1_2 {
+            // В ядре функция необязательная, ее поддержку нужно проверить.
+            let mut vulkan_12_features = vk::PhysicalDeviceVulkan12Features::default();
+            let mut features =
+                vk::PhysicalDeviceFeatures2::builder().push_next(&mut vulkan_12_features);
+            unsafe { instance.get_physical_device_features2(p_device, &mut features) };
+            if vulkan_12_features.draw_indirect_count == vk::TRUE {
+                return DrawIndirectCountSupport::Core;
+            }
+        }
+
+        let has_extension = unsafe { instance.enumerate_device_extension_properties(p_device) }
+            .unwrap_or_default()
+            .iter()
+            .any(|extension| {
+                let name = unsafe { CStr::from_ptr(extension.extension_name.as_ptr()) };
+                name == khr::DrawIndirectCount::name()
+            });
+        if has_extension {
+            DrawIndirectCountSupport::Extension
+        } else {
+            DrawIndirectCountSupport::Unsupported
+        }
+    }
+
+    // Расширение устройства, которое нужно включить для этого способа.
+    pub fn extension_name(self) -> Option<&'static CStr> {
+        match self {
+            DrawIndirectCountSupport::Extension => Some(khr::DrawIndirectCount::name()),
+            _ => None,
+        }
+    }
+}
+
+// Команда непрямой отрисовки с числом команд из буфера: из ядра или из расширения.
+#[derive(Clone)]
+pub enum DrawIndirectCount {
+    Core,
+    Extension(khr::DrawIndirectCount),
+}
+
+impl DrawIndirectCount {
+    pub fn new(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        support: DrawIndirectCountSupport,
+    ) -> Option<Self> {
+        match support {
+            DrawIndirectCountSupport::Core => Some(DrawIndirectCount::Core),
+            DrawIndirectCountSupport::Extension => Some(DrawIndirectCount::Extension(
+                khr::DrawIndirectCount::new(instance, device),
+            )),
+            DrawIndirectCountSupport::Unsupported => None,
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub unsafe fn cmd_draw_indexed_indirect_count(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        count_buffer: vk::Buffer,
+        count_buffer_offset: vk::DeviceSize,
+        max_draw_count: u32,
+        stride: u32,
+    ) {
+        match self {
+            DrawIndirectCount::Core => device.cmd_draw_indexed_indirect_count(
+                command_buffer,
+                buffer,
+                offset,
+                count_buffer,
+                count_buffer_offset,
+                max_draw_count,
+                stride,
+            ),
+            DrawIndirectCount::Extension(loader) => loader.cmd_draw_indexed_indirect_count(
+                command_buffer,
+                buffer,
+                offset,
+                count_buffer,
+                count_buffer_offset,
+                max_draw_count,
+                stride,
+            ),
+        }
+    }
+}
@@ -0,0 +1,44 @@
+// Как растеризуется геометрия сцены. Каркас и точки задаются режимом полигонов конвейера (polygon_mode),
+// для которого нужна необязательная функция устройства fill_mode_non_solid. Без нее доступна только заливка.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrawMode {
+    // Обычная заливка треугольников.
+    Fill,
+    // Только ребра треугольников (PolygonMode::LINE).
+    Wireframe,
+    // Только вершины (PolygonMode::POINT).
+    Points,
+    // Заливка, а поверх нее каркас другим цветом.
+    Overlay,
+}
+
+impl DrawMode {
+    pub const ALL: [DrawMode; 4] = [
+        DrawMode::Fill,
+        DrawMode::Wireframe,
+        DrawMode::Points,
+        DrawMode::Overlay,
+    ];
+
+    pub fn is_supported(self, non_solid_supported: bool) -> bool {
+        self == DrawMode::Fill || non_solid_supported
+    }
+
+    // Следующий режим, используется для переключения по клавише. Недоступные на устройстве режимы пропускаются.
+    pub fn next(self, non_solid_supported: bool) -> Self {
+        let index = Self::ALL.iter().position(|&mode| mode == self).unwrap();
+        (1..=Self::ALL.len())
+            .map(|offset| Self::ALL[(index + offset) % Self::ALL.len()])
+            .find(|mode| mode.is_supported(non_solid_supported))
+            .unwrap_or(DrawMode::Fill)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DrawMode::Fill => "Fill",
+            DrawMode::Wireframe => "Wireframe",
+            DrawMode::Points => "Points",
+            DrawMode::Overlay => "Overlay",
+        }
+    }
+}
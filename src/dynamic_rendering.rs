@@ -0,0 +1,102 @@
+use ash::extensions::khr;
+use ash::vk;
+
+use std::ffi::CStr;
+
+// Динамический рендеринг позволяет рисовать прямо в image view, без объектов vk::RenderPass и vk::Framebuffer.
+// В Vulkan 1.3 он входит в ядро, а на более старых драйверах доступен через расширение VK_KHR_dynamic_rendering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DynamicRenderingSupport {
+    Core,
+    Extension,
+    Unsupported,
+}
+
+impl DynamicRenderingSupport {
+    pub fn query(instance: &ash::Instance, p_device: vk::PhysicalDevice) -> Self {
+        let properties = unsafe { instance.get_physical_device_properties(p_device) };
+        let has_extension = unsafe { instance.enumerate_device_extension_properties(p_device) }
+            .unwrap_or_default()
+            .iter()
+            .any(|extension| {
+                let name = unsafe { CStr::from_ptr(extension.extension_name.as_ptr()) };
+                name == khr::DynamicRendering::name()
+            });
+
+        let support = if properties.api_version >= vk::API_VERSION_1_3 {
+            DynamicRenderingSupport::Core
+        } else if has_extension {
+            DynamicRenderingSupport::Extension
+        } else {
+            return DynamicRenderingSupport::Unsupported;
+        };
+
+        // Наличия версии или расширения мало, нужно еще убедиться, что устройство поддерживает саму функцию.
+        let mut dynamic_rendering_features = vk::PhysicalDeviceDynamicRenderingFeatures::default();
+        let mut features =
+            vk::PhysicalDeviceFeatures2::builder().push_next(&mut dynamic_rendering_features);
+        unsafe { instance.get_physical_device_features2(p_device, &mut features) };
+
+        if dynamic_rendering_features.dynamic_rendering == vk::TRUE {
+            support
+        } else {
+            DynamicRenderingSupport::Unsupported
+        }
+    }
+
+    // Расширение устройства, которое нужно включить для этого способа.
+    pub fn extension_name(self) -> Option<&'static CStr> {
+        match self {
+            DynamicRenderingSupport::Extension => Some(khr::DynamicRendering::name()),
+            _ => None,
+        }
+    }
+}
+
+// Команды начала и конца динамического рендеринга: из ядра или из расширения.
+#[derive(Clone)]
+pub enum DynamicRendering {
+    Core,
+    Extension(khr::DynamicRendering),
+}
+
+impl DynamicRendering {
+    pub fn new(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        support: DynamicRenderingSupport,
+    ) -> Option<Self> {
+        match support {
+            DynamicRenderingSupport::Core => Some(DynamicRendering::Core),
+            DynamicRenderingSupport::Extension => Some(DynamicRendering::Extension(
+                khr::DynamicRendering::new(instance, device),
+            )),
+            DynamicRenderingSupport::Unsupported => None,
+        }
+    }
+
+    pub unsafe fn cmd_begin_rendering(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        rendering_info: &vk::RenderingInfo,
+    ) {
+        match self {
+            DynamicRendering::Core => device.cmd_begin_rendering(command_buffer, rendering_info),
+            DynamicRendering::Extension(loader) => {
+                loader.cmd_begin_rendering(command_buffer, rendering_info)
+            }
+        }
+    }
+
+    pub unsafe fn cmd_end_rendering(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+    ) {
+        match self {
+            DynamicRendering::Core => device.cmd_end_rendering(command_buffer),
+            DynamicRendering::Extension(loader) => loader.cmd_end_rendering(command_buffer),
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use ash::vk;
+use bevy_ecs::component::{Component, StorageType};
+use bevy_ecs::prelude::{IntoSystemConfigs, Query, ResMut, Resource, Schedule, SystemSet, World};
+
+use crate::render_graph::{ImageHandle, RenderGraph};
+use crate::scene::{Light, Material, MeshHandle, SceneDraw, SceneLight, SkinHandle, Transform};
+use crate::scene_renderer::SceneRenderer;
+
+// Связка с bevy_ecs для приложений, которые хранят сцену в мире ECS, а не в Scene. Сущность рисуется,
+// если у нее есть компоненты Transform, MeshHandle и Material, а скелетная сетка деформируется палитрой
+// из компонента SkinHandle. Сущность с компонентами Transform и Light освещает сцену, и источник света
+// добавляется, двигается и убирается как любой компонент. Transform сущности задает ее положение в мире:
+// иерархию, если она нужна, приложение ведет само.
+//
+// SceneRenderer живет в мире как ресурс. Каждый кадр системы extract_draws и extract_lights собирают
+// сущности в ресурс DrawList, а add_pass добавляет в граф проход, который рисует этот список.
+
+impl Component for Transform {
+    const STORAGE_TYPE: StorageType = StorageType::Table;
+}
+
+impl Component for MeshHandle {
+    const STORAGE_TYPE: StorageType = StorageType::Table;
+}
+
+impl Component for Material {
+    const STORAGE_TYPE: StorageType = StorageType::Table;
+}
+
+impl Component for SkinHandle {
+    const STORAGE_TYPE: StorageType = StorageType::Table;
+}
+
+impl Component for Light {
+    const STORAGE_TYPE: StorageType = StorageType::Table;
+}
+
+impl Resource for SceneRenderer {}
+
+// Отрисовки и источники света, собранные из мира за текущий кадр.
+#[derive(Default)]
+pub struct DrawList {
+    draws: Vec<SceneDraw>,
+    lights: Vec<SceneLight>,
+}
+
+impl Resource for DrawList {}
+
+impl DrawList {
+    pub fn draws(&self) -> &[SceneDraw] {
+        &self.draws
+    }
+
+    pub fn lights(&self) -> &[SceneLight] {
+        &self.lights
+    }
+}
+
+// Набор систем, переносящих данные мира в ресурсы отрисовки. Системы, которые двигают сущности,
+// должны выполняться до него: animate.before(ExtractDraws).
+#[derive(Debug, Clone, PartialEq, Eq, Hash, SystemSet)]
+pub struct ExtractDraws;
+
+// Кладет в мир ресурсы отрисовки. Сетки после этого создаются через world.resource_mut::<SceneRenderer>().
+pub fn insert_render_resources(world: &mut World, renderer: SceneRenderer) {
+    world.insert_resource(renderer);
+    world.init_resource::<DrawList>();
+}
+
+// Добавляет в расписание приложения системы набора ExtractDraws.
+pub fn add_extract_systems(schedule: &mut Schedule) {
+    schedule.add_systems((extract_draws, extract_lights).in_set(ExtractDraws));
+}
+
+pub fn extract_draws(
+    entities: Query<(&Transform, &MeshHandle, &Material, Option<&SkinHandle>)>,
+    mut draw_list: ResMut<DrawList>,
+) {
+    draw_list.draws.clear();
+    draw_list.draws.extend(
+        entities
+            .iter()
+            .map(|(transform, &mesh, &material, skin)| SceneDraw {
+                mesh,
+                material,
+                world: transform.matrix(),
+                skin: skin.copied(),
+            }),
+    );
+}
+
+pub fn extract_lights(entities: Query<(&Transform, &Light)>, mut draw_list: ResMut<DrawList>) {
+    draw_list.lights.clear();
+    draw_list
+        .lights
+        .extend(entities.iter().map(|(transform, &light)| SceneLight {
+            light,
+            world: transform.matrix(),
+        }));
+}
+
+// Добавляет в граф проход name, рисующий DrawList последнего выполнения систем ExtractDraws.
+#[allow(clippy::too_many_arguments)]
+pub fn add_pass(
+    world: &mut World,
+    device: &ash::Device,
+    graph: &mut RenderGraph,
+    target: ImageHandle,
+    extent: vk::Extent2D,
+    frame_index: usize,
+    name: &str,
+    view_projection: [f32; 16],
+    camera_position: [f32; 3],
+) {
+    world.resource_scope(|world, mut renderer: bevy_ecs::world::Mut<SceneRenderer>| {
+        renderer.add_pass(
+            device,
+            graph,
+            target,
+            extent,
+            frame_index,
+            name,
+            world.resource::<DrawList>().draws().iter().copied(),
+            world.resource::<DrawList>().lights().iter().copied(),
+            view_projection,
+            camera_position,
+        );
+    });
+}
+
+// Забирает ресурсы отрисовки из мира и освобождает их. Вызывается после ожидания простоя устройства.
+pub unsafe fn destroy_render_resources(world: &mut World, device: &ash::Device) {
+    world.remove_resource::<DrawList>();
+    if let Some(mut renderer) = world.remove_resource::<SceneRenderer>() {
+        renderer.destroy(device);
+    }
+}
@@ -0,0 +1,221 @@
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+// Скользящее окно последних значений одной метрики в миллисекундах.
+pub struct RollingStats {
+    samples: VecDeque<f64>,
+    capacity: usize,
+}
+
+impl RollingStats {
+    pub fn new(capacity: usize) -> Self {
+        RollingStats {
+            samples: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    pub fn push(&mut self, value: f64) {
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(value);
+    }
+
+    pub fn average(&self) -> f64 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+        self.samples.iter().sum::<f64>() / self.samples.len() as f64
+    }
+
+    // Перцентиль методом ближайшего ранга, percentile от 0 до 100.
+    pub fn percentile(&self, percentile: f64) -> f64 {
+        if self.samples.is_empty() {
+            return 0.0;
+        }
+        let mut sorted = self.samples.iter().copied().collect::<Vec<_>>();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+        let rank = (percentile / 100.0 * sorted.len() as f64).ceil() as usize;
+        sorted[rank.clamp(1, sorted.len()) - 1]
+    }
+
+    pub fn max(&self) -> f64 {
+        self.samples.iter().copied().fold(0.0, f64::max)
+    }
+}
+
+// Статистика времени кадра: именованные метрики (фазы процессора, проходы видеокарты) и частота кадров.
+// Метрики хранятся в порядке первого появления, чтобы отчет всегда выводился одинаково.
+pub struct FrameStats {
+    window: usize,
+    metrics: Vec<(String, RollingStats)>,
+    report_interval: Duration,
+    last_report: Instant,
+    frames_since_report: u32,
+    fps: f64,
+}
+
+impl FrameStats {
+    // window — сколько последних кадров учитывается, report_interval — как часто обновлять отчет.
+    pub fn new(window: usize, report_interval: Duration) -> Self {
+        FrameStats {
+            window,
+            metrics: Vec::new(),
+            report_interval,
+            last_report: Instant::now(),
+            frames_since_report: 0,
+            fps: 0.0,
+        }
+    }
+
+    pub fn record(&mut self, name: &str, milliseconds: f64) {
+        match self.metrics.iter_mut().find(|(metric, _)| metric == name) {
+            Some((_, stats)) => stats.push(milliseconds),
+            None => {
+                let mut stats = RollingStats::new(self.window);
+                stats.push(milliseconds);
+                self.metrics.push((name.to_owned(), stats));
+            }
+        }
+    }
+
+    pub fn record_duration(&mut self, name: &str, duration: Duration) {
+        self.record(name, duration.as_secs_f64() * 1000.0);
+    }
+
+    pub fn get(&self, name: &str) -> Option<&RollingStats> {
+        self.metrics
+            .iter()
+            .find(|(metric, _)| metric == name)
+            .map(|(_, stats)| stats)
+    }
+
+    // Все метрики в порядке первого появления.
+    pub fn metrics(&self) -> impl Iterator<Item = (&str, &RollingStats)> {
+        self.metrics
+            .iter()
+            .map(|(name, stats)| (name.as_str(), stats))
+    }
+
+    pub fn fps(&self) -> f64 {
+        self.fps
+    }
+
+    // Вызывается один раз за кадр. Возвращает true, когда прошел интервал отчета и частота кадров пересчитана.
+    pub fn end_frame(&mut self) -> bool {
+        self.frames_since_report += 1;
+        let elapsed = self.last_report.elapsed();
+        if elapsed < self.report_interval {
+            return false;
+        }
+        self.fps = self.frames_since_report as f64 / elapsed.as_secs_f64();
+        self.frames_since_report = 0;
+        self.last_report = Instant::now();
+        true
+    }
+
+    // Таблица со средним, перцентилями и максимумом каждой метрики.
+    pub fn report(&self) -> String {
+        let mut report = String::new();
+        writeln!(report, "{:.1} FPS", self.fps).unwrap();
+        writeln!(
+            report,
+            "{:<24}{:>9}{:>9}{:>9}{:>9}{:>9}",
+            "ms", "avg", "p50", "p95", "p99", "max"
+        )
+        .unwrap();
+        for (name, stats) in &self.metrics {
+            writeln!(
+                report,
+                "{:<24}{:>9.3}{:>9.3}{:>9.3}{:>9.3}{:>9.3}",
+                name,
+                stats.average(),
+                stats.percentile(50.0),
+                stats.percentile(95.0),
+                stats.percentile(99.0),
+                stats.max()
+            )
+            .unwrap();
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_window_reports_zero() {
+        let stats = RollingStats::new(4);
+        assert_eq!(stats.average(), 0.0);
+        assert_eq!(stats.percentile(0.0), 0.0);
+        assert_eq!(stats.percentile(50.0), 0.0);
+        assert_eq!(stats.percentile(100.0), 0.0);
+        assert_eq!(stats.max(), 0.0);
+    }
+
+    #[test]
+    fn single_sample_is_every_percentile() {
+        let mut stats = RollingStats::new(4);
+        stats.push(7.5);
+        assert_eq!(stats.average(), 7.5);
+        for percentile in [0.0, 1.0, 50.0, 99.0, 100.0] {
+            assert_eq!(stats.percentile(percentile), 7.5);
+        }
+        assert_eq!(stats.max(), 7.5);
+    }
+
+    #[test]
+    fn percentiles_use_nearest_rank() {
+        let mut stats = RollingStats::new(10);
+        for value in [5.0, 1.0, 4.0, 2.0, 3.0] {
+            stats.push(value);
+        }
+        // p0 — наименьшее значение, p100 — наибольшее.
+        assert_eq!(stats.percentile(0.0), 1.0);
+        assert_eq!(stats.percentile(20.0), 1.0);
+        assert_eq!(stats.percentile(21.0), 2.0);
+        assert_eq!(stats.percentile(50.0), 3.0);
+        assert_eq!(stats.percentile(100.0), 5.0);
+        assert_eq!(stats.average(), 3.0);
+    }
+
+    #[test]
+    fn window_evicts_oldest_samples() {
+        let mut stats = RollingStats::new(3);
+        for value in [100.0, 1.0, 2.0] {
+            stats.push(value);
+        }
+        assert_eq!(stats.max(), 100.0);
+        stats.push(3.0);
+        // Старейшее значение 100 вытеснено, в окне остались 1, 2, 3.
+        assert_eq!(stats.max(), 3.0);
+        assert_eq!(stats.average(), 2.0);
+        assert_eq!(stats.percentile(0.0), 1.0);
+        for value in [4.0, 5.0, 6.0, 7.0] {
+            stats.push(value);
+        }
+        assert_eq!(stats.average(), 6.0);
+        assert_eq!(stats.percentile(0.0), 5.0);
+        assert_eq!(stats.percentile(100.0), 7.0);
+    }
+
+    #[test]
+    fn metrics_keep_first_appearance_order() {
+        let mut frame_stats = FrameStats::new(2, Duration::from_secs(1));
+        frame_stats.record("gpu", 2.0);
+        frame_stats.record("cpu", 1.0);
+        frame_stats.record("gpu", 4.0);
+        frame_stats.record("gpu", 6.0);
+        let names = frame_stats
+            .metrics()
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["gpu", "cpu"]);
+        assert_eq!(frame_stats.get("gpu").unwrap().average(), 5.0);
+        assert!(frame_stats.get("missing").is_none());
+    }
+}
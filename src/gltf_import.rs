@@ -0,0 +1,686 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use glam::{Mat4, Quat, Vec3};
+use gltf::animation::util::ReadOutputs;
+use gltf::khr_lights_punctual::Kind;
+use gltf::mesh::Mode;
+
+use crate::animation::{
+    validate_keyframes, AnimationClip, Channel, Interpolation, Keyframes, Skin,
+};
+use crate::scene::{
+    CameraProjection, Light, LightKind, Material, MaterialId, MeshData, MeshHandle, MeshVertex,
+    NodeId, Scene, SkinHandle, TextureHandle, TextureImage, Transform, VertexJoints,
+};
+use crate::scene_renderer::SceneRenderer;
+
+// Импорт сцен glTF 2.0: текстовых .gltf с внешними файлами или буферами в data URI и двоичных .glb.
+// Узлы становятся узлами Scene с той же иерархией, каждый примитив сетки — отдельной сеткой SceneRenderer,
+// материалы PBR metallic-roughness — материалами сцены с цветом, текстурой цвета и параметрами
+// металличности и шероховатости. Камеры и источники света KHR_lights_punctual попадают в узлы сцены.
+// Скины и анимации узлов возвращаются вместе с корнем: их проигрывает приложение. Анимации весов
+// морфинга пропускаются.
+//
+// Файл сначала полностью читается и проверяется, и только потом в сцене и SceneRenderer что-то создается,
+// поэтому при ошибке сцена остается прежней. Из текстур поддерживается только текстура цвета,
+// а параметры выборки из файла заменяются выборкой SceneRenderer с повторением.
+
+// Примитив, переведенный в сетку сцены, и номер его материала в файле.
+struct Primitive {
+    mesh: MeshData,
+    material: Option<usize>,
+}
+
+// Скин файла: суставы — номера в списке узлов, как у NodeEntry::parent.
+struct SkinEntry {
+    joints: Vec<usize>,
+    inverse_bind_matrices: Vec<Mat4>,
+}
+
+// Канал анимации файла до переноса в сцену.
+struct ChannelEntry {
+    // Номер в списке узлов.
+    node: usize,
+    interpolation: Interpolation,
+    times: Vec<f32>,
+    keyframes: Keyframes,
+}
+
+// Результат загрузки: корень модели, скины с узлами их сеток и анимации.
+pub struct GltfScene {
+    pub root: NodeId,
+    // Узел скелетной сетки и ее скин. Матрицы суставов считаются для этого узла.
+    pub skins: Vec<(NodeId, Skin)>,
+    pub animations: Vec<AnimationClip>,
+}
+
+// Узел файла в порядке добавления в сцену: родитель всегда раньше детей.
+struct NodeEntry<'a> {
+    node: gltf::Node<'a>,
+    // Номер родителя в том же списке. Без него узел становится ребенком корня модели.
+    parent: Option<usize>,
+}
+
+// Загружает файл и добавляет его сцену под новый корневой узел с именем файла.
+// max_image_dimension — наибольшая сторона текстуры, которую поддерживает устройство
+// (PhysicalDeviceLimits::max_image_dimension2_d).
+pub fn load_gltf(
+    device: &ash::Device,
+    renderer: &mut SceneRenderer,
+    scene: &mut Scene,
+    path: &Path,
+    max_image_dimension: u32,
+) -> Result<GltfScene, String> {
+    let error = |message: String| format!("{}: {}", path.display(), message);
+    let (document, buffers, images) =
+        gltf::import(path).map_err(|import_error| error(import_error.to_string()))?;
+
+    let nodes = node_order(&document).map_err(error)?;
+    let meshes = document
+        .meshes()
+        .map(|mesh| {
+            mesh.primitives()
+                .map(|primitive| {
+                    read_primitive(&primitive, &buffers).map_err(|message| {
+                        format!(
+                            "mesh {} primitive {}: {}",
+                            mesh_name(&mesh),
+                            primitive.index(),
+                            message
+                        )
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(error)?;
+
+    // Номер узла файла в списке узлов. Узлы вне иерархии сцены в сцену не попадают.
+    let mut node_positions = vec![None; document.nodes().len()];
+    for (position, entry) in nodes.iter().enumerate() {
+        node_positions[entry.node.index()] = Some(position);
+    }
+    let skins = document
+        .skins()
+        .map(|skin| {
+            read_skin(&skin, &buffers, &node_positions)
+                .map_err(|message| format!("skin {}: {}", skin.index(), message))
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(error)?;
+    for entry in &nodes {
+        if let (Some(mesh), Some(skin)) = (entry.node.mesh(), entry.node.skin()) {
+            let joint_count = skins[skin.index()].joints.len();
+            let max_joint = meshes[mesh.index()]
+                .iter()
+                .flat_map(|primitive| &primitive.mesh.joints)
+                .flat_map(|vertex| vertex.joints.iter().copied())
+                .max();
+            if let Some(joint) = max_joint.filter(|&joint| joint as usize >= joint_count) {
+                return Err(error(format!(
+                    "mesh {} uses joint {}, but skin {} has {} joints",
+                    mesh_name(&mesh),
+                    joint,
+                    skin.index(),
+                    joint_count
+                )));
+            }
+        }
+    }
+    let animations = document
+        .animations()
+        .map(|animation| {
+            let name = animation
+                .name()
+                .map_or_else(|| format!("animation {}", animation.index()), str::to_owned);
+            read_animation(&animation, &buffers, &node_positions)
+                .map(|channels| (name.clone(), channels))
+                .map_err(|message| format!("{}: {}", name, message))
+        })
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(error)?;
+
+    // Из изображений переводятся только те, что служат текстурой цвета.
+    let mut texture_images = HashMap::new();
+    for material in document.materials() {
+        if let Some(info) = material.pbr_metallic_roughness().base_color_texture() {
+            let image = info.texture().source().index();
+            if let std::collections::hash_map::Entry::Vacant(entry) = texture_images.entry(image) {
+                let pixels = convert_image(&images[image], max_image_dimension)
+                    .map_err(|message| error(format!("image {}: {}", image, message)))?;
+                entry.insert(pixels);
+            }
+        }
+    }
+    if texture_images.len() > renderer.remaining_textures() {
+        return Err(error(format!(
+            "{} textures do not fit into {} free texture slots",
+            texture_images.len(),
+            renderer.remaining_textures()
+        )));
+    }
+
+    // Дальше ошибок нет: файл переносится в сцену и видеопамять.
+    let textures = texture_images
+        .into_iter()
+        .map(|(image, pixels)| (image, renderer.create_texture(device, &pixels)))
+        .collect::<HashMap<usize, TextureHandle>>();
+    let materials = document
+        .materials()
+        .map(|material| scene.add_material(convert_material(&material, &textures)))
+        .collect::<Vec<_>>();
+    // Материал по умолчанию из спецификации: белый, полностью металлический и шероховатый.
+    let mut default_material = None;
+    let meshes = meshes
+        .into_iter()
+        .map(|primitives| {
+            primitives
+                .into_iter()
+                .map(|primitive| {
+                    let material = match primitive.material {
+                        Some(material) => materials[material],
+                        None => *default_material.get_or_insert_with(|| {
+                            scene.add_material(Material {
+                                metallic: 1.0,
+                                ..Material::from_color([1.0; 4])
+                            })
+                        }),
+                    };
+                    (renderer.create_mesh(device, &primitive.mesh), material)
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let root_name = path.file_name().map_or_else(
+        || "gltf".to_owned(),
+        |name| name.to_string_lossy().into_owned(),
+    );
+    let root = scene.add_node(&root_name, None, Transform::IDENTITY);
+    let mut scene_nodes: Vec<NodeId> = Vec::with_capacity(nodes.len());
+    // У каждой скелетной сетки своя палитра: матрицы суставов зависят от положения ее узла.
+    let mut skinned_nodes = Vec::new();
+    for entry in &nodes {
+        let parent = entry.parent.map_or(root, |parent| scene_nodes[parent]);
+        let skin = match (entry.node.mesh(), entry.node.skin()) {
+            (Some(_), Some(skin)) => {
+                let handle = renderer.create_skin(skins[skin.index()].joints.len());
+                skinned_nodes.push((scene_nodes.len(), skin.index(), handle));
+                Some(handle)
+            }
+            _ => None,
+        };
+        let node = add_node(scene, &entry.node, parent, &meshes, skin);
+        scene_nodes.push(node);
+    }
+    let skins = skinned_nodes
+        .into_iter()
+        .map(|(node, skin, handle)| {
+            let skin = &skins[skin];
+            (
+                scene_nodes[node],
+                Skin {
+                    joints: skin
+                        .joints
+                        .iter()
+                        .map(|&joint| scene_nodes[joint])
+                        .collect(),
+                    inverse_bind_matrices: skin.inverse_bind_matrices.clone(),
+                    handle,
+                },
+            )
+        })
+        .collect::<Vec<_>>();
+    let animations = animations
+        .into_iter()
+        .map(|(name, channels)| AnimationClip {
+            name,
+            channels: channels
+                .into_iter()
+                .map(|channel| Channel {
+                    node: scene_nodes[channel.node],
+                    interpolation: channel.interpolation,
+                    times: channel.times,
+                    keyframes: channel.keyframes,
+                })
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+
+    println!(
+        "Loaded glTF {}: {} nodes, {} meshes, {} materials, {} textures, {} cameras, {} lights, \
+         {} skins, {} animations",
+        path.display(),
+        nodes.len(),
+        meshes.len(),
+        materials.len(),
+        textures.len(),
+        nodes
+            .iter()
+            .filter(|entry| entry.node.camera().is_some())
+            .count(),
+        nodes
+            .iter()
+            .filter(|entry| entry.node.light().is_some())
+            .count(),
+        skins.len(),
+        animations.len(),
+    );
+    Ok(GltfScene {
+        root,
+        skins,
+        animations,
+    })
+}
+
+// Обходит узлы сцены файла в глубину. Сценой считается сцена по умолчанию, затем первая сцена,
+// а если сцен нет — все узлы, которые не являются чьими-то детьми.
+fn node_order(document: &gltf::Document) -> Result<Vec<NodeEntry<'_>>, String> {
+    let roots = match document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    {
+        Some(scene) => scene.nodes().collect::<Vec<_>>(),
+        None => {
+            let mut is_child = vec![false; document.nodes().len()];
+            for node in document.nodes() {
+                for child in node.children() {
+                    is_child[child.index()] = true;
+                }
+            }
+            document
+                .nodes()
+                .filter(|node| !is_child[node.index()])
+                .collect()
+        }
+    };
+
+    // В glTF узлы образуют деревья: узел, встреченный дважды, означает цикл или общий узел.
+    let mut visited = vec![false; document.nodes().len()];
+    let mut entries = Vec::new();
+    let mut stack = roots
+        .into_iter()
+        .rev()
+        .map(|node| (node, None))
+        .collect::<Vec<_>>();
+    while let Some((node, parent)) = stack.pop() {
+        if std::mem::replace(&mut visited[node.index()], true) {
+            return Err(format!(
+                "node {} appears more than once in the node hierarchy",
+                node.index()
+            ));
+        }
+        let index = entries.len();
+        let children = node.children().collect::<Vec<_>>();
+        stack.extend(children.into_iter().rev().map(|child| (child, Some(index))));
+        entries.push(NodeEntry { node, parent });
+    }
+    Ok(entries)
+}
+
+fn add_node(
+    scene: &mut Scene,
+    node: &gltf::Node,
+    parent: NodeId,
+    meshes: &[Vec<(MeshHandle, MaterialId)>],
+    skin: Option<SkinHandle>,
+) -> NodeId {
+    let (translation, rotation, scale) = node.transform().decomposed();
+    let name = node
+        .name()
+        .map_or_else(|| format!("node {}", node.index()), str::to_owned);
+    let id = scene.add_node(
+        &name,
+        Some(parent),
+        Transform {
+            translation: Vec3::from(translation),
+            rotation: Quat::from_array(rotation),
+            scale: Vec3::from(scale),
+        },
+    );
+
+    // В узле сцены одна сетка, поэтому примитивы сверх первого получают дочерние узлы без смещения.
+    if let Some(mesh) = node.mesh() {
+        let primitives = &meshes[mesh.index()];
+        for (number, &(mesh_handle, material)) in primitives.iter().enumerate() {
+            let target = if number == 0 {
+                id
+            } else {
+                scene.add_node(
+                    &format!("{} primitive {}", name, number),
+                    Some(id),
+                    Transform::IDENTITY,
+                )
+            };
+            scene.set_mesh(target, mesh_handle, material);
+            if let Some(skin) = skin {
+                scene.set_skin(target, skin);
+            }
+        }
+    }
+
+    if let Some(camera) = node.camera() {
+        scene.set_camera(id, convert_camera(&camera));
+    }
+    if let Some(light) = node.light() {
+        scene.set_light(id, convert_light(&light));
+    }
+    id
+}
+
+// Суставы скина и обратные матрицы привязки. Без матриц в файле они единичные.
+fn read_skin(
+    skin: &gltf::Skin,
+    buffers: &[gltf::buffer::Data],
+    node_positions: &[Option<usize>],
+) -> Result<SkinEntry, String> {
+    let joints = skin
+        .joints()
+        .map(|joint| {
+            node_positions[joint.index()]
+                .ok_or_else(|| format!("joint node {} is not in the scene", joint.index()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let reader = skin.reader(|buffer| buffers.get(buffer.index()).map(|data| &data.0[..]));
+    let inverse_bind_matrices = match reader.read_inverse_bind_matrices() {
+        Some(matrices) => matrices
+            .map(|matrix| Mat4::from_cols_array_2d(&matrix))
+            .collect::<Vec<_>>(),
+        None => vec![Mat4::IDENTITY; joints.len()],
+    };
+    if inverse_bind_matrices.len() != joints.len() {
+        return Err(format!(
+            "{} inverse bind matrices for {} joints",
+            inverse_bind_matrices.len(),
+            joints.len()
+        ));
+    }
+    Ok(SkinEntry {
+        joints,
+        inverse_bind_matrices,
+    })
+}
+
+// Каналы анимации. Каналы весов морфинга и узлов вне сцены пропускаются.
+fn read_animation(
+    animation: &gltf::Animation,
+    buffers: &[gltf::buffer::Data],
+    node_positions: &[Option<usize>],
+) -> Result<Vec<ChannelEntry>, String> {
+    let mut channels = Vec::new();
+    for channel in animation.channels() {
+        let node = match node_positions[channel.target().node().index()] {
+            Some(node) => node,
+            None => continue,
+        };
+        let reader = channel.reader(|buffer| buffers.get(buffer.index()).map(|data| &data.0[..]));
+        let error = |message: &str| format!("channel {}: {}", channel.index(), message);
+        let times = reader
+            .read_inputs()
+            .ok_or_else(|| error("no keyframe times"))?
+            .collect::<Vec<_>>();
+        let keyframes = match reader
+            .read_outputs()
+            .ok_or_else(|| error("no keyframe values"))?
+        {
+            ReadOutputs::Translations(values) => {
+                Keyframes::Translation(values.map(Vec3::from).collect())
+            }
+            ReadOutputs::Rotations(values) => {
+                Keyframes::Rotation(values.into_f32().map(Quat::from_array).collect())
+            }
+            ReadOutputs::Scales(values) => Keyframes::Scale(values.map(Vec3::from).collect()),
+            ReadOutputs::MorphTargetWeights(_) => continue,
+        };
+        let interpolation = match channel.sampler().interpolation() {
+            gltf::animation::Interpolation::Step => Interpolation::Step,
+            gltf::animation::Interpolation::Linear => Interpolation::Linear,
+            gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline,
+        };
+        validate_keyframes(interpolation, &times, &keyframes).map_err(|message| error(&message))?;
+        channels.push(ChannelEntry {
+            node,
+            interpolation,
+            times,
+            keyframes,
+        });
+    }
+    Ok(channels)
+}
+
+fn mesh_name(mesh: &gltf::Mesh) -> String {
+    mesh.name()
+        .map_or_else(|| mesh.index().to_string(), str::to_owned)
+}
+
+// Переводит примитив в индексированный список треугольников. Полосы и веера разворачиваются,
+// точки и линии не поддерживаются.
+fn read_primitive(
+    primitive: &gltf::Primitive,
+    buffers: &[gltf::buffer::Data],
+) -> Result<Primitive, String> {
+    let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| &data.0[..]));
+    let positions = reader
+        .read_positions()
+        .ok_or_else(|| "no POSITION attribute".to_owned())?
+        .collect::<Vec<_>>();
+    let normals = reader.read_normals().map(Iterator::collect::<Vec<_>>);
+    // Набор текстурных координат выбирает текстура цвета материала.
+    let uv_set = primitive
+        .material()
+        .pbr_metallic_roughness()
+        .base_color_texture()
+        .map_or(0, |info| info.tex_coord());
+    let uvs = reader
+        .read_tex_coords(uv_set)
+        .map(|uvs| uvs.into_f32().collect::<Vec<_>>());
+    let check_length = |name: &str, length: usize| {
+        if length == positions.len() {
+            Ok(())
+        } else {
+            Err(format!(
+                "{} has {} elements, but POSITION has {}",
+                name,
+                length,
+                positions.len()
+            ))
+        }
+    };
+    if let Some(normals) = &normals {
+        check_length("NORMAL", normals.len())?;
+    }
+    if let Some(uvs) = &uvs {
+        check_length(&format!("TEXCOORD_{}", uv_set), uvs.len())?;
+    }
+    // Суставы без весов и веса без суставов бесполезны, поэтому читаются только вместе.
+    let joints = match (reader.read_joints(0), reader.read_weights(0)) {
+        (Some(joints), Some(weights)) => {
+            let joints = joints.into_u16().collect::<Vec<_>>();
+            let weights = weights.into_f32().collect::<Vec<_>>();
+            check_length("JOINTS_0", joints.len())?;
+            check_length("WEIGHTS_0", weights.len())?;
+            joints
+                .into_iter()
+                .zip(weights)
+                .map(|(joints, weights)| VertexJoints {
+                    joints: joints.map(u32::from),
+                    weights,
+                })
+                .collect()
+        }
+        _ => Vec::new(),
+    };
+
+    let vertex_indices = match reader.read_indices() {
+        Some(indices) => indices.into_u32().collect::<Vec<_>>(),
+        None => (0..positions.len() as u32).collect(),
+    };
+    if let Some(&index) = vertex_indices
+        .iter()
+        .find(|&&index| index as usize >= positions.len())
+    {
+        return Err(format!(
+            "index {} is out of range for {} vertices",
+            index,
+            positions.len()
+        ));
+    }
+    let indices = match primitive.mode() {
+        Mode::Triangles => {
+            let mut indices = vertex_indices;
+            indices.truncate(indices.len() / 3 * 3);
+            indices
+        }
+        // В полосе у каждого второго треугольника обход меняется, и его вершины переставляются обратно.
+        Mode::TriangleStrip => (2..vertex_indices.len())
+            .flat_map(|last| {
+                let [a, b, c] = [last - 2, last - 1, last].map(|index| vertex_indices[index]);
+                if last % 2 == 0 {
+                    [a, b, c]
+                } else {
+                    [b, a, c]
+                }
+            })
+            .collect(),
+        Mode::TriangleFan => (2..vertex_indices.len())
+            .flat_map(|last| {
+                [
+                    vertex_indices[0],
+                    vertex_indices[last - 1],
+                    vertex_indices[last],
+                ]
+            })
+            .collect(),
+        mode => return Err(format!("primitive mode {:?} is not supported", mode)),
+    };
+
+    let mut mesh = MeshData {
+        vertices: positions
+            .iter()
+            .enumerate()
+            .map(|(index, &position)| MeshVertex {
+                position,
+                normal: normals.as_ref().map_or([0.0; 3], |normals| normals[index]),
+                uv: uvs.as_ref().map_or([0.0; 2], |uvs| uvs[index]),
+            })
+            .collect(),
+        indices,
+        joints,
+    };
+    if normals.is_none() {
+        mesh.compute_normals();
+    }
+    Ok(Primitive {
+        mesh,
+        material: primitive.material().index(),
+    })
+}
+
+fn convert_material(
+    material: &gltf::Material,
+    textures: &HashMap<usize, TextureHandle>,
+) -> Material {
+    let pbr = material.pbr_metallic_roughness();
+    Material {
+        base_color: pbr.base_color_factor(),
+        base_color_texture: pbr
+            .base_color_texture()
+            .map(|info| textures[&info.texture().source().index()]),
+        metallic: pbr.metallic_factor(),
+        roughness: pbr.roughness_factor(),
+    }
+}
+
+fn convert_camera(camera: &gltf::Camera) -> CameraProjection {
+    match camera.projection() {
+        gltf::camera::Projection::Perspective(perspective) => CameraProjection::Perspective {
+            vertical_fov: perspective.yfov(),
+            aspect_ratio: perspective.aspect_ratio(),
+            near: perspective.znear(),
+            far: perspective.zfar(),
+        },
+        gltf::camera::Projection::Orthographic(orthographic) => CameraProjection::Orthographic {
+            half_width: orthographic.xmag(),
+            half_height: orthographic.ymag(),
+            near: orthographic.znear(),
+            far: orthographic.zfar(),
+        },
+    }
+}
+
+fn convert_light(light: &gltf::khr_lights_punctual::Light) -> Light {
+    Light {
+        kind: match light.kind() {
+            Kind::Directional => LightKind::Directional,
+            Kind::Point => LightKind::Point,
+            Kind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => LightKind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            },
+        },
+        color: light.color(),
+        intensity: light.intensity(),
+        range: light.range(),
+    }
+}
+
+// Приводит декодированное изображение к RGBA с 8 битами на канал. У 16-битных каналов берется старший байт.
+fn convert_image(image: &gltf::image::Data, max_dimension: u32) -> Result<TextureImage, String> {
+    use gltf::image::Format;
+
+    // Vulkan не создает изображения нулевого размера и больше предела устройства.
+    if image.width == 0 || image.height == 0 {
+        return Err(format!(
+            "image size {}x{} is empty",
+            image.width, image.height
+        ));
+    }
+    if image.width > max_dimension || image.height > max_dimension {
+        return Err(format!(
+            "image size {}x{} exceeds {} pixels supported by the device",
+            image.width, image.height, max_dimension
+        ));
+    }
+
+    let high_bytes = || {
+        image
+            .pixels
+            .chunks_exact(2)
+            .map(|bytes| (u16::from_ne_bytes([bytes[0], bytes[1]]) >> 8) as u8)
+            .collect::<Vec<_>>()
+    };
+    let expand = |channels: &[u8], count: usize| -> Vec<u8> {
+        channels
+            .chunks_exact(count)
+            .flat_map(|pixel| match count {
+                1 => [pixel[0], pixel[0], pixel[0], 255],
+                2 => [pixel[0], pixel[1], 0, 255],
+                3 => [pixel[0], pixel[1], pixel[2], 255],
+                _ => [pixel[0], pixel[1], pixel[2], pixel[3]],
+            })
+            .collect()
+    };
+    let pixels = match image.format {
+        Format::R8 => expand(&image.pixels, 1),
+        Format::R8G8 => expand(&image.pixels, 2),
+        Format::R8G8B8 => expand(&image.pixels, 3),
+        Format::R8G8B8A8 => image.pixels.clone(),
+        Format::R16 => expand(&high_bytes(), 1),
+        Format::R16G16 => expand(&high_bytes(), 2),
+        Format::R16G16B16 => expand(&high_bytes(), 3),
+        Format::R16G16B16A16 => high_bytes(),
+        format => return Err(format!("pixel format {:?} is not supported", format)),
+    };
+    if pixels.len() != image.width as usize * image.height as usize * 4 {
+        return Err("pixel data does not match image size".to_owned());
+    }
+    Ok(TextureImage {
+        width: image.width,
+        height: image.height,
+        pixels,
+    })
+}
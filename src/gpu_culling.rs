@@ -0,0 +1,413 @@
+use ash::vk;
+
+use std::ffi::CString;
+
+use crate::draw_indirect_count::DrawIndirectCount;
+use crate::instancing::{InstanceData, InstancedRenderer, DEPTH_CLEAR_VALUE};
+use crate::memory::{as_bytes, Buffer};
+use crate::push_constants::PushConstants;
+use crate::render_graph::{BufferUsage, ImageHandle, LoadOp, RenderGraph};
+
+// Отрисовка, которую готовит видеокарта. Объекты сцены загружаются в видеопамять один раз, а каждый кадр
+// вычислительный шейдер отсекает их по пирамиде видимости и записывает команды отрисовки для видимых.
+// Процессор записывает только запуск шейдера и один вызов непрямой отрисовки, сколько бы ни было объектов.
+//
+// Каждая команда рисует один экземпляр сетки из InstancedRenderer, а first_instance команды выбирает объект
+// в буфере экземпляров. Поэтому нужна функция устройства draw_indirect_first_instance.
+
+// Размер группы в cull.wgsl.
+const WORKGROUP_SIZE: u32 = 64;
+
+// Push-константы cull.wgsl.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct CullPushConstants {
+    planes: [[f32; 4]; 6],
+    object_count: u32,
+    index_count: u32,
+}
+
+unsafe impl PushConstants for CullPushConstants {
+    const STAGES: vk::ShaderStageFlags = vk::ShaderStageFlags::COMPUTE;
+}
+
+// Буферы одного кадра в полете: команды отрисовки и их число. Их пишет только видеокарта.
+struct FrameBuffers {
+    commands: Buffer,
+    count: Buffer,
+    descriptor_set: vk::DescriptorSet,
+}
+
+pub struct GpuCulling {
+    pipeline: vk::Pipeline,
+    pipeline_layout: vk::PipelineLayout,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    // Данные экземпляров для вершинного шейдера и ограничивающие сферы для отсечения.
+    instances: Buffer,
+    bounds: Buffer,
+    object_count: u32,
+    frames: Vec<FrameBuffers>,
+    // Если есть, число команд берется из буфера, и пустые команды не выполняются вовсе.
+    draw_indirect_count: Option<DrawIndirectCount>,
+    // Сколько команд можно выполнить одним вызовом: 1 без функции multi_draw_indirect.
+    max_draw_indirect_count: u32,
+}
+
+impl GpuCulling {
+    // objects — неподвижные объекты сцены, bounding_radius — радиус сферы вокруг сетки в ее собственных координатах.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        objects: &[InstanceData],
+        bounding_radius: f32,
+        frames_in_flight: usize,
+        draw_indirect_count: Option<DrawIndirectCount>,
+        max_draw_indirect_count: u32,
+    ) -> Self {
+        let object_count = objects.len() as u32;
+        // Центр сферы — перенос из матрицы модели, радиус увеличивается на самый большой масштаб по осям.
+        let bounds = objects
+            .iter()
+            .map(|object| {
+                let model = glam::Mat4::from_cols_array(&object.model);
+                let scale = model
+                    .x_axis
+                    .truncate()
+                    .length()
+                    .max(model.y_axis.truncate().length())
+                    .max(model.z_axis.truncate().length());
+                model.w_axis.truncate().extend(bounding_radius * scale)
+            })
+            .collect::<Vec<_>>();
+
+        // Данные объектов не меняются, поэтому лучше всего подходит видеопамять, доступная процессору.
+        let upload = |data: &[u8], usage| Buffer::upload(device, &memory_properties, data, usage);
+        let instances = upload(as_bytes(objects), vk::BufferUsageFlags::VERTEX_BUFFER);
+        let bounds = upload(as_bytes(&bounds), vk::BufferUsageFlags::STORAGE_BUFFER);
+
+        let bindings = [0, 1, 2].map(|binding| {
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(binding)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::COMPUTE)
+                .build()
+        });
+        let descriptor_set_layout_create_info =
+            vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+        let descriptor_set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(&descriptor_set_layout_create_info, None)
+                .expect("Failed to create culling Descriptor Set Layout!")
+        };
+
+        let pool_sizes = [vk::DescriptorPoolSize {
+            ty: vk::DescriptorType::STORAGE_BUFFER,
+            descriptor_count: 3 * frames_in_flight as u32,
+        }];
+        let descriptor_pool_create_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(frames_in_flight as u32)
+            .pool_sizes(&pool_sizes);
+        let descriptor_pool = unsafe {
+            device
+                .create_descriptor_pool(&descriptor_pool_create_info, None)
+                .expect("Failed to create culling Descriptor Pool!")
+        };
+
+        let set_layouts = vec![descriptor_set_layout; frames_in_flight];
+        let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&set_layouts);
+        let descriptor_sets = unsafe {
+            device
+                .allocate_descriptor_sets(&descriptor_set_allocate_info)
+                .expect("Failed to allocate culling Descriptor Sets!")
+        };
+
+        // Команды и счетчик читаются только видеокартой, процессору к ним доступ не нужен.
+        let usage = vk::BufferUsageFlags::STORAGE_BUFFER
+            | vk::BufferUsageFlags::INDIRECT_BUFFER
+            | vk::BufferUsageFlags::TRANSFER_DST;
+        let frames = descriptor_sets
+            .into_iter()
+            .map(|descriptor_set| {
+                let commands = Buffer::new(
+                    device,
+                    &memory_properties,
+                    (object_count.max(1) as usize
+                        * std::mem::size_of::<vk::DrawIndexedIndirectCommand>())
+                        as vk::DeviceSize,
+                    usage,
+                    &[vk::MemoryPropertyFlags::DEVICE_LOCAL],
+                );
+                let count = Buffer::new(
+                    device,
+                    &memory_properties,
+                    std::mem::size_of::<u32>() as vk::DeviceSize,
+                    usage,
+                    &[vk::MemoryPropertyFlags::DEVICE_LOCAL],
+                );
+                let buffer_infos = [&bounds, &commands, &count].map(|buffer| {
+                    [vk::DescriptorBufferInfo {
+                        buffer: buffer.buffer,
+                        offset: 0,
+                        range: vk::WHOLE_SIZE,
+                    }]
+                });
+                let writes = [0, 1, 2].map(|binding| {
+                    vk::WriteDescriptorSet::builder()
+                        .dst_set(descriptor_set)
+                        .dst_binding(binding as u32)
+                        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                        .buffer_info(&buffer_infos[binding])
+                        .build()
+                });
+                unsafe { device.update_descriptor_sets(&writes, &[]) };
+                FrameBuffers {
+                    commands,
+                    count,
+                    descriptor_set,
+                }
+            })
+            .collect();
+
+        let push_constant_ranges = [CullPushConstants::range()];
+        let set_layouts = [descriptor_set_layout];
+        let pipeline_layout_create_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&set_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+        let pipeline_layout = unsafe {
+            device
+                .create_pipeline_layout(&pipeline_layout_create_info, None)
+                .expect("Failed to create culling Pipeline Layout!")
+        };
+
+        let pipeline = create_pipeline(device, pipeline_layout);
+
+        GpuCulling {
+            pipeline,
+            pipeline_layout,
+            descriptor_set_layout,
+            descriptor_pool,
+            instances,
+            bounds,
+            object_count,
+            frames,
+            draw_indirect_count,
+            max_draw_indirect_count: max_draw_indirect_count.max(1),
+        }
+    }
+
+    pub fn object_count(&self) -> u32 {
+        self.object_count
+    }
+
+    // Добавляет в граф отсечение объектов и их непрямую отрисовку сеткой renderer поверх target.
+    // Вызывается после ожидания забора кадра frame_index.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_passes(
+        &self,
+        graph: &mut RenderGraph,
+        renderer: &InstancedRenderer,
+        target: ImageHandle,
+        extent: vk::Extent2D,
+        frame_index: usize,
+        view_projection: [f32; 16],
+    ) {
+        if self.object_count == 0 {
+            return;
+        }
+        let frame = &self.frames[frame_index];
+        let command_stride = std::mem::size_of::<vk::DrawIndexedIndirectCommand>() as u32;
+        let commands = graph.import_buffer(
+            "culled commands",
+            frame.commands.buffer,
+            frame.commands.size,
+            false,
+        );
+        let count =
+            graph.import_buffer("culled count", frame.count.buffer, frame.count.size, false);
+
+        // Без счетчика в буфере выполняются все object_count команд, поэтому команды невидимых объектов
+        // должны быть пустыми: перед отсечением буфер команд обнуляется целиком.
+        let zero_commands = self.draw_indirect_count.is_none();
+        let mut reset = graph
+            .add_pass("cull reset")
+            .write_buffer(count, BufferUsage::Transfer);
+        if zero_commands {
+            reset = reset.write_buffer(commands, BufferUsage::Transfer);
+        }
+        reset.execute(move |context| unsafe {
+            context.device.cmd_fill_buffer(
+                context.command_buffer,
+                context.buffer(count),
+                0,
+                vk::WHOLE_SIZE,
+                0,
+            );
+            if zero_commands {
+                context.device.cmd_fill_buffer(
+                    context.command_buffer,
+                    context.buffer(commands),
+                    0,
+                    vk::WHOLE_SIZE,
+                    0,
+                );
+            }
+        });
+
+        let mesh = renderer.mesh_binding();
+        let push_constants = CullPushConstants {
+            planes: frustum_planes(glam::Mat4::from_cols_array(&view_projection)),
+            object_count: self.object_count,
+            index_count: mesh.index_count,
+        };
+        let pipeline = self.pipeline;
+        let pipeline_layout = self.pipeline_layout;
+        let descriptor_set = frame.descriptor_set;
+        let object_count = self.object_count;
+        let compute_stage = vk::PipelineStageFlags::COMPUTE_SHADER;
+        graph
+            .add_pass("cull")
+            .write_buffer(commands, BufferUsage::Storage(compute_stage))
+            .write_buffer(count, BufferUsage::Storage(compute_stage))
+            .execute(move |context| unsafe {
+                let device = context.device;
+                let command_buffer = context.command_buffer;
+                device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::COMPUTE, pipeline);
+                device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::COMPUTE,
+                    pipeline_layout,
+                    0,
+                    &[descriptor_set],
+                    &[],
+                );
+                push_constants.push(device, command_buffer, pipeline_layout);
+                device.cmd_dispatch(command_buffer, object_count.div_ceil(WORKGROUP_SIZE), 1, 1);
+            });
+
+        let depth = renderer.create_depth_image(graph, extent);
+        let instance_buffer = self.instances.buffer;
+        let draw_indirect_count = self.draw_indirect_count.clone();
+        let max_draw_indirect_count = self.max_draw_indirect_count;
+        graph
+            .add_pass("culled instances")
+            .color_attachment(target, LoadOp::Load)
+            .depth_attachment(depth, LoadOp::Clear(DEPTH_CLEAR_VALUE))
+            .read_buffer(commands, BufferUsage::Indirect)
+            .read_buffer(count, BufferUsage::Indirect)
+            .execute(move |context| unsafe {
+                let device = context.device;
+                let command_buffer = context.command_buffer;
+                mesh.bind(
+                    device,
+                    command_buffer,
+                    extent,
+                    instance_buffer,
+                    view_projection,
+                );
+                let commands = context.buffer(commands);
+                match draw_indirect_count {
+                    // Видеокарта сама читает, сколько команд записало отсечение.
+                    Some(draw_indirect_count) if object_count <= max_draw_indirect_count => {
+                        draw_indirect_count.cmd_draw_indexed_indirect_count(
+                            device,
+                            command_buffer,
+                            commands,
+                            0,
+                            context.buffer(count),
+                            0,
+                            object_count,
+                            command_stride,
+                        )
+                    }
+                    // Иначе выполняются все команды, пустые ничего не рисуют. Если за один вызов
+                    // столько команд не выполнить, буфер рисуется по частям.
+                    _ => {
+                        let mut first = 0;
+                        while first < object_count {
+                            let draw_count = (object_count - first).min(max_draw_indirect_count);
+                            device.cmd_draw_indexed_indirect(
+                                command_buffer,
+                                commands,
+                                first as vk::DeviceSize * command_stride as vk::DeviceSize,
+                                draw_count,
+                                command_stride,
+                            );
+                            first += draw_count;
+                        }
+                    }
+                }
+            });
+    }
+
+    pub unsafe fn destroy(&mut self, device: &ash::Device) {
+        for frame in self.frames.drain(..) {
+            frame.commands.destroy(device);
+            frame.count.destroy(device);
+        }
+        self.instances.destroy(device);
+        self.bounds.destroy(device);
+        device.destroy_pipeline(self.pipeline, None);
+        device.destroy_pipeline_layout(self.pipeline_layout, None);
+        // Наборы дескрипторов освобождаются вместе с пулом.
+        device.destroy_descriptor_pool(self.descriptor_pool, None);
+        device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+    }
+}
+
+// Плоскости пирамиды видимости из матрицы вида и проекции (метод Грибба и Хартмана). Глубина в Vulkan
+// от 0 до 1, поэтому ближняя плоскость — третья строка матрицы, а не сумма третьей и четвертой.
+// Нормали смотрят внутрь и нормированы, чтобы расстояние до плоскости можно было сравнивать с радиусом.
+fn frustum_planes(view_projection: glam::Mat4) -> [[f32; 4]; 6] {
+    let rows = [0, 1, 2, 3].map(|index| view_projection.row(index));
+    [
+        rows[3] + rows[0],
+        rows[3] - rows[0],
+        rows[3] + rows[1],
+        rows[3] - rows[1],
+        rows[2],
+        rows[3] - rows[2],
+    ]
+    .map(|plane| (plane / plane.truncate().length()).to_array())
+}
+
+fn create_pipeline(device: &ash::Device, pipeline_layout: vk::PipelineLayout) -> vk::Pipeline {
+    let code = include_bytes!("spv/cull_comp.spv");
+    let shader_module_create_info = vk::ShaderModuleCreateInfo {
+        code_size: code.len(),
+        p_code: code.as_ptr() as *const u32,
+        ..Default::default()
+    };
+    let shader_module = unsafe {
+        device
+            .create_shader_module(&shader_module_create_info, None)
+            .expect("Failed to create culling Shader Module!")
+    };
+
+    let main_function_name = CString::new("main").unwrap();
+    let stage = vk::PipelineShaderStageCreateInfo::builder()
+        .module(shader_module)
+        .name(&main_function_name)
+        .stage(vk::ShaderStageFlags::COMPUTE);
+    let compute_pipeline_create_info = vk::ComputePipelineCreateInfo::builder()
+        .stage(stage.build())
+        .layout(pipeline_layout);
+
+    let pipeline = unsafe {
+        device
+            .create_compute_pipelines(
+                vk::PipelineCache::null(),
+                &[compute_pipeline_create_info.build()],
+                None,
+            )
+            .expect("Failed to create culling Pipeline!")[0]
+    };
+
+    unsafe { device.destroy_shader_module(shader_module, None) };
+
+    pipeline
+}
@@ -0,0 +1,203 @@
+use ash::vk;
+
+use crate::render_graph::PassScope;
+
+// Запросы времени (timestamp queries) записываются видеокартой вокруг каждого прохода графа рендеринга.
+// Результаты читаются, когда этот же кадр в полете начинается снова: к этому моменту его забор уже просигналил,
+// поэтому чтение не останавливает цикл отрисовки.
+//
+// Раскладка запросов одного кадра в полете: 0 и 1 — начало и конец кадра, далее по паре на каждый проход.
+pub struct GpuTimer {
+    query_pool: vk::QueryPool,
+    // Наносекунд в одном тике счетчика.
+    timestamp_period: f64,
+    // Маска значащих бит счетчика.
+    valid_mask: u64,
+    max_scopes: u32,
+    frames: Vec<FrameQueries>,
+    current: usize,
+}
+
+#[derive(Default)]
+struct FrameQueries {
+    scopes: Vec<String>,
+    // Начало прохода записано, а конец еще нет.
+    open_scope: bool,
+    written: bool,
+}
+
+impl GpuTimer {
+    // Возвращает None, если очередь не поддерживает запросы времени.
+    pub fn new(
+        instance: &ash::Instance,
+        device: &ash::Device,
+        p_device: vk::PhysicalDevice,
+        queue_family_index: u32,
+        frames_in_flight: usize,
+        max_scopes: u32,
+    ) -> Option<Self> {
+        let valid_bits = unsafe { instance.get_physical_device_queue_family_properties(p_device) }
+            [queue_family_index as usize]
+            .timestamp_valid_bits;
+        if valid_bits == 0 {
+            return None;
+        }
+        let limits = unsafe { instance.get_physical_device_properties(p_device) }.limits;
+
+        let queries_per_frame = 2 + 2 * max_scopes;
+        let query_pool_create_info = vk::QueryPoolCreateInfo::builder()
+            .query_type(vk::QueryType::TIMESTAMP)
+            .query_count(queries_per_frame * frames_in_flight as u32);
+        let query_pool = unsafe {
+            device
+                .create_query_pool(&query_pool_create_info, None)
+                .expect("Failed to create timestamp Query Pool!")
+        };
+
+        Some(GpuTimer {
+            query_pool,
+            timestamp_period: limits.timestamp_period as f64,
+            valid_mask: if valid_bits >= 64 {
+                u64::MAX
+            } else {
+                (1 << valid_bits) - 1
+            },
+            max_scopes,
+            frames: (0..frames_in_flight)
+                .map(|_| FrameQueries::default())
+                .collect(),
+            current: 0,
+        })
+    }
+
+    fn queries_per_frame(&self) -> u32 {
+        2 + 2 * self.max_scopes
+    }
+
+    fn first_query(&self) -> u32 {
+        self.current as u32 * self.queries_per_frame()
+    }
+
+    // Вызывается после ожидания забора кадра frame_index. Возвращает время в миллисекундах,
+    // измеренное в прошлый раз, когда этот кадр в полете выполнялся: сначала весь кадр ("frame"), затем проходы.
+    pub fn begin_frame(&mut self, device: &ash::Device, frame_index: usize) -> Vec<(String, f64)> {
+        self.current = frame_index;
+        let frame = &mut self.frames[frame_index];
+        if !frame.written {
+            return Vec::new();
+        }
+        frame.written = false;
+        let scopes = std::mem::take(&mut frame.scopes);
+
+        let query_count = 2 + 2 * scopes.len() as u32;
+        let mut timestamps = vec![0u64; query_count as usize];
+        let result = unsafe {
+            device.get_query_pool_results(
+                self.query_pool,
+                self.first_query(),
+                query_count,
+                &mut timestamps,
+                vk::QueryResultFlags::TYPE_64,
+            )
+        };
+        // NOT_READY означает, что кадр так и не был выполнен, например был пропущен из-за пересоздания цепочки обмена.
+        if result.is_err() {
+            return Vec::new();
+        }
+
+        let milliseconds = |begin: u64, end: u64| {
+            let ticks =
+                (end & self.valid_mask).wrapping_sub(begin & self.valid_mask) & self.valid_mask;
+            ticks as f64 * self.timestamp_period / 1_000_000.0
+        };
+
+        std::iter::once((
+            "frame".to_owned(),
+            milliseconds(timestamps[0], timestamps[1]),
+        ))
+        .chain(scopes.into_iter().enumerate().map(|(index, name)| {
+            let begin = timestamps[2 + 2 * index];
+            let end = timestamps[3 + 2 * index];
+            (name, milliseconds(begin, end))
+        }))
+        .collect()
+    }
+
+    // Сбрасывает запросы текущего кадра и отмечает его начало. Вызывается вне прохода рендеринга.
+    pub unsafe fn write_frame_begin(
+        &mut self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+    ) {
+        let first_query = self.first_query();
+        device.cmd_reset_query_pool(
+            command_buffer,
+            self.query_pool,
+            first_query,
+            self.queries_per_frame(),
+        );
+        device.cmd_write_timestamp(
+            command_buffer,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            self.query_pool,
+            first_query,
+        );
+        self.frames[self.current].written = true;
+    }
+
+    pub unsafe fn write_frame_end(
+        &mut self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+    ) {
+        device.cmd_write_timestamp(
+            command_buffer,
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            self.query_pool,
+            self.first_query() + 1,
+        );
+    }
+
+    pub unsafe fn destroy(&self, device: &ash::Device) {
+        device.destroy_query_pool(self.query_pool, None);
+    }
+}
+
+impl PassScope for GpuTimer {
+    // Проходы сверх max_scopes не измеряются.
+    unsafe fn begin_pass(
+        &mut self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        name: &str,
+    ) {
+        let first_query = self.first_query();
+        let frame = &mut self.frames[self.current];
+        if frame.scopes.len() as u32 >= self.max_scopes {
+            return;
+        }
+        device.cmd_write_timestamp(
+            command_buffer,
+            vk::PipelineStageFlags::TOP_OF_PIPE,
+            self.query_pool,
+            first_query + 2 + 2 * frame.scopes.len() as u32,
+        );
+        frame.scopes.push(name.to_owned());
+        frame.open_scope = true;
+    }
+
+    unsafe fn end_pass(&mut self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        let first_query = self.first_query();
+        let frame = &mut self.frames[self.current];
+        if !frame.open_scope {
+            return;
+        }
+        device.cmd_write_timestamp(
+            command_buffer,
+            vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            self.query_pool,
+            first_query + 1 + 2 * frame.scopes.len() as u32,
+        );
+        frame.open_scope = false;
+    }
+}
@@ -0,0 +1,347 @@
+use glam::Vec2;
+
+use std::collections::{HashMap, HashSet};
+
+use winit::event::{
+    DeviceEvent, ElementState, KeyboardInput, ModifiersState, MouseButton, MouseScrollDelta,
+    VirtualKeyCode, WindowEvent,
+};
+
+// Приложение проверяет не конкретные клавиши, а именованные действия ("screenshot", "move_forward").
+// Какие клавиши и кнопки мыши вызывают действие, задается в файле привязок.
+
+// Привязки по умолчанию. Файл пользователя переопределяет из них только те действия, которые в нем указаны.
+pub const DEFAULT_BINDINGS: &str = include_str!("../input.toml");
+// Сколько строк прокрутки дает колесо с точной прокруткой (тачпад) на один пиксель.
+const SCROLL_LINES_PER_PIXEL: f32 = 1.0 / 50.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BindingInput {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+}
+
+// Привязка срабатывает, когда нажата клавиша или кнопка и зажаты все ее модификаторы.
+// Лишние модификаторы не мешают: "W" срабатывает и вместе с Shift.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Binding {
+    pub input: BindingInput,
+    pub modifiers: ModifiersState,
+}
+
+impl Binding {
+    // Разбирает привязку вида "Ctrl+Shift+S", "F1" или "MouseRight".
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut parts = text.split('+').map(str::trim).collect::<Vec<_>>();
+        let name = parts.pop().filter(|name| !name.is_empty());
+        let name = name.ok_or_else(|| format!("Empty binding \"{}\"", text))?;
+
+        let mut modifiers = ModifiersState::empty();
+        for modifier in parts {
+            modifiers |= match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => ModifiersState::CTRL,
+                "shift" => ModifiersState::SHIFT,
+                "alt" => ModifiersState::ALT,
+                "logo" | "super" | "cmd" => ModifiersState::LOGO,
+                _ => return Err(format!("Unknown modifier \"{}\" in \"{}\"", modifier, text)),
+            };
+        }
+
+        let input = match name.strip_prefix("Mouse") {
+            Some("Left") => BindingInput::Mouse(MouseButton::Left),
+            Some("Right") => BindingInput::Mouse(MouseButton::Right),
+            Some("Middle") => BindingInput::Mouse(MouseButton::Middle),
+            Some(number) if number.parse::<u16>().is_ok() => {
+                BindingInput::Mouse(MouseButton::Other(number.parse().unwrap()))
+            }
+            // Названия клавиш совпадают с вариантами VirtualKeyCode, поэтому разбираются его десериализацией.
+            _ => BindingInput::Key(
+                toml::Value::String(name.to_owned())
+                    .try_into()
+                    .map_err(|_| format!("Unknown key \"{}\" in \"{}\"", name, text))?,
+            ),
+        };
+
+        Ok(Binding { input, modifiers })
+    }
+}
+
+// Таблица действий: имя действия и все его привязки.
+#[derive(Debug, Clone, Default)]
+pub struct InputMap {
+    actions: HashMap<String, Vec<Binding>>,
+}
+
+impl InputMap {
+    // Разбирает файл привязок: строки вида action = "F1" или action = ["Escape", "Ctrl+Q"].
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let table = text
+            .parse::<toml::Value>()
+            .map_err(|error| error.to_string())?;
+        let table = match table {
+            toml::Value::Table(table) => table,
+            _ => return Err("Bindings file must be a table".to_owned()),
+        };
+
+        let mut actions = HashMap::new();
+        for (action, value) in table {
+            let bindings = match value {
+                toml::Value::String(binding) => vec![Binding::parse(&binding)?],
+                toml::Value::Array(bindings) => bindings
+                    .iter()
+                    .map(|binding| match binding {
+                        toml::Value::String(binding) => Binding::parse(binding),
+                        _ => Err(format!("Binding of \"{}\" must be a string", action)),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?,
+                _ => {
+                    return Err(format!(
+                        "Action \"{}\" must be a string or an array of strings",
+                        action
+                    ))
+                }
+            };
+            actions.insert(action, bindings);
+        }
+        Ok(InputMap { actions })
+    }
+
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|error| error.to_string())?;
+        Self::parse(&text)
+    }
+
+    // Привязки по умолчанию, поверх которых записаны действия из other.
+    pub fn merged(mut self, other: InputMap) -> Self {
+        self.actions.extend(other.actions);
+        self
+    }
+
+    pub fn bindings(&self, action: &str) -> &[Binding] {
+        self.actions.get(action).map_or(&[], Vec::as_slice)
+    }
+}
+
+// Состояние клавиатуры и мыши за кадр: что зажато сейчас, что нажато и отпущено с прошлого кадра,
+// модификаторы, сдвиг мыши и прокрутка колеса.
+pub struct Input {
+    map: InputMap,
+    held: HashSet<BindingInput>,
+    pressed: HashSet<BindingInput>,
+    released: HashSet<BindingInput>,
+    modifiers: ModifiersState,
+    mouse_delta: Vec2,
+    scroll: f32,
+}
+
+impl Input {
+    pub fn new(map: InputMap) -> Self {
+        Input {
+            map,
+            held: HashSet::new(),
+            pressed: HashSet::new(),
+            released: HashSet::new(),
+            modifiers: ModifiersState::empty(),
+            mouse_delta: Vec2::ZERO,
+            scroll: 0.0,
+        }
+    }
+
+    pub fn handle_window_event(&mut self, event: &WindowEvent) {
+        match event {
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(keycode),
+                        state,
+                        ..
+                    },
+                ..
+            } => self.set_state(BindingInput::Key(*keycode), *state),
+            WindowEvent::MouseInput { state, button, .. } => {
+                self.set_state(BindingInput::Mouse(*button), *state)
+            }
+            WindowEvent::ModifiersChanged(modifiers) => self.modifiers = *modifiers,
+            WindowEvent::MouseWheel { delta, .. } => {
+                self.scroll += match delta {
+                    MouseScrollDelta::LineDelta(_, y) => *y,
+                    MouseScrollDelta::PixelDelta(delta) => delta.y as f32 * SCROLL_LINES_PER_PIXEL,
+                };
+            }
+            // Отпускание клавиш вне окна не приходит, поэтому при потере фокуса все считается отпущенным.
+            WindowEvent::Focused(false) => {
+                self.released.extend(self.held.drain());
+                self.modifiers = ModifiersState::empty();
+            }
+            _ => {}
+        }
+    }
+
+    // Движение мыши берется из событий устройства: они приходят и тогда, когда курсор захвачен окном
+    // и упирается в его край.
+    pub fn handle_device_event(&mut self, event: &DeviceEvent) {
+        if let DeviceEvent::MouseMotion { delta } = event {
+            self.mouse_delta += Vec2::new(delta.0 as f32, delta.1 as f32);
+        }
+    }
+
+    // Автоповтор зажатой клавиши присылает новые нажатия, но нажатием за кадр считается только первое.
+    fn set_state(&mut self, input: BindingInput, state: ElementState) {
+        match state {
+            ElementState::Pressed => {
+                if self.held.insert(input) {
+                    self.pressed.insert(input);
+                }
+            }
+            ElementState::Released => {
+                if self.held.remove(&input) {
+                    self.released.insert(input);
+                }
+            }
+        }
+    }
+
+    // Вызывается в конце кадра, после всех проверок: нажатия, отпускания и сдвиги копятся только до следующего кадра.
+    pub fn end_frame(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+        self.mouse_delta = Vec2::ZERO;
+        self.scroll = 0.0;
+    }
+
+    pub fn is_held(&self, input: BindingInput) -> bool {
+        self.held.contains(&input)
+    }
+
+    pub fn was_pressed(&self, input: BindingInput) -> bool {
+        self.pressed.contains(&input)
+    }
+
+    pub fn was_released(&self, input: BindingInput) -> bool {
+        self.released.contains(&input)
+    }
+
+    pub fn modifiers(&self) -> ModifiersState {
+        self.modifiers
+    }
+
+    // Сдвиг мыши в пикселях с прошлого кадра.
+    pub fn mouse_delta(&self) -> Vec2 {
+        self.mouse_delta
+    }
+
+    // Прокрутка колеса в строках с прошлого кадра, вверх положительная.
+    pub fn scroll(&self) -> f32 {
+        self.scroll
+    }
+
+    fn any_binding(&self, action: &str, state: impl Fn(BindingInput) -> bool) -> bool {
+        self.map
+            .bindings(action)
+            .iter()
+            .any(|binding| state(binding.input) && self.modifiers.contains(binding.modifiers))
+    }
+
+    // Действие активно, пока зажата любая его привязка.
+    pub fn action_held(&self, action: &str) -> bool {
+        self.any_binding(action, |input| self.is_held(input))
+    }
+
+    // Действие началось в этом кадре.
+    pub fn action_pressed(&self, action: &str) -> bool {
+        self.any_binding(action, |input| self.was_pressed(input))
+    }
+
+    // Действие закончилось в этом кадре.
+    pub fn action_released(&self, action: &str) -> bool {
+        self.any_binding(action, |input| self.was_released(input))
+    }
+
+    // -1, 0 или 1 в зависимости от того, какое из двух действий активно.
+    pub fn action_axis(&self, negative: &str, positive: &str) -> f32 {
+        self.action_held(positive) as i32 as f32 - self.action_held(negative) as i32 as f32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_keys_with_modifiers() {
+        assert_eq!(
+            Binding::parse("Alt+Return").unwrap(),
+            Binding {
+                input: BindingInput::Key(VirtualKeyCode::Return),
+                modifiers: ModifiersState::ALT,
+            }
+        );
+        assert_eq!(
+            Binding::parse("ctrl + Shift+S").unwrap(),
+            Binding {
+                input: BindingInput::Key(VirtualKeyCode::S),
+                modifiers: ModifiersState::CTRL | ModifiersState::SHIFT,
+            }
+        );
+        assert_eq!(
+            Binding::parse("F12").unwrap().input,
+            BindingInput::Key(VirtualKeyCode::F12)
+        );
+    }
+
+    #[test]
+    fn parses_mouse_buttons() {
+        assert_eq!(
+            Binding::parse("MouseRight").unwrap().input,
+            BindingInput::Mouse(MouseButton::Right)
+        );
+        assert_eq!(
+            Binding::parse("Logo+Mouse4").unwrap(),
+            Binding {
+                input: BindingInput::Mouse(MouseButton::Other(4)),
+                modifiers: ModifiersState::LOGO,
+            }
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_names() {
+        for text in ["Banana", "Hyper+S", "Ctrl+", "", "MouseSide"] {
+            assert!(Binding::parse(text).is_err(), "{}", text);
+        }
+        assert!(InputMap::parse("quit = \"Banana\"").is_err());
+        assert!(InputMap::parse("quit = 1").is_err());
+        assert!(InputMap::parse("quit = [\"Escape\", 1]").is_err());
+    }
+
+    #[test]
+    fn parses_arrays_of_bindings() {
+        let map = InputMap::parse("quit = [\"Escape\", \"Ctrl+Q\"]\nscreenshot = \"F12\"").unwrap();
+        assert_eq!(
+            map.bindings("quit"),
+            &[
+                Binding::parse("Escape").unwrap(),
+                Binding::parse("Ctrl+Q").unwrap()
+            ]
+        );
+        assert_eq!(map.bindings("screenshot").len(), 1);
+        assert!(map.bindings("jump").is_empty());
+    }
+
+    #[test]
+    fn merged_overrides_only_listed_actions() {
+        let defaults = InputMap::parse(DEFAULT_BINDINGS).unwrap();
+        let fullscreen = defaults.bindings("toggle_fullscreen").to_vec();
+        let user = InputMap::parse("screenshot = [\"P\", \"MouseMiddle\"]").unwrap();
+        let map = defaults.merged(user);
+        assert_eq!(
+            map.bindings("screenshot"),
+            &[
+                Binding::parse("P").unwrap(),
+                Binding::parse("MouseMiddle").unwrap()
+            ]
+        );
+        assert_eq!(map.bindings("toggle_fullscreen"), fullscreen.as_slice());
+        assert_eq!(fullscreen, vec![Binding::parse("Alt+Return").unwrap()]);
+    }
+}
@@ -0,0 +1,443 @@
+use ash::vk;
+
+use std::ffi::CString;
+
+use crate::memory::{as_bytes, Buffer};
+use crate::push_constants::PushConstants;
+use crate::render_graph::{ImageDesc, ImageHandle, LoadOp, RenderGraph, RenderGraphCache};
+use crate::scene::{MeshData, MeshVertex};
+
+// Отрисовка множества копий одной сетки одним вызовом. Вершины сетки читаются из привязки 0
+// с частотой VertexInputRate::VERTEX, а матрица модели и цвет каждой копии — из привязки 1
+// с частотой VertexInputRate::INSTANCE: следующий элемент этого буфера берется для следующего экземпляра.
+
+// Данные одного экземпляра. Раскладка совпадает с атрибутами 2–6 в instanced.vert.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct InstanceData {
+    // Матрица модели по столбцам.
+    pub model: [f32; 16],
+    // Линейный цвет RGBA.
+    pub color: [f32; 4],
+}
+
+// Радиус сферы, описанной вокруг куба сетки: половина его диагонали.
+pub const MESH_BOUNDING_RADIUS: f32 = 0.8660254;
+
+// Глубина очищается до дальней плоскости.
+pub(crate) const DEPTH_CLEAR_VALUE: vk::ClearValue = vk::ClearValue {
+    depth_stencil: vk::ClearDepthStencilValue {
+        depth: 1.0,
+        stencil: 0,
+    },
+};
+
+// Push-константы instanced.vert.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct InstancingPushConstants {
+    view_projection: [f32; 16],
+}
+
+unsafe impl PushConstants for InstancingPushConstants {
+    const STAGES: vk::ShaderStageFlags = vk::ShaderStageFlags::VERTEX;
+}
+
+// Конвейер и буферы сетки, которые нужны при записи команд отрисовки. Копируются в замыкание прохода,
+// поэтому ими пользуется и непрямая отрисовка в gpu_culling.
+#[derive(Clone, Copy)]
+pub(crate) struct MeshBinding {
+    pipeline: vk::Pipeline,
+    pipeline_layout: vk::PipelineLayout,
+    vertex_buffer: vk::Buffer,
+    index_buffer: vk::Buffer,
+    pub index_count: u32,
+}
+
+impl MeshBinding {
+    // Привязывает конвейер, сетку и буфер экземпляров, задает область вывода во весь кадр и матрицу вида и проекции.
+    // После этого остается только вызов отрисовки.
+    pub unsafe fn bind(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        extent: vk::Extent2D,
+        instance_buffer: vk::Buffer,
+        view_projection: [f32; 16],
+    ) {
+        device.cmd_bind_pipeline(
+            command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            self.pipeline,
+        );
+        let viewport = vk::Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: extent.width as f32,
+            height: extent.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        };
+        let scissor = vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent,
+        };
+        device.cmd_set_viewport(command_buffer, 0, std::slice::from_ref(&viewport));
+        device.cmd_set_scissor(command_buffer, 0, std::slice::from_ref(&scissor));
+        // Привязка 0 — вершины сетки, привязка 1 — экземпляры.
+        device.cmd_bind_vertex_buffers(
+            command_buffer,
+            0,
+            &[self.vertex_buffer, instance_buffer],
+            &[0, 0],
+        );
+        device.cmd_bind_index_buffer(command_buffer, self.index_buffer, 0, vk::IndexType::UINT32);
+        InstancingPushConstants { view_projection }.push(
+            device,
+            command_buffer,
+            self.pipeline_layout,
+        );
+    }
+}
+
+pub struct InstancedRenderer {
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    depth_format: vk::Format,
+    mesh: MeshBinding,
+    vertices: Buffer,
+    indices: Buffer,
+    instances: Vec<InstanceData>,
+    // Буфер экземпляров для каждого кадра в полете.
+    frames: Vec<Option<Buffer>>,
+}
+
+impl InstancedRenderer {
+    // Сетка — куб с ребром 1 и центром в начале координат.
+    pub fn new(
+        device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        graph_cache: &mut RenderGraphCache,
+        color_format: vk::Format,
+        depth_format: vk::Format,
+        frames_in_flight: usize,
+    ) -> Self {
+        // Матрица вида и проекции передается через push-константы.
+        let push_constant_ranges = [InstancingPushConstants::range()];
+        let pipeline_layout_create_info =
+            vk::PipelineLayoutCreateInfo::builder().push_constant_ranges(&push_constant_ranges);
+        let pipeline_layout = unsafe {
+            device
+                .create_pipeline_layout(&pipeline_layout_create_info, None)
+                .expect("Failed to create instancing Pipeline Layout!")
+        };
+
+        let pipeline = create_pipeline(
+            device,
+            graph_cache,
+            color_format,
+            depth_format,
+            pipeline_layout,
+        );
+
+        let cube = MeshData::cube();
+        let upload = |data: &[u8], usage| Buffer::upload(device, &memory_properties, data, usage);
+        let vertices = upload(
+            as_bytes(&cube.vertices),
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+        );
+        let indices = upload(as_bytes(&cube.indices), vk::BufferUsageFlags::INDEX_BUFFER);
+
+        InstancedRenderer {
+            memory_properties,
+            depth_format,
+            mesh: MeshBinding {
+                pipeline,
+                pipeline_layout,
+                vertex_buffer: vertices.buffer,
+                index_buffer: indices.buffer,
+                index_count: cube.indices.len() as u32,
+            },
+            vertices,
+            indices,
+            instances: Vec::new(),
+            frames: (0..frames_in_flight).map(|_| None).collect(),
+        }
+    }
+
+    // Добавляет экземпляр в очередь кадра.
+    pub fn draw(&mut self, instance: InstanceData) {
+        self.instances.push(instance);
+    }
+
+    // Добавляет в граф проход, рисующий все экземпляры кадра поверх target одним вызовом cmd_draw_indexed.
+    // Глубина у прохода своя, временная, и очищается в начале прохода. Вызывается после ожидания забора кадра frame_index.
+    pub fn add_pass(
+        &mut self,
+        device: &ash::Device,
+        graph: &mut RenderGraph,
+        target: ImageHandle,
+        extent: vk::Extent2D,
+        frame_index: usize,
+        view_projection: [f32; 16],
+    ) {
+        let instances = std::mem::take(&mut self.instances);
+        if instances.is_empty() {
+            return;
+        }
+
+        let instance_buffer = self.frame_buffer(
+            device,
+            frame_index,
+            std::mem::size_of_val(instances.as_slice()) as vk::DeviceSize,
+        );
+        unsafe {
+            let data = device
+                .map_memory(
+                    instance_buffer.1,
+                    0,
+                    vk::WHOLE_SIZE,
+                    vk::MemoryMapFlags::empty(),
+                )
+                .expect("Failed to map instance memory!")
+                as *mut InstanceData;
+            std::ptr::copy_nonoverlapping(instances.as_ptr(), data, instances.len());
+            device.unmap_memory(instance_buffer.1);
+        }
+
+        let depth = self.create_depth_image(graph, extent);
+        let mesh = self.mesh;
+        let instance_count = instances.len() as u32;
+        graph
+            .add_pass("instances")
+            .color_attachment(target, LoadOp::Load)
+            .depth_attachment(depth, LoadOp::Clear(DEPTH_CLEAR_VALUE))
+            .execute(move |context| unsafe {
+                let device = context.device;
+                let command_buffer = context.command_buffer;
+                mesh.bind(
+                    device,
+                    command_buffer,
+                    extent,
+                    instance_buffer.0,
+                    view_projection,
+                );
+                device.cmd_draw_indexed(command_buffer, mesh.index_count, instance_count, 0, 0, 0);
+            });
+    }
+
+    pub(crate) fn mesh_binding(&self) -> MeshBinding {
+        self.mesh
+    }
+
+    // Временная глубина для прохода, который рисует сетку. Конвейер создан под ее формат.
+    pub(crate) fn create_depth_image(
+        &self,
+        graph: &mut RenderGraph,
+        extent: vk::Extent2D,
+    ) -> ImageHandle {
+        graph.create_image(
+            "instancing depth",
+            ImageDesc {
+                format: self.depth_format,
+                extent,
+            },
+        )
+    }
+
+    // Возвращает буфер экземпляров кадра не меньше size байт. Старый буфер мог использоваться
+    // только этим же кадром, а его забор уже дождались, поэтому удаляется сразу.
+    fn frame_buffer(
+        &mut self,
+        device: &ash::Device,
+        frame_index: usize,
+        size: vk::DeviceSize,
+    ) -> (vk::Buffer, vk::DeviceMemory) {
+        let slot = &mut self.frames[frame_index];
+        if slot.as_ref().is_none_or(|buffer| buffer.size < size) {
+            if let Some(buffer) = slot.take() {
+                unsafe { buffer.destroy(device) };
+            }
+            *slot = Some(Buffer::new(
+                device,
+                &self.memory_properties,
+                size.next_power_of_two(),
+                vk::BufferUsageFlags::VERTEX_BUFFER,
+                &[vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT],
+            ));
+        }
+        let buffer = slot.as_ref().unwrap();
+        (buffer.buffer, buffer.memory)
+    }
+
+    pub unsafe fn destroy(&mut self, device: &ash::Device) {
+        for buffer in self.frames.iter_mut().filter_map(Option::take) {
+            buffer.destroy(device);
+        }
+        self.vertices.destroy(device);
+        self.indices.destroy(device);
+        device.destroy_pipeline(self.mesh.pipeline, None);
+        device.destroy_pipeline_layout(self.mesh.pipeline_layout, None);
+    }
+}
+
+fn create_pipeline(
+    device: &ash::Device,
+    graph_cache: &mut RenderGraphCache,
+    color_format: vk::Format,
+    depth_format: vk::Format,
+    pipeline_layout: vk::PipelineLayout,
+) -> vk::Pipeline {
+    let create_shader_module = |code: &[u8]| {
+        let shader_module_create_info = vk::ShaderModuleCreateInfo {
+            code_size: code.len(),
+            p_code: code.as_ptr() as *const u32,
+            ..Default::default()
+        };
+        unsafe {
+            device
+                .create_shader_module(&shader_module_create_info, None)
+                .expect("Failed to create instancing Shader Module!")
+        }
+    };
+    let vert_shader_module = create_shader_module(include_bytes!("spv/instanced_vert.spv"));
+    let frag_shader_module = create_shader_module(include_bytes!("spv/instanced_frag.spv"));
+
+    let main_function_name = CString::new("main").unwrap();
+    let shader_stages = [
+        vk::PipelineShaderStageCreateInfo::builder()
+            .module(vert_shader_module)
+            .name(&main_function_name)
+            .stage(vk::ShaderStageFlags::VERTEX)
+            .build(),
+        vk::PipelineShaderStageCreateInfo::builder()
+            .module(frag_shader_module)
+            .name(&main_function_name)
+            .stage(vk::ShaderStageFlags::FRAGMENT)
+            .build(),
+    ];
+
+    let vertex_binding_descriptions = [
+        vk::VertexInputBindingDescription {
+            binding: 0,
+            stride: std::mem::size_of::<MeshVertex>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX,
+        },
+        vk::VertexInputBindingDescription {
+            binding: 1,
+            stride: std::mem::size_of::<InstanceData>() as u32,
+            input_rate: vk::VertexInputRate::INSTANCE,
+        },
+    ];
+    let mut vertex_attribute_descriptions = vec![
+        vk::VertexInputAttributeDescription {
+            location: 0,
+            binding: 0,
+            format: vk::Format::R32G32B32_SFLOAT,
+            offset: 0,
+        },
+        vk::VertexInputAttributeDescription {
+            location: 1,
+            binding: 0,
+            format: vk::Format::R32G32B32_SFLOAT,
+            offset: 12,
+        },
+    ];
+    // Атрибут не может быть больше vec4, поэтому матрица модели занимает четыре позиции подряд.
+    for column in 0..4 {
+        vertex_attribute_descriptions.push(vk::VertexInputAttributeDescription {
+            location: 2 + column,
+            binding: 1,
+            format: vk::Format::R32G32B32A32_SFLOAT,
+            offset: column * 16,
+        });
+    }
+    vertex_attribute_descriptions.push(vk::VertexInputAttributeDescription {
+        location: 6,
+        binding: 1,
+        format: vk::Format::R32G32B32A32_SFLOAT,
+        offset: 64,
+    });
+    let vertex_input_state_create_info = vk::PipelineVertexInputStateCreateInfo::builder()
+        .vertex_binding_descriptions(&vertex_binding_descriptions)
+        .vertex_attribute_descriptions(&vertex_attribute_descriptions);
+
+    let input_assembly_state_create_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+
+    let viewport_state_create_info = vk::PipelineViewportStateCreateInfo::builder()
+        .viewport_count(1)
+        .scissor_count(1);
+
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state_create_info =
+        vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+
+    // Проекция камеры переворачивает ось y, и обход против часовой стрелки снаружи куба
+    // остается обходом против часовой стрелки в кадре.
+    let rasterization_state_create_info = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::BACK)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE);
+
+    let multisample_state_create_info = vk::PipelineMultisampleStateCreateInfo::builder()
+        .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+
+    let depth_state_create_info = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(true)
+        .depth_write_enable(true)
+        .depth_compare_op(vk::CompareOp::LESS);
+
+    let color_blend_attachment_states = [vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(vk::ColorComponentFlags::RGBA)
+        .blend_enable(false)
+        .build()];
+    let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
+        .attachments(&color_blend_attachment_states);
+
+    let color_attachment_formats = [color_format];
+    let mut pipeline_rendering_create_info = vk::PipelineRenderingCreateInfo::builder()
+        .color_attachment_formats(&color_attachment_formats)
+        .depth_attachment_format(depth_format);
+
+    let mut graphic_pipeline_create_info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(&shader_stages)
+        .vertex_input_state(&vertex_input_state_create_info)
+        .input_assembly_state(&input_assembly_state_create_info)
+        .viewport_state(&viewport_state_create_info)
+        .rasterization_state(&rasterization_state_create_info)
+        .multisample_state(&multisample_state_create_info)
+        .depth_stencil_state(&depth_state_create_info)
+        .color_blend_state(&color_blend_state)
+        .dynamic_state(&dynamic_state_create_info)
+        .layout(pipeline_layout);
+
+    if graph_cache.uses_dynamic_rendering() {
+        graphic_pipeline_create_info =
+            graphic_pipeline_create_info.push_next(&mut pipeline_rendering_create_info);
+    } else {
+        let render_pass = graph_cache.compatible_render_pass(
+            device,
+            &color_attachment_formats,
+            Some(depth_format),
+        );
+        graphic_pipeline_create_info = graphic_pipeline_create_info.render_pass(render_pass);
+    }
+
+    let pipeline = unsafe {
+        device
+            .create_graphics_pipelines(
+                vk::PipelineCache::null(),
+                &[graphic_pipeline_create_info.build()],
+                None,
+            )
+            .expect("Failed to create instancing Pipeline!")[0]
+    };
+
+    unsafe {
+        device.destroy_shader_module(vert_shader_module, None);
+        device.destroy_shader_module(frag_shader_module, None);
+    }
+
+    pipeline
+}
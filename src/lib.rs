@@ -0,0 +1,33 @@
+// Почти все функции, работающие с объектами Vulkan, небезопасны по одной и той же причине:
+// объекты должны быть созданы на том же устройстве и не использоваться видеокартой в момент вызова.
+#![allow(clippy::missing_safety_doc)]
+
+pub mod animation;
+pub mod camera;
+pub mod debug_panel;
+pub mod draw_indirect_count;
+pub mod draw_mode;
+pub mod dynamic_rendering;
+pub mod ecs;
+pub mod frame_stats;
+pub mod gltf_import;
+pub mod gpu_culling;
+pub mod gpu_timer;
+pub mod input;
+pub mod instancing;
+pub mod memory;
+pub mod obj;
+pub mod push_constants;
+pub mod queries;
+pub mod render_graph;
+pub mod replay;
+pub mod scene;
+pub mod scene_renderer;
+pub mod screenshot;
+pub mod sprites;
+pub mod swapchain;
+pub mod text;
+pub mod ui;
+pub mod viewport;
+pub mod window_mode;
+pub mod window_surface;
@@ -1,27 +1,74 @@
 use ash::extensions::ext::DebugUtils;
-use ash::extensions::khr::{Surface, Swapchain, RayTracingPipeline};
-use ash::version::{DeviceV1_0, EntryV1_0, InstanceV1_0};
+use ash::extensions::khr::{Surface, Swapchain};
 use ash::vk;
 
-use ash_window;
-use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
-use winit::event_loop::ControlFlow;
+use bevy_ecs::prelude::{Component, IntoSystemConfigs, Query, Res, Resource, Schedule, World};
+use winit::event::{Event, WindowEvent};
+use winit::event_loop::{ControlFlow, EventLoopWindowTarget};
 
+use std::collections::HashMap;
 use std::ffi::CString;
 use std::iter::FromIterator;
-
+use std::time::{Duration, Instant};
+
+use ash_lern2::animation::{AnimationClip, Channel, Interpolation, Keyframes, Pose, Skin};
+use ash_lern2::camera::{Camera, CameraMode};
+use ash_lern2::debug_panel::{DebugPanel, DeviceInfo};
+use ash_lern2::draw_indirect_count::{DrawIndirectCount, DrawIndirectCountSupport};
+use ash_lern2::draw_mode::DrawMode;
+use ash_lern2::dynamic_rendering::{DynamicRendering, DynamicRenderingSupport};
+use ash_lern2::ecs::{self, ExtractDraws};
+use ash_lern2::frame_stats::FrameStats;
+use ash_lern2::gltf_import::load_gltf;
+use ash_lern2::gpu_culling::GpuCulling;
+use ash_lern2::gpu_timer::GpuTimer;
+use ash_lern2::input::{Input, InputMap, DEFAULT_BINDINGS};
+use ash_lern2::instancing::{InstanceData, InstancedRenderer, MESH_BOUNDING_RADIUS};
+use ash_lern2::obj::ObjModel;
+use ash_lern2::push_constants::PushConstants;
+use ash_lern2::queries::{OcclusionQueries, OcclusionResult, PassStatistics, PipelineStatistics};
+use ash_lern2::render_graph::{
+    supported_depth_format, ImportedImage, LoadOp, PassScope, RenderGraph, RenderGraphCache,
+};
+use ash_lern2::replay::{InputPlayer, InputRecorder, RecordedEvent};
+use ash_lern2::scene::{
+    Light, LightKind, Material, MeshData, MeshHandle, MeshVertex, NodeId, Scene, TextureHandle,
+    TextureImage, Transform, VertexJoints,
+};
+use ash_lern2::scene_renderer::SceneRenderer;
+use ash_lern2::screenshot::Screenshots;
+use ash_lern2::sprites::{Sprite, SpriteBatch, SpriteTexture};
+use ash_lern2::text::{TextMode, TextRenderer, TextStyle};
+use ash_lern2::ui::{UiInput, UiRenderer};
+use ash_lern2::viewport::ViewportLayout;
+use ash_lern2::window_mode::{print_monitors, WindowMode, WindowModeController};
+use ash_lern2::window_surface::WindowSurface;
+
+// Размер окна в логических единицах: на экране с масштабом 2 окно будет вдвое больше в пикселях.
 const WINDOW_WIDTH: f64 = 820.0;
 const WINDOW_HEIGHT: f64 = 640.0;
 const APP_NAME: &str = "My second vulkan app";
 const MAX_FRAMES_IN_FLIGHT: usize = 2;
+// Сколько проходов графа рендеринга можно измерить за кадр.
+const MAX_TIMED_PASSES: u32 = 16;
+// Сколько запросов видимости можно сделать за кадр.
+const MAX_OCCLUSION_QUERIES: u32 = 64;
+// Размер точек в режиме точек, если устройство поддерживает точки крупнее пикселя.
+const POINT_SIZE: f32 = 5.0;
+// Цвет каркаса поверх заливки.
+const OVERLAY_COLOR: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
+// Сколько кубов в демонстрации инстансинга, если не задано аргументом --instances.
+const DEFAULT_INSTANCE_COUNT: usize = 20000;
+// Сколько объектов в демонстрации отсечения на видеокарте, если не задано аргументом --objects.
+const DEFAULT_CULLED_OBJECT_COUNT: usize = 65536;
 fn main() {
-    let entry = unsafe { ash::Entry::new() }.unwrap();
+    let entry = unsafe { ash::Entry::load() }.unwrap();
 
     let event_loop = winit::event_loop::EventLoop::new();
 
     let window = winit::window::WindowBuilder::new()
         .with_title(APP_NAME)
-        .with_inner_size(winit::dpi::PhysicalSize::new(WINDOW_WIDTH, WINDOW_HEIGHT))
+        .with_inner_size(winit::dpi::LogicalSize::new(WINDOW_WIDTH, WINDOW_HEIGHT))
         .with_visible(false)
         .build(&event_loop)
         .unwrap();
@@ -35,23 +82,23 @@ fn main() {
             .application_version(0)
             .engine_name(&app_name)
             .engine_version(0)
-            // Буду использовать версию 1.2.168 в надежде опробовать vulkan ray trasing.
-            .api_version(vk::make_version(1, 2, 168));
+            // Запрашиваем версию 1.3: в ней динамический рендеринг входит в ядро.
+            // На устройствах с более старой версией будет использована версия устройства.
+            .api_version(vk::make_api_version(0, 1, 3, 0));
 
         let extensions_names_raw = {
             // Для создания surface нам необходимо зарегистрировать платформазависемые расширения, их любезно предоставит
             // библиотека ash_window.
-            let mut extensions = ash_window::enumerate_required_extensions(&window).unwrap();
+            let mut extensions = ash_window::enumerate_required_extensions(&window)
+                .unwrap()
+                .to_vec();
             // Для возможности отлавливать сообшения об ошибкфх в Vulkan необходимо зарегистрироват расширение DebugUtils
-            extensions.push(DebugUtils::name());
-            let extensions_names_raw = extensions
-                .iter()
-                .map(|ext| ext.as_ptr())
-                .collect::<Vec<_>>();
-            extensions_names_raw
+            extensions.push(DebugUtils::name().as_ptr());
+            extensions
         };
 
-        let requred_validation_layer_raw_names = [CString::new("VK_LAYER_KHRONOS_validation").unwrap()];
+        let requred_validation_layer_raw_names =
+            [CString::new("VK_LAYER_KHRONOS_validation").unwrap()];
 
         let enable_layer_names: Vec<*const i8> = requred_validation_layer_raw_names
             .iter()
@@ -147,9 +194,8 @@ fn main() {
                             }
                             .unwrap();
 
-                            if info.queue_count > 0
-                                && is_present_support
-                                //&& !info.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+                            if info.queue_count > 0 && is_present_support
+                            //&& !info.queue_flags.contains(vk::QueueFlags::GRAPHICS)
                             {
                                 Some(index as u32)
                             } else {
@@ -169,16 +215,45 @@ fn main() {
             .expect("No device found with graphics and present support")
     };
 
+    // Если устройство поддерживает динамический рендеринг, рисуем без объектов прохода рендеринга и фреймбуферов.
+    // Флаг --render-pass принудительно включает старый путь, например чтобы сравнить их.
+    let dynamic_rendering_support = if std::env::args().any(|arg| arg == "--render-pass") {
+        DynamicRenderingSupport::Unsupported
+    } else {
+        DynamicRenderingSupport::query(&instance, p_device)
+    };
+    println!("Dynamic rendering: {:?}", dynamic_rendering_support);
+
+    // Статистика конвейера и точные запросы видимости — необязательные функции устройства.
+    let supported_features = unsafe { instance.get_physical_device_features(p_device) };
+    let pipeline_statistics_supported = supported_features.pipeline_statistics_query == vk::TRUE;
+    let occlusion_query_precise = supported_features.occlusion_query_precise == vk::TRUE;
+    // Каркас и точки требуют fill_mode_non_solid, без нее сцена рисуется только заливкой.
+    // Точки крупнее одного пикселя требуют large_points.
+    let non_solid_supported = supported_features.fill_mode_non_solid == vk::TRUE;
+    let large_points_supported = supported_features.large_points == vk::TRUE;
+    if !non_solid_supported {
+        println!("Wireframe and point modes are not supported");
+    }
+    // Отсечение на видеокарте пишет команды с first_instance, а выполняет их одним вызовом, если есть multi_draw_indirect.
+    // Число команд из буфера берется, если поддерживается drawIndirectCount.
+    let indirect_first_instance_supported =
+        supported_features.draw_indirect_first_instance == vk::TRUE;
+    let multi_draw_indirect_supported = supported_features.multi_draw_indirect == vk::TRUE;
+    let draw_indirect_count_support = DrawIndirectCountSupport::query(&instance, p_device);
+    println!("Draw indirect count: {:?}", draw_indirect_count_support);
+
     //Имея физическое устройство – можно создать логическое.
     //Именно оно нам и понадобится для дальнейшей работы с объектами, вроде буферов или шейдеров.
     let device = {
         let device_extension_names_raw = {
-            let device_extension_names = vec![Swapchain::name()];
-            let device_extension_names_raw = device_extension_names
+            let mut device_extension_names = vec![Swapchain::name()];
+            device_extension_names.extend(dynamic_rendering_support.extension_name());
+            device_extension_names.extend(draw_indirect_count_support.extension_name());
+            device_extension_names
                 .iter()
                 .map(|name| name.as_ptr())
-                .collect::<Vec<_>>();
-            device_extension_names_raw
+                .collect::<Vec<_>>()
         };
 
         let queue_family_indexes =
@@ -198,211 +273,63 @@ fn main() {
 
         let features = vk::PhysicalDeviceFeatures::builder()
             .shader_clip_distance(true)
-            .fill_mode_non_solid(true);
+            .fill_mode_non_solid(non_solid_supported)
+            .large_points(large_points_supported)
+            .pipeline_statistics_query(pipeline_statistics_supported)
+            .occlusion_query_precise(occlusion_query_precise)
+            .draw_indirect_first_instance(indirect_first_instance_supported)
+            .multi_draw_indirect(multi_draw_indirect_supported);
+
+        let mut dynamic_rendering_features =
+            vk::PhysicalDeviceDynamicRenderingFeatures::builder().dynamic_rendering(true);
 
-        let device_create_info = vk::DeviceCreateInfo::builder()
+        let mut device_create_info = vk::DeviceCreateInfo::builder()
             .queue_create_infos(&device_queue_create_infos)
             .enabled_extension_names(&device_extension_names_raw)
             .enabled_features(&features);
+        if dynamic_rendering_support != DynamicRenderingSupport::Unsupported {
+            device_create_info = device_create_info.push_next(&mut dynamic_rendering_features);
+        }
+        let mut vulkan_12_features =
+            vk::PhysicalDeviceVulkan12Features::builder().draw_indirect_count(true);
+        if draw_indirect_count_support == DrawIndirectCountSupport::Core {
+            device_create_info = device_create_info.push_next(&mut vulkan_12_features);
+        }
 
-        let device = unsafe {
+        unsafe {
             instance
                 .create_device(p_device, &device_create_info, None)
                 .expect("Error create device")
-        };
-
-        device
+        }
     };
 
     let swapchain_loader = Swapchain::new(&instance, &device);
 
-    let (swapchain, surface_format, surface_resolution) = {
-        // Получаем информацию о поверхности нашего окна.
-        let surface_capabilities =
-            unsafe { surface_loader.get_physical_device_surface_capabilities(p_device, surface) }
-                .unwrap();
-
-        // Количество изображений в цепочке обмена.
-        let image_count = if surface_capabilities.max_image_count > 0
-            && surface_capabilities.min_image_count + 1 > surface_capabilities.max_image_count
-        {
-            surface_capabilities.max_image_count
-        } else {
-            surface_capabilities.min_image_count + 1
-        };
-
-
-        // Формат изображения. Важно указать формат, поддерживаемый поверностию нашего окна.
-        let surface_format = {
-            let formats_support =
-                unsafe { surface_loader.get_physical_device_surface_formats(p_device, surface) }
-                    .expect("Failed to query for surface formats.");
-
-            formats_support
-                .iter()
-                .find_map(|format_support| {
-                    if format_support.format == vk::Format::B8G8R8A8_SRGB
-                        && format_support.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
-                    {
-                        return Some(format_support.clone());
-                    }
-                    None
-                })
-                .unwrap_or(formats_support.first().unwrap().clone())
-        };
-
-        // Получаем размер поверхности.
-        let surface_resolution = surface_capabilities.current_extent;
-
-        let pre_transform = vk::SurfaceTransformFlagsKHR::IDENTITY;
-
-        // Описываем в как будут подаваться наши изображения из очереди на поверхность.
-        let present_mode = vk::PresentModeKHR::FIFO;
-            // unsafe { surface_loader.get_physical_device_surface_present_modes(p_device, surface) }
-            //     .unwrap()
-            //     .iter()
-            //     .cloned()
-            //     .find(|&mode| mode == vk::PresentModeKHR::MAILBOX)
-            //     .unwrap_or(vk::PresentModeKHR::FIFO);
-
-        let swapchain_create_info = vk::SwapchainCreateInfoKHR::builder()
-            .surface(surface)
-            .min_image_count(image_count)
-            .image_color_space(surface_format.color_space)
-            .image_format(surface_format.format)
-            .image_extent(surface_resolution)
-            .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
-            .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
-            .pre_transform(pre_transform)
-            .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
-            .present_mode(present_mode)
-            .clipped(true)
-            .image_array_layers(1);
-
-        let swapchain = unsafe {
-            swapchain_loader
-                .create_swapchain(&swapchain_create_info, None)
-                .expect("Error create swapchain")
-        };
-
-        (swapchain, surface_format, surface_resolution)
-    };
-
-    let swapchain_images = unsafe {
-        swapchain_loader
-            .get_swapchain_images(swapchain)
-            .expect("Failed to get Swapchain Images.")
+    // Цепочка обмена вынесена в отдельный модуль, так как при изменении размера окна ее нужно пересоздавать.
+    // Окно хранится вместе со своей поверхностью и цепочкой обмена: окон может быть несколько.
+    let mut main_window = WindowSurface::new(
+        window,
+        surface,
+        &device,
+        &surface_loader,
+        &swapchain_loader,
+        p_device,
+        true,
+    );
+
+    // Проходы рендеринга, фреймбуферы и временные изображения создает граф рендеринга и хранит их в этом кэше.
+    let mut graph_cache = {
+        let memory_properties = unsafe { instance.get_physical_device_memory_properties(p_device) };
+        let dynamic_rendering =
+            DynamicRendering::new(&instance, &device, dynamic_rendering_support);
+        RenderGraphCache::new(
+            memory_properties,
+            dynamic_rendering,
+            MAX_FRAMES_IN_FLIGHT + 1,
+        )
     };
 
-    //Чтобы использовать что-либо VkImage, в том числе в цепочке подкачки, в конвейере рендеринга,
-    //мы должны создать VkImageViewобъект. Просмотр изображения - это буквально взгляд в изображение.
-    //В нем описывается, как получить доступ к изображению и к какой части изображения получить доступ,
-    //например, следует ли рассматривать его как текстуру глубины 2D текстуры без каких-либо уровней mipmapping.
-    let swapchain_images_viev = swapchain_images
-        .iter()
-        .map(|&image| {
-            let imageview_create_info = vk::ImageViewCreateInfo::builder()
-                .view_type(vk::ImageViewType::TYPE_2D)
-                .format(surface_format.format)
-                .components(vk::ComponentMapping {
-                    r: vk::ComponentSwizzle::IDENTITY,
-                    g: vk::ComponentSwizzle::IDENTITY,
-                    b: vk::ComponentSwizzle::IDENTITY,
-                    a: vk::ComponentSwizzle::IDENTITY,
-                })
-                .subresource_range(vk::ImageSubresourceRange {
-                    aspect_mask: vk::ImageAspectFlags::COLOR,
-                    base_mip_level: 0,
-                    level_count: 1,
-                    base_array_layer: 0,
-                    layer_count: 1,
-                })
-                .image(image);
-            unsafe {
-                device
-                    .create_image_view(&imageview_create_info, None)
-                    .expect("Failed to create Image View!")
-            }
-        })
-        .collect::<Vec<_>>();
-
-    let render_pass = {
-        // Прежде чем мы сможем завершить создание конвейера, нам нужно сообщить Vulkan о прикреплениях фреймбуфера,
-        // которые будут использоваться при рендеринге. Нам нужно указать, сколько будет буферов цвета и глубины,
-        // сколько сэмплов использовать для каждого из них и как их содержимое должно обрабатываться во время операций рендеринга.
-        // Вся эта информация заключена в объект прохода рендеринга
-
-        // В нашем случае у нас будет только одно прикрепление буфера цвета,
-        // представленное одним из изображений из цепочки подкачки
-        let color_attachment = vk::AttachmentDescription::builder()
-            .format(surface_format.format)
-            // Прикрепленный цвета должны соответствовать формату цепь изображений свопа,
-            // и мы не делаем ничего с мультисэмплинг еще, так что мы будем придерживаться 1 образца.
-            .samples(vk::SampleCountFlags::TYPE_1)
-            // У нас есть следующие варианты load_op:
-            //      vk::AttachmentLoadOp::LOAD:         Сохранить существующее содержимое вложения
-            //      vk::AttachmentLoadOp::CLEAR:        Очистить значения до константы в начале
-            //      vk::AttachmentLoadOp::DONT_CARE:    Существующее содержимое не определено; мы не заботимся о них
-            .load_op(vk::AttachmentLoadOp::CLEAR)
-            //Есть только две возможности store_op:
-            //      vk::AttachmentStoreOp::STORE:       Обработанное содержимое будет сохранено в памяти и может быть прочитано позже.
-            //      vk::AttachmentStoreOp::DONT_CARE:   Содержимое фреймбуфера будет неопределенным после операции рендеринга.
-            .store_op(vk::AttachmentStoreOp::STORE)
-            // stencil_load_op/ stencil_store Opприменимы к данным трафарета.
-            // Наше приложение ничего не делает с буфером трафарета,
-            // поэтому результаты загрузки и сохранения не имеют значения.
-            .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
-            .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
-            // Текстуры и фреймбуферы в Vulkan представлены VkImageобъектами с определенным форматом пикселей,
-            // однако расположение пикселей в памяти может меняться в зависимости от того, что вы пытаетесь сделать с изображением.
-            // Вот некоторые из наиболее распространенных макетов:
-            //      vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL: Изображения используются как цветные вложения.
-            //      vk::ImageLayout::PRESENT_SRC_KHR:          Изображения, которые будут представлены в цепочке обмена
-            //      vk::ImageLayout::TRANSFER_DST_OPTIMAL:     Изображения, которые будут использоваться в качестве места назначения для операции копирования из памяти
-            //      vk::ImageLayout::UNDEFINED:                Предостережение этого специального значения заключается в том,
-            // что не гарантируется сохранение содержимого изображения, но это не имеет значения, поскольку мы собираемся очистить это все равно.
-            .initial_layout(vk::ImageLayout::UNDEFINED)
-            .final_layout(vk::ImageLayout::PRESENT_SRC_KHR)
-            .build();
-
-        // Один проход рендеринга может состоять из нескольких подпроходов.
-        // Подпроходы - это последующие операции рендеринга, которые зависят от содержимого кадровых буферов на предыдущих проходах,
-        // например, последовательность эффектов постобработки, которые применяются один за другим.
-        // Если вы сгруппируете эти операции рендеринга в один проход рендеринга, то Vulkan сможет изменить порядок операций и
-        // сохранить полосу пропускания памяти для, возможно, лучшей производительности.
-        // Однако для нашего самого первого треугольника мы будем придерживаться одного подпрохода
-
-        let color_attachment_ref = vk::AttachmentReference::builder()
-            .attachment(0)
-            .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
-
-        let subpass = vk::SubpassDescription::builder()
-            // Vulkan может также поддерживать подпроходы вычислений в будущем, поэтому мы должны четко указать, что это подпроходы графики
-            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
-            // На индекс вложения в этом массиве напрямую ссылается фрагментный шейдер
-            // с помощью layout(location = 0) out vec4 outColorдирективы!
-            //Подпроходом могут быть ссылки на следующие другие типы вложений:
-            //      p_input_attachments:        Вложения, считываемые из шейдера.
-            //      p_resolve_attachments:      Вложения, используемые для вложений цветов с множественной выборкой
-            //      p_depthStencil_attachment:  Приложение для данных глубины и трафарета
-            //      p_preserve_attachments:     Вложения, которые не используются этим подпроходом, но для которых необходимо сохранить данные.
-            .color_attachments(std::slice::from_ref(&color_attachment_ref));
-
-        let render_pass_attachments = std::slice::from_ref(&color_attachment);
-
-        // Tеперь, когда были описаны вложение и базовый подпроход, ссылающийся на него, мы можем создать сам проход рендеринга.
-        let renderpass_create_info = vk::RenderPassCreateInfo::builder()
-            .attachments(render_pass_attachments)
-            .subpasses(std::slice::from_ref(&subpass));
-
-        unsafe {
-            device
-                .create_render_pass(&renderpass_create_info, None)
-                .expect("Failed to create render pass!")
-        }
-    };
-
-    let (graphics_pipeline, pipeline_layout) = {
+    let (graphics_pipeline, wireframe_pipeline, point_pipeline, pipeline_layout) = {
         let vert_shader_module = {
             let vert_shader_code = include_bytes!("spv/vert.spv");
 
@@ -479,27 +406,23 @@ fn main() {
 
         // Область просмотра в основном описывает область фреймбуфера,
         // в которую будет отображаться вывод. Это почти всегда будет (0, 0)к (width, height)
-        let viewports = vec![vk::Viewport::builder()
-            .width(WINDOW_WIDTH as f32)
-            .height(WINDOW_HEIGHT as f32)
-            .min_depth(0.0)
-            .max_depth(1.1)
-            .build()];
-
+        //
         // В то время как видовые экраны определяют преобразование изображения в буфер кадра,
         // прямоугольники-ножницы определяют, в каких областях фактически будут храниться пиксели.
         // Любые пиксели за пределами прямоугольников-ножниц будут отброшены растеризатором.
         // Они действуют как фильтр, а не как преобразование.
-        let scissors = vec![vk::Rect2D {
-            offset: vk::Offset2D { x: 0, y: 0 },
-            extent: surface_resolution,
-        }];
-
-        // Теперь этот видовой экран и прямоугольник с ножницами нужно объединить в состояние видового экрана
-        // с помощью vk::PipelineViewportStateCreateInfo
+        //
+        // Сами значения мы не запекаем в конвейер: они задаются при записи каждого кадра (см. динамическое состояние ниже),
+        // поэтому здесь указываем только их количество.
         let viewport_state_create_info = vk::PipelineViewportStateCreateInfo::builder()
-            .viewports(&viewports)
-            .scissors(&scissors);
+            .viewport_count(1)
+            .scissor_count(1);
+
+        // Динамическое состояние позволяет менять часть настроек конвейера без его пересоздания.
+        // Размер окна может меняться, поэтому viewport и scissor устанавливаются командами cmd_set_viewport/cmd_set_scissor.
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_state_create_info =
+            vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
 
         // Растеризатор берет геометрию, сформированную вершинами из вершинного шейдера,
         // и превращает ее в фрагменты, которые будут раскрашены фрагментным шейдером.
@@ -561,7 +484,7 @@ fn main() {
         // а вторая структура vk::PipelineColorBlendStateCreateInfo содержит глобальные настройки смешивания цветов.
         // В нашем случае у нас только один фреймбуфер
         let color_blend_attachment_states = vec![vk::PipelineColorBlendAttachmentState::builder()
-            .color_write_mask(vk::ColorComponentFlags::all())
+            .color_write_mask(vk::ColorComponentFlags::RGBA)
             .blend_enable(true)
             .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
             .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
@@ -584,18 +507,28 @@ fn main() {
             // которые можно изменять во время рисования, чтобы изменить поведение ваших шейдеров без необходимости их воссоздания.
             // Обычно они используются для передачи матрицы преобразования в вершинный шейдер или для создания сэмплеров текстуры во фрагментном шейдере.
             // Эти единые значения необходимо указать во время создания конвейера путем создания VkPipelineLayout объекта.
-            // Несмотря на то, что мы не будем использовать их до следующей главы, нам все равно необходимо создать пустой макет конвейера.
-
-            let pipeline_layout_create_info = vk::PipelineLayoutCreateInfo::default();
-            let pipeline_layout = unsafe {
+            // Пока у нас нет uniform буферов: преобразование треугольника и время передаются через push-константы.
+            // Их размер ограничен устройством, поэтому структура проверяется по его пределам.
+            ScenePushConstants::validate(
+                &unsafe { instance.get_physical_device_properties(p_device) }.limits,
+            )
+            .expect("Scene push constants do not fit the device!");
+            let push_constant_ranges = [ScenePushConstants::range()];
+            let pipeline_layout_create_info =
+                vk::PipelineLayoutCreateInfo::builder().push_constant_ranges(&push_constant_ranges);
+            unsafe {
                 device
                     .create_pipeline_layout(&pipeline_layout_create_info, None)
                     .expect("Failed to create pipeline layout!")
-            };
-            pipeline_layout
+            }
         };
 
-        let graphic_pipeline_create_infos = [vk::GraphicsPipelineCreateInfo::builder()
+        // При динамическом рендеринге конвейеру нужно знать только форматы вложений.
+        let color_attachment_formats = [main_window.swapchain_bundle.surface_format.format];
+        let mut pipeline_rendering_create_info = vk::PipelineRenderingCreateInfo::builder()
+            .color_attachment_formats(&color_attachment_formats);
+
+        let mut graphic_pipeline_create_info = vk::GraphicsPipelineCreateInfo::builder()
             .stages(&shader_stages)
             .vertex_input_state(&vertex_input_state_create_info)
             .input_assembly_state(&vertex_input_assembly_state_info)
@@ -604,9 +537,44 @@ fn main() {
             .multisample_state(&multisample_state_create_info)
             .depth_stencil_state(&depth_state_create_info)
             .color_blend_state(&color_blend_state)
-            .layout(pipeline_layout)
-            .render_pass(render_pass)
-            .build()];
+            .dynamic_state(&dynamic_state_create_info)
+            .layout(pipeline_layout);
+
+        if graph_cache.uses_dynamic_rendering() {
+            graphic_pipeline_create_info =
+                graphic_pipeline_create_info.push_next(&mut pipeline_rendering_create_info);
+        } else {
+            // Конвейер создается для конкретного прохода рендеринга, но может использоваться с любым совместимым с ним,
+            // то есть с теми же форматами вложений. Поэтому достаточно попросить у графа совместимый проход.
+            let render_pass =
+                graph_cache.compatible_render_pass(&device, &color_attachment_formats, None);
+            graphic_pipeline_create_info = graphic_pipeline_create_info.render_pass(render_pass);
+        }
+
+        // Каркасный конвейер и конвейер точек отличаются только режимом растеризации, переключаются
+        // в панели отладки. Без fill_mode_non_solid они не создаются.
+        let wireframe_rasterization_state_create_info = vk::PipelineRasterizationStateCreateInfo {
+            polygon_mode: vk::PolygonMode::LINE,
+            ..*rasterization_statue_create_info
+        };
+        let point_rasterization_state_create_info = vk::PipelineRasterizationStateCreateInfo {
+            polygon_mode: vk::PolygonMode::POINT,
+            ..*rasterization_statue_create_info
+        };
+        let graphic_pipeline_create_info = graphic_pipeline_create_info.build();
+        let mut graphic_pipeline_create_infos = vec![graphic_pipeline_create_info];
+        if non_solid_supported {
+            graphic_pipeline_create_infos.extend([
+                vk::GraphicsPipelineCreateInfo {
+                    p_rasterization_state: &wireframe_rasterization_state_create_info,
+                    ..graphic_pipeline_create_info
+                },
+                vk::GraphicsPipelineCreateInfo {
+                    p_rasterization_state: &point_rasterization_state_create_info,
+                    ..graphic_pipeline_create_info
+                },
+            ]);
+        }
 
         let graphics_pipelines = unsafe {
             device
@@ -623,30 +591,209 @@ fn main() {
             device.destroy_shader_module(frag_shader_module, None);
         }
 
-        (graphics_pipelines[0], pipeline_layout)
+        (
+            graphics_pipelines[0],
+            graphics_pipelines.get(1).copied(),
+            graphics_pipelines.get(2).copied(),
+            pipeline_layout,
+        )
+    };
+    // Размер точек в режиме точек, в пикселях.
+    let point_size = if large_points_supported {
+        let point_size_range = unsafe { instance.get_physical_device_properties(p_device) }
+            .limits
+            .point_size_range;
+        POINT_SIZE.clamp(point_size_range[0], point_size_range[1])
+    } else {
+        1.0
     };
 
-    // Вложения, указанные во время создания прохода рендеринга, связываются путем их обертывания в VkFramebufferобъект.
-    // Объект фреймбуфера ссылается на все VkImageViewобъекты, представляющие вложения.
-    let framebuffers = swapchain_images_viev
-        .iter()
-        .map(|image_view| {
-            let attachments = std::slice::from_ref(image_view);
-
-            let framebuffer_create_info = vk::FramebufferCreateInfo::builder()
-                .render_pass(render_pass)
-                .attachments(attachments)
-                .width(surface_resolution.width)
-                .height(surface_resolution.height)
-                .layers(1);
-
-            unsafe {
-                device
-                    .create_framebuffer(&framebuffer_create_info, None)
-                    .expect("Failed to create Framebuffer!")
+    // Интерфейс отладки рисуется egui поверх сцены. Панель показывается и скрывается действием toggle_debug_panel (F1).
+    let mut ui_renderer = UiRenderer::new(
+        &device,
+        unsafe { instance.get_physical_device_memory_properties(p_device) },
+        &mut graph_cache,
+        main_window.swapchain_bundle.surface_format.format,
+        MAX_FRAMES_IN_FLIGHT + 1,
+    );
+    let ui_context = egui::Context::default();
+
+    // Шрифт для текста на экране можно указать аргументом --font <путь к TTF>,
+    // иначе берется шрифт Ubuntu, встроенный в egui. В нем есть кириллица.
+    let font_data = argument_value("--font")
+        .map(|path| std::fs::read(&path).expect("Failed to read font file!"))
+        .unwrap_or_else(|| {
+            egui::FontDefinitions::default().font_data["Ubuntu-Light"]
+                .font
+                .to_vec()
+        });
+    let mut text_renderer = TextRenderer::new(
+        &device,
+        unsafe { instance.get_physical_device_memory_properties(p_device) },
+        &mut graph_cache,
+        main_window.swapchain_bundle.surface_format.format,
+        MAX_FRAMES_IN_FLIGHT + 1,
+        font_data,
+    )
+    .expect("Failed to parse font!");
+
+    // Двумерные спрайты. Демонстрация с тысячами спрайтов включается действием toggle_sprite_demo (F4).
+    let mut sprite_batch = SpriteBatch::new(
+        &device,
+        unsafe { instance.get_physical_device_memory_properties(p_device) },
+        &mut graph_cache,
+        main_window.swapchain_bundle.surface_format.format,
+        MAX_FRAMES_IN_FLIGHT + 1,
+    );
+    let sprite_textures = [
+        {
+            // Шахматная клетка 8 на 8.
+            let pixels = (0..64)
+                .flat_map(|index| {
+                    let value = if (index % 8 + index / 8) % 2 == 0 {
+                        255
+                    } else {
+                        96
+                    };
+                    [value, value, value, 255]
+                })
+                .collect::<Vec<u8>>();
+            sprite_batch.create_texture(&device, 8, 8, &pixels)
+        },
+        {
+            // Круг 32 на 32 с мягким краем.
+            let pixels = (0..32 * 32)
+                .flat_map(|index| {
+                    let x = (index % 32) as f32 - 15.5;
+                    let y = (index / 32) as f32 - 15.5;
+                    let alpha = (16.0 - (x * x + y * y).sqrt()).clamp(0.0, 1.0);
+                    [255, 255, 255, (alpha * 255.0) as u8]
+                })
+                .collect::<Vec<u8>>();
+            sprite_batch.create_texture(&device, 32, 32, &pixels)
+        },
+    ];
+    // Множество кубов одним вызовом отрисовки. Демонстрация включается действием toggle_instancing_demo (F6),
+    // число кубов задается аргументом --instances <число>.
+    let mut instanced_renderer = InstancedRenderer::new(
+        &device,
+        unsafe { instance.get_physical_device_memory_properties(p_device) },
+        &mut graph_cache,
+        main_window.swapchain_bundle.surface_format.format,
+        supported_depth_format(&instance, p_device),
+        MAX_FRAMES_IN_FLIGHT + 1,
+    );
+    let instance_count = argument_value("--instances")
+        .and_then(|count| count.parse().ok())
+        .unwrap_or(DEFAULT_INSTANCE_COUNT);
+    // Неподвижное поле кубов, которое отсекается и рисуется видеокартой. Демонстрация включается действием
+    // toggle_gpu_culling_demo (F7), число объектов задается аргументом --objects <число>.
+    let mut gpu_culling = if indirect_first_instance_supported {
+        let object_count = argument_value("--objects")
+            .and_then(|count| count.parse().ok())
+            .unwrap_or(DEFAULT_CULLED_OBJECT_COUNT);
+        let max_draw_indirect_count = if multi_draw_indirect_supported {
+            unsafe { instance.get_physical_device_properties(p_device) }
+                .limits
+                .max_draw_indirect_count
+        } else {
+            1
+        };
+        Some(GpuCulling::new(
+            &device,
+            unsafe { instance.get_physical_device_memory_properties(p_device) },
+            &culling_demo_objects(object_count),
+            MESH_BOUNDING_RADIUS,
+            MAX_FRAMES_IN_FLIGHT + 1,
+            DrawIndirectCount::new(&instance, &device, draw_indirect_count_support),
+            max_draw_indirect_count,
+        ))
+    } else {
+        println!("GPU culling is not supported: draw_indirect_first_instance is missing");
+        None
+    };
+    // Иерархия узлов с мировыми матрицами, которые складываются из преобразований родителей.
+    // Демонстрация включается действием toggle_scene_demo (F8).
+    let mut scene_renderer = SceneRenderer::new(
+        &device,
+        unsafe { instance.get_physical_device_memory_properties(p_device) },
+        &mut graph_cache,
+        main_window.swapchain_bundle.surface_format.format,
+        supported_depth_format(&instance, p_device),
+        MAX_FRAMES_IN_FLIGHT + 1,
+    );
+    let cube_mesh = scene_renderer.create_mesh(&device, &MeshData::cube());
+    let mut scene = create_scene_demo(cube_mesh);
+    let mut skeletal_animations = vec![add_tentacle_demo(&device, &mut scene_renderer, &mut scene)];
+    // Фонарик светит из камеры и включается действием toggle_flashlight (L).
+    let flashlight = scene.add_node("flashlight", None, Transform::IDENTITY);
+    // Наибольшая сторона текстуры, которую поддерживает устройство.
+    let max_image_dimension = unsafe { instance.get_physical_device_properties(p_device) }
+        .limits
+        .max_image_dimension2_d;
+    // Модель из файла, заданного аргументом --model <файл.obj|.gltf|.glb>, добавляется в сцену,
+    // и сцена сразу включена.
+    let model_loaded = match argument_value("--model") {
+        Some(path) => {
+            let path = std::path::Path::new(&path);
+            let extension = path
+                .extension()
+                .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+            let result = match extension.as_deref() {
+                Some("gltf") | Some("glb") => load_gltf(
+                    &device,
+                    &mut scene_renderer,
+                    &mut scene,
+                    path,
+                    max_image_dimension,
+                )
+                .map(|gltf| {
+                    skeletal_animations.push(SkeletalAnimation::new(
+                        &scene,
+                        gltf.skins,
+                        gltf.animations,
+                    ))
+                }),
+                _ => ObjModel::load(path).map(|model| {
+                    add_obj_model(
+                        &device,
+                        &mut scene_renderer,
+                        &mut scene,
+                        &model,
+                        max_image_dimension,
+                    )
+                }),
+            };
+            match result {
+                Ok(()) => true,
+                Err(error) => {
+                    println!("Failed to load model {}", error);
+                    false
+                }
             }
-        })
-        .collect::<Vec<_>>();
+        }
+        None => false,
+    };
+    // Кольцо кубов, которые хранятся сущностями мира ECS и рисуются тем же SceneRenderer, что живет в мире
+    // как ресурс. Демонстрация включается действием toggle_ecs_demo (F10).
+    let mut world = World::new();
+    ecs::insert_render_resources(&mut world, scene_renderer);
+    world.insert_resource(DemoTime(0.0));
+    spawn_ecs_demo(&mut world, cube_mesh);
+    let mut ecs_schedule = Schedule::default();
+    ecs_schedule.add_systems(animate_ecs_demo.before(ExtractDraws));
+    ecs::add_extract_systems(&mut ecs_schedule);
+    let mut ui_input = UiInput::new(max_image_dimension as usize);
+    let device_info = DeviceInfo::query(
+        &instance,
+        p_device,
+        &if graph_cache.uses_dynamic_rendering() {
+            format!("dynamic rendering ({:?})", dynamic_rendering_support)
+        } else {
+            "render passes".to_owned()
+        },
+    );
+    let mut debug_panel = DebugPanel::new(main_window.swapchain_bundle.vsync, non_solid_supported);
 
     // Мы должны создать пул команд, прежде чем мы сможем создавать буферы команд.
     // Пулы команд управляют памятью, которая используется для хранения буферов, и буферы команд выделяются из них.
@@ -660,7 +807,9 @@ fn main() {
             //
             //      VK_COMMAND_POOL_CREATE_RESET_COMMAND_BUFFER_BIT: Разрешить перезапись буферов команд по отдельности,
             // без этого флага все они должны быть сброшены вместе
-            .flags(vk::CommandPoolCreateFlags::empty());
+            //
+            // Мы перезаписываем буфер команд каждый кадр, так как viewport и scissor зависят от текущего размера окна.
+            .flags(vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER);
 
         unsafe {
             device
@@ -669,79 +818,23 @@ fn main() {
         }
     };
 
+    // Буферы команд выделяются с помощью allocate_command_buffers функции, которая принимает
+    // vk::CommandBufferAllocateInfo структуру в качестве параметра, указывающего пул команд и количество выделяемых буферов.
+    // Буфер команд нужен на каждый кадр в полете: пока видеокарта выполняет один, мы записываем следующий.
     let command_buffers = {
-        // Буферы команд выделяются с помощью allocate_command_buffers функции, которая принимает
-        // vk::CommandBufferAllocateInfo структуру в качестве параметра, указывающего пул команд и количество выделяемых буферов
         let command_buffer_allocate_info = vk::CommandBufferAllocateInfo::builder()
             .command_pool(command_pool)
-            .command_buffer_count(framebuffers.len() as u32)
+            .command_buffer_count(MAX_FRAMES_IN_FLIGHT as u32 + 1)
             //В levelопределяет параметр , если выделенные командные буфера являются первичными или вторичными буферами команд.
             //      vk::CommandBufferLevel::PRIMARY:    Может быть отправлен в очередь для выполнения, но не может быть вызван из других буферов команд.
             //      vk::CommandBufferLevel::SECONDARY:  Не может быть отправлено напрямую, но может быть вызвано из первичных командных буферов.
             .level(vk::CommandBufferLevel::PRIMARY);
 
-        let command_buffers = unsafe {
+        unsafe {
             device
                 .allocate_command_buffers(&command_buffer_allocate_info)
                 .expect("Failed to allocate Command Buffers!")
-        };
-
-        for (i, &command_buffer) in command_buffers.iter().enumerate() {
-            // Мы начинаем запись командного буфера с вызова begin_command_buffer небольшой  vk::CommandBufferBeginInfo структурой
-            // в качестве аргумента, который указывает некоторые детали использования этого конкретного командного буфера.
-            let command_buffer_begin_info = vk::CommandBufferBeginInfo::builder()
-                // В flagsопределяет параметр , как мы будем использовать буфер команд. Доступны следующие значения:
-                //      vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT:        Командный буфер будет перезаписан сразу после его выполнения.
-                //      vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE:   Это дополнительный буфер команд, который будет полностью находиться в пределах одного прохода рендеринга.
-                //      vk::CommandBufferUsageFlags::SIMULTANEOUS_USE:       Командный буфер можно повторно отправить, пока он уже ожидает выполнения.
-                .flags(vk::CommandBufferUsageFlags::SIMULTANEOUS_USE);
-
-            unsafe {
-                device
-                    .begin_command_buffer(command_buffer, &command_buffer_begin_info)
-                    .expect("Failed to begin recording Command Buffer at beginning!");
-            }
-
-            let clear_values = [vk::ClearValue {
-                color: vk::ClearColorValue {
-                    float32: [0.0, 0.0, 0.0, 1.0],
-                },
-            }];
-
-            // Рисование начинается с начала прохода рендеринга с cmd_begin_render_pass.
-            // Этап рендеринга настраивается с использованием некоторых параметров в RenderPassBeginInfo.
-
-            let render_pass_begin_info = vk::RenderPassBeginInfo::builder()
-                .render_pass(render_pass)
-                .framebuffer(framebuffers[i])
-                .render_area(vk::Rect2D {
-                    offset: vk::Offset2D { x: 0, y: 0 },
-                    extent: surface_resolution,
-                })
-                .clear_values(&clear_values);
-
-            unsafe {
-                device.cmd_begin_render_pass(
-                    command_buffer,
-                    &render_pass_begin_info,
-                    vk::SubpassContents::INLINE,
-                );
-                device.cmd_bind_pipeline(
-                    command_buffer,
-                    vk::PipelineBindPoint::GRAPHICS,
-                    graphics_pipeline,
-                );
-                device.cmd_draw(command_buffer, 3, 1, 0, 0);
-
-                device.cmd_end_render_pass(command_buffer);
-
-                device
-                    .end_command_buffer(command_buffer)
-                    .expect("Failed to record Command Buffer at Ending!");
-            }
         }
-
-        command_buffers
     };
 
     let mut sync_objects = SyncObjects::default();
@@ -774,7 +867,20 @@ fn main() {
         }
     }
 
-    window.set_visible(true);
+    // Ввод записывается в файл аргументом --record <файл> и воспроизводится аргументом --replay <файл>.
+    // Воспроизведение без окна не поддерживается: кадры рисуются в цепочку обмена окна, поэтому нужен дисплей.
+    // С --hidden окно только скрывается, кадры по-прежнему показываются на его поверхность.
+    // Когда запись кончается, приложение закрывается.
+    let mut input_recorder = argument_value("--record")
+        .map(|path| InputRecorder::create(&path).expect("Failed to create input recording!"));
+    let mut input_player = argument_value("--replay").map(|path| {
+        let player = InputPlayer::load(&path).expect("Failed to load input recording!");
+        println!("Replaying {} frames from {}", player.frame_count(), path);
+        player
+    });
+    let hidden = input_player.is_some() && std::env::args().any(|arg| arg == "--hidden");
+
+    main_window.window.set_visible(!hidden);
     //-----------------------------------------------------------------------------------------------------------------
     let graphics_queue = unsafe { device.get_device_queue(graphics_family_index, 0) };
     let present_queue = unsafe { device.get_device_queue(present_family_index, 0) };
@@ -784,51 +890,820 @@ fn main() {
     let in_flight_fences = sync_objects.inflight_fences;
     let mut current_frame = 0;
 
-    event_loop.run(move |event, _, control_flow| match event {
-        Event::WindowEvent { event, .. } => match event {
-            WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
-            WindowEvent::KeyboardInput { input, .. } => match input {
-                KeyboardInput {
-                    virtual_keycode,
-                    state,
-                    ..
-                } => match (virtual_keycode, state) {
-                    (Some(VirtualKeyCode::Escape), ElementState::Pressed) => {
-                        *control_flow = ControlFlow::Exit
+    // Раскладка областей просмотра, переключается действием cycle_viewport_layout (V).
+    let mut viewport_layout = ViewportLayout::Single;
+    // По действию dump_render_graph (F9) скомпилированное расписание графа рендеринга сохраняется в файл для Graphviz.
+    let mut dump_render_graph = false;
+    // По действию screenshot (F12) показанное изображение сохраняется в PNG.
+    let mut screenshots =
+        Screenshots::new(unsafe { instance.get_physical_device_memory_properties(p_device) });
+    let mut screenshot_requested = false;
+    let mut sprite_demo = false;
+    let mut instancing_demo = false;
+    let mut gpu_culling_demo = false;
+    let mut scene_demo = model_loaded;
+    let mut ecs_demo = false;
+    // Клавиши и кнопки мыши назначаются действиям в input.toml. Свой файл привязок передается аргументом
+    // --bindings <путь>, он переопределяет только указанные в нем действия.
+    let mut input_map = InputMap::parse(DEFAULT_BINDINGS).expect("Invalid default bindings!");
+    if let Some(path) = argument_value("--bindings") {
+        match InputMap::load(&path) {
+            Ok(bindings) => input_map = input_map.merged(bindings),
+            Err(error) => println!("Failed to load bindings from {}: {}", path, error),
+        }
+    }
+    let mut input = Input::new(input_map);
+    // Alt+Enter (действие toggle_fullscreen) переключает окно в полноэкранный режим и обратно.
+    // --fullscreen-mode borderless|exclusive выбирает режим, --monitor <номер> — монитор,
+    // --video-mode 1920x1080@60 — видеорежим для исключительного режима, --list-monitors печатает мониторы.
+    let fullscreen_mode = match argument_value("--fullscreen-mode").as_deref() {
+        Some("exclusive") => WindowMode::Exclusive,
+        Some("borderless") | None => WindowMode::Borderless,
+        Some(mode) => {
+            println!("Unknown fullscreen mode \"{}\", using borderless", mode);
+            WindowMode::Borderless
+        }
+    };
+    let mut window_mode = WindowModeController::new(fullscreen_mode);
+    window_mode.set_monitor(argument_value("--monitor").and_then(|index| index.parse().ok()));
+    if let Some(video_mode) = argument_value("--video-mode") {
+        if let Err(error) = window_mode.set_video_mode(&video_mode) {
+            println!("{}", error);
+        }
+    }
+    if std::env::args().any(|arg| arg == "--list-monitors") {
+        print_monitors(&main_window.window);
+    }
+    if !hidden && std::env::args().any(|arg| arg == "--fullscreen") {
+        window_mode.toggle(&main_window.window);
+        debug_panel.window_mode = window_mode.mode();
+    }
+    // Камера сцены. Действие toggle_camera_mode переключает свободный полет и вращение вокруг точки.
+    let mut camera = Camera::new(
+        CameraMode::Orbit,
+        glam::Vec3::new(0.0, 0.0, 1.5),
+        glam::Vec3::ZERO,
+    );
+    let mut cursor_grabbed = false;
+    let mut last_frame_start = Instant::now();
+    // Записанное время последнего кадра при воспроизведении. Текст на экране показывает его вместо настоящего.
+    let mut replay_delta_time = None;
+    // Время сцены — сумма времен кадров. При воспроизведении оно совпадает с записанным.
+    let mut scene_time = 0.0f32;
+    // Статистика времени кадра: FPS и среднее время выводятся в заголовок окна раз в секунду,
+    // а по действию print_frame_stats (F3) в консоль печатается подробная таблица с перцентилями.
+    let mut frame_stats = FrameStats::new(240, Duration::from_secs(1));
+    let mut gpu_timer = GpuTimer::new(
+        &instance,
+        &device,
+        p_device,
+        graphics_family_index,
+        MAX_FRAMES_IN_FLIGHT + 1,
+        MAX_TIMED_PASSES,
+    );
+    if gpu_timer.is_none() {
+        println!("GPU timestamps are not supported by the graphics queue");
+    }
+    // Статистика конвейера и видимость областей просмотра тоже печатаются по F3.
+    let mut pipeline_statistics = pipeline_statistics_supported
+        .then(|| PipelineStatistics::new(&device, MAX_FRAMES_IN_FLIGHT + 1, MAX_TIMED_PASSES));
+    if pipeline_statistics.is_none() {
+        println!("Pipeline statistics queries are not supported");
+    }
+    let mut occlusion_queries = OcclusionQueries::new(
+        &device,
+        MAX_FRAMES_IN_FLIGHT + 1,
+        MAX_OCCLUSION_QUERIES,
+        occlusion_query_precise,
+    );
+    let mut last_pass_statistics: Vec<(String, PassStatistics)> = Vec::new();
+    let mut last_occlusion: Vec<OcclusionResult> = Vec::new();
+    // Второе окно показывает сцену неподвижной камерой со стороны. Оно открывается аргументом --tool-window
+    // или действием toggle_tool_window (F5) и рисуется теми же конвейерами и ресурсами, что и главное.
+    let tool_camera = Camera::new(
+        CameraMode::Orbit,
+        glam::Vec3::new(1.2, 0.9, 1.2),
+        glam::Vec3::ZERO,
+    );
+    let mut tool_window = None;
+    if !hidden && std::env::args().any(|arg| arg == "--tool-window") {
+        tool_window = open_tool_window(
+            &event_loop,
+            &entry,
+            &instance,
+            &device,
+            &surface_loader,
+            &swapchain_loader,
+            p_device,
+            present_family_index,
+            &main_window,
+        );
+    }
+
+    event_loop.run(move |event, event_loop, control_flow| match event {
+        Event::WindowEvent { window_id, event }
+            if Some(window_id) == tool_window.as_ref().map(WindowSurface::id) =>
+        {
+            // Второе окно без интерфейса, его ввод не управляет сценой и не записывается.
+            match event {
+                WindowEvent::CloseRequested => {
+                    if let Some(window) = tool_window.take() {
+                        unsafe {
+                            close_window(
+                                &device,
+                                &mut graph_cache,
+                                &surface_loader,
+                                &swapchain_loader,
+                                window,
+                            )
+                        };
                     }
-                    _ => {}
-                },
-            },
-            _ => {}
-        },
+                }
+                WindowEvent::Resized(_) | WindowEvent::ScaleFactorChanged { .. } => {
+                    if let Some(window) = tool_window.as_mut() {
+                        window.swapchain_dirty = true;
+                    }
+                }
+                _ => {}
+            }
+        }
+        Event::WindowEvent { window_id, event } if window_id == main_window.id() => {
+            if let Some(input_recorder) = input_recorder.as_mut() {
+                if let Some(recorded) = RecordedEvent::from_window_event(&event) {
+                    input_recorder.record(recorded);
+                }
+            }
+            match event {
+                WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+                // При переносе окна на экран с другим масштабом winit меняет его размер в пикселях,
+                // сохраняя логический, а интерфейс и текст берут новый масштаб в следующем кадре.
+                WindowEvent::Resized(_) | WindowEvent::ScaleFactorChanged { .. } => {
+                    main_window.swapchain_dirty = true
+                }
+                // При воспроизведении ввод пользователя не учитывается, события берутся из записи.
+                _ if input_player.is_some() => {}
+                _ => dispatch_input_event(
+                    &event,
+                    &mut ui_input,
+                    &ui_context,
+                    main_window.window.scale_factor() as f32,
+                    &mut input,
+                ),
+            }
+        }
+        Event::DeviceEvent { event, .. } => {
+            if let Some(input_recorder) = input_recorder.as_mut() {
+                if let Some(recorded) = RecordedEvent::from_device_event(&event) {
+                    input_recorder.record(recorded);
+                }
+            }
+            if input_player.is_none() {
+                input.handle_device_event(&event);
+            }
+        }
         Event::MainEventsCleared => {
-            if !window.inner_size().width == 0 ||  !window.inner_size().height == 0 {
-                window.request_redraw();
+            // Свернутое окно имеет нулевой размер, для него цепочку обмена создать нельзя, поэтому не рисуем.
+            for window in std::iter::once(&main_window).chain(tool_window.as_ref()) {
+                if !window.is_minimized() {
+                    window.window.request_redraw();
+                }
             }
-        },
-        Event::RedrawRequested(_window_id) => {
+        }
+        Event::RedrawRequested(window_id) => {
+            // Сцена обновляется один раз за кадр главного окна, остальные окна показывают ее текущее состояние.
+            if window_id == main_window.id() {
+                // Время кадра. При воспроизведении оно берется из записи, а перед кадром подаются его записанные события.
+                // Иначе камера двигается на скорость, умноженную на время прошлого кадра. Время ограничено,
+                // чтобы после долгой паузы (например, перетаскивания окна) камера не улетела.
+                let delta_time = match input_player.as_mut() {
+                    Some(input_player) => match input_player.next_frame() {
+                        Some(frame) => {
+                            for event in frame.events {
+                                match (event.to_window_event(), event.to_device_event()) {
+                                    (Some(WindowEvent::Resized(size)), _) => {
+                                        main_window.window.set_inner_size(size)
+                                    }
+                                    (Some(event), _) => dispatch_input_event(
+                                        &event,
+                                        &mut ui_input,
+                                        &ui_context,
+                                        main_window.window.scale_factor() as f32,
+                                        &mut input,
+                                    ),
+                                    (None, Some(event)) => input.handle_device_event(&event),
+                                    (None, None) => {}
+                                }
+                            }
+                            ui_input.advance_recorded_time(frame.delta_time);
+                            replay_delta_time = Some(frame.delta_time);
+                            frame.delta_time
+                        }
+                        None => {
+                            println!("Replay finished");
+                            *control_flow = ControlFlow::Exit;
+                            return;
+                        }
+                    },
+                    None => {
+                        let now = Instant::now();
+                        let delta_time = (now - last_frame_start).as_secs_f32().min(0.1);
+                        last_frame_start = now;
+                        delta_time
+                    }
+                };
+                if let Some(input_recorder) = input_recorder.as_mut() {
+                    input_recorder.end_frame(delta_time);
+                }
+                scene_time += delta_time;
+
+                // Действия, начатые с прошлого кадра. Нажатия и сдвиги мыши сбрасываются сразу после обработки,
+                // до любого раннего выхода из кадра, чтобы одно нажатие не сработало дважды.
+                if input.action_pressed("quit") {
+                    *control_flow = ControlFlow::Exit;
+                    return;
+                }
+                if input.action_pressed("cycle_viewport_layout") {
+                    viewport_layout = viewport_layout.next();
+                    println!("Viewport layout: {:?}", viewport_layout);
+                }
+                if input.action_pressed("toggle_fullscreen") {
+                    window_mode.toggle(&main_window.window);
+                    debug_panel.window_mode = window_mode.mode();
+                    main_window.swapchain_dirty = true;
+                }
+                if input.action_pressed("toggle_tool_window") {
+                    match tool_window.take() {
+                        Some(window) => unsafe {
+                            close_window(
+                                &device,
+                                &mut graph_cache,
+                                &surface_loader,
+                                &swapchain_loader,
+                                window,
+                            )
+                        },
+                        None => {
+                            tool_window = open_tool_window(
+                                event_loop,
+                                &entry,
+                                &instance,
+                                &device,
+                                &surface_loader,
+                                &swapchain_loader,
+                                p_device,
+                                present_family_index,
+                                &main_window,
+                            )
+                        }
+                    }
+                }
+                if input.action_pressed("toggle_camera_mode") {
+                    camera.toggle_mode();
+                    println!("Camera: {:?}", camera.mode);
+                }
+                if input.action_pressed("toggle_debug_panel") {
+                    debug_panel.visible = !debug_panel.visible;
+                }
+                if input.action_pressed("cycle_draw_mode") {
+                    debug_panel.draw_mode = debug_panel.draw_mode.next(non_solid_supported);
+                    println!("Draw mode: {}", debug_panel.draw_mode.name());
+                }
+                if input.action_pressed("print_frame_stats") {
+                    print!("{}", frame_stats.report());
+                    for (name, statistics) in &last_pass_statistics {
+                        println!("{}: {:?}", name, statistics);
+                    }
+                    for result in &last_occlusion {
+                        println!(
+                            "viewport {}: {} ({} samples)",
+                            result.object,
+                            if result.is_visible() {
+                                "visible"
+                            } else {
+                                "hidden"
+                            },
+                            result.samples
+                        );
+                    }
+                }
+                if input.action_pressed("toggle_sprite_demo") {
+                    sprite_demo = !sprite_demo;
+                }
+                if input.action_pressed("toggle_instancing_demo") {
+                    instancing_demo = !instancing_demo;
+                }
+                if input.action_pressed("toggle_gpu_culling_demo") {
+                    gpu_culling_demo = !gpu_culling_demo;
+                }
+                if input.action_pressed("toggle_scene_demo") {
+                    scene_demo = !scene_demo;
+                }
+                if input.action_pressed("toggle_ecs_demo") {
+                    ecs_demo = !ecs_demo;
+                }
+                if input.action_pressed("toggle_flashlight") {
+                    if scene.light(flashlight).is_some() {
+                        scene.remove_light(flashlight);
+                    } else {
+                        scene.set_light(flashlight, FLASHLIGHT);
+                    }
+                }
+                if input.action_pressed("dump_render_graph") {
+                    dump_render_graph = true;
+                }
+                if input.action_pressed("screenshot") {
+                    screenshot_requested = true;
+                }
+
+                camera.update(&input, delta_time);
+                // Пока мышь поворачивает взгляд, курсор скрыт и не покидает окно. При воспроизведении курсор не трогаем.
+                let grab = input_player.is_none() && camera.wants_cursor_grab(&input);
+                if grab != cursor_grabbed {
+                    if let Err(error) = main_window.window.set_cursor_grab(grab) {
+                        println!("Failed to grab cursor: {}", error);
+                    }
+                    main_window.window.set_cursor_visible(!grab);
+                    cursor_grabbed = grab;
+                }
+                input.end_frame();
+            }
+
+            // Кадры всех окон идут по одному кругу кадров в полете: забор слота ждет и кадр другого окна,
+            // поэтому буферы и запросы слота к началу кадра любого окна свободны.
+            let is_main_window = window_id == main_window.id();
+            let target = if is_main_window {
+                &mut main_window
+            } else {
+                match tool_window.as_mut() {
+                    Some(window) if window.id() == window_id => window,
+                    _ => return,
+                }
+            };
+
+            if target.swapchain_dirty {
+                unsafe {
+                    device
+                        .device_wait_idle()
+                        .expect("Failed to wait device idle!");
+                    graph_cache.release_framebuffers(&device);
+                    target.recreate_swapchain(
+                        &device,
+                        &surface_loader,
+                        &swapchain_loader,
+                        p_device,
+                        debug_panel.vsync,
+                    );
+                }
+            }
+
+            let frame_start = Instant::now();
+
             // Берем из масисва забор текущего фрэйма
             let wait_fences = [in_flight_fences[current_frame]];
 
-            let (image_index, _is_sub_optimal) = unsafe {
+            unsafe {
                 // Ожидаем
                 device
-                    .wait_for_fences(&wait_fences, true, std::u64::MAX)
+                    .wait_for_fences(&wait_fences, true, u64::MAX)
                     .expect("Failed to wait for Fence!");
+            }
+            let mut phase_start = Instant::now();
+            frame_stats.record_duration("cpu wait fence", phase_start - frame_start);
 
+            let acquire_result = unsafe {
                 // Убедились что видеокарта отрисовала нам в текуший фрэйм. Получаем следующее изображение из цепочки обмена
-                swapchain_loader
-                    .acquire_next_image(
-                        swapchain,
-                        std::u64::MAX,
-                        // Этот semaphore сигналезирует о получении следующего изображения
-                        image_available_semaphores[current_frame],
-                        vk::Fence::null(),
-                    )
-                    .expect("Failed to acquire next image.")
+                swapchain_loader.acquire_next_image(
+                    target.swapchain_bundle.swapchain,
+                    u64::MAX,
+                    // Этот semaphore сигналезирует о получении следующего изображения
+                    image_available_semaphores[current_frame],
+                    vk::Fence::null(),
+                )
+            };
+
+            let image_index = match acquire_result {
+                Ok((image_index, _is_sub_optimal)) => image_index,
+                // Цепочка обмена больше не соответствует поверхности, пересоздадим ее в следующем кадре.
+                Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                    target.swapchain_dirty = true;
+                    return;
+                }
+                Err(error) => panic!("Failed to acquire next image: {}", error),
+            };
+            frame_stats.record_duration("cpu acquire", phase_start.elapsed());
+            phase_start = Instant::now();
+
+            // Запросы времени этого кадра в полете уже выполнены видеокартой, забираем их результаты.
+            // Запросы делает только главное окно (см. запись буфера команд): его кадр в том же слоте заведомо выполнен.
+            if is_main_window {
+                if let Some(gpu_timer) = gpu_timer.as_mut() {
+                    for (name, milliseconds) in gpu_timer.begin_frame(&device, current_frame) {
+                        frame_stats.record(&format!("gpu {}", name), milliseconds);
+                    }
+                }
+                if let Some(pipeline_statistics) = pipeline_statistics.as_mut() {
+                    let statistics = pipeline_statistics.begin_frame(&device, current_frame);
+                    if !statistics.is_empty() {
+                        last_pass_statistics = statistics;
+                    }
+                }
+                let occlusion = occlusion_queries.begin_frame(&device, current_frame);
+                if !occlusion.is_empty() {
+                    last_occlusion = occlusion;
+                }
+            }
+
+            // Забор дождались, значит ресурсы кадров, которые давно не использовались, можно освобождать,
+            // а снимки экрана из завершенных кадров — сохранять.
+            graph_cache.begin_frame(&device);
+            screenshots.poll(&device);
+            world
+                .resource_mut::<SceneRenderer>()
+                .begin_frame(&device, current_frame);
+
+            let mut graph = RenderGraph::new();
+
+            // Текстуры и фреймбуферы в Vulkan представлены VkImageобъектами с определенным форматом пикселей,
+            // однако расположение пикселей в памяти может меняться в зависимости от того, что вы пытаетесь сделать с изображением.
+            // Вот некоторые из наиболее распространенных макетов:
+            //      vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL: Изображения используются как цветные вложения.
+            //      vk::ImageLayout::PRESENT_SRC_KHR:          Изображения, которые будут представлены в цепочке обмена
+            //      vk::ImageLayout::TRANSFER_DST_OPTIMAL:     Изображения, которые будут использоваться в качестве места назначения для операции копирования из памяти
+            //      vk::ImageLayout::UNDEFINED:                Предостережение этого специального значения заключается в том,
+            // что не гарантируется сохранение содержимого изображения, но это не имеет значения, поскольку мы собираемся очистить это все равно.
+            let backbuffer = graph.import_image(
+                "swapchain",
+                ImportedImage {
+                    image: target.swapchain_bundle.images[image_index as usize],
+                    view: target.swapchain_bundle.image_views[image_index as usize],
+                    format: target.swapchain_bundle.surface_format.format,
+                    extent: target.swapchain_bundle.surface_resolution,
+                    initial_layout: vk::ImageLayout::UNDEFINED,
+                    initial_stage: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                    final_layout: Some(vk::ImageLayout::PRESENT_SRC_KHR),
+                },
+            );
+
+            let clear_value = vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: [0.0, 0.0, 0.0, 1.0],
+                },
+            };
+
+            // В режиме наложения треугольник рисуется дважды: заливкой, а затем каркасом цвета OVERLAY_COLOR.
+            // Если режим не поддерживается устройством, остается заливка.
+            let (scene_pipeline, overlay_pipeline) = match debug_panel.draw_mode {
+                DrawMode::Fill => (graphics_pipeline, None),
+                DrawMode::Wireframe => (wireframe_pipeline.unwrap_or(graphics_pipeline), None),
+                DrawMode::Points => (point_pipeline.unwrap_or(graphics_pipeline), None),
+                DrawMode::Overlay => (graphics_pipeline, wireframe_pipeline),
+            };
+            let push_constants = ScenePushConstants {
+                model_view_projection: [0.0; 16],
+                overlay_color: [0.0; 4],
+                point_size,
+                time: scene_time,
             };
+            // Треугольник стоит в начале координат мира.
+            let triangle_model = glam::Mat4::IDENTITY;
+            // Второе окно показывает сцену своей камерой в одной области.
+            let (scene_camera, scene_layout, occlusion) = if is_main_window {
+                occlusion_queries.add_reset_pass(&mut graph);
+                (camera, viewport_layout, Some(&mut occlusion_queries))
+            } else {
+                (tool_camera, ViewportLayout::Single, None)
+            };
+            graph
+                .add_pass("triangle")
+                .color_attachment(backbuffer, LoadOp::Clear(clear_value))
+                .execute(move |context| unsafe {
+                    draw_triangle(
+                        context.device,
+                        context.command_buffer,
+                        scene_pipeline,
+                        pipeline_layout,
+                        context.extent,
+                        scene_layout,
+                        &scene_camera,
+                        triangle_model,
+                        push_constants,
+                        occlusion,
+                    );
+                    if let Some(overlay_pipeline) = overlay_pipeline {
+                        draw_triangle(
+                            context.device,
+                            context.command_buffer,
+                            overlay_pipeline,
+                            pipeline_layout,
+                            context.extent,
+                            scene_layout,
+                            &scene_camera,
+                            triangle_model,
+                            ScenePushConstants {
+                                overlay_color: OVERLAY_COLOR,
+                                ..push_constants
+                            },
+                            None,
+                        );
+                    }
+                });
+
+            // Кубы рисуются камерой своего окна на всю его площадь. Заполнение буфера экземпляров
+            // попадает в статистику кадра как cpu instances, а сам проход — как gpu instances.
+            if instancing_demo {
+                let fill_start = Instant::now();
+                draw_instancing_demo(&mut instanced_renderer, instance_count, scene_time);
+                if is_main_window {
+                    frame_stats.record_duration("cpu instances", fill_start.elapsed());
+                }
+            }
+            let extent = target.swapchain_bundle.surface_resolution;
+            let view_projection = scene_camera
+                .view_projection(extent.width as f32 / extent.height as f32)
+                .to_cols_array();
+            let camera_position = scene_camera.position().to_array();
+            instanced_renderer.add_pass(
+                &device,
+                &mut graph,
+                backbuffer,
+                extent,
+                current_frame,
+                view_projection,
+            );
+            // Поле кубов процессор не перебирает: отсечение и команды отрисовки готовит видеокарта.
+            // Время проходов попадает в статистику как gpu cull и gpu culled instances.
+            if let (true, Some(gpu_culling)) = (gpu_culling_demo, gpu_culling.as_ref()) {
+                gpu_culling.add_passes(
+                    &mut graph,
+                    &instanced_renderer,
+                    backbuffer,
+                    extent,
+                    current_frame,
+                    view_projection,
+                );
+            }
+            // Анимация меняет только локальные преобразования, мировые матрицы пересчитываются перед обходом сцены.
+            // Оба окна рисуют одну сцену, поэтому она анимируется кадрами главного окна.
+            if scene_demo {
+                if is_main_window {
+                    animate_scene_demo(&mut scene, scene_time);
+                    // Фонарик повторяет положение и поворот камеры: ее взгляд, как и свет, направлен по оси -z.
+                    let (_, rotation, translation) = scene_camera
+                        .view()
+                        .inverse()
+                        .to_scale_rotation_translation();
+                    scene.set_local_transform(
+                        flashlight,
+                        Transform {
+                            translation,
+                            rotation,
+                            ..Transform::IDENTITY
+                        },
+                    );
+                    for animation in &skeletal_animations {
+                        animation.pose(&mut scene, scene_time);
+                    }
+                    scene.update_world_matrices();
+                    let mut scene_renderer = world.resource_mut::<SceneRenderer>();
+                    for animation in &skeletal_animations {
+                        animation.update_skins(&scene, &mut scene_renderer);
+                    }
+                }
+                world.resource_mut::<SceneRenderer>().add_pass(
+                    &device,
+                    &mut graph,
+                    backbuffer,
+                    extent,
+                    current_frame,
+                    "scene",
+                    scene.draws(),
+                    scene.lights(),
+                    view_projection,
+                    camera_position,
+                );
+            }
+            // Мир обновляется расписанием один раз за кадр главного окна, после чего в DrawList лежат его сущности.
+            if ecs_demo {
+                if is_main_window {
+                    world.resource_mut::<DemoTime>().0 = scene_time;
+                    ecs_schedule.run(&mut world);
+                }
+                ecs::add_pass(
+                    &mut world,
+                    &device,
+                    &mut graph,
+                    backbuffer,
+                    extent,
+                    current_frame,
+                    "ecs",
+                    view_projection,
+                    camera_position,
+                );
+            }
+
+            if is_main_window && sprite_demo {
+                draw_sprite_demo(
+                    &mut sprite_batch,
+                    &sprite_textures,
+                    target.swapchain_bundle.surface_resolution,
+                    scene_time,
+                );
+            }
+            sprite_batch.add_pass(
+                &device,
+                &mut graph,
+                backbuffer,
+                target.swapchain_bundle.surface_resolution,
+                current_frame,
+            );
+
+            // Текст, как и интерфейс, раскладывается в логических единицах, поэтому на экране с масштабом 2
+            // он занимает то же место, что и с масштабом 1.
+            let pixels_per_point = target.window.scale_factor() as f32;
+            let screen_size = [
+                target.swapchain_bundle.surface_resolution.width as f32 / pixels_per_point,
+                target.swapchain_bundle.surface_resolution.height as f32 / pixels_per_point,
+            ];
+            text_renderer.set_scale_factor(pixels_per_point);
+            if is_main_window {
+                // Растровый текст четок в своем размере, крупная надпись рисуется через SDF.
+                // При воспроизведении показываются записанные значения, чтобы кадры совпадали с кадрами записи.
+                let (fps, frame_time) = match replay_delta_time {
+                    Some(delta_time) => (1.0 / delta_time as f64, delta_time as f64 * 1000.0),
+                    None => (
+                        frame_stats.fps(),
+                        frame_stats
+                            .get("cpu frame")
+                            .map_or(0.0, |stats| stats.average()),
+                    ),
+                };
+                text_renderer.queue(
+                    &format!(
+                        "Кадров в секунду: {:.1}\nВремя кадра: {:.2} мс",
+                        fps, frame_time
+                    ),
+                    [8.0, screen_size[1] - 48.0],
+                    TextStyle {
+                        size: 16.0,
+                        color: [1.0, 1.0, 1.0, 1.0],
+                        mode: TextMode::Bitmap,
+                    },
+                );
+                let title_style = TextStyle {
+                    size: 40.0,
+                    color: [1.0, 0.8, 0.2, 1.0],
+                    mode: TextMode::Sdf,
+                };
+                let title = "Привет, Vulkan!";
+                let title_size = text_renderer.measure(title, title_style.size);
+                text_renderer.queue(
+                    title,
+                    [screen_size[0] - title_size[0] - 16.0, 8.0],
+                    title_style,
+                );
+            } else {
+                // Во втором окне — положение камеры главного окна, которую видно отсюда со стороны.
+                let position = match camera.mode {
+                    CameraMode::Fly => camera.fly.position,
+                    CameraMode::Orbit => camera.orbit.position(),
+                };
+                text_renderer.queue(
+                    &format!(
+                        "Вид со стороны\nКамера: {:.2} {:.2} {:.2}",
+                        position.x, position.y, position.z
+                    ),
+                    [8.0, 8.0],
+                    TextStyle {
+                        size: 16.0,
+                        color: [1.0, 1.0, 1.0, 1.0],
+                        mode: TextMode::Bitmap,
+                    },
+                );
+            }
+            text_renderer.add_passes(
+                &device,
+                &mut graph,
+                backbuffer,
+                target.swapchain_bundle.surface_resolution,
+                current_frame,
+            );
+
+            // Интерфейс есть только в главном окне.
+            if is_main_window {
+                // Он рисуется последним, поверх сцены и текста, и попадает на снимки экрана.
+                let ui_output = ui_context.run(
+                    ui_input.take(target.swapchain_bundle.surface_resolution, pixels_per_point),
+                    |context| {
+                        debug_panel.show(
+                            context,
+                            &device_info,
+                            Some(&frame_stats).filter(|_| input_player.is_none()),
+                            target.swapchain_bundle.present_mode,
+                        )
+                    },
+                );
+                let ui_meshes = ui_context.tessellate(ui_output.shapes);
+                ui_renderer.add_passes(
+                    &device,
+                    &mut graph,
+                    backbuffer,
+                    target.swapchain_bundle.surface_resolution,
+                    current_frame,
+                    pixels_per_point,
+                    ui_output.textures_delta,
+                    ui_meshes,
+                );
+                // Размер окна после смены режима приходит событием Resized не на всех системах,
+                // поэтому цепочка обмена пересоздается в любом случае.
+                if debug_panel.window_mode != window_mode.mode() {
+                    window_mode.set_mode(&target.window, debug_panel.window_mode);
+                    target.swapchain_dirty = true;
+                }
+            }
+            // Режим показа меняется только вместе с цепочкой обмена. Сравнивается запрошенный режим, а не настоящий:
+            // на поверхности, где есть только FIFO, цепочка обмена иначе пересоздавалась бы каждый кадр.
+            if debug_panel.vsync != target.swapchain_bundle.vsync {
+                target.swapchain_dirty = true;
+            }
+
+            if is_main_window && screenshot_requested {
+                let format = target.swapchain_bundle.surface_format.format;
+                if !target
+                    .swapchain_bundle
+                    .image_usage
+                    .contains(vk::ImageUsageFlags::TRANSFER_SRC)
+                {
+                    println!("Screenshots are not supported: swapchain images can not be copied");
+                } else if !Screenshots::is_format_supported(format) {
+                    println!("Screenshots are not supported for {:?}", format);
+                } else {
+                    screenshots.capture(
+                        &device,
+                        &mut graph,
+                        backbuffer,
+                        target.swapchain_bundle.surface_resolution,
+                        format,
+                        in_flight_fences[current_frame],
+                    );
+                }
+                screenshot_requested = false;
+            }
+
+            let compiled_graph = graph.compile(&device, &mut graph_cache);
+            if dump_render_graph {
+                match std::fs::write("render_graph.dot", compiled_graph.to_dot()) {
+                    Ok(()) => println!("Render graph saved to render_graph.dot"),
+                    Err(error) => println!("Failed to save render graph: {}", error),
+                }
+                dump_render_graph = false;
+            }
+
+            let command_buffer = command_buffers[current_frame];
+            unsafe {
+                device
+                    .reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())
+                    .expect("Failed to reset Command Buffer!");
+
+                // Мы начинаем запись командного буфера с вызова begin_command_buffer небольшой  vk::CommandBufferBeginInfo структурой
+                // в качестве аргумента, который указывает некоторые детали использования этого конкретного командного буфера.
+                let command_buffer_begin_info = vk::CommandBufferBeginInfo::builder()
+                    // В flagsопределяет параметр , как мы будем использовать буфер команд. Доступны следующие значения:
+                    //      vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT:        Командный буфер будет перезаписан сразу после его выполнения.
+                    //      vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE:   Это дополнительный буфер команд, который будет полностью находиться в пределах одного прохода рендеринга.
+                    //      vk::CommandBufferUsageFlags::SIMULTANEOUS_USE:       Командный буфер можно повторно отправить, пока он уже ожидает выполнения.
+                    .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+                device
+                    .begin_command_buffer(command_buffer, &command_buffer_begin_info)
+                    .expect("Failed to begin recording Command Buffer at beginning!");
+            }
+
+            // Запросы пишет только главное окно: слоты запросов выбираются в begin_frame, которое вызывается
+            // только для его кадров, и кадр второго окна иначе перезаписал бы слот кадра, который еще выполняется.
+            let mut scopes: Vec<&mut dyn PassScope> = Vec::new();
+            if is_main_window {
+                if let Some(gpu_timer) = gpu_timer.as_mut() {
+                    unsafe { gpu_timer.write_frame_begin(&device, command_buffer) };
+                    scopes.push(gpu_timer);
+                }
+                if let Some(pipeline_statistics) = pipeline_statistics.as_mut() {
+                    unsafe { pipeline_statistics.reset(&device, command_buffer) };
+                    scopes.push(pipeline_statistics);
+                }
+            }
+            compiled_graph.execute(&device, command_buffer, &mut scopes);
+
+            unsafe {
+                if let (true, Some(gpu_timer)) = (is_main_window, gpu_timer.as_mut()) {
+                    gpu_timer.write_frame_end(&device, command_buffer);
+                }
+                device
+                    .end_command_buffer(command_buffer)
+                    .expect("Failed to record Command Buffer at Ending!");
+            }
+            frame_stats.record_duration("cpu record", phase_start.elapsed());
+            phase_start = Instant::now();
 
-            
             let wait_semaphores = [image_available_semaphores[current_frame]];
             let wait_stages = [vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
             let signal_semaphores = [render_finished_semaphores[current_frame]];
@@ -837,7 +1712,7 @@ fn main() {
                 // Ждем получения изображения из цепочки обменя
                 .wait_semaphores(&wait_semaphores)
                 .wait_dst_stage_mask(&wait_stages)
-                .command_buffers(std::slice::from_ref(&command_buffers[image_index as usize]))
+                .command_buffers(std::slice::from_ref(&command_buffer))
                 // Сигналезоровать о выполнении команд
                 .signal_semaphores(&signal_semaphores)
                 .build()];
@@ -857,43 +1732,86 @@ fn main() {
                     )
                     .expect("Failed to execute queue submit.");
             };
+            frame_stats.record_duration("cpu submit", phase_start.elapsed());
+            phase_start = Instant::now();
 
-            let swapchains = std::slice::from_ref(&swapchain);
+            let swapchains = std::slice::from_ref(&target.swapchain_bundle.swapchain);
 
             let present_info = vk::PresentInfoKHR::builder()
-                // Ждем получения изображения из цепочки обмена. Надо это переписать 
-                .wait_semaphores(&wait_semaphores)
-                .swapchains(&swapchains)
+                // Ждем, пока команды отрисовки будут выполнены
+                .wait_semaphores(&signal_semaphores)
+                .swapchains(swapchains)
                 .image_indices(std::slice::from_ref(&image_index));
 
             // Показываем изображение с нашим триугольником на экран
-            unsafe {
-                swapchain_loader
-                    .queue_present(present_queue, &present_info)
-                    .expect("Failed to execute queue present.");
+            let present_result =
+                unsafe { swapchain_loader.queue_present(present_queue, &present_info) };
+            match present_result {
+                Ok(false) => {}
+                Ok(true) | Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => target.swapchain_dirty = true,
+                Err(error) => panic!("Failed to execute queue present: {}", error),
+            }
+            frame_stats.record_duration("cpu present", phase_start.elapsed());
+            frame_stats.record_duration("cpu frame", frame_start.elapsed());
+
+            // Частота кадров и заголовок относятся к главному окну.
+            if is_main_window && frame_stats.end_frame() {
+                let average = |name: &str| {
+                    frame_stats
+                        .get(name)
+                        .map(|stats| stats.average())
+                        .unwrap_or_default()
+                };
+                target.window.set_title(&format!(
+                    "{} - {:.0} FPS, CPU {:.2} ms, GPU {:.2} ms",
+                    APP_NAME,
+                    frame_stats.fps(),
+                    average("cpu frame"),
+                    average("gpu frame")
+                ));
             }
 
-            current_frame = current_frame + 1;
+            current_frame += 1;
             if current_frame > MAX_FRAMES_IN_FLIGHT {
                 current_frame = 0;
             };
         }
         Event::LoopDestroyed => {
+            if let Some(input_recorder) = input_recorder.as_mut() {
+                input_recorder.finish();
+            }
             unsafe {
-                device.device_wait_idle().expect("Failed to wait device idle!");
+                device
+                    .device_wait_idle()
+                    .expect("Failed to wait device idle!");
                 device.destroy_command_pool(command_pool, None);
-                framebuffers
-                    .iter()
-                    .for_each(|&framebuffer| device.destroy_framebuffer(framebuffer, None));
+                graph_cache.destroy(&device);
+                screenshots.destroy(&device);
+                if let Some(gpu_timer) = gpu_timer.as_ref() {
+                    gpu_timer.destroy(&device);
+                }
+                if let Some(pipeline_statistics) = pipeline_statistics.as_ref() {
+                    pipeline_statistics.destroy(&device);
+                }
+                occlusion_queries.destroy(&device);
+                ui_renderer.destroy(&device);
+                text_renderer.destroy(&device);
+                sprite_batch.destroy(&device);
+                instanced_renderer.destroy(&device);
+                if let Some(gpu_culling) = gpu_culling.as_mut() {
+                    gpu_culling.destroy(&device);
+                }
+                ecs::destroy_render_resources(&mut world, &device);
                 device.destroy_pipeline(graphics_pipeline, None);
+                for pipeline in wireframe_pipeline.into_iter().chain(point_pipeline) {
+                    device.destroy_pipeline(pipeline, None);
+                }
                 device.destroy_pipeline_layout(pipeline_layout, None);
-                device.destroy_render_pass(render_pass, None);
-                swapchain_images_viev
-                    .iter()
-                    .for_each(|&image_view| device.destroy_image_view(image_view, None));
-                swapchain_loader.destroy_swapchain(swapchain, None);
+                if let Some(tool_window) = tool_window.as_ref() {
+                    tool_window.destroy(&device, &surface_loader, &swapchain_loader);
+                }
+                main_window.destroy(&device, &surface_loader, &swapchain_loader);
                 device.destroy_device(None);
-                surface_loader.destroy_surface(surface, None);
                 debug_utils_loader.destroy_debug_utils_messenger(utils_messenger, None);
                 instance.destroy_instance(None);
             };
@@ -902,6 +1820,680 @@ fn main() {
     });
 }
 
+// Передает событие ввода интерфейсу, а если он его не забрал (например, клик мимо окна панели) — подсистеме ввода.
+fn dispatch_input_event(
+    event: &WindowEvent,
+    ui_input: &mut UiInput,
+    ui_context: &egui::Context,
+    pixels_per_point: f32,
+    input: &mut Input,
+) {
+    if !ui_input.handle_event(ui_context, event, pixels_per_point) {
+        input.handle_window_event(event);
+    }
+}
+
+// Открывает второе окно рядом с главным. Конвейеры созданы под формат цепочки обмена главного окна,
+// поэтому окно, поверхность которого дает другой формат, сразу закрывается.
+#[allow(clippy::too_many_arguments)]
+fn open_tool_window(
+    event_loop: &EventLoopWindowTarget<()>,
+    entry: &ash::Entry,
+    instance: &ash::Instance,
+    device: &ash::Device,
+    surface_loader: &Surface,
+    swapchain_loader: &Swapchain,
+    p_device: vk::PhysicalDevice,
+    present_family_index: u32,
+    main_window: &WindowSurface,
+) -> Option<WindowSurface> {
+    let mut builder = winit::window::WindowBuilder::new()
+        .with_title(format!("{} - tool view", APP_NAME))
+        .with_inner_size(winit::dpi::LogicalSize::new(
+            WINDOW_WIDTH / 2.0,
+            WINDOW_HEIGHT / 2.0,
+        ));
+    if let Ok(position) = main_window.window.outer_position() {
+        let size = main_window.window.outer_size();
+        builder = builder.with_position(winit::dpi::PhysicalPosition::new(
+            position.x + size.width as i32 + 16,
+            position.y,
+        ));
+    }
+    let window = match builder.build(event_loop) {
+        Ok(window) => window,
+        Err(error) => {
+            println!("Failed to open tool window: {}", error);
+            return None;
+        }
+    };
+
+    let vsync = main_window.swapchain_bundle.vsync;
+    let tool_window = match WindowSurface::create(
+        entry,
+        instance,
+        window,
+        device,
+        surface_loader,
+        swapchain_loader,
+        p_device,
+        present_family_index,
+        vsync,
+    ) {
+        Ok(tool_window) => tool_window,
+        Err(error) => {
+            println!("Failed to open tool window: {}", error);
+            return None;
+        }
+    };
+    let format = tool_window.swapchain_bundle.surface_format;
+    if format != main_window.swapchain_bundle.surface_format {
+        println!(
+            "Failed to open tool window: surface format {:?} differs from the main window",
+            format.format
+        );
+        unsafe { tool_window.destroy(device, surface_loader, swapchain_loader) };
+        return None;
+    }
+    Some(tool_window)
+}
+
+// Закрывает дополнительное окно. Его цепочка обмена и фреймбуферы могут еще использоваться кадрами в полете,
+// поэтому сначала дожидаемся устройства.
+unsafe fn close_window(
+    device: &ash::Device,
+    graph_cache: &mut RenderGraphCache,
+    surface_loader: &Surface,
+    swapchain_loader: &Swapchain,
+    window: WindowSurface,
+) {
+    device
+        .device_wait_idle()
+        .expect("Failed to wait device idle!");
+    graph_cache.release_framebuffers(device);
+    window.destroy(device, surface_loader, swapchain_loader);
+}
+
+// Значение аргумента командной строки вида --name <значение>.
+fn argument_value(name: &str) -> Option<String> {
+    std::env::args().skip_while(|arg| arg != name).nth(1)
+}
+
+// Спираль из нескольких тысяч вращающихся спрайтов двух текстур на двух слоях.
+fn draw_sprite_demo(
+    sprite_batch: &mut SpriteBatch,
+    textures: &[SpriteTexture; 2],
+    extent: vk::Extent2D,
+    time: f32,
+) {
+    const SPRITE_COUNT: usize = 4000;
+    let center = [extent.width as f32 / 2.0, extent.height as f32 / 2.0];
+    let radius = center[0].min(center[1]);
+    for index in 0..SPRITE_COUNT {
+        let t = index as f32 / SPRITE_COUNT as f32;
+        let angle = t * 40.0 + time * 0.5;
+        let distance = t * radius;
+        let mut sprite = Sprite::new(
+            textures[index % 2],
+            [
+                center[0] + angle.cos() * distance,
+                center[1] + angle.sin() * distance,
+            ],
+            [6.0 + 6.0 * t, 6.0 + 6.0 * t],
+        );
+        sprite.rotation = angle + time * 2.0;
+        sprite.color = [t, 1.0 - t, 0.5 + 0.5 * (time + t * 6.0).sin(), 0.9];
+        sprite.layer = (index % 2) as i32;
+        sprite_batch.draw(sprite);
+    }
+}
+
+// Волнистый пол из кубов под треугольником. Каждый кубик вращается, цвет зависит от положения в решетке.
+fn draw_instancing_demo(renderer: &mut InstancedRenderer, count: usize, time: f32) {
+    const SPACING: f32 = 0.1;
+    let side = (count as f32).sqrt().ceil() as usize;
+    let half_size = (side - 1) as f32 * SPACING / 2.0;
+    for index in 0..count {
+        let (column, row) = ((index % side) as f32, (index / side) as f32);
+        let x = column * SPACING - half_size;
+        let z = row * SPACING - half_size;
+        let wave = (x * 2.0 + time).sin() * (z * 2.0 + time * 0.7).cos();
+        let rotation = glam::Quat::from_rotation_y(time + (column + row) * 0.1);
+        renderer.draw(InstanceData {
+            model: glam::Mat4::from_scale_rotation_translation(
+                glam::Vec3::splat(SPACING * 0.6),
+                rotation,
+                glam::Vec3::new(x, -0.8 + wave * 0.1, z),
+            )
+            .to_cols_array(),
+            color: [
+                column / side as f32,
+                0.5 + 0.5 * wave,
+                row / side as f32,
+                1.0,
+            ],
+        });
+    }
+}
+
+// Солнце, планета и луна над треугольником. Узлы орбит не рисуются: их поворот уносит
+// за собой всех потомков, поэтому луна кружится вокруг планеты, а вместе с ней и вокруг солнца.
+fn create_scene_demo(cube: MeshHandle) -> Scene {
+    let mut scene = Scene::new();
+    let sun_material = scene.add_material(Material::from_color([1.0, 0.8, 0.2, 1.0]));
+    // Гладкая планета дает заметный блик от солнца.
+    let planet_material = scene.add_material(Material {
+        roughness: 0.3,
+        ..Material::from_color([0.2, 0.5, 1.0, 1.0])
+    });
+    let moon_material = scene.add_material(Material::from_color([0.7, 0.7, 0.7, 1.0]));
+
+    let system = scene.add_node(
+        "system",
+        None,
+        Transform::from_translation(glam::Vec3::new(0.0, 1.0, -1.5)),
+    );
+    let sun = scene.add_node(
+        "sun",
+        Some(system),
+        Transform {
+            scale: glam::Vec3::splat(0.4),
+            ..Transform::IDENTITY
+        },
+    );
+    scene.set_mesh(sun, cube, sun_material);
+    // Солнце освещает планету и луну, а слабый свет неба падает на всю сцену сверху.
+    scene.set_light(
+        sun,
+        Light {
+            kind: LightKind::Point,
+            color: [1.0, 0.85, 0.6],
+            intensity: 1.5,
+            range: Some(6.0),
+        },
+    );
+    let sky = scene.add_node(
+        "sky light",
+        None,
+        Transform {
+            rotation: glam::Quat::from_rotation_x(-1.0),
+            ..Transform::IDENTITY
+        },
+    );
+    scene.set_light(
+        sky,
+        Light {
+            kind: LightKind::Directional,
+            color: [0.8, 0.9, 1.0],
+            intensity: 0.6,
+            range: None,
+        },
+    );
+    let planet_orbit = scene.add_node("planet orbit", Some(system), Transform::IDENTITY);
+    let planet = scene.add_node(
+        "planet",
+        Some(planet_orbit),
+        Transform::from_translation(glam::Vec3::new(0.8, 0.0, 0.0)),
+    );
+    let planet_body = scene.add_node(
+        "planet body",
+        Some(planet),
+        Transform {
+            scale: glam::Vec3::splat(0.15),
+            ..Transform::IDENTITY
+        },
+    );
+    scene.set_mesh(planet_body, cube, planet_material);
+    let moon_orbit = scene.add_node("moon orbit", Some(planet), Transform::IDENTITY);
+    let moon = scene.add_node(
+        "moon",
+        Some(moon_orbit),
+        Transform {
+            translation: glam::Vec3::new(0.25, 0.0, 0.0),
+            scale: glam::Vec3::splat(0.06),
+            ..Transform::IDENTITY
+        },
+    );
+    scene.set_mesh(moon, cube, moon_material);
+    scene.update_world_matrices();
+    scene
+}
+
+const FLASHLIGHT: Light = Light {
+    kind: LightKind::Spot {
+        inner_cone_angle: 0.2,
+        outer_cone_angle: 0.35,
+    },
+    color: [1.0, 1.0, 0.9],
+    intensity: 3.0,
+    range: Some(10.0),
+};
+
+fn animate_scene_demo(scene: &mut Scene, time: f32) {
+    let rotations = [
+        ("sun", glam::Quat::from_rotation_y(time * 0.3)),
+        ("planet orbit", glam::Quat::from_rotation_y(time * 0.8)),
+        ("planet body", glam::Quat::from_rotation_y(time * 2.0)),
+        ("moon orbit", glam::Quat::from_rotation_z(time * 3.0)),
+    ];
+    for (name, rotation) in rotations.iter().copied() {
+        if let Some(node) = scene.find(name) {
+            let local = scene.local_transform(node);
+            scene.set_local_transform(node, Transform { rotation, ..local });
+        }
+    }
+}
+
+// Скелетные сетки с общими клипами: тентакль демонстрации или анимации модели glTF.
+struct SkeletalAnimation {
+    skins: Vec<(NodeId, Skin)>,
+    clips: Vec<AnimationClip>,
+    // Поза до анимации. Свойства, которые клип не анимирует, берутся из нее.
+    bind_pose: Pose,
+}
+
+impl SkeletalAnimation {
+    fn new(scene: &Scene, skins: Vec<(NodeId, Skin)>, clips: Vec<AnimationClip>) -> Self {
+        let bind_pose = Pose::capture(scene, clips.iter().flat_map(AnimationClip::nodes));
+        SkeletalAnimation {
+            skins,
+            clips,
+            bind_pose,
+        }
+    }
+
+    // Первый клип проигрывается по кругу, а если есть второй, поза плавно перетекает между ними.
+    fn pose(&self, scene: &mut Scene, time: f32) {
+        let sample = |clip: &AnimationClip| {
+            let mut pose = self.bind_pose.clone();
+            clip.sample(time % clip.duration().max(f32::EPSILON), &mut pose);
+            pose
+        };
+        let pose = match self.clips.as_slice() {
+            [] => return,
+            [clip] => sample(clip),
+            [first, second, ..] => {
+                sample(first).blend(&sample(second), (time * 0.4).sin() * 0.5 + 0.5)
+            }
+        };
+        pose.apply(scene);
+    }
+
+    // Загружает палитры скинов. Вызывается после пересчета мировых матриц.
+    fn update_skins(&self, scene: &Scene, renderer: &mut SceneRenderer) {
+        for (node, skin) in &self.skins {
+            renderer.set_joint_matrices(skin.handle, &skin.joint_matrices(scene, *node));
+        }
+    }
+}
+
+// Тентакль слева от солнца: вытянутый брусок на цепочке суставов. Клип sway раскачивает его
+// линейной интерполяцией, клип twist закручивает кубическим сплайном, а кадр смешивает оба.
+fn add_tentacle_demo(
+    device: &ash::Device,
+    renderer: &mut SceneRenderer,
+    scene: &mut Scene,
+) -> SkeletalAnimation {
+    const JOINTS: usize = 6;
+    const LEVELS: usize = 24;
+    const SEGMENT: f32 = 0.2;
+    const HALF_WIDTH: f32 = 0.05;
+
+    // Каждый уровень бруска привязан к двум соседним суставам с весами по высоте.
+    let joints_at = |y: f32| {
+        let position = y / SEGMENT;
+        let joint = (position.floor() as usize).min(JOINTS - 1);
+        let next = (joint + 1).min(JOINTS - 1);
+        let t = (position - joint as f32).min(1.0);
+        VertexJoints {
+            joints: [joint as u32, next as u32, 0, 0],
+            weights: [1.0 - t, t, 0.0, 0.0],
+        }
+    };
+    let height = SEGMENT * JOINTS as f32;
+    let mut mesh = MeshData::default();
+    let sides = [glam::Vec3::X, glam::Vec3::Z, -glam::Vec3::X, -glam::Vec3::Z];
+    for normal in sides.iter().copied() {
+        let right = glam::Vec3::Y.cross(normal);
+        let first = mesh.vertices.len() as u32;
+        for level in 0..=LEVELS {
+            let y = height * level as f32 / LEVELS as f32;
+            for side in [-1.0, 1.0] {
+                let position = (normal + right * side) * HALF_WIDTH + glam::Vec3::Y * y;
+                mesh.vertices.push(MeshVertex {
+                    position: position.to_array(),
+                    normal: normal.to_array(),
+                    uv: [(side + 1.0) / 2.0, 1.0 - y / height],
+                });
+                mesh.joints.push(joints_at(y));
+            }
+        }
+        for level in 0..LEVELS as u32 {
+            let bottom = first + level * 2;
+            let top = bottom + 2;
+            mesh.indices
+                .extend([bottom, bottom + 1, top + 1, bottom, top + 1, top]);
+        }
+    }
+    let cap = mesh.vertices.len() as u32;
+    for (x, z) in [(-1.0, 1.0), (1.0, 1.0), (1.0, -1.0), (-1.0, -1.0)] {
+        mesh.vertices.push(MeshVertex {
+            position: [x * HALF_WIDTH, height, z * HALF_WIDTH],
+            normal: [0.0, 1.0, 0.0],
+            uv: [0.5, 0.0],
+        });
+        mesh.joints.push(joints_at(height));
+    }
+    mesh.indices
+        .extend([cap, cap + 1, cap + 2, cap, cap + 2, cap + 3]);
+
+    let root = scene.add_node(
+        "tentacle",
+        None,
+        Transform::from_translation(glam::Vec3::new(-1.3, -0.6, -1.5)),
+    );
+    let mut joints = Vec::new();
+    for index in 0..JOINTS {
+        let offset = if index == 0 { 0.0 } else { SEGMENT };
+        let joint = scene.add_node(
+            &format!("tentacle joint {}", index),
+            Some(joints.last().copied().unwrap_or(root)),
+            Transform::from_translation(glam::Vec3::new(0.0, offset, 0.0)),
+        );
+        joints.push(joint);
+    }
+    let body = scene.add_node("tentacle body", Some(root), Transform::IDENTITY);
+    let material = scene.add_material(Material::from_color([0.6, 0.2, 0.8, 1.0]));
+    scene.set_mesh(body, renderer.create_mesh(device, &mesh), material);
+    let skin = Skin {
+        inverse_bind_matrices: (0..JOINTS)
+            .map(|index| {
+                glam::Mat4::from_translation(glam::Vec3::new(0.0, -SEGMENT * index as f32, 0.0))
+            })
+            .collect(),
+        joints: joints.clone(),
+        handle: renderer.create_skin(JOINTS),
+    };
+    scene.set_skin(body, skin.handle);
+
+    let sway = AnimationClip {
+        name: "sway".to_owned(),
+        channels: joints
+            .iter()
+            .map(|&node| Channel {
+                node,
+                interpolation: Interpolation::Linear,
+                times: vec![0.0, 1.0, 2.0, 3.0, 4.0],
+                keyframes: Keyframes::Rotation(
+                    [0.0, 0.3, 0.0, -0.3, 0.0]
+                        .iter()
+                        .map(|&angle| glam::Quat::from_rotation_z(angle))
+                        .collect(),
+                ),
+            })
+            .collect(),
+    };
+    // У сплайна касательные нулевые, и поворот плавно замирает в каждом ключе.
+    let zero = glam::Quat::from_xyzw(0.0, 0.0, 0.0, 0.0);
+    let twist = AnimationClip {
+        name: "twist".to_owned(),
+        channels: joints
+            .iter()
+            .map(|&node| Channel {
+                node,
+                interpolation: Interpolation::CubicSpline,
+                times: vec![0.0, 1.5, 3.0],
+                keyframes: Keyframes::Rotation(
+                    [0.4, -0.4, 0.4]
+                        .iter()
+                        .flat_map(|&angle| [zero, glam::Quat::from_rotation_x(angle), zero])
+                        .collect(),
+                ),
+            })
+            .collect(),
+    };
+    SkeletalAnimation::new(scene, vec![(body, skin)], vec![sway, twist])
+}
+
+// Добавляет модель в сцену узлом model с дочерним узлом на каждую сетку. Модель уменьшается или
+// увеличивается до размера около единицы и ставится центром в начало координат.
+fn add_obj_model(
+    device: &ash::Device,
+    renderer: &mut SceneRenderer,
+    scene: &mut Scene,
+    model: &ObjModel,
+    max_image_dimension: u32,
+) {
+    // Материалы часто ссылаются на один и тот же файл текстуры, а загружается он один раз.
+    let mut texture_paths = Vec::new();
+    for path in model
+        .materials
+        .iter()
+        .filter_map(|material| material.diffuse_texture.as_ref())
+    {
+        if !texture_paths.contains(&path) {
+            texture_paths.push(path);
+        }
+    }
+    let mut textures: HashMap<std::path::PathBuf, TextureHandle> = HashMap::new();
+    if texture_paths.len() > renderer.remaining_textures() {
+        println!(
+            "{} textures do not fit into {} free texture slots, materials are untextured",
+            texture_paths.len(),
+            renderer.remaining_textures()
+        );
+    } else {
+        for path in texture_paths {
+            let image = TextureImage::load_png(path).and_then(|image| {
+                if image.width == 0 || image.height == 0 {
+                    Err(format!("{}: image is empty", path.display()))
+                } else if image.width.max(image.height) > max_image_dimension {
+                    Err(format!(
+                        "{}: image size {}x{} exceeds {} pixels supported by the device",
+                        path.display(),
+                        image.width,
+                        image.height,
+                        max_image_dimension
+                    ))
+                } else {
+                    Ok(image)
+                }
+            });
+            match image {
+                Ok(image) => {
+                    textures.insert(path.clone(), renderer.create_texture(device, &image));
+                }
+                Err(error) => println!("Failed to load texture {}", error),
+            }
+        }
+    }
+    let materials = model
+        .materials
+        .iter()
+        .map(|material| {
+            let [r, g, b] = material.diffuse;
+            scene.add_material(Material {
+                base_color_texture: material
+                    .diffuse_texture
+                    .as_ref()
+                    .and_then(|path| textures.get(path).copied()),
+                ..Material::from_color([r, g, b, material.dissolve])
+            })
+        })
+        .collect::<Vec<_>>();
+    let default_material = scene.add_material(Material::from_color([0.8, 0.8, 0.8, 1.0]));
+
+    let bounds = model
+        .meshes
+        .iter()
+        .filter_map(|mesh| mesh.mesh.bounds())
+        .reduce(|(min_a, max_a), (min_b, max_b)| (min_a.min(min_b), max_a.max(max_b)));
+    let (center, size) = bounds.map_or((glam::Vec3::ZERO, 1.0), |(min, max)| {
+        (
+            (min + max) / 2.0,
+            (max - min).max_element().max(f32::EPSILON),
+        )
+    });
+    let scale = 1.0 / size;
+    let root = scene.add_node(
+        "model",
+        None,
+        Transform {
+            translation: -center * scale,
+            scale: glam::Vec3::splat(scale),
+            ..Transform::IDENTITY
+        },
+    );
+    for mesh in &model.meshes {
+        let node = scene.add_node(&mesh.name, Some(root), Transform::IDENTITY);
+        let material = mesh
+            .material
+            .map_or(default_material, |material| materials[material]);
+        scene.set_mesh(node, renderer.create_mesh(device, &mesh.mesh), material);
+    }
+    println!(
+        "Loaded model: {} meshes, {} materials",
+        model.meshes.len(),
+        model.materials.len()
+    );
+}
+
+// Время сцены для систем демонстрации ECS.
+#[derive(Resource)]
+struct DemoTime(f32);
+
+// Кубы кольца вращаются вокруг своей оси со своей скоростью.
+#[derive(Component)]
+struct Spin(f32);
+
+// Кольцо кубов за треугольником, от синего к красному, и источник света в его центре.
+fn spawn_ecs_demo(world: &mut World, cube: MeshHandle) {
+    const COUNT: usize = 24;
+    for index in 0..COUNT {
+        let t = index as f32 / COUNT as f32;
+        let angle = t * std::f32::consts::TAU;
+        world.spawn((
+            Transform {
+                translation: glam::Vec3::new(angle.cos() * 2.0, angle.sin() * 2.0, -2.5),
+                scale: glam::Vec3::splat(0.15),
+                ..Transform::IDENTITY
+            },
+            cube,
+            Material::from_color([t, 0.3, 1.0 - t, 1.0]),
+            Spin(1.0 + t * 2.0),
+        ));
+    }
+    // Кольцо освещается точечным светом из своего центра.
+    world.spawn((
+        Transform::from_translation(glam::Vec3::new(0.0, 0.0, -2.0)),
+        Light {
+            kind: LightKind::Point,
+            color: [1.0, 1.0, 1.0],
+            intensity: 4.0,
+            range: None,
+        },
+    ));
+}
+
+fn animate_ecs_demo(time: Res<DemoTime>, mut cubes: Query<(&mut Transform, &Spin)>) {
+    for (mut transform, spin) in cubes.iter_mut() {
+        transform.rotation = glam::Quat::from_euler(
+            glam::EulerRot::XYZ,
+            time.0 * spin.0,
+            time.0 * spin.0 * 0.5,
+            0.0,
+        );
+    }
+}
+
+// Большое неподвижное поле кубов вокруг начала координат, из которого камера видит только часть.
+fn culling_demo_objects(count: usize) -> Vec<InstanceData> {
+    const SPACING: f32 = 0.5;
+    let side = (count as f32).sqrt().ceil() as usize;
+    let half_size = (side - 1) as f32 * SPACING / 2.0;
+    (0..count)
+        .map(|index| {
+            let (column, row) = ((index % side) as f32, (index / side) as f32);
+            let x = column * SPACING - half_size;
+            let z = row * SPACING - half_size;
+            let height = 0.5 + 0.5 * (x * 0.3).sin() * (z * 0.2).cos();
+            InstanceData {
+                model: glam::Mat4::from_scale_rotation_translation(
+                    glam::Vec3::new(0.3, 0.3 + height, 0.3),
+                    glam::Quat::IDENTITY,
+                    glam::Vec3::new(x, -1.5, z),
+                )
+                .to_cols_array(),
+                color: [height, 0.4, 1.0 - height, 1.0],
+            }
+        })
+        .collect()
+}
+
+// Push-константы треугольника. Раскладка совпадает с блоком PushConstants в shader.vert и shader.frag.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ScenePushConstants {
+    // Произведение проекции, вида и матрицы модели объекта, у каждой отрисовки свое.
+    model_view_projection: [f32; 16],
+    // Цвет, которым закрашиваются фрагменты. При нулевой альфе остается цвет вершин.
+    overlay_color: [f32; 4],
+    point_size: f32,
+    // Время сцены в секундах.
+    time: f32,
+}
+
+unsafe impl PushConstants for ScenePushConstants {
+    const STAGES: vk::ShaderStageFlags = vk::ShaderStageFlags::from_raw(
+        vk::ShaderStageFlags::VERTEX.as_raw() | vk::ShaderStageFlags::FRAGMENT.as_raw(),
+    );
+}
+
+// Рисует треугольник в уже начатом графом проходе рендеринга. Viewport и scissor задаются здесь,
+// из текущего размера цепочки обмена, поэтому при изменении размера окна конвейер пересоздавать не нужно.
+#[allow(clippy::too_many_arguments)]
+unsafe fn draw_triangle(
+    device: &ash::Device,
+    command_buffer: vk::CommandBuffer,
+    graphics_pipeline: vk::Pipeline,
+    pipeline_layout: vk::PipelineLayout,
+    extent: vk::Extent2D,
+    viewport_layout: ViewportLayout,
+    camera: &Camera,
+    model: glam::Mat4,
+    push_constants: ScenePushConstants,
+    mut occlusion_queries: Option<&mut OcclusionQueries>,
+) {
+    device.cmd_bind_pipeline(
+        command_buffer,
+        vk::PipelineBindPoint::GRAPHICS,
+        graphics_pipeline,
+    );
+
+    // Для каждой области split-screen задаем свои viewport и scissor и повторяем отрисовку.
+    // Запрос видимости с номером области показывает, сколько образцов треугольника попало в нее.
+    // Второе окно запросов не делает, чтобы его кадры не смешивались с результатами главного.
+    for (index, (viewport, scissor)) in viewport_layout.regions(extent).into_iter().enumerate() {
+        device.cmd_set_viewport(command_buffer, 0, std::slice::from_ref(&viewport));
+        device.cmd_set_scissor(command_buffer, 0, std::slice::from_ref(&scissor));
+        // Проекция считается для пропорций своей области, иначе в split-screen изображение растянется.
+        let push_constants = ScenePushConstants {
+            model_view_projection: (camera.view_projection(viewport.width / viewport.height)
+                * model)
+                .to_cols_array(),
+            ..push_constants
+        };
+        push_constants.push(device, command_buffer, pipeline_layout);
+        let queried = occlusion_queries.as_mut().is_some_and(|occlusion_queries| {
+            occlusion_queries.begin(device, command_buffer, index as u32)
+        });
+        device.cmd_draw(command_buffer, 3, 1, 0, 0);
+        if let (true, Some(occlusion_queries)) = (queried, occlusion_queries.as_mut()) {
+            occlusion_queries.end(device, command_buffer);
+        }
+    }
+}
+
 // Отчет об ошибках — мощный инструмент,
 // который позволяет получать информацию от слоев,
 // используя функцию обратного вызова (callback).
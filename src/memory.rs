@@ -0,0 +1,112 @@
+use ash::vk;
+
+// Видеокарта предлагает несколько типов памяти, отличающихся допустимыми операциями и производительностью.
+// type_bits берется из vk::MemoryRequirements и содержит по биту на каждый подходящий ресурсу тип памяти,
+// из них выбираем первый, обладающий всеми запрошенными свойствами.
+pub fn find_memory_type(
+    memory_properties: &vk::PhysicalDeviceMemoryProperties,
+    type_bits: u32,
+    flags: vk::MemoryPropertyFlags,
+) -> Option<u32> {
+    memory_properties.memory_types[..memory_properties.memory_type_count as usize]
+        .iter()
+        .enumerate()
+        .find(|(index, memory_type)| {
+            type_bits & (1 << index) != 0 && memory_type.property_flags.contains(flags)
+        })
+        .map(|(index, _)| index as u32)
+}
+
+// Буфер вместе с выделенной под него памятью.
+pub struct Buffer {
+    pub buffer: vk::Buffer,
+    pub memory: vk::DeviceMemory,
+    pub size: vk::DeviceSize,
+}
+
+impl Buffer {
+    // memory_flags перечисляются в порядке предпочтения: берется первый набор свойств,
+    // для которого нашелся подходящий тип памяти.
+    pub fn new(
+        device: &ash::Device,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+        memory_flags: &[vk::MemoryPropertyFlags],
+    ) -> Self {
+        let buffer_create_info = vk::BufferCreateInfo::builder()
+            .size(size)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+        unsafe {
+            let buffer = device
+                .create_buffer(&buffer_create_info, None)
+                .expect("Failed to create buffer!");
+            let requirements = device.get_buffer_memory_requirements(buffer);
+            let memory_type_index = memory_flags
+                .iter()
+                .find_map(|&flags| {
+                    find_memory_type(memory_properties, requirements.memory_type_bits, flags)
+                })
+                .expect("No suitable memory type for buffer!");
+
+            let allocate_info = vk::MemoryAllocateInfo::builder()
+                .allocation_size(requirements.size)
+                .memory_type_index(memory_type_index);
+            let memory = device
+                .allocate_memory(&allocate_info, None)
+                .expect("Failed to allocate buffer memory!");
+            device
+                .bind_buffer_memory(buffer, memory, 0)
+                .expect("Failed to bind buffer memory!");
+
+            Buffer {
+                buffer,
+                memory,
+                size,
+            }
+        }
+    }
+
+    // Буфер с неизменяемыми данными, которые записываются один раз при создании. Лучше всего для него
+    // подходит видеопамять, доступная процессору, а если такой нет — обычная память, видимая процессору.
+    pub fn upload(
+        device: &ash::Device,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        data: &[u8],
+        usage: vk::BufferUsageFlags,
+    ) -> Self {
+        // Буфер нулевого размера создать нельзя.
+        let buffer = Buffer::new(
+            device,
+            memory_properties,
+            data.len().max(1) as vk::DeviceSize,
+            usage,
+            &[
+                vk::MemoryPropertyFlags::DEVICE_LOCAL
+                    | vk::MemoryPropertyFlags::HOST_VISIBLE
+                    | vk::MemoryPropertyFlags::HOST_COHERENT,
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            ],
+        );
+        unsafe {
+            let mapped = device
+                .map_memory(buffer.memory, 0, buffer.size, vk::MemoryMapFlags::empty())
+                .expect("Failed to map buffer memory!");
+            std::ptr::copy_nonoverlapping(data.as_ptr(), mapped as *mut u8, data.len());
+            device.unmap_memory(buffer.memory);
+        }
+        buffer
+    }
+
+    pub unsafe fn destroy(&self, device: &ash::Device) {
+        device.destroy_buffer(self.buffer, None);
+        device.free_memory(self.memory, None);
+    }
+}
+
+// Байтовое представление среза для копирования в память видеокарты.
+pub fn as_bytes<T: Copy>(data: &[T]) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(data.as_ptr() as *const u8, std::mem::size_of_val(data)) }
+}
@@ -0,0 +1,431 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::scene::{MeshData, MeshVertex};
+
+// Загрузка моделей Wavefront OBJ с материалами из MTL. Поддерживаются позиции, текстурные координаты,
+// нормали, многоугольники (разбиваются веером на треугольники), объекты, группы и материалы с цветом,
+// прозрачностью и диффузной текстурой. Кривые, поверхности и прочие редкие команды пропускаются.
+//
+// В OBJ у каждого угла грани свои номера позиции, текстурной координаты и нормали. Одинаковые тройки
+// номеров становятся одной вершиной, поэтому сетки получаются индексированными. Вершинам, у которых
+// в файле нет нормали, она вычисляется сглаживанием.
+
+// Материал из MTL.
+#[derive(Debug, Clone)]
+pub struct ObjMaterial {
+    pub name: String,
+    // Диффузный цвет Kd, линейный RGB.
+    pub diffuse: [f32; 3],
+    // Непрозрачность d: 1 — непрозрачный.
+    pub dissolve: f32,
+    // Файл диффузной текстуры map_Kd относительно текущего каталога.
+    pub diffuse_texture: Option<PathBuf>,
+}
+
+impl ObjMaterial {
+    fn new(name: &str) -> Self {
+        ObjMaterial {
+            name: name.to_owned(),
+            diffuse: [0.8, 0.8, 0.8],
+            dissolve: 1.0,
+            diffuse_texture: None,
+        }
+    }
+}
+
+// Часть модели с одним материалом: все грани одного объекта или группы после одного usemtl.
+#[derive(Debug, Clone)]
+pub struct ObjMesh {
+    pub name: String,
+    pub mesh: MeshData,
+    // Номер в ObjModel::materials.
+    pub material: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ObjModel {
+    pub meshes: Vec<ObjMesh>,
+    pub materials: Vec<ObjMaterial>,
+}
+
+// Номера позиции, текстурной координаты и нормали одного угла грани, начиная с нуля.
+type Corner = (usize, Option<usize>, Option<usize>);
+
+// Сетка, которая сейчас собирается из граней.
+struct MeshBuilder {
+    name: String,
+    material: Option<usize>,
+    mesh: MeshData,
+    vertices: HashMap<Corner, u32>,
+    // Есть ли у вершины нормаль из файла, по одному значению на вершину сетки.
+    explicit_normals: Vec<bool>,
+}
+
+impl MeshBuilder {
+    fn new(name: &str, material: Option<usize>) -> Self {
+        MeshBuilder {
+            name: name.to_owned(),
+            material,
+            mesh: MeshData::default(),
+            vertices: HashMap::new(),
+            explicit_normals: Vec::new(),
+        }
+    }
+
+    fn finish(mut self) -> Option<ObjMesh> {
+        if self.mesh.indices.is_empty() {
+            return None;
+        }
+        // Сглаженные нормали вычисляются по всей сетке, но заменяют только отсутствующие в файле.
+        if self.explicit_normals.contains(&false) {
+            let explicit = self
+                .mesh
+                .vertices
+                .iter()
+                .map(|vertex| vertex.normal)
+                .collect::<Vec<_>>();
+            self.mesh.compute_normals();
+            for ((vertex, normal), &is_explicit) in self
+                .mesh
+                .vertices
+                .iter_mut()
+                .zip(explicit)
+                .zip(&self.explicit_normals)
+            {
+                if is_explicit {
+                    vertex.normal = normal;
+                }
+            }
+        }
+        Some(ObjMesh {
+            name: self.name,
+            mesh: self.mesh,
+            material: self.material,
+        })
+    }
+}
+
+impl ObjModel {
+    // Читает OBJ и все MTL, на которые он ссылается. Пути в файлах считаются от каталога файла.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|error| format!("{}: {}", path.display(), error))?;
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+        Self::parse(&text, |name| {
+            let mtl_path = directory.join(name);
+            let text = std::fs::read_to_string(&mtl_path)
+                .map_err(|error| format!("{}: {}", mtl_path.display(), error))?;
+            parse_mtl(&text, mtl_path.parent().unwrap_or_else(|| Path::new("")))
+                .map_err(|error| format!("{}:{}", mtl_path.display(), error))
+        })
+        .map_err(|error| format!("{}:{}", path.display(), error))
+    }
+
+    // Разбирает текст OBJ. load_mtl читает библиотеку материалов по имени из mtllib.
+    // Ошибка начинается с номера строки.
+    pub fn parse(
+        text: &str,
+        mut load_mtl: impl FnMut(&str) -> Result<Vec<ObjMaterial>, String>,
+    ) -> Result<Self, String> {
+        let mut positions: Vec<[f32; 3]> = Vec::new();
+        let mut uvs: Vec<[f32; 2]> = Vec::new();
+        let mut normals: Vec<[f32; 3]> = Vec::new();
+        let mut model = ObjModel::default();
+        let mut group_name = String::new();
+        let mut current = MeshBuilder::new("", None);
+
+        for (number, line) in text.lines().enumerate() {
+            let error = |message: String| format!("{}: {}", number + 1, message);
+            let line = line.split('#').next().unwrap_or("").trim();
+            let mut fields = line.split_whitespace();
+            let command = match fields.next() {
+                Some(command) => command,
+                None => continue,
+            };
+            let arguments = fields.collect::<Vec<_>>();
+            match command {
+                "v" => positions.push(parse_floats(&arguments).map_err(error)?),
+                "vt" => {
+                    // Начало текстурных координат в OBJ в левом нижнем углу, а в Vulkan — в левом верхнем.
+                    let [u, v] = parse_floats::<2>(&arguments).map_err(error)?;
+                    uvs.push([u, 1.0 - v]);
+                }
+                "vn" => normals.push(parse_floats(&arguments).map_err(error)?),
+                "f" => {
+                    if arguments.len() < 3 {
+                        return Err(error("face has less than 3 vertices".to_owned()));
+                    }
+                    let corners = arguments
+                        .iter()
+                        .map(|corner| {
+                            parse_corner(corner, positions.len(), uvs.len(), normals.len())
+                        })
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(error)?;
+                    let indices = corners
+                        .iter()
+                        .map(|&corner| add_vertex(&mut current, corner, &positions, &uvs, &normals))
+                        .collect::<Vec<_>>();
+                    for index in 1..indices.len() - 1 {
+                        current.mesh.indices.extend([
+                            indices[0],
+                            indices[index],
+                            indices[index + 1],
+                        ]);
+                    }
+                }
+                "o" | "g" => {
+                    group_name = arguments.join(" ");
+                    let material = current.material;
+                    let finished =
+                        std::mem::replace(&mut current, MeshBuilder::new(&group_name, material));
+                    model.meshes.extend(finished.finish());
+                }
+                "usemtl" => {
+                    let name = arguments.join(" ");
+                    let material = model
+                        .materials
+                        .iter()
+                        .position(|material| material.name == name);
+                    if material.is_none() {
+                        println!("OBJ material {} is not defined, using default", name);
+                    }
+                    let finished =
+                        std::mem::replace(&mut current, MeshBuilder::new(&group_name, material));
+                    model.meshes.extend(finished.finish());
+                }
+                "mtllib" => {
+                    for name in arguments {
+                        model.materials.extend(load_mtl(name).map_err(error)?);
+                    }
+                }
+                _ => {}
+            }
+        }
+        model.meshes.extend(current.finish());
+        Ok(model)
+    }
+}
+
+fn add_vertex(
+    builder: &mut MeshBuilder,
+    corner: Corner,
+    positions: &[[f32; 3]],
+    uvs: &[[f32; 2]],
+    normals: &[[f32; 3]],
+) -> u32 {
+    if let Some(&index) = builder.vertices.get(&corner) {
+        return index;
+    }
+    let (position, uv, normal) = corner;
+    builder.explicit_normals.push(normal.is_some());
+    let index = builder.mesh.vertices.len() as u32;
+    builder.mesh.vertices.push(MeshVertex {
+        position: positions[position],
+        normal: normal.map_or([0.0; 3], |normal| normals[normal]),
+        uv: uv.map_or([0.0; 2], |uv| uvs[uv]),
+    });
+    builder.vertices.insert(corner, index);
+    index
+}
+
+// Угол грани: v, v/vt, v//vn или v/vt/vn. Номера начинаются с единицы, отрицательные считаются с конца.
+fn parse_corner(
+    text: &str,
+    position_count: usize,
+    uv_count: usize,
+    normal_count: usize,
+) -> Result<Corner, String> {
+    let mut parts = text.split('/');
+    let index = |part: Option<&str>, count: usize| -> Result<Option<usize>, String> {
+        let part = match part {
+            Some(part) if !part.is_empty() => part,
+            _ => return Ok(None),
+        };
+        let number = part
+            .parse::<i64>()
+            .map_err(|_| format!("invalid index {}", part))?;
+        let index = if number < 0 {
+            count as i64 + number
+        } else {
+            number - 1
+        };
+        if index < 0 || index >= count as i64 {
+            return Err(format!("index {} is out of range", number));
+        }
+        Ok(Some(index as usize))
+    };
+    let position = index(parts.next(), position_count)?
+        .ok_or_else(|| format!("face vertex {} has no position", text))?;
+    let uv = index(parts.next(), uv_count)?;
+    let normal = index(parts.next(), normal_count)?;
+    Ok((position, uv, normal))
+}
+
+// Первые N чисел строки. Лишние числа, как w у позиций, пропускаются.
+fn parse_floats<const N: usize>(arguments: &[&str]) -> Result<[f32; N], String> {
+    if arguments.len() < N {
+        return Err(format!("expected {} numbers", N));
+    }
+    let mut values = [0.0; N];
+    for (value, argument) in values.iter_mut().zip(arguments) {
+        *value = argument
+            .parse()
+            .map_err(|_| format!("invalid number {}", argument))?;
+    }
+    Ok(values)
+}
+
+// Разбирает текст MTL. Пути текстур считаются от directory. Ошибка начинается с номера строки.
+pub fn parse_mtl(text: &str, directory: &Path) -> Result<Vec<ObjMaterial>, String> {
+    let mut materials: Vec<ObjMaterial> = Vec::new();
+    for (number, line) in text.lines().enumerate() {
+        let error = |message: String| format!("{}: {}", number + 1, message);
+        let line = line.split('#').next().unwrap_or("").trim();
+        let mut fields = line.split_whitespace();
+        let command = match fields.next() {
+            Some(command) => command,
+            None => continue,
+        };
+        let arguments = fields.collect::<Vec<_>>();
+        if command == "newmtl" {
+            materials.push(ObjMaterial::new(&arguments.join(" ")));
+            continue;
+        }
+        let material = match materials.last_mut() {
+            Some(material) => material,
+            None => return Err(error(format!("{} before newmtl", command))),
+        };
+        match command {
+            "Kd" => material.diffuse = parse_floats(&arguments).map_err(error)?,
+            "d" => material.dissolve = parse_floats::<1>(&arguments).map_err(error)?[0],
+            "Tr" => material.dissolve = 1.0 - parse_floats::<1>(&arguments).map_err(error)?[0],
+            // Перед именем файла могут стоять параметры вроде -s 1 1 1. Имя файла — последнее слово.
+            "map_Kd" => {
+                let file = arguments
+                    .last()
+                    .ok_or_else(|| error("map_Kd without file".to_owned()))?;
+                material.diffuse_texture = Some(directory.join(file.replace('\\', "/")));
+            }
+            _ => {}
+        }
+    }
+    Ok(materials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<ObjModel, String> {
+        ObjModel::parse(text, |name| Err(format!("unexpected mtllib {}", name)))
+    }
+
+    const SQUARE: &str = "
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+";
+
+    #[test]
+    fn shared_corners_become_one_vertex() {
+        let model = parse(&format!("{}f 1 2 3\nf 1 3 4\n", SQUARE)).unwrap();
+        assert_eq!(model.meshes.len(), 1);
+        let mesh = &model.meshes[0].mesh;
+        assert_eq!(mesh.vertices.len(), 4);
+        assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3]);
+    }
+
+    #[test]
+    fn corners_with_different_attributes_are_not_merged() {
+        let text = format!("{}vt 0 0\nvt 1 1\nf 1/1 2/1 3/1\nf 1/2 3/1 4/1\n", SQUARE);
+        let mesh = &parse(&text).unwrap().meshes[0].mesh;
+        assert_eq!(mesh.vertices.len(), 5);
+        // Ось v текстурных координат переворачивается.
+        assert_eq!(mesh.vertices[0].uv, [0.0, 1.0]);
+        assert_eq!(mesh.vertices[3].uv, [1.0, 0.0]);
+    }
+
+    #[test]
+    fn negative_indices_count_from_end() {
+        let absolute = parse(&format!("{}f 2 3 4\n", SQUARE)).unwrap();
+        let relative = parse(&format!("{}f -3 -2 -1\n", SQUARE)).unwrap();
+        let positions = |model: &ObjModel| {
+            model.meshes[0]
+                .mesh
+                .vertices
+                .iter()
+                .map(|vertex| vertex.position)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(positions(&absolute), positions(&relative));
+    }
+
+    #[test]
+    fn polygons_are_triangulated_as_fan() {
+        let text = "v 0 0 0\nv 1 0 0\nv 2 1 0\nv 1 2 0\nv 0 1 0\nf 1 2 3 4 5\n";
+        let mesh = &parse(text).unwrap().meshes[0].mesh;
+        assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3, 0, 3, 4]);
+    }
+
+    #[test]
+    fn out_of_range_indices_are_errors() {
+        for face in ["f 1 2 5", "f 0 1 2", "f -5 1 2", "f 1/1 2 3", "f 1//1 2 3"] {
+            let error = parse(&format!("{}{}\n", SQUARE, face)).unwrap_err();
+            assert!(error.starts_with("6: "), "{}: {}", face, error);
+            assert!(error.contains("out of range"), "{}: {}", face, error);
+        }
+        assert!(parse(&format!("{}f 1 2\n", SQUARE)).is_err());
+    }
+
+    #[test]
+    fn missing_normals_are_computed() {
+        let mesh = &parse(&format!("{}f 1 2 3 4\n", SQUARE)).unwrap().meshes[0].mesh;
+        for vertex in &mesh.vertices {
+            assert_eq!(vertex.normal, [0.0, 0.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn explicit_normals_are_kept() {
+        let text = format!("{}vn 1 0 0\nf 1//1 2//1 3//1\nf 1 3 4\n", SQUARE);
+        let mesh = &parse(&text).unwrap().meshes[0].mesh;
+        // Углы без нормали — отдельные вершины, даже если их позиции уже встречались с нормалью.
+        assert_eq!(mesh.vertices.len(), 6);
+        for vertex in &mesh.vertices[..3] {
+            assert_eq!(vertex.normal, [1.0, 0.0, 0.0]);
+        }
+        for vertex in &mesh.vertices[3..] {
+            assert_eq!(vertex.normal, [0.0, 0.0, 1.0]);
+        }
+    }
+
+    #[test]
+    fn usemtl_splits_meshes() {
+        let text = format!(
+            "mtllib box.mtl\n{}o box\nusemtl red\nf 1 2 3\nusemtl blue\nf 1 3 4\nusemtl missing\nf 1 2 4\n",
+            SQUARE
+        );
+        let model = ObjModel::parse(&text, |name| {
+            assert_eq!(name, "box.mtl");
+            parse_mtl(
+                "newmtl red\nKd 1 0 0\nnewmtl blue\nKd 0 0 1\nd 0.5\n",
+                Path::new(""),
+            )
+        })
+        .unwrap();
+        assert_eq!(model.materials.len(), 2);
+        assert_eq!(model.materials[1].dissolve, 0.5);
+        let meshes = model
+            .meshes
+            .iter()
+            .map(|mesh| (mesh.name.as_str(), mesh.material, mesh.mesh.indices.len()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            meshes,
+            vec![("box", Some(0), 3), ("box", Some(1), 3), ("box", None, 3)]
+        );
+    }
+}
@@ -0,0 +1,60 @@
+use ash::vk;
+
+// Push-константы — небольшой блок данных, который записывается прямо в буфер команд перед отрисовкой,
+// без буферов и дескрипторов. Для каждого конвейера объявляется своя #[repr(C)] структура, раскладка которой
+// совпадает с блоком layout(push_constant) в его шейдерах, и для нее реализуется этот трейт.
+//
+// Любое устройство предоставляет не меньше 128 байт push-констант. Структуры, которые в них помещаются,
+// можно не проверять, а для больших нужно вызвать validate с пределами устройства.
+//
+// Трейт небезопасный: push побайтово копирует структуру в буфер команд. Реализующая его структура обязана
+// быть #[repr(C)], иначе порядок полей не совпадет с шейдером, и не должна содержать выравнивающих пропусков
+// между полями и в конце: байты пропусков не инициализированы, и читать их нельзя. Проще всего этого
+// добиться, если все поля — 4-байтовые числа и массивы из них.
+pub unsafe trait PushConstants: Copy {
+    // Этапы конвейера, в шейдерах которых объявлен блок.
+    const STAGES: vk::ShaderStageFlags;
+
+    // Размер блока. Vulkan требует, чтобы он был кратен 4.
+    fn size() -> u32 {
+        let size = std::mem::size_of::<Self>() as u32;
+        assert_eq!(size % 4, 0, "Push constants size must be a multiple of 4");
+        size
+    }
+
+    // Диапазон для vk::PipelineLayoutCreateInfo.
+    fn range() -> vk::PushConstantRange {
+        vk::PushConstantRange {
+            stage_flags: Self::STAGES,
+            offset: 0,
+            size: Self::size(),
+        }
+    }
+
+    // Проверяет, что блок помещается в push-константы устройства.
+    fn validate(limits: &vk::PhysicalDeviceLimits) -> Result<(), String> {
+        if Self::size() <= limits.max_push_constants_size {
+            Ok(())
+        } else {
+            Err(format!(
+                "{} bytes of push constants do not fit into {} bytes supported by the device",
+                Self::size(),
+                limits.max_push_constants_size
+            ))
+        }
+    }
+
+    // Записывает значение в буфер команд. Раскладка конвейера должна быть создана с диапазоном Self::range().
+    unsafe fn push(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        pipeline_layout: vk::PipelineLayout,
+    ) {
+        let bytes = std::slice::from_raw_parts(
+            self as *const Self as *const u8,
+            std::mem::size_of::<Self>(),
+        );
+        device.cmd_push_constants(command_buffer, pipeline_layout, Self::STAGES, 0, bytes);
+    }
+}
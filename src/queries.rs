@@ -0,0 +1,323 @@
+use ash::vk;
+
+use crate::render_graph::{PassScope, RenderGraph};
+
+// Статистика конвейера и запросы видимости, как и запросы времени, читаются без ожидания:
+// результаты кадра в полете забираются, когда этот кадр начинается снова и его забор уже просигналил.
+
+// Счетчики, которые собираются для каждого прохода. Порядок полей совпадает с порядком бит
+// vk::QueryPipelineStatisticFlags, в этом же порядке видеокарта записывает результаты.
+const STATISTIC_FLAGS: vk::QueryPipelineStatisticFlags = vk::QueryPipelineStatisticFlags::from_raw(
+    vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_VERTICES.as_raw()
+        | vk::QueryPipelineStatisticFlags::INPUT_ASSEMBLY_PRIMITIVES.as_raw()
+        | vk::QueryPipelineStatisticFlags::VERTEX_SHADER_INVOCATIONS.as_raw()
+        | vk::QueryPipelineStatisticFlags::CLIPPING_PRIMITIVES.as_raw()
+        | vk::QueryPipelineStatisticFlags::FRAGMENT_SHADER_INVOCATIONS.as_raw()
+        | vk::QueryPipelineStatisticFlags::COMPUTE_SHADER_INVOCATIONS.as_raw(),
+);
+const STATISTIC_COUNT: usize = 6;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PassStatistics {
+    pub input_vertices: u64,
+    pub input_primitives: u64,
+    pub vertex_shader_invocations: u64,
+    // Примитивы, прошедшие отсечение.
+    pub clipping_primitives: u64,
+    pub fragment_shader_invocations: u64,
+    pub compute_shader_invocations: u64,
+}
+
+// Запросы статистики конвейера вокруг каждого прохода графа рендеринга.
+// Нужна функция устройства pipeline_statistics_query, ее наличие проверяет вызывающий код.
+pub struct PipelineStatistics {
+    query_pool: vk::QueryPool,
+    max_scopes: u32,
+    frames: Vec<FrameScopes>,
+    current: usize,
+}
+
+#[derive(Default)]
+struct FrameScopes {
+    scopes: Vec<String>,
+    // Запрос прохода начат, но еще не закончен.
+    open_scope: bool,
+    written: bool,
+}
+
+impl PipelineStatistics {
+    pub fn new(device: &ash::Device, frames_in_flight: usize, max_scopes: u32) -> Self {
+        let query_pool_create_info = vk::QueryPoolCreateInfo::builder()
+            .query_type(vk::QueryType::PIPELINE_STATISTICS)
+            .pipeline_statistics(STATISTIC_FLAGS)
+            .query_count(max_scopes * frames_in_flight as u32);
+        let query_pool = unsafe {
+            device
+                .create_query_pool(&query_pool_create_info, None)
+                .expect("Failed to create pipeline statistics Query Pool!")
+        };
+
+        PipelineStatistics {
+            query_pool,
+            max_scopes,
+            frames: (0..frames_in_flight)
+                .map(|_| FrameScopes::default())
+                .collect(),
+            current: 0,
+        }
+    }
+
+    fn first_query(&self) -> u32 {
+        self.current as u32 * self.max_scopes
+    }
+
+    // Вызывается после ожидания забора кадра frame_index. Возвращает статистику проходов,
+    // собранную в прошлый раз, когда этот кадр в полете выполнялся.
+    pub fn begin_frame(
+        &mut self,
+        device: &ash::Device,
+        frame_index: usize,
+    ) -> Vec<(String, PassStatistics)> {
+        self.current = frame_index;
+        let frame = &mut self.frames[frame_index];
+        if !frame.written {
+            return Vec::new();
+        }
+        frame.written = false;
+        let scopes = std::mem::take(&mut frame.scopes);
+        if scopes.is_empty() {
+            return Vec::new();
+        }
+
+        let mut results = vec![[0u64; STATISTIC_COUNT]; scopes.len()];
+        let result = unsafe {
+            device.get_query_pool_results(
+                self.query_pool,
+                self.first_query(),
+                scopes.len() as u32,
+                &mut results,
+                vk::QueryResultFlags::TYPE_64,
+            )
+        };
+        if result.is_err() {
+            return Vec::new();
+        }
+
+        scopes
+            .into_iter()
+            .zip(results)
+            .map(|(name, values)| {
+                let statistics = PassStatistics {
+                    input_vertices: values[0],
+                    input_primitives: values[1],
+                    vertex_shader_invocations: values[2],
+                    clipping_primitives: values[3],
+                    fragment_shader_invocations: values[4],
+                    compute_shader_invocations: values[5],
+                };
+                (name, statistics)
+            })
+            .collect()
+    }
+
+    // Сбрасывает запросы текущего кадра. Вызывается до выполнения графа, вне прохода рендеринга.
+    pub unsafe fn reset(&mut self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        device.cmd_reset_query_pool(
+            command_buffer,
+            self.query_pool,
+            self.first_query(),
+            self.max_scopes,
+        );
+        self.frames[self.current].written = true;
+    }
+
+    pub unsafe fn destroy(&self, device: &ash::Device) {
+        device.destroy_query_pool(self.query_pool, None);
+    }
+}
+
+impl PassScope for PipelineStatistics {
+    // Проходы сверх max_scopes не измеряются.
+    unsafe fn begin_pass(
+        &mut self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        name: &str,
+    ) {
+        let first_query = self.first_query();
+        let frame = &mut self.frames[self.current];
+        if frame.scopes.len() as u32 >= self.max_scopes {
+            return;
+        }
+        device.cmd_begin_query(
+            command_buffer,
+            self.query_pool,
+            first_query + frame.scopes.len() as u32,
+            vk::QueryControlFlags::empty(),
+        );
+        frame.scopes.push(name.to_owned());
+        frame.open_scope = true;
+    }
+
+    unsafe fn end_pass(&mut self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        let first_query = self.first_query();
+        let frame = &mut self.frames[self.current];
+        if !frame.open_scope {
+            return;
+        }
+        device.cmd_end_query(
+            command_buffer,
+            self.query_pool,
+            first_query + frame.scopes.len() as u32 - 1,
+        );
+        frame.open_scope = false;
+    }
+}
+
+// Запросы видимости: сколько образцов прошло тест глубины между begin и end.
+// Каждый запрос помечается номером объекта, по которому вызывающий код потом узнает, виден ли объект.
+pub struct OcclusionQueries {
+    query_pool: vk::QueryPool,
+    max_queries: u32,
+    // Без функции occlusion_query_precise видеокарта гарантирует только, что результат не равен нулю для видимого объекта.
+    precise: bool,
+    frames: Vec<Vec<u32>>,
+    current: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct OcclusionResult {
+    pub object: u32,
+    // Точное число образцов, только если включена функция occlusion_query_precise.
+    pub samples: u64,
+}
+
+impl OcclusionResult {
+    pub fn is_visible(&self) -> bool {
+        self.samples > 0
+    }
+}
+
+impl OcclusionQueries {
+    pub fn new(
+        device: &ash::Device,
+        frames_in_flight: usize,
+        max_queries: u32,
+        precise: bool,
+    ) -> Self {
+        let query_pool_create_info = vk::QueryPoolCreateInfo::builder()
+            .query_type(vk::QueryType::OCCLUSION)
+            .query_count(max_queries * frames_in_flight as u32);
+        let query_pool = unsafe {
+            device
+                .create_query_pool(&query_pool_create_info, None)
+                .expect("Failed to create occlusion Query Pool!")
+        };
+
+        OcclusionQueries {
+            query_pool,
+            max_queries,
+            precise,
+            frames: vec![Vec::new(); frames_in_flight],
+            current: 0,
+        }
+    }
+
+    fn first_query(&self) -> u32 {
+        self.current as u32 * self.max_queries
+    }
+
+    // Вызывается после ожидания забора кадра frame_index. Возвращает результаты запросов,
+    // записанных в прошлый раз, когда этот кадр в полете выполнялся.
+    pub fn begin_frame(
+        &mut self,
+        device: &ash::Device,
+        frame_index: usize,
+    ) -> Vec<OcclusionResult> {
+        self.current = frame_index;
+        let objects = std::mem::take(&mut self.frames[frame_index]);
+        if objects.is_empty() {
+            return Vec::new();
+        }
+
+        let mut samples = vec![0u64; objects.len()];
+        let result = unsafe {
+            device.get_query_pool_results(
+                self.query_pool,
+                self.first_query(),
+                objects.len() as u32,
+                &mut samples,
+                vk::QueryResultFlags::TYPE_64,
+            )
+        };
+        if result.is_err() {
+            return Vec::new();
+        }
+
+        objects
+            .into_iter()
+            .zip(samples)
+            .map(|(object, samples)| OcclusionResult { object, samples })
+            .collect()
+    }
+
+    // Добавляет в граф проход, сбрасывающий запросы текущего кадра. Сброс нельзя делать внутри прохода рендеринга,
+    // поэтому проход нужно добавить раньше проходов, которые используют запросы.
+    pub fn add_reset_pass(&self, graph: &mut RenderGraph) {
+        let query_pool = self.query_pool;
+        let first_query = self.first_query();
+        let query_count = self.max_queries;
+        graph
+            .add_pass("reset occlusion queries")
+            .side_effect()
+            .execute(move |context| unsafe {
+                context.device.cmd_reset_query_pool(
+                    context.command_buffer,
+                    query_pool,
+                    first_query,
+                    query_count,
+                )
+            });
+    }
+
+    // Начинает запрос для объекта. Возвращает false, если запросы этого кадра закончились,
+    // тогда end вызывать не нужно.
+    pub unsafe fn begin(
+        &mut self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        object: u32,
+    ) -> bool {
+        let first_query = self.first_query();
+        let objects = &mut self.frames[self.current];
+        if objects.len() as u32 >= self.max_queries {
+            return false;
+        }
+        let flags = if self.precise {
+            vk::QueryControlFlags::PRECISE
+        } else {
+            vk::QueryControlFlags::empty()
+        };
+        device.cmd_begin_query(
+            command_buffer,
+            self.query_pool,
+            first_query + objects.len() as u32,
+            flags,
+        );
+        objects.push(object);
+        true
+    }
+
+    pub unsafe fn end(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        let objects = &self.frames[self.current];
+        device.cmd_end_query(
+            command_buffer,
+            self.query_pool,
+            self.first_query() + objects.len() as u32 - 1,
+        );
+    }
+
+    pub unsafe fn destroy(&self, device: &ash::Device) {
+        device.destroy_query_pool(self.query_pool, None);
+    }
+}
@@ -0,0 +1,1809 @@
+use ash::vk;
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::dynamic_rendering::DynamicRendering;
+use crate::memory::find_memory_type;
+
+// Граф рендеринга. Каждый кадр проходы объявляют, какие изображения и буферы они читают и пишут,
+// а граф по этим объявлениям:
+//   - отбрасывает проходы, результат которых никому не нужен;
+//   - выделяет временные (transient) изображения и буферы;
+//   - расставляет барьеры и переходы layout между проходами;
+//   - создает проходы рендеринга и фреймбуферы для проходов, рисующих во вложения,
+//     либо, если устройство поддерживает динамический рендеринг, рисует прямо в image view.
+// Граф строится заново каждый кадр, а объекты Vulkan переиспользуются через RenderGraphCache.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ImageHandle(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BufferHandle(usize);
+
+// Описание временного изображения. Флаги использования граф вычисляет сам по объявлениям проходов.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ImageDesc {
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+}
+
+// Описание временного буфера. Флаги использования граф вычисляет сам по объявлениям проходов.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BufferDesc {
+    pub size: vk::DeviceSize,
+}
+
+// Изображение, которое живет вне графа, например изображение цепочки обмена.
+#[derive(Debug, Clone, Copy)]
+pub struct ImportedImage {
+    pub image: vk::Image,
+    pub view: vk::ImageView,
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+    // Layout, в котором изображение находится до начала графа, и этап, который нужно дождаться перед первым использованием.
+    // Для изображения цепочки обмена это UNDEFINED и COLOR_ATTACHMENT_OUTPUT (тот же этап, на котором ждем семафор).
+    pub initial_layout: vk::ImageLayout,
+    pub initial_stage: vk::PipelineStageFlags,
+    // Если указан, изображение считается результатом графа и в конце переводится в этот layout.
+    pub final_layout: Option<vk::ImageLayout>,
+}
+
+// Способ использования изображения вне вложений прохода рендеринга.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageUsage {
+    // Чтение через сэмплер на указанном этапе конвейера.
+    Sampled(vk::PipelineStageFlags),
+    // storage image на указанном этапе конвейера.
+    Storage(vk::PipelineStageFlags),
+    // Источник или приемник команд копирования.
+    Transfer,
+}
+
+// Способ использования буфера.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferUsage {
+    Vertex,
+    Index,
+    Indirect,
+    Uniform(vk::PipelineStageFlags),
+    Storage(vk::PipelineStageFlags),
+    Transfer,
+}
+
+// Что делать с содержимым вложения в начале прохода.
+#[derive(Clone, Copy)]
+pub enum LoadOp {
+    Clear(vk::ClearValue),
+    Load,
+    DontCare,
+}
+
+impl LoadOp {
+    fn vk(self) -> vk::AttachmentLoadOp {
+        match self {
+            LoadOp::Clear(_) => vk::AttachmentLoadOp::CLEAR,
+            LoadOp::Load => vk::AttachmentLoadOp::LOAD,
+            LoadOp::DontCare => vk::AttachmentLoadOp::DONT_CARE,
+        }
+    }
+}
+
+// Команды, которые записываются вокруг каждого прохода при выполнении графа: запросы времени, статистики и т.п.
+// begin_pass вызывается после барьеров прохода и до начала прохода рендеринга, end_pass — после его конца.
+pub trait PassScope {
+    unsafe fn begin_pass(
+        &mut self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        name: &str,
+    );
+    unsafe fn end_pass(&mut self, device: &ash::Device, command_buffer: vk::CommandBuffer);
+}
+
+// То, что проход получает при записи своих команд.
+pub struct PassContext<'c> {
+    pub device: &'c ash::Device,
+    pub command_buffer: vk::CommandBuffer,
+    // Размер вложений прохода. Для проходов без вложений нулевой.
+    pub extent: vk::Extent2D,
+    images: &'c [PhysicalImage],
+    buffers: &'c [vk::Buffer],
+}
+
+impl<'c> PassContext<'c> {
+    pub fn image(&self, handle: ImageHandle) -> vk::Image {
+        self.images[handle.0].image
+    }
+
+    pub fn image_view(&self, handle: ImageHandle) -> vk::ImageView {
+        self.images[handle.0].view
+    }
+
+    pub fn buffer(&self, handle: BufferHandle) -> vk::Buffer {
+        self.buffers[handle.0]
+    }
+}
+
+type RecordFn<'a> = Box<dyn FnOnce(&PassContext) + 'a>;
+
+enum ImageSource {
+    Imported(ImportedImage),
+    Transient(ImageDesc),
+}
+
+struct ImageNode {
+    name: String,
+    source: ImageSource,
+}
+
+impl ImageNode {
+    fn format(&self) -> vk::Format {
+        match &self.source {
+            ImageSource::Imported(image) => image.format,
+            ImageSource::Transient(desc) => desc.format,
+        }
+    }
+
+    fn extent(&self) -> vk::Extent2D {
+        match &self.source {
+            ImageSource::Imported(image) => image.extent,
+            ImageSource::Transient(desc) => desc.extent,
+        }
+    }
+}
+
+enum BufferSource {
+    Imported {
+        buffer: vk::Buffer,
+        size: vk::DeviceSize,
+        output: bool,
+    },
+    Transient(BufferDesc),
+}
+
+struct BufferNode {
+    name: String,
+    source: BufferSource,
+}
+
+// Доступ к ресурсу внутри прохода: layout (только для изображений), этап конвейера и тип доступа к памяти.
+#[derive(Debug, Clone, Copy)]
+struct Access {
+    layout: vk::ImageLayout,
+    stage: vk::PipelineStageFlags,
+    access: vk::AccessFlags,
+    write: bool,
+}
+
+struct ImageUse {
+    handle: ImageHandle,
+    access: Access,
+    usage: vk::ImageUsageFlags,
+}
+
+struct BufferUse {
+    handle: BufferHandle,
+    access: Access,
+    usage: vk::BufferUsageFlags,
+}
+
+struct PassNode<'a> {
+    name: String,
+    color_attachments: Vec<(ImageHandle, LoadOp)>,
+    depth_attachment: Option<(ImageHandle, LoadOp)>,
+    images: Vec<ImageUse>,
+    buffers: Vec<BufferUse>,
+    side_effect: bool,
+    record: Option<RecordFn<'a>>,
+}
+
+impl<'a> PassNode<'a> {
+    fn reads_image(&self, handle: ImageHandle) -> bool {
+        self.images.iter().any(|image_use| {
+            image_use.handle == handle
+                && (!image_use.access.write
+                    || image_use.access.access.intersects(
+                        vk::AccessFlags::COLOR_ATTACHMENT_READ
+                            | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ,
+                    ))
+        })
+    }
+
+    fn is_raster(&self) -> bool {
+        !self.color_attachments.is_empty() || self.depth_attachment.is_some()
+    }
+}
+
+#[derive(Default)]
+pub struct RenderGraph<'a> {
+    images: Vec<ImageNode>,
+    buffers: Vec<BufferNode>,
+    passes: Vec<PassNode<'a>>,
+}
+
+impl<'a> RenderGraph<'a> {
+    pub fn new() -> Self {
+        RenderGraph {
+            images: Vec::new(),
+            buffers: Vec::new(),
+            passes: Vec::new(),
+        }
+    }
+
+    pub fn import_image(&mut self, name: &str, image: ImportedImage) -> ImageHandle {
+        self.images.push(ImageNode {
+            name: name.to_owned(),
+            source: ImageSource::Imported(image),
+        });
+        ImageHandle(self.images.len() - 1)
+    }
+
+    pub fn create_image(&mut self, name: &str, desc: ImageDesc) -> ImageHandle {
+        self.images.push(ImageNode {
+            name: name.to_owned(),
+            source: ImageSource::Transient(desc),
+        });
+        ImageHandle(self.images.len() - 1)
+    }
+
+    // output означает, что содержимое буфера нужно после графа (например, читается процессором),
+    // и проходы, которые его пишут, нельзя отбрасывать.
+    pub fn import_buffer(
+        &mut self,
+        name: &str,
+        buffer: vk::Buffer,
+        size: vk::DeviceSize,
+        output: bool,
+    ) -> BufferHandle {
+        self.buffers.push(BufferNode {
+            name: name.to_owned(),
+            source: BufferSource::Imported {
+                buffer,
+                size,
+                output,
+            },
+        });
+        BufferHandle(self.buffers.len() - 1)
+    }
+
+    pub fn create_buffer(&mut self, name: &str, desc: BufferDesc) -> BufferHandle {
+        self.buffers.push(BufferNode {
+            name: name.to_owned(),
+            source: BufferSource::Transient(desc),
+        });
+        BufferHandle(self.buffers.len() - 1)
+    }
+
+    pub fn add_pass(&mut self, name: &str) -> PassBuilder<'_, 'a> {
+        PassBuilder {
+            graph: self,
+            pass: PassNode {
+                name: name.to_owned(),
+                color_attachments: Vec::new(),
+                depth_attachment: None,
+                images: Vec::new(),
+                buffers: Vec::new(),
+                side_effect: false,
+                record: None,
+            },
+        }
+    }
+
+    // Проход нужен, если у него есть побочный эффект или он пишет ресурс, который нужен кому-то дальше.
+    // Идем с конца: все, что читает нужный проход, тоже становится нужным.
+    fn cull(&self) -> Vec<bool> {
+        let mut needed_images = self.images
+            .iter()
+            .map(|node| matches!(&node.source, ImageSource::Imported(image) if image.final_layout.is_some()))
+            .collect::<Vec<_>>();
+        let mut needed_buffers = self
+            .buffers
+            .iter()
+            .map(|node| matches!(node.source, BufferSource::Imported { output: true, .. }))
+            .collect::<Vec<_>>();
+
+        let mut alive = vec![false; self.passes.len()];
+        for (index, pass) in self.passes.iter().enumerate().rev() {
+            let writes_needed = pass
+                .images
+                .iter()
+                .any(|image_use| image_use.access.write && needed_images[image_use.handle.0])
+                || pass.buffers.iter().any(|buffer_use| {
+                    buffer_use.access.write && needed_buffers[buffer_use.handle.0]
+                });
+
+            if !(pass.side_effect || writes_needed) {
+                continue;
+            }
+            alive[index] = true;
+
+            // Ресурс, который проход полностью перезаписывает, до него уже не нужен.
+            for image_use in &pass.images {
+                if image_use.access.write && !pass.reads_image(image_use.handle) {
+                    needed_images[image_use.handle.0] = false;
+                }
+            }
+            for image_use in &pass.images {
+                if pass.reads_image(image_use.handle) {
+                    needed_images[image_use.handle.0] = true;
+                }
+            }
+            for buffer_use in &pass.buffers {
+                if !buffer_use.access.write {
+                    needed_buffers[buffer_use.handle.0] = true;
+                }
+            }
+        }
+        alive
+    }
+
+    // Компилирует граф: отбрасывает ненужные проходы, выделяет ресурсы и вычисляет барьеры.
+    pub fn compile(self, device: &ash::Device, cache: &mut RenderGraphCache) -> CompiledGraph<'a> {
+        let alive = self.cull();
+        let RenderGraph {
+            images,
+            buffers,
+            passes,
+        } = self;
+
+        // Флаги использования ресурсов собираем только по выжившим проходам.
+        let mut image_usage = vec![vk::ImageUsageFlags::empty(); images.len()];
+        let mut buffer_usage = vec![vk::BufferUsageFlags::empty(); buffers.len()];
+        for pass in passes
+            .iter()
+            .zip(&alive)
+            .filter(|(_, &alive)| alive)
+            .map(|(pass, _)| pass)
+        {
+            for image_use in &pass.images {
+                image_usage[image_use.handle.0] |= image_use.usage;
+            }
+            for buffer_use in &pass.buffers {
+                buffer_usage[buffer_use.handle.0] |= buffer_use.usage;
+            }
+        }
+
+        // Временные ресурсы, которые никто не использует, не выделяются вовсе.
+        let physical_images = images
+            .iter()
+            .zip(&image_usage)
+            .map(|(node, &usage)| match &node.source {
+                ImageSource::Imported(image) => PhysicalImage {
+                    image: image.image,
+                    view: image.view,
+                },
+                ImageSource::Transient(_) if usage.is_empty() => PhysicalImage::default(),
+                ImageSource::Transient(desc) => cache.acquire_image(device, *desc, usage),
+            })
+            .collect::<Vec<_>>();
+        let physical_buffers = buffers
+            .iter()
+            .zip(&buffer_usage)
+            .map(|(node, &usage)| match node.source {
+                BufferSource::Imported { buffer, .. } => buffer,
+                BufferSource::Transient(_) if usage.is_empty() => vk::Buffer::null(),
+                BufferSource::Transient(desc) => cache.acquire_buffer(device, desc, usage),
+            })
+            .collect::<Vec<_>>();
+
+        // Начальное состояние ресурсов. Временные ресурсы могли использоваться предыдущим кадром,
+        // поэтому перед первой записью ждем все этапы (зависимость write-after-read).
+        let mut image_states = images
+            .iter()
+            .map(|node| match &node.source {
+                ImageSource::Imported(image) => Access {
+                    layout: image.initial_layout,
+                    stage: image.initial_stage,
+                    access: vk::AccessFlags::empty(),
+                    write: false,
+                },
+                ImageSource::Transient(_) => Access {
+                    layout: vk::ImageLayout::UNDEFINED,
+                    stage: vk::PipelineStageFlags::ALL_COMMANDS,
+                    access: vk::AccessFlags::empty(),
+                    write: false,
+                },
+            })
+            .collect::<Vec<_>>();
+        // Импортированные буферы могли быть записаны видеокартой раньше, поэтому считаем их записанными.
+        let mut buffer_states = buffers
+            .iter()
+            .map(|node| Access {
+                layout: vk::ImageLayout::UNDEFINED,
+                stage: vk::PipelineStageFlags::ALL_COMMANDS,
+                access: match node.source {
+                    BufferSource::Imported { .. } => vk::AccessFlags::MEMORY_WRITE,
+                    BufferSource::Transient(_) => vk::AccessFlags::empty(),
+                },
+                write: matches!(node.source, BufferSource::Imported { .. }),
+            })
+            .collect::<Vec<_>>();
+
+        let mut steps = Vec::new();
+        let mut culled = Vec::new();
+        let mut passes_info = Vec::new();
+
+        for (pass, alive) in passes.into_iter().zip(alive) {
+            let mut info = PassInfo {
+                name: pass.name.clone(),
+                alive,
+                order: None,
+                image_edges: pass
+                    .images
+                    .iter()
+                    .map(|image_use| {
+                        (
+                            image_use.handle,
+                            image_use.access.write,
+                            image_use.access.layout,
+                        )
+                    })
+                    .collect(),
+                buffer_edges: pass
+                    .buffers
+                    .iter()
+                    .map(|buffer_use| (buffer_use.handle, buffer_use.access.write))
+                    .collect(),
+                transitions: Vec::new(),
+            };
+
+            if !alive {
+                culled.push(pass.name);
+                passes_info.push(info);
+                continue;
+            }
+            info.order = Some(steps.len());
+
+            let mut barriers = Barriers::default();
+            for image_use in &pass.images {
+                let state = &mut image_states[image_use.handle.0];
+                let format = images[image_use.handle.0].format();
+                if let Some(barrier) = transition_image(
+                    state,
+                    image_use.access,
+                    physical_images[image_use.handle.0].image,
+                    aspect_mask(format),
+                ) {
+                    barriers.src_stage |= barrier.0;
+                    barriers.dst_stage |= image_use.access.stage;
+                    if barrier.1.old_layout != barrier.1.new_layout {
+                        info.transitions.push((
+                            image_use.handle,
+                            barrier.1.old_layout,
+                            barrier.1.new_layout,
+                        ));
+                    }
+                    barriers.images.push(barrier.1);
+                }
+            }
+            for buffer_use in &pass.buffers {
+                let state = &mut buffer_states[buffer_use.handle.0];
+                if let Some(barrier) = transition_buffer(
+                    state,
+                    buffer_use.access,
+                    physical_buffers[buffer_use.handle.0],
+                ) {
+                    barriers.src_stage |= barrier.0;
+                    barriers.dst_stage |= buffer_use.access.stage;
+                    barriers.buffers.push(barrier.1);
+                }
+            }
+
+            // Для проходов с вложениями заранее получаем проход рендеринга и фреймбуфер,
+            // а при динамическом рендеринге — описания вложений для cmd_begin_rendering.
+            let raster = if pass.is_raster() {
+                let attachments = pass
+                    .color_attachments
+                    .iter()
+                    .chain(pass.depth_attachment.iter());
+                let extent = attachments
+                    .clone()
+                    .map(|&(handle, _)| images[handle.0].extent())
+                    .next()
+                    .unwrap();
+                let clear_value = |load: LoadOp| match load {
+                    LoadOp::Clear(value) => value,
+                    _ => vk::ClearValue::default(),
+                };
+
+                let target = if cache.dynamic_rendering.is_some() {
+                    let attachment_info = |&(handle, load): &(ImageHandle, LoadOp), layout| {
+                        vk::RenderingAttachmentInfo::builder()
+                            .image_view(physical_images[handle.0].view)
+                            .image_layout(layout)
+                            .load_op(load.vk())
+                            .store_op(vk::AttachmentStoreOp::STORE)
+                            .clear_value(clear_value(load))
+                            .build()
+                    };
+                    RasterTarget::Dynamic {
+                        color: pass
+                            .color_attachments
+                            .iter()
+                            .map(|attachment| {
+                                attachment_info(
+                                    attachment,
+                                    vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                                )
+                            })
+                            .collect(),
+                        depth: pass.depth_attachment.as_ref().map(|attachment| {
+                            attachment_info(
+                                attachment,
+                                vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                            )
+                        }),
+                    }
+                } else {
+                    let key = RenderPassKey {
+                        color: pass
+                            .color_attachments
+                            .iter()
+                            .map(|&(handle, load)| (images[handle.0].format(), load.vk()))
+                            .collect(),
+                        depth: pass
+                            .depth_attachment
+                            .map(|(handle, load)| (images[handle.0].format(), load.vk())),
+                    };
+                    let render_pass = cache.render_pass(device, &key);
+                    let views = attachments
+                        .clone()
+                        .map(|&(handle, _)| physical_images[handle.0].view)
+                        .collect::<Vec<_>>();
+                    let framebuffer = cache.framebuffer(device, render_pass, views, extent);
+                    RasterTarget::RenderPass {
+                        render_pass,
+                        framebuffer,
+                        clear_values: attachments.map(|&(_, load)| clear_value(load)).collect(),
+                    }
+                };
+                Some(RasterInfo { extent, target })
+            } else {
+                None
+            };
+
+            steps.push(Step {
+                name: pass.name,
+                barriers,
+                raster,
+                record: pass.record,
+            });
+            passes_info.push(info);
+        }
+
+        // Результаты графа переводим в требуемый layout, например PRESENT_SRC_KHR для цепочки обмена.
+        let mut final_barriers = Barriers::default();
+        for (index, node) in images.iter().enumerate() {
+            if let ImageSource::Imported(ImportedImage {
+                format,
+                final_layout: Some(final_layout),
+                ..
+            }) = node.source
+            {
+                let access = Access {
+                    layout: final_layout,
+                    stage: vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                    access: vk::AccessFlags::empty(),
+                    write: false,
+                };
+                if let Some(barrier) = transition_image(
+                    &mut image_states[index],
+                    access,
+                    physical_images[index].image,
+                    aspect_mask(format),
+                ) {
+                    final_barriers.src_stage |= barrier.0;
+                    final_barriers.dst_stage |= access.stage;
+                    final_barriers.images.push(barrier.1);
+                }
+            }
+        }
+
+        // Выходные буферы читает процессор, поэтому записи видеокарты нужно сделать видимыми для него.
+        for (index, node) in buffers.iter().enumerate() {
+            if let BufferSource::Imported { output: true, .. } = node.source {
+                let access = Access {
+                    layout: vk::ImageLayout::UNDEFINED,
+                    stage: vk::PipelineStageFlags::HOST,
+                    access: vk::AccessFlags::HOST_READ,
+                    write: false,
+                };
+                if let Some(barrier) =
+                    transition_buffer(&mut buffer_states[index], access, physical_buffers[index])
+                {
+                    final_barriers.src_stage |= barrier.0;
+                    final_barriers.dst_stage |= access.stage;
+                    final_barriers.buffers.push(barrier.1);
+                }
+            }
+        }
+
+        let resources_info = images
+            .iter()
+            .zip(&image_usage)
+            .map(|(node, usage)| {
+                let (kind, format, extent) = match &node.source {
+                    ImageSource::Imported(image) => ("imported", image.format, image.extent),
+                    ImageSource::Transient(desc) if usage.is_empty() => {
+                        ("unused", desc.format, desc.extent)
+                    }
+                    ImageSource::Transient(desc) => ("transient", desc.format, desc.extent),
+                };
+                format!(
+                    "{}\\n{:?} {}x{}\\n{}",
+                    node.name, format, extent.width, extent.height, kind
+                )
+            })
+            .collect();
+        let buffers_info = buffers
+            .iter()
+            .zip(&buffer_usage)
+            .map(|(node, usage)| {
+                let (kind, size) = match node.source {
+                    BufferSource::Imported { size, .. } => ("imported", size),
+                    BufferSource::Transient(desc) if usage.is_empty() => ("unused", desc.size),
+                    BufferSource::Transient(desc) => ("transient", desc.size),
+                };
+                format!("{}\\n{} bytes\\n{}", node.name, size, kind)
+            })
+            .collect();
+
+        CompiledGraph {
+            steps,
+            final_barriers,
+            dynamic_rendering: cache.dynamic_rendering.clone(),
+            images: physical_images,
+            buffers: physical_buffers,
+            culled,
+            passes_info,
+            images_info: resources_info,
+            buffers_info,
+        }
+    }
+}
+
+pub struct PassBuilder<'g, 'a> {
+    graph: &'g mut RenderGraph<'a>,
+    pass: PassNode<'a>,
+}
+
+impl<'g, 'a> PassBuilder<'g, 'a> {
+    pub fn color_attachment(mut self, image: ImageHandle, load: LoadOp) -> Self {
+        let read = matches!(load, LoadOp::Load);
+        self.pass.images.push(ImageUse {
+            handle: image,
+            access: Access {
+                layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                stage: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                access: if read {
+                    vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                } else {
+                    vk::AccessFlags::COLOR_ATTACHMENT_WRITE
+                },
+                write: true,
+            },
+            usage: vk::ImageUsageFlags::COLOR_ATTACHMENT,
+        });
+        self.pass.color_attachments.push((image, load));
+        self
+    }
+
+    pub fn depth_attachment(mut self, image: ImageHandle, load: LoadOp) -> Self {
+        let read = matches!(load, LoadOp::Load);
+        self.pass.images.push(ImageUse {
+            handle: image,
+            access: Access {
+                layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                stage: vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS
+                    | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                access: if read {
+                    vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE
+                } else {
+                    vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE
+                },
+                write: true,
+            },
+            usage: vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+        });
+        self.pass.depth_attachment = Some((image, load));
+        self
+    }
+
+    pub fn read_image(mut self, image: ImageHandle, usage: ImageUsage) -> Self {
+        self.pass.images.push(image_use(image, usage, false));
+        self
+    }
+
+    pub fn write_image(mut self, image: ImageHandle, usage: ImageUsage) -> Self {
+        self.pass.images.push(image_use(image, usage, true));
+        self
+    }
+
+    pub fn read_buffer(mut self, buffer: BufferHandle, usage: BufferUsage) -> Self {
+        self.pass.buffers.push(buffer_use(buffer, usage, false));
+        self
+    }
+
+    pub fn write_buffer(mut self, buffer: BufferHandle, usage: BufferUsage) -> Self {
+        self.pass.buffers.push(buffer_use(buffer, usage, true));
+        self
+    }
+
+    // Проход с побочным эффектом никогда не отбрасывается, даже если его результаты никто не читает.
+    pub fn side_effect(mut self) -> Self {
+        self.pass.side_effect = true;
+        self
+    }
+
+    // Завершает объявление прохода. Функция вызывается при выполнении графа,
+    // для проходов с вложениями — внутри уже начатого прохода рендеринга.
+    pub fn execute(mut self, record: impl FnOnce(&PassContext) + 'a) {
+        self.pass.record = Some(Box::new(record));
+        self.graph.passes.push(self.pass);
+    }
+}
+
+fn image_use(handle: ImageHandle, usage: ImageUsage, write: bool) -> ImageUse {
+    let (layout, stage, access, flags) = match usage {
+        ImageUsage::Sampled(stage) => {
+            assert!(!write, "Sampled image can not be written");
+            (
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                stage,
+                vk::AccessFlags::SHADER_READ,
+                vk::ImageUsageFlags::SAMPLED,
+            )
+        }
+        ImageUsage::Storage(stage) => (
+            vk::ImageLayout::GENERAL,
+            stage,
+            if write {
+                vk::AccessFlags::SHADER_WRITE
+            } else {
+                vk::AccessFlags::SHADER_READ
+            },
+            vk::ImageUsageFlags::STORAGE,
+        ),
+        ImageUsage::Transfer if write => (
+            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::ImageUsageFlags::TRANSFER_DST,
+        ),
+        ImageUsage::Transfer => (
+            vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::TRANSFER_READ,
+            vk::ImageUsageFlags::TRANSFER_SRC,
+        ),
+    };
+    ImageUse {
+        handle,
+        access: Access {
+            layout,
+            stage,
+            access,
+            write,
+        },
+        usage: flags,
+    }
+}
+
+fn buffer_use(handle: BufferHandle, usage: BufferUsage, write: bool) -> BufferUse {
+    let shader_access = if write {
+        vk::AccessFlags::SHADER_WRITE
+    } else {
+        vk::AccessFlags::SHADER_READ
+    };
+    let (stage, access, flags) = match usage {
+        BufferUsage::Vertex => (
+            vk::PipelineStageFlags::VERTEX_INPUT,
+            vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+        ),
+        BufferUsage::Index => (
+            vk::PipelineStageFlags::VERTEX_INPUT,
+            vk::AccessFlags::INDEX_READ,
+            vk::BufferUsageFlags::INDEX_BUFFER,
+        ),
+        BufferUsage::Indirect => (
+            vk::PipelineStageFlags::DRAW_INDIRECT,
+            vk::AccessFlags::INDIRECT_COMMAND_READ,
+            vk::BufferUsageFlags::INDIRECT_BUFFER,
+        ),
+        BufferUsage::Uniform(stage) => (
+            stage,
+            vk::AccessFlags::UNIFORM_READ,
+            vk::BufferUsageFlags::UNIFORM_BUFFER,
+        ),
+        BufferUsage::Storage(stage) => (stage, shader_access, vk::BufferUsageFlags::STORAGE_BUFFER),
+        BufferUsage::Transfer if write => (
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::TRANSFER_WRITE,
+            vk::BufferUsageFlags::TRANSFER_DST,
+        ),
+        BufferUsage::Transfer => (
+            vk::PipelineStageFlags::TRANSFER,
+            vk::AccessFlags::TRANSFER_READ,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+        ),
+    };
+    assert!(
+        !write
+            || !matches!(
+                usage,
+                BufferUsage::Vertex
+                    | BufferUsage::Index
+                    | BufferUsage::Indirect
+                    | BufferUsage::Uniform(_)
+            ),
+        "{:?} buffer can not be written",
+        usage
+    );
+    BufferUse {
+        handle,
+        access: Access {
+            layout: vk::ImageLayout::UNDEFINED,
+            stage,
+            access,
+            write,
+        },
+        usage: flags,
+    }
+}
+
+pub fn aspect_mask(format: vk::Format) -> vk::ImageAspectFlags {
+    match format {
+        vk::Format::D16_UNORM | vk::Format::X8_D24_UNORM_PACK32 | vk::Format::D32_SFLOAT => {
+            vk::ImageAspectFlags::DEPTH
+        }
+        vk::Format::D16_UNORM_S8_UINT
+        | vk::Format::D24_UNORM_S8_UINT
+        | vk::Format::D32_SFLOAT_S8_UINT => {
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        }
+        vk::Format::S8_UINT => vk::ImageAspectFlags::STENCIL,
+        _ => vk::ImageAspectFlags::COLOR,
+    }
+}
+
+// Первый формат глубины, который устройство умеет использовать как вложение с оптимальным тайлингом.
+// D16_UNORM обязан поддерживаться любым устройством, поэтому он остается запасным вариантом.
+pub fn supported_depth_format(
+    instance: &ash::Instance,
+    p_device: vk::PhysicalDevice,
+) -> vk::Format {
+    [vk::Format::D32_SFLOAT, vk::Format::X8_D24_UNORM_PACK32]
+        .iter()
+        .copied()
+        .find(|&format| {
+            unsafe { instance.get_physical_device_format_properties(p_device, format) }
+                .optimal_tiling_features
+                .contains(vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT)
+        })
+        .unwrap_or(vk::Format::D16_UNORM)
+}
+
+// Барьер нужен, если меняется layout или хотя бы один из доступов — запись.
+// Несколько чтений подряд в одном layout обходятся без барьера, их этапы накапливаются.
+fn needs_barrier(state: &Access, next: Access) -> bool {
+    state.layout != next.layout || state.write || next.write
+}
+
+fn apply(state: &mut Access, next: Access, barrier: bool) {
+    if barrier {
+        *state = next;
+    } else {
+        state.stage |= next.stage;
+        state.access |= next.access;
+    }
+}
+
+fn transition_image(
+    state: &mut Access,
+    next: Access,
+    image: vk::Image,
+    aspect_mask: vk::ImageAspectFlags,
+) -> Option<(vk::PipelineStageFlags, vk::ImageMemoryBarrier)> {
+    let barrier = needs_barrier(state, next);
+    let result = if barrier {
+        let image_barrier = vk::ImageMemoryBarrier::builder()
+            .src_access_mask(if state.write {
+                state.access
+            } else {
+                vk::AccessFlags::empty()
+            })
+            .dst_access_mask(next.access)
+            .old_layout(state.layout)
+            .new_layout(next.layout)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .image(image)
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask,
+                base_mip_level: 0,
+                level_count: vk::REMAINING_MIP_LEVELS,
+                base_array_layer: 0,
+                layer_count: vk::REMAINING_ARRAY_LAYERS,
+            })
+            .build();
+        Some((state.stage, image_barrier))
+    } else {
+        None
+    };
+    apply(state, next, barrier);
+    result
+}
+
+fn transition_buffer(
+    state: &mut Access,
+    next: Access,
+    buffer: vk::Buffer,
+) -> Option<(vk::PipelineStageFlags, vk::BufferMemoryBarrier)> {
+    let barrier = needs_barrier(state, next);
+    let result = if barrier {
+        let buffer_barrier = vk::BufferMemoryBarrier::builder()
+            .src_access_mask(if state.write {
+                state.access
+            } else {
+                vk::AccessFlags::empty()
+            })
+            .dst_access_mask(next.access)
+            .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+            .buffer(buffer)
+            .offset(0)
+            .size(vk::WHOLE_SIZE)
+            .build();
+        Some((state.stage, buffer_barrier))
+    } else {
+        None
+    };
+    apply(state, next, barrier);
+    result
+}
+
+#[derive(Default)]
+struct Barriers {
+    src_stage: vk::PipelineStageFlags,
+    dst_stage: vk::PipelineStageFlags,
+    images: Vec<vk::ImageMemoryBarrier>,
+    buffers: Vec<vk::BufferMemoryBarrier>,
+}
+
+impl Barriers {
+    unsafe fn record(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        if self.images.is_empty() && self.buffers.is_empty() {
+            return;
+        }
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            self.src_stage,
+            self.dst_stage,
+            vk::DependencyFlags::empty(),
+            &[],
+            &self.buffers,
+            &self.images,
+        );
+    }
+}
+
+enum RasterTarget {
+    RenderPass {
+        render_pass: vk::RenderPass,
+        framebuffer: vk::Framebuffer,
+        clear_values: Vec<vk::ClearValue>,
+    },
+    Dynamic {
+        color: Vec<vk::RenderingAttachmentInfo>,
+        depth: Option<vk::RenderingAttachmentInfo>,
+    },
+}
+
+struct RasterInfo {
+    extent: vk::Extent2D,
+    target: RasterTarget,
+}
+
+struct Step<'a> {
+    name: String,
+    barriers: Barriers,
+    raster: Option<RasterInfo>,
+    record: Option<RecordFn<'a>>,
+}
+
+struct PassInfo {
+    name: String,
+    alive: bool,
+    order: Option<usize>,
+    image_edges: Vec<(ImageHandle, bool, vk::ImageLayout)>,
+    buffer_edges: Vec<(BufferHandle, bool)>,
+    transitions: Vec<(ImageHandle, vk::ImageLayout, vk::ImageLayout)>,
+}
+
+// Результат компиляции графа: расписание проходов с барьерами перед каждым из них.
+pub struct CompiledGraph<'a> {
+    steps: Vec<Step<'a>>,
+    final_barriers: Barriers,
+    dynamic_rendering: Option<DynamicRendering>,
+    images: Vec<PhysicalImage>,
+    buffers: Vec<vk::Buffer>,
+    culled: Vec<String>,
+    passes_info: Vec<PassInfo>,
+    images_info: Vec<String>,
+    buffers_info: Vec<String>,
+}
+
+impl<'a> CompiledGraph<'a> {
+    // Имена отброшенных проходов.
+    pub fn culled_passes(&self) -> &[String] {
+        &self.culled
+    }
+
+    // Расписание в формате Graphviz dot: проходы — прямоугольники в порядке выполнения,
+    // ресурсы — овалы, ребра — чтение и запись. Отброшенные проходы рисуются пунктиром.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph render_graph {{").unwrap();
+        writeln!(dot, "    rankdir=LR;").unwrap();
+        writeln!(dot, "    node [fontname=\"monospace\"];").unwrap();
+
+        for (index, label) in self.images_info.iter().enumerate() {
+            writeln!(
+                dot,
+                "    image{} [shape=ellipse, label=\"{}\"];",
+                index, label
+            )
+            .unwrap();
+        }
+        for (index, label) in self.buffers_info.iter().enumerate() {
+            writeln!(
+                dot,
+                "    buffer{} [shape=ellipse, style=rounded, label=\"{}\"];",
+                index, label
+            )
+            .unwrap();
+        }
+
+        for (index, pass) in self.passes_info.iter().enumerate() {
+            let mut label = match pass.order {
+                Some(order) => format!("#{} {}", order, pass.name),
+                None => format!("{} (culled)", pass.name),
+            };
+            for (handle, old_layout, new_layout) in &pass.transitions {
+                write!(
+                    label,
+                    "\\n{}: {:?} -> {:?}",
+                    handle.0, old_layout, new_layout
+                )
+                .unwrap();
+            }
+            let style = if pass.alive {
+                "style=bold"
+            } else {
+                "style=dashed, color=gray"
+            };
+            writeln!(
+                dot,
+                "    pass{} [shape=box, {}, label=\"{}\"];",
+                index, style, label
+            )
+            .unwrap();
+
+            for (handle, write, layout) in &pass.image_edges {
+                if *write {
+                    writeln!(
+                        dot,
+                        "    pass{} -> image{} [label=\"{:?}\"];",
+                        index, handle.0, layout
+                    )
+                    .unwrap();
+                } else {
+                    writeln!(
+                        dot,
+                        "    image{} -> pass{} [label=\"{:?}\"];",
+                        handle.0, index, layout
+                    )
+                    .unwrap();
+                }
+            }
+            for (handle, write) in &pass.buffer_edges {
+                if *write {
+                    writeln!(dot, "    pass{} -> buffer{};", index, handle.0).unwrap();
+                } else {
+                    writeln!(dot, "    buffer{} -> pass{};", handle.0, index).unwrap();
+                }
+            }
+        }
+
+        // Порядок выполнения показываем невидимыми для раскладки, но заметными пунктирными ребрами.
+        let order = self
+            .passes_info
+            .iter()
+            .enumerate()
+            .filter(|(_, pass)| pass.alive)
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        for pair in order.windows(2) {
+            writeln!(
+                dot,
+                "    pass{} -> pass{} [style=dotted, color=blue, constraint=false];",
+                pair[0], pair[1]
+            )
+            .unwrap();
+        }
+
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+
+    // Записывает все проходы в буфер команд. Каждый проход окружается вызовами scopes, например запросами времени.
+    pub fn execute(
+        self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        scopes: &mut [&mut dyn PassScope],
+    ) {
+        for step in self.steps {
+            unsafe { step.barriers.record(device, command_buffer) };
+            for scope in scopes.iter_mut() {
+                unsafe { scope.begin_pass(device, command_buffer, &step.name) };
+            }
+
+            let extent = step
+                .raster
+                .as_ref()
+                .map(|raster| raster.extent)
+                .unwrap_or_default();
+            let context = PassContext {
+                device,
+                command_buffer,
+                extent,
+                images: &self.images,
+                buffers: &self.buffers,
+            };
+
+            let render_area = vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent,
+            };
+            match step.raster.as_ref().map(|raster| &raster.target) {
+                Some(RasterTarget::RenderPass {
+                    render_pass,
+                    framebuffer,
+                    clear_values,
+                }) => {
+                    // Рисование начинается с начала прохода рендеринга с cmd_begin_render_pass.
+                    // Этап рендеринга настраивается с использованием некоторых параметров в RenderPassBeginInfo.
+                    let render_pass_begin_info = vk::RenderPassBeginInfo::builder()
+                        .render_pass(*render_pass)
+                        .framebuffer(*framebuffer)
+                        .render_area(render_area)
+                        .clear_values(clear_values);
+                    unsafe {
+                        device.cmd_begin_render_pass(
+                            command_buffer,
+                            &render_pass_begin_info,
+                            vk::SubpassContents::INLINE,
+                        );
+                    }
+                }
+                Some(RasterTarget::Dynamic { color, depth }) => {
+                    // Вложения уже переведены барьерами графа в нужные layout, остается только указать их.
+                    let mut rendering_info = vk::RenderingInfo::builder()
+                        .render_area(render_area)
+                        .layer_count(1)
+                        .color_attachments(color);
+                    if let Some(depth) = depth.as_ref() {
+                        rendering_info = rendering_info.depth_attachment(depth);
+                    }
+                    let dynamic_rendering = self.dynamic_rendering.as_ref().unwrap();
+                    unsafe {
+                        dynamic_rendering.cmd_begin_rendering(
+                            device,
+                            command_buffer,
+                            &rendering_info,
+                        )
+                    };
+                }
+                None => {}
+            }
+
+            if let Some(record) = step.record {
+                record(&context);
+            }
+
+            match step.raster.as_ref().map(|raster| &raster.target) {
+                Some(RasterTarget::RenderPass { .. }) => unsafe {
+                    device.cmd_end_render_pass(command_buffer)
+                },
+                Some(RasterTarget::Dynamic { .. }) => unsafe {
+                    self.dynamic_rendering
+                        .as_ref()
+                        .unwrap()
+                        .cmd_end_rendering(device, command_buffer)
+                },
+                None => {}
+            }
+            for scope in scopes.iter_mut().rev() {
+                unsafe { scope.end_pass(device, command_buffer) };
+            }
+        }
+
+        unsafe { self.final_barriers.record(device, command_buffer) };
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct PhysicalImage {
+    image: vk::Image,
+    view: vk::ImageView,
+}
+
+// Ключ прохода рендеринга: форматы вложений и операции загрузки.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct RenderPassKey {
+    color: Vec<(vk::Format, vk::AttachmentLoadOp)>,
+    depth: Option<(vk::Format, vk::AttachmentLoadOp)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct FramebufferKey {
+    render_pass: vk::RenderPass,
+    views: Vec<vk::ImageView>,
+    extent: vk::Extent2D,
+}
+
+struct CachedImage {
+    desc: ImageDesc,
+    usage: vk::ImageUsageFlags,
+    image: vk::Image,
+    view: vk::ImageView,
+    memory: vk::DeviceMemory,
+    last_used: u64,
+}
+
+struct CachedBuffer {
+    desc: BufferDesc,
+    usage: vk::BufferUsageFlags,
+    buffer: vk::Buffer,
+    memory: vk::DeviceMemory,
+    last_used: u64,
+}
+
+// Объекты Vulkan, которые переживают отдельный кадр: проходы рендеринга, фреймбуферы и временные ресурсы.
+// Фреймбуферы и временные ресурсы, которые не использовались дольше, чем кадров в полете, уничтожаются.
+pub struct RenderGraphCache {
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    // Если задан, проходы с вложениями используют динамический рендеринг вместо проходов рендеринга.
+    dynamic_rendering: Option<DynamicRendering>,
+    frames_in_flight: u64,
+    frame: u64,
+    render_passes: HashMap<RenderPassKey, vk::RenderPass>,
+    framebuffers: HashMap<FramebufferKey, (vk::Framebuffer, u64)>,
+    images: Vec<CachedImage>,
+    buffers: Vec<CachedBuffer>,
+}
+
+impl RenderGraphCache {
+    pub fn new(
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        dynamic_rendering: Option<DynamicRendering>,
+        frames_in_flight: usize,
+    ) -> Self {
+        RenderGraphCache {
+            memory_properties,
+            dynamic_rendering,
+            frames_in_flight: frames_in_flight as u64,
+            frame: 0,
+            render_passes: HashMap::new(),
+            framebuffers: HashMap::new(),
+            images: Vec::new(),
+            buffers: Vec::new(),
+        }
+    }
+
+    pub fn uses_dynamic_rendering(&self) -> bool {
+        self.dynamic_rendering.is_some()
+    }
+
+    // Вызывается в начале кадра, после ожидания его забора.
+    pub fn begin_frame(&mut self, device: &ash::Device) {
+        self.frame += 1;
+        let frame = self.frame;
+        let keep = self.frames_in_flight;
+        let expired = |last_used: u64| last_used + keep < frame;
+
+        unsafe {
+            self.framebuffers
+                .retain(|_, &mut (framebuffer, last_used)| {
+                    if expired(last_used) {
+                        device.destroy_framebuffer(framebuffer, None);
+                        false
+                    } else {
+                        true
+                    }
+                });
+            self.images.retain(|image| {
+                if expired(image.last_used) {
+                    device.destroy_image_view(image.view, None);
+                    device.destroy_image(image.image, None);
+                    device.free_memory(image.memory, None);
+                    false
+                } else {
+                    true
+                }
+            });
+            self.buffers.retain(|buffer| {
+                if expired(buffer.last_used) {
+                    device.destroy_buffer(buffer.buffer, None);
+                    device.free_memory(buffer.memory, None);
+                    false
+                } else {
+                    true
+                }
+            });
+        }
+    }
+
+    // Проход рендеринга, совместимый с проходами графа с такими же форматами вложений.
+    // Нужен при создании графических конвейеров.
+    pub fn compatible_render_pass(
+        &mut self,
+        device: &ash::Device,
+        color_formats: &[vk::Format],
+        depth_format: Option<vk::Format>,
+    ) -> vk::RenderPass {
+        let key = RenderPassKey {
+            color: color_formats
+                .iter()
+                .map(|&format| (format, vk::AttachmentLoadOp::DONT_CARE))
+                .collect(),
+            depth: depth_format.map(|format| (format, vk::AttachmentLoadOp::DONT_CARE)),
+        };
+        self.render_pass(device, &key)
+    }
+
+    fn render_pass(&mut self, device: &ash::Device, key: &RenderPassKey) -> vk::RenderPass {
+        if let Some(&render_pass) = self.render_passes.get(key) {
+            return render_pass;
+        }
+
+        // Прежде чем мы сможем завершить создание конвейера, нам нужно сообщить Vulkan о прикреплениях фреймбуфера,
+        // которые будут использоваться при рендеринге. Нам нужно указать, сколько будет буферов цвета и глубины,
+        // сколько сэмплов использовать для каждого из них и как их содержимое должно обрабатываться во время операций рендеринга.
+        // Вся эта информация заключена в объект прохода рендеринга
+        let attachment =
+            |format: vk::Format, load_op: vk::AttachmentLoadOp, layout: vk::ImageLayout| {
+                vk::AttachmentDescription::builder()
+                    .format(format)
+                    // Мы не делаем ничего с мультисэмплинг еще, так что мы будем придерживаться 1 образца.
+                    .samples(vk::SampleCountFlags::TYPE_1)
+                    // У нас есть следующие варианты load_op:
+                    //      vk::AttachmentLoadOp::LOAD:         Сохранить существующее содержимое вложения
+                    //      vk::AttachmentLoadOp::CLEAR:        Очистить значения до константы в начале
+                    //      vk::AttachmentLoadOp::DONT_CARE:    Существующее содержимое не определено; мы не заботимся о них
+                    .load_op(load_op)
+                    //Есть только две возможности store_op:
+                    //      vk::AttachmentStoreOp::STORE:       Обработанное содержимое будет сохранено в памяти и может быть прочитано позже.
+                    //      vk::AttachmentStoreOp::DONT_CARE:   Содержимое фреймбуфера будет неопределенным после операции рендеринга.
+                    .store_op(vk::AttachmentStoreOp::STORE)
+                    .stencil_load_op(load_op)
+                    .stencil_store_op(vk::AttachmentStoreOp::STORE)
+                    // Переходы layout между проходами выполняет граф явными барьерами,
+                    // поэтому внутри прохода рендеринга layout вложения не меняется.
+                    .initial_layout(layout)
+                    .final_layout(layout)
+                    .build()
+            };
+
+        let mut attachments = key
+            .color
+            .iter()
+            .map(|&(format, load_op)| {
+                attachment(format, load_op, vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            })
+            .collect::<Vec<_>>();
+
+        let color_attachment_refs = (0..key.color.len() as u32)
+            .map(|index| vk::AttachmentReference {
+                attachment: index,
+                layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            })
+            .collect::<Vec<_>>();
+
+        let depth_attachment_ref = key.depth.map(|(format, load_op)| {
+            attachments.push(attachment(
+                format,
+                load_op,
+                vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            ));
+            vk::AttachmentReference {
+                attachment: attachments.len() as u32 - 1,
+                layout: vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            }
+        });
+
+        // Один проход рендеринга может состоять из нескольких подпроходов.
+        // Подпроходы - это последующие операции рендеринга, которые зависят от содержимого кадровых буферов на предыдущих проходах,
+        // например, последовательность эффектов постобработки, которые применяются один за другим.
+        // Граф же выполняет каждый свой проход отдельным проходом рендеринга с одним подпроходом,
+        // а зависимости между ними выражает явными барьерами.
+        let mut subpass = vk::SubpassDescription::builder()
+            // Vulkan может также поддерживать подпроходы вычислений в будущем, поэтому мы должны четко указать, что это подпроходы графики
+            .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
+            // На индекс вложения в этом массиве напрямую ссылается фрагментный шейдер
+            // с помощью layout(location = 0) out vec4 outColorдирективы!
+            //Подпроходом могут быть ссылки на следующие другие типы вложений:
+            //      p_input_attachments:        Вложения, считываемые из шейдера.
+            //      p_resolve_attachments:      Вложения, используемые для вложений цветов с множественной выборкой
+            //      p_depthStencil_attachment:  Приложение для данных глубины и трафарета
+            //      p_preserve_attachments:     Вложения, которые не используются этим подпроходом, но для которых необходимо сохранить данные.
+            .color_attachments(&color_attachment_refs);
+        if let Some(depth_attachment_ref) = depth_attachment_ref.as_ref() {
+            subpass = subpass.depth_stencil_attachment(depth_attachment_ref);
+        }
+
+        let renderpass_create_info = vk::RenderPassCreateInfo::builder()
+            .attachments(&attachments)
+            .subpasses(std::slice::from_ref(&subpass));
+
+        let render_pass = unsafe {
+            device
+                .create_render_pass(&renderpass_create_info, None)
+                .expect("Failed to create render pass!")
+        };
+        self.render_passes.insert(key.clone(), render_pass);
+        render_pass
+    }
+
+    fn framebuffer(
+        &mut self,
+        device: &ash::Device,
+        render_pass: vk::RenderPass,
+        views: Vec<vk::ImageView>,
+        extent: vk::Extent2D,
+    ) -> vk::Framebuffer {
+        let frame = self.frame;
+        let key = FramebufferKey {
+            render_pass,
+            views,
+            extent,
+        };
+        let entry = self.framebuffers.entry(key).or_insert_with_key(|key| {
+            let framebuffer_create_info = vk::FramebufferCreateInfo::builder()
+                .render_pass(key.render_pass)
+                .attachments(&key.views)
+                .width(key.extent.width)
+                .height(key.extent.height)
+                .layers(1);
+
+            let framebuffer = unsafe {
+                device
+                    .create_framebuffer(&framebuffer_create_info, None)
+                    .expect("Failed to create Framebuffer!")
+            };
+            (framebuffer, frame)
+        });
+        entry.1 = frame;
+        entry.0
+    }
+
+    fn acquire_image(
+        &mut self,
+        device: &ash::Device,
+        desc: ImageDesc,
+        usage: vk::ImageUsageFlags,
+    ) -> PhysicalImage {
+        let frame = self.frame;
+        // Одно и то же изображение не может быть выдано дважды за кадр.
+        if let Some(cached) = self
+            .images
+            .iter_mut()
+            .find(|image| image.desc == desc && image.usage == usage && image.last_used != frame)
+        {
+            cached.last_used = frame;
+            return PhysicalImage {
+                image: cached.image,
+                view: cached.view,
+            };
+        }
+
+        let image_create_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(desc.format)
+            .extent(vk::Extent3D {
+                width: desc.extent.width,
+                height: desc.extent.height,
+                depth: 1,
+            })
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+
+        let (image, memory, view) = unsafe {
+            let image = device
+                .create_image(&image_create_info, None)
+                .expect("Failed to create transient image!");
+            let requirements = device.get_image_memory_requirements(image);
+            let memory = self.allocate(device, requirements);
+            device
+                .bind_image_memory(image, memory, 0)
+                .expect("Failed to bind transient image memory!");
+
+            let view_create_info = vk::ImageViewCreateInfo::builder()
+                .view_type(vk::ImageViewType::TYPE_2D)
+                .format(desc.format)
+                .subresource_range(vk::ImageSubresourceRange {
+                    aspect_mask: aspect_mask(desc.format),
+                    base_mip_level: 0,
+                    level_count: 1,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                })
+                .image(image);
+            let view = device
+                .create_image_view(&view_create_info, None)
+                .expect("Failed to create transient Image View!");
+            (image, memory, view)
+        };
+
+        self.images.push(CachedImage {
+            desc,
+            usage,
+            image,
+            view,
+            memory,
+            last_used: frame,
+        });
+        PhysicalImage { image, view }
+    }
+
+    fn acquire_buffer(
+        &mut self,
+        device: &ash::Device,
+        desc: BufferDesc,
+        usage: vk::BufferUsageFlags,
+    ) -> vk::Buffer {
+        let frame = self.frame;
+        if let Some(cached) = self.buffers.iter_mut().find(|buffer| {
+            buffer.desc == desc && buffer.usage == usage && buffer.last_used != frame
+        }) {
+            cached.last_used = frame;
+            return cached.buffer;
+        }
+
+        let buffer_create_info = vk::BufferCreateInfo::builder()
+            .size(desc.size)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+        let (buffer, memory) = unsafe {
+            let buffer = device
+                .create_buffer(&buffer_create_info, None)
+                .expect("Failed to create transient buffer!");
+            let requirements = device.get_buffer_memory_requirements(buffer);
+            let memory = self.allocate(device, requirements);
+            device
+                .bind_buffer_memory(buffer, memory, 0)
+                .expect("Failed to bind transient buffer memory!");
+            (buffer, memory)
+        };
+
+        self.buffers.push(CachedBuffer {
+            desc,
+            usage,
+            buffer,
+            memory,
+            last_used: frame,
+        });
+        buffer
+    }
+
+    unsafe fn allocate(
+        &self,
+        device: &ash::Device,
+        requirements: vk::MemoryRequirements,
+    ) -> vk::DeviceMemory {
+        let memory_type_index = find_memory_type(
+            &self.memory_properties,
+            requirements.memory_type_bits,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        )
+        .expect("No suitable memory type for transient resource!");
+        let allocate_info = vk::MemoryAllocateInfo::builder()
+            .allocation_size(requirements.size)
+            .memory_type_index(memory_type_index);
+        device
+            .allocate_memory(&allocate_info, None)
+            .expect("Failed to allocate transient resource memory!")
+    }
+
+    // Уничтожает фреймбуферы. Вызывается после device_wait_idle перед уничтожением image view, на которые они ссылаются.
+    pub unsafe fn release_framebuffers(&mut self, device: &ash::Device) {
+        self.framebuffers
+            .drain()
+            .for_each(|(_, (framebuffer, _))| device.destroy_framebuffer(framebuffer, None));
+    }
+
+    pub unsafe fn destroy(&mut self, device: &ash::Device) {
+        self.release_framebuffers(device);
+        self.render_passes
+            .drain()
+            .for_each(|(_, render_pass)| device.destroy_render_pass(render_pass, None));
+        self.images.drain(..).for_each(|image| {
+            device.destroy_image_view(image.view, None);
+            device.destroy_image(image.image, None);
+            device.free_memory(image.memory, None);
+        });
+        self.buffers.drain(..).for_each(|buffer| {
+            device.destroy_buffer(buffer.buffer, None);
+            device.free_memory(buffer.memory, None);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXTENT: vk::Extent2D = vk::Extent2D {
+        width: 64,
+        height: 64,
+    };
+
+    fn transient(graph: &mut RenderGraph, name: &str) -> ImageHandle {
+        graph.create_image(
+            name,
+            ImageDesc {
+                format: vk::Format::R8G8B8A8_UNORM,
+                extent: EXTENT,
+            },
+        )
+    }
+
+    // Изображение цепочки обмена: результат графа.
+    fn backbuffer(graph: &mut RenderGraph) -> ImageHandle {
+        graph.import_image(
+            "backbuffer",
+            ImportedImage {
+                image: vk::Image::null(),
+                view: vk::ImageView::null(),
+                format: vk::Format::B8G8R8A8_SRGB,
+                extent: EXTENT,
+                initial_layout: vk::ImageLayout::UNDEFINED,
+                initial_stage: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                final_layout: Some(vk::ImageLayout::PRESENT_SRC_KHR),
+            },
+        )
+    }
+
+    const SAMPLED: ImageUsage = ImageUsage::Sampled(vk::PipelineStageFlags::FRAGMENT_SHADER);
+
+    #[test]
+    fn culls_pass_writing_unused_transient() {
+        let mut graph = RenderGraph::new();
+        let unused = transient(&mut graph, "unused");
+        let output = backbuffer(&mut graph);
+        graph
+            .add_pass("dead")
+            .color_attachment(unused, LoadOp::DontCare)
+            .execute(|_| {});
+        graph
+            .add_pass("present")
+            .color_attachment(output, LoadOp::DontCare)
+            .execute(|_| {});
+        assert_eq!(graph.cull(), vec![false, true]);
+    }
+
+    #[test]
+    fn keeps_side_effect_passes() {
+        let mut graph = RenderGraph::new();
+        let unused = transient(&mut graph, "unused");
+        graph
+            .add_pass("readback")
+            .color_attachment(unused, LoadOp::DontCare)
+            .side_effect()
+            .execute(|_| {});
+        graph.add_pass("idle").execute(|_| {});
+        assert_eq!(graph.cull(), vec![true, false]);
+    }
+
+    #[test]
+    fn keeps_producer_of_read_resource() {
+        let mut graph = RenderGraph::new();
+        let shadow = transient(&mut graph, "shadow");
+        let overwritten = transient(&mut graph, "overwritten");
+        let output = backbuffer(&mut graph);
+        let counts = graph.create_buffer("counts", BufferDesc { size: 16 });
+        graph
+            .add_pass("shadow")
+            .color_attachment(shadow, LoadOp::DontCare)
+            .execute(|_| {});
+        // Его результат полностью перезаписывает следующий проход, поэтому он не нужен.
+        graph
+            .add_pass("stale")
+            .color_attachment(overwritten, LoadOp::DontCare)
+            .execute(|_| {});
+        graph
+            .add_pass("count")
+            .write_buffer(
+                counts,
+                BufferUsage::Storage(vk::PipelineStageFlags::COMPUTE_SHADER),
+            )
+            .color_attachment(overwritten, LoadOp::DontCare)
+            .execute(|_| {});
+        graph
+            .add_pass("lighting")
+            .read_image(shadow, SAMPLED)
+            .read_image(overwritten, SAMPLED)
+            .read_buffer(counts, BufferUsage::Indirect)
+            .color_attachment(output, LoadOp::DontCare)
+            .execute(|_| {});
+        assert_eq!(graph.cull(), vec![true, false, true, true]);
+    }
+
+    #[test]
+    fn dot_shows_order_edges_and_culled_passes() {
+        let pass = |name: &str, order: Option<usize>, image_edges| PassInfo {
+            name: name.to_owned(),
+            alive: order.is_some(),
+            order,
+            image_edges,
+            buffer_edges: Vec::new(),
+            transitions: Vec::new(),
+        };
+        let graph = CompiledGraph {
+            steps: Vec::new(),
+            final_barriers: Barriers::default(),
+            dynamic_rendering: None,
+            images: Vec::new(),
+            buffers: Vec::new(),
+            culled: vec!["dead".to_owned()],
+            passes_info: vec![
+                pass(
+                    "shadow",
+                    Some(0),
+                    vec![(
+                        ImageHandle(0),
+                        true,
+                        vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                    )],
+                ),
+                pass("dead", None, Vec::new()),
+                pass(
+                    "lighting",
+                    Some(1),
+                    vec![(
+                        ImageHandle(0),
+                        false,
+                        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                    )],
+                ),
+            ],
+            images_info: vec!["shadow".to_owned()],
+            buffers_info: Vec::new(),
+        };
+        assert_eq!(graph.culled_passes(), &["dead".to_owned()]);
+
+        let dot = graph.to_dot();
+        assert!(dot.starts_with("digraph render_graph {"));
+        assert!(dot.trim_end().ends_with('}'));
+        assert!(dot.contains("image0 [shape=ellipse, label=\"shadow\"];"));
+        assert!(dot.contains("pass0 [shape=box, style=bold, label=\"#0 shadow\"];"));
+        assert!(
+            dot.contains("pass1 [shape=box, style=dashed, color=gray, label=\"dead (culled)\"];")
+        );
+        assert!(dot.contains("pass0 -> image0 [label=\"COLOR_ATTACHMENT_OPTIMAL\"];"));
+        assert!(dot.contains("image0 -> pass2 [label=\"SHADER_READ_ONLY_OPTIMAL\"];"));
+        // Ребро порядка выполнения пропускает отброшенный проход.
+        assert!(dot.contains("pass0 -> pass2 [style=dotted"));
+        assert!(!dot.contains("pass1 -> pass"));
+    }
+}
@@ -0,0 +1,504 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::time::Instant;
+
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+use winit::event::{
+    DeviceEvent, DeviceId, ElementState, KeyboardInput, ModifiersState, MouseButton,
+    MouseScrollDelta, VirtualKeyCode, WindowEvent,
+};
+
+// Запись ввода для воспроизведения ошибок. В файл попадают события ввода с номером кадра, в который
+// они пришли, и время каждого кадра. При воспроизведении события кадра подаются перед его отрисовкой,
+// а время кадра берется из записи, поэтому камера и анимации проходят те же состояния.
+// Кадры при воспроизведении рисуются в окно, так что без дисплея оно не работает.
+//
+// Файл текстовый, по строке на запись:
+//     e <кадр> <мс от начала записи> <событие>   событие, пришедшее перед отрисовкой кадра
+//     f <кадр> <мс от начала записи> <время кадра в секундах>   конец событий кадра
+
+const HEADER: &str = "# ash-lern2 input recording v1";
+
+// Событие ввода в виде, который можно сохранить в файл и восстановить обратно.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecordedEvent {
+    Key {
+        scancode: u32,
+        keycode: Option<VirtualKeyCode>,
+        pressed: bool,
+    },
+    MouseButton {
+        button: MouseButton,
+        pressed: bool,
+    },
+    CursorMoved {
+        x: f64,
+        y: f64,
+    },
+    CursorLeft,
+    // lines — прокрутка в строках (колесо), иначе в пикселях (тачпад).
+    Wheel {
+        lines: bool,
+        x: f64,
+        y: f64,
+    },
+    Modifiers(u32),
+    Character(char),
+    Focused(bool),
+    Resized {
+        width: u32,
+        height: u32,
+    },
+    MouseMotion {
+        x: f64,
+        y: f64,
+    },
+}
+
+impl RecordedEvent {
+    // Событие окна, которое нужно записывать, или None для всех остальных.
+    pub fn from_window_event(event: &WindowEvent) -> Option<Self> {
+        Some(match event {
+            WindowEvent::KeyboardInput { input, .. } => RecordedEvent::Key {
+                scancode: input.scancode,
+                keycode: input.virtual_keycode,
+                pressed: input.state == ElementState::Pressed,
+            },
+            WindowEvent::MouseInput { state, button, .. } => RecordedEvent::MouseButton {
+                button: *button,
+                pressed: *state == ElementState::Pressed,
+            },
+            WindowEvent::CursorMoved { position, .. } => RecordedEvent::CursorMoved {
+                x: position.x,
+                y: position.y,
+            },
+            WindowEvent::CursorLeft { .. } => RecordedEvent::CursorLeft,
+            WindowEvent::MouseWheel { delta, .. } => match delta {
+                MouseScrollDelta::LineDelta(x, y) => RecordedEvent::Wheel {
+                    lines: true,
+                    x: *x as f64,
+                    y: *y as f64,
+                },
+                MouseScrollDelta::PixelDelta(delta) => RecordedEvent::Wheel {
+                    lines: false,
+                    x: delta.x,
+                    y: delta.y,
+                },
+            },
+            WindowEvent::ModifiersChanged(modifiers) => RecordedEvent::Modifiers(modifiers.bits()),
+            WindowEvent::ReceivedCharacter(character) => RecordedEvent::Character(*character),
+            WindowEvent::Focused(focused) => RecordedEvent::Focused(*focused),
+            WindowEvent::Resized(size) => RecordedEvent::Resized {
+                width: size.width,
+                height: size.height,
+            },
+            _ => return None,
+        })
+    }
+
+    pub fn from_device_event(event: &DeviceEvent) -> Option<Self> {
+        match event {
+            DeviceEvent::MouseMotion { delta } => Some(RecordedEvent::MouseMotion {
+                x: delta.0,
+                y: delta.1,
+            }),
+            _ => None,
+        }
+    }
+
+    // Восстанавливает событие окна. Для событий устройства возвращает None.
+    // Устройство у восстановленных событий фиктивное, приложение его не различает.
+    #[allow(deprecated)]
+    pub fn to_window_event(&self) -> Option<WindowEvent<'static>> {
+        let device_id = unsafe { DeviceId::dummy() };
+        let state = |pressed: bool| {
+            if pressed {
+                ElementState::Pressed
+            } else {
+                ElementState::Released
+            }
+        };
+        Some(match *self {
+            RecordedEvent::Key {
+                scancode,
+                keycode,
+                pressed,
+            } => WindowEvent::KeyboardInput {
+                device_id,
+                input: KeyboardInput {
+                    scancode,
+                    state: state(pressed),
+                    virtual_keycode: keycode,
+                    modifiers: ModifiersState::empty(),
+                },
+                is_synthetic: false,
+            },
+            RecordedEvent::MouseButton { button, pressed } => WindowEvent::MouseInput {
+                device_id,
+                state: state(pressed),
+                button,
+                modifiers: ModifiersState::empty(),
+            },
+            RecordedEvent::CursorMoved { x, y } => WindowEvent::CursorMoved {
+                device_id,
+                position: PhysicalPosition::new(x, y),
+                modifiers: ModifiersState::empty(),
+            },
+            RecordedEvent::CursorLeft => WindowEvent::CursorLeft { device_id },
+            RecordedEvent::Wheel { lines, x, y } => WindowEvent::MouseWheel {
+                device_id,
+                delta: if lines {
+                    MouseScrollDelta::LineDelta(x as f32, y as f32)
+                } else {
+                    MouseScrollDelta::PixelDelta(PhysicalPosition::new(x, y))
+                },
+                phase: winit::event::TouchPhase::Moved,
+                modifiers: ModifiersState::empty(),
+            },
+            RecordedEvent::Modifiers(bits) => {
+                WindowEvent::ModifiersChanged(ModifiersState::from_bits_truncate(bits))
+            }
+            RecordedEvent::Character(character) => WindowEvent::ReceivedCharacter(character),
+            RecordedEvent::Focused(focused) => WindowEvent::Focused(focused),
+            RecordedEvent::Resized { width, height } => {
+                WindowEvent::Resized(PhysicalSize::new(width, height))
+            }
+            RecordedEvent::MouseMotion { .. } => return None,
+        })
+    }
+
+    pub fn to_device_event(&self) -> Option<DeviceEvent> {
+        match *self {
+            RecordedEvent::MouseMotion { x, y } => Some(DeviceEvent::MouseMotion { delta: (x, y) }),
+            _ => None,
+        }
+    }
+
+    // Числа записываются в кратчайшем виде, который читается обратно без потери точности.
+    fn to_line(self) -> String {
+        match self {
+            RecordedEvent::Key {
+                scancode,
+                keycode,
+                pressed,
+            } => format!(
+                "key {} {} {}",
+                scancode,
+                keycode.map_or("-".to_owned(), |keycode| format!("{:?}", keycode)),
+                pressed
+            ),
+            RecordedEvent::MouseButton { button, pressed } => {
+                let button = match button {
+                    MouseButton::Left => "Left".to_owned(),
+                    MouseButton::Right => "Right".to_owned(),
+                    MouseButton::Middle => "Middle".to_owned(),
+                    MouseButton::Other(number) => number.to_string(),
+                };
+                format!("button {} {}", button, pressed)
+            }
+            RecordedEvent::CursorMoved { x, y } => format!("cursor {} {}", x, y),
+            RecordedEvent::CursorLeft => "cursor_left".to_owned(),
+            RecordedEvent::Wheel { lines, x, y } => format!("wheel {} {} {}", lines, x, y),
+            RecordedEvent::Modifiers(bits) => format!("modifiers {}", bits),
+            RecordedEvent::Character(character) => format!("char {}", character as u32),
+            RecordedEvent::Focused(focused) => format!("focused {}", focused),
+            RecordedEvent::Resized { width, height } => format!("resized {} {}", width, height),
+            RecordedEvent::MouseMotion { x, y } => format!("motion {} {}", x, y),
+        }
+    }
+
+    fn parse(text: &str) -> Result<Self, String> {
+        let fields = text.split_whitespace().collect::<Vec<_>>();
+        let field = |index: usize| {
+            fields
+                .get(index)
+                .copied()
+                .ok_or_else(|| format!("Missing field in \"{}\"", text))
+        };
+        let number = |index: usize| -> Result<f64, String> {
+            field(index)?
+                .parse()
+                .map_err(|_| format!("Invalid number in \"{}\"", text))
+        };
+        let flag = |index: usize| -> Result<bool, String> {
+            field(index)?
+                .parse()
+                .map_err(|_| format!("Invalid flag in \"{}\"", text))
+        };
+        let integer = |index: usize| -> Result<u32, String> {
+            field(index)?
+                .parse()
+                .map_err(|_| format!("Invalid integer in \"{}\"", text))
+        };
+
+        Ok(match field(0)? {
+            "key" => RecordedEvent::Key {
+                scancode: integer(1)?,
+                keycode: match field(2)? {
+                    "-" => None,
+                    // Названия клавиш совпадают с вариантами VirtualKeyCode, поэтому разбираются его десериализацией.
+                    name => Some(
+                        toml::Value::String(name.to_owned())
+                            .try_into()
+                            .map_err(|_| format!("Unknown key in \"{}\"", text))?,
+                    ),
+                },
+                pressed: flag(3)?,
+            },
+            "button" => RecordedEvent::MouseButton {
+                button: match field(1)? {
+                    "Left" => MouseButton::Left,
+                    "Right" => MouseButton::Right,
+                    "Middle" => MouseButton::Middle,
+                    number => MouseButton::Other(
+                        number
+                            .parse()
+                            .map_err(|_| format!("Unknown mouse button in \"{}\"", text))?,
+                    ),
+                },
+                pressed: flag(2)?,
+            },
+            "cursor" => RecordedEvent::CursorMoved {
+                x: number(1)?,
+                y: number(2)?,
+            },
+            "cursor_left" => RecordedEvent::CursorLeft,
+            "wheel" => RecordedEvent::Wheel {
+                lines: flag(1)?,
+                x: number(2)?,
+                y: number(3)?,
+            },
+            "modifiers" => RecordedEvent::Modifiers(integer(1)?),
+            "char" => RecordedEvent::Character(
+                char::from_u32(integer(1)?)
+                    .ok_or_else(|| format!("Invalid character in \"{}\"", text))?,
+            ),
+            "focused" => RecordedEvent::Focused(flag(1)?),
+            "resized" => RecordedEvent::Resized {
+                width: integer(1)?,
+                height: integer(2)?,
+            },
+            "motion" => RecordedEvent::MouseMotion {
+                x: number(1)?,
+                y: number(2)?,
+            },
+            kind => return Err(format!("Unknown event \"{}\"", kind)),
+        })
+    }
+}
+
+// Пишет события и кадры в файл записи.
+pub struct InputRecorder {
+    writer: BufWriter<File>,
+    start: Instant,
+    frame: u64,
+}
+
+impl InputRecorder {
+    pub fn create(path: &str) -> std::io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "{}", HEADER)?;
+        Ok(InputRecorder {
+            writer,
+            start: Instant::now(),
+            frame: 0,
+        })
+    }
+
+    fn milliseconds(&self) -> f64 {
+        self.start.elapsed().as_secs_f64() * 1000.0
+    }
+
+    // Ошибки записи не должны останавливать приложение, поэтому только печатаются.
+    pub fn record(&mut self, event: RecordedEvent) {
+        let line = format!(
+            "e {} {:.3} {}",
+            self.frame,
+            self.milliseconds(),
+            event.to_line()
+        );
+        if let Err(error) = writeln!(self.writer, "{}", line) {
+            println!("Failed to write input recording: {}", error);
+        }
+    }
+
+    // Вызывается в начале отрисовки кадра: все записанные до этого события относятся к нему.
+    pub fn end_frame(&mut self, delta_time: f32) {
+        let line = format!("f {} {:.3} {}", self.frame, self.milliseconds(), delta_time);
+        if let Err(error) = writeln!(self.writer, "{}", line) {
+            println!("Failed to write input recording: {}", error);
+        }
+        self.frame += 1;
+    }
+
+    // Цикл событий winit завершает процесс, не вызывая деструкторы, поэтому буфер сбрасывается явно.
+    pub fn finish(&mut self) {
+        if let Err(error) = self.writer.flush() {
+            println!("Failed to write input recording: {}", error);
+        }
+    }
+}
+
+// Один записанный кадр: события, пришедшие перед его отрисовкой, и его время.
+#[derive(Debug, Clone)]
+pub struct RecordedFrame {
+    pub events: Vec<RecordedEvent>,
+    pub delta_time: f32,
+}
+
+// Читает запись и отдает ее по кадрам.
+pub struct InputPlayer {
+    frames: VecDeque<RecordedFrame>,
+    frame_count: usize,
+}
+
+impl InputPlayer {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|error| error.to_string())?;
+        let mut lines = text.lines();
+        if lines.next() != Some(HEADER) {
+            return Err(format!("{} is not an input recording", path));
+        }
+
+        let mut frames = VecDeque::new();
+        let mut events = Vec::new();
+        for (number, line) in lines.enumerate() {
+            // Номер кадра и время в записи только для человека: порядок строк уже задает кадры.
+            let mut fields = line.splitn(4, ' ');
+            let kind = fields.next();
+            let payload = fields.nth(2).unwrap_or("");
+            let error = |message: String| format!("{}:{}: {}", path, number + 2, message);
+            match kind {
+                Some("e") => events.push(RecordedEvent::parse(payload).map_err(error)?),
+                Some("f") => {
+                    let delta_time = payload
+                        .parse()
+                        .map_err(|_| error(format!("Invalid frame time \"{}\"", payload)))?;
+                    frames.push_back(RecordedFrame {
+                        events: std::mem::take(&mut events),
+                        delta_time,
+                    });
+                }
+                Some("") | None => {}
+                Some(kind) => return Err(error(format!("Unknown record \"{}\"", kind))),
+            }
+        }
+
+        let frame_count = frames.len();
+        Ok(InputPlayer {
+            frames,
+            frame_count,
+        })
+    }
+
+    pub fn frame_count(&self) -> usize {
+        self.frame_count
+    }
+
+    // Следующий кадр записи или None, когда запись закончилась.
+    pub fn next_frame(&mut self) -> Option<RecordedFrame> {
+        self.frames.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all_events() -> Vec<RecordedEvent> {
+        vec![
+            RecordedEvent::Key {
+                scancode: 17,
+                keycode: Some(VirtualKeyCode::W),
+                pressed: true,
+            },
+            RecordedEvent::Key {
+                scancode: 999,
+                keycode: None,
+                pressed: false,
+            },
+            RecordedEvent::MouseButton {
+                button: MouseButton::Right,
+                pressed: true,
+            },
+            RecordedEvent::MouseButton {
+                button: MouseButton::Other(8),
+                pressed: false,
+            },
+            RecordedEvent::CursorMoved {
+                x: 0.1 + 0.2,
+                y: -17.25,
+            },
+            RecordedEvent::CursorLeft,
+            RecordedEvent::Wheel {
+                lines: true,
+                x: 0.0,
+                y: -1.0,
+            },
+            RecordedEvent::Wheel {
+                lines: false,
+                x: 1e-7,
+                y: 123.456,
+            },
+            RecordedEvent::Modifiers(ModifiersState::CTRL.bits() | ModifiersState::ALT.bits()),
+            RecordedEvent::Character('ж'),
+            RecordedEvent::Character(' '),
+            RecordedEvent::Focused(false),
+            RecordedEvent::Resized {
+                width: 1920,
+                height: 1080,
+            },
+            RecordedEvent::MouseMotion {
+                x: std::f64::consts::PI,
+                y: -2.5,
+            },
+        ]
+    }
+
+    #[test]
+    fn events_round_trip_through_lines() {
+        for event in all_events() {
+            let line = event.to_line();
+            assert_eq!(RecordedEvent::parse(&line), Ok(event), "{}", line);
+        }
+        assert!(RecordedEvent::parse("key 17").is_err());
+        assert!(RecordedEvent::parse("key 17 Banana true").is_err());
+        assert!(RecordedEvent::parse("teleport 1 2").is_err());
+    }
+
+    #[test]
+    fn player_groups_events_by_frame() {
+        let path =
+            std::env::temp_dir().join(format!("ash-lern2-replay-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        let events = all_events();
+
+        let mut recorder = InputRecorder::create(path).unwrap();
+        recorder.record(events[0]);
+        recorder.record(events[1]);
+        recorder.end_frame(0.016);
+        recorder.end_frame(1.0 / 3.0);
+        for &event in &events[2..] {
+            recorder.record(event);
+        }
+        recorder.end_frame(0.25);
+        // События после последнего кадра не относятся ни к одному кадру.
+        recorder.record(events[0]);
+        recorder.finish();
+        drop(recorder);
+
+        let player = InputPlayer::load(path);
+        std::fs::remove_file(path).unwrap();
+        let mut player = player.unwrap();
+        assert_eq!(player.frame_count(), 3);
+        let frames = std::iter::from_fn(|| player.next_frame()).collect::<Vec<_>>();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0].events, &events[..2]);
+        assert_eq!(frames[0].delta_time, 0.016);
+        assert!(frames[1].events.is_empty());
+        assert_eq!(frames[1].delta_time, 1.0 / 3.0);
+        assert_eq!(frames[2].events, &events[2..]);
+        assert_eq!(frames[2].delta_time, 0.25);
+    }
+}
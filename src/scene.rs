@@ -0,0 +1,523 @@
+use glam::{Mat4, Quat, Vec3};
+
+use std::path::Path;
+
+// Граф сцены: узлы с локальными преобразованиями относительно родителя. Мировая матрица узла —
+// произведение матриц всех его предков и его собственной. Она хранится в узле и пересчитывается
+// в update_world_matrices только для узлов, у которых изменилось преобразование, и их потомков.
+//
+// Родитель всегда добавляется раньше потомка, поэтому в массиве узлов родитель стоит раньше детей,
+// и мировые матрицы считаются одним проходом по массиву, без рекурсии.
+
+// Узел, созданный в Scene::add_node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+// Сетка, созданная в SceneRenderer::create_mesh.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MeshHandle(pub(crate) usize);
+
+// Текстура, созданная в SceneRenderer::create_texture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextureHandle(pub(crate) usize);
+
+// Палитра матриц суставов, созданная в SceneRenderer::create_skin.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SkinHandle(pub(crate) usize);
+
+// Материал, добавленный в Scene::add_material.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct MaterialId(usize);
+
+// Вершина сетки: позиция и нормаль в координатах модели и текстурные координаты.
+// Начало текстурных координат в левом верхнем углу текстуры.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MeshVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+}
+
+// Суставы, к которым привязана вершина скелетной сетки, и их веса. Сумма весов равна 1.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct VertexJoints {
+    // Номера суставов в палитре скина.
+    pub joints: [u32; 4],
+    pub weights: [f32; 4],
+}
+
+// Сетка в памяти процессора, из нее SceneRenderer создает буферы вершин и индексов.
+#[derive(Debug, Clone, Default)]
+pub struct MeshData {
+    pub vertices: Vec<MeshVertex>,
+    pub indices: Vec<u32>,
+    // У скелетной сетки по элементу на вершину, у обычной пусто.
+    pub joints: Vec<VertexJoints>,
+}
+
+impl MeshData {
+    // Куб с ребром 1 и центром в начале координат. У каждой грани свои вершины, чтобы у граней были свои нормали.
+    // Вершины грани обходятся против часовой стрелки, если смотреть на нее снаружи.
+    pub fn cube() -> Self {
+        // Нормаль грани и две оси в ее плоскости, векторное произведение которых равно нормали.
+        let faces: [([f32; 3], [f32; 3], [f32; 3]); 6] = [
+            ([1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]),
+            ([-1.0, 0.0, 0.0], [0.0, 0.0, 1.0], [0.0, 1.0, 0.0]),
+            ([0.0, 1.0, 0.0], [0.0, 0.0, 1.0], [1.0, 0.0, 0.0]),
+            ([0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]),
+            ([0.0, 0.0, 1.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]),
+            ([0.0, 0.0, -1.0], [0.0, 1.0, 0.0], [1.0, 0.0, 0.0]),
+        ];
+        let mut mesh = MeshData::default();
+        for (normal, u, v) in faces {
+            let first_vertex = mesh.vertices.len() as u32;
+            for (a, b) in [(-0.5, -0.5), (0.5, -0.5), (0.5, 0.5), (-0.5, 0.5)] {
+                mesh.vertices.push(MeshVertex {
+                    position: [0, 1, 2].map(|axis| normal[axis] * 0.5 + u[axis] * a + v[axis] * b),
+                    normal,
+                    uv: [a + 0.5, 0.5 - b],
+                });
+            }
+            mesh.indices
+                .extend([0, 1, 2, 0, 2, 3].map(|index| first_vertex + index));
+        }
+        mesh
+    }
+
+    // Заменяет нормали сглаженными: нормаль вершины — сумма нормалей треугольников, в которые она входит.
+    // Векторное произведение не нормируется, поэтому большие треугольники влияют сильнее.
+    pub fn compute_normals(&mut self) {
+        let mut normals = vec![Vec3::ZERO; self.vertices.len()];
+        for triangle in self.indices.chunks_exact(3) {
+            let [a, b, c] = [0, 1, 2].map(|corner| triangle[corner] as usize);
+            let position = |index: usize| Vec3::from(self.vertices[index].position);
+            let normal = (position(b) - position(a)).cross(position(c) - position(a));
+            for index in [a, b, c] {
+                normals[index] += normal;
+            }
+        }
+        for (vertex, normal) in self.vertices.iter_mut().zip(normals) {
+            vertex.normal = normal.normalize_or_zero().to_array();
+        }
+    }
+
+    // Наименьший и наибольший углы ограничивающего параллелепипеда, выровненного по осям.
+    pub fn bounds(&self) -> Option<(Vec3, Vec3)> {
+        let mut positions = self
+            .vertices
+            .iter()
+            .map(|vertex| Vec3::from(vertex.position));
+        let first = positions.next()?;
+        Some(positions.fold((first, first), |(min, max), position| {
+            (min.min(position), max.max(position))
+        }))
+    }
+}
+
+// Пиксели текстуры в памяти процессора: RGBA в sRGB, по 4 байта на пиксель, строки сверху вниз.
+#[derive(Debug, Clone)]
+pub struct TextureImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl TextureImage {
+    // Читает PNG любого формата пикселей и приводит его к RGBA с 8 битами на канал.
+    pub fn load_png(path: &Path) -> Result<Self, String> {
+        let file =
+            std::fs::File::open(path).map_err(|error| format!("{}: {}", path.display(), error))?;
+        Self::decode_png(std::io::BufReader::new(file))
+            .map_err(|error| format!("{}: {}", path.display(), error))
+    }
+
+    pub fn decode_png(reader: impl std::io::Read) -> Result<Self, String> {
+        let mut decoder = png::Decoder::new(reader);
+        decoder.set_transformations(png::Transformations::normalize_to_color8());
+        let mut reader = decoder.read_info().map_err(|error| error.to_string())?;
+        let mut data = vec![0; reader.output_buffer_size()];
+        let info = reader
+            .next_frame(&mut data)
+            .map_err(|error| error.to_string())?;
+        data.truncate(info.buffer_size());
+        let pixels = match info.color_type {
+            png::ColorType::Rgba => data,
+            png::ColorType::Rgb => data
+                .chunks_exact(3)
+                .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+                .collect(),
+            png::ColorType::GrayscaleAlpha => data
+                .chunks_exact(2)
+                .flat_map(|ga| [ga[0], ga[0], ga[0], ga[1]])
+                .collect(),
+            png::ColorType::Grayscale => data.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+            // Палитра раскрывается в RGB или RGBA преобразованием normalize_to_color8.
+            png::ColorType::Indexed => return Err("unexpected indexed PNG".to_owned()),
+        };
+        Ok(TextureImage {
+            width: info.width,
+            height: info.height,
+            pixels,
+        })
+    }
+}
+
+// Внешний вид поверхности.
+#[derive(Debug, Clone, Copy)]
+pub struct Material {
+    // Линейный цвет RGBA.
+    pub base_color: [f32; 4],
+    // Текстура в sRGB, на которую умножается base_color. Без нее поверхность одного цвета.
+    pub base_color_texture: Option<TextureHandle>,
+    // Параметры металличности и шероховатости PBR от 0 до 1.
+    pub metallic: f32,
+    pub roughness: f32,
+}
+
+impl Material {
+    // Неметаллический матовый материал одного цвета.
+    pub fn from_color(base_color: [f32; 4]) -> Self {
+        Material {
+            base_color,
+            base_color_texture: None,
+            metallic: 0.0,
+            roughness: 1.0,
+        }
+    }
+}
+
+// Проекция камеры, загруженной вместе со сценой. Камера смотрит вдоль оси -z своего узла.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CameraProjection {
+    Perspective {
+        // Угол обзора по вертикали в радианах.
+        vertical_fov: f32,
+        // Отношение ширины к высоте. Без него берется отношение сторон окна.
+        aspect_ratio: Option<f32>,
+        near: f32,
+        // Без дальней плоскости проекция бесконечная.
+        far: Option<f32>,
+    },
+    Orthographic {
+        half_width: f32,
+        half_height: f32,
+        near: f32,
+        far: f32,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LightKind {
+    // Свет из бесконечности вдоль оси -z узла.
+    Directional,
+    // Свет из точки во все стороны.
+    Point,
+    // Конус вдоль оси -z узла. Углы от оси в радианах: внутри inner свет полный, за outer его нет.
+    Spot {
+        inner_cone_angle: f32,
+        outer_cone_angle: f32,
+    },
+}
+
+// Источник света узла.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Light {
+    pub kind: LightKind,
+    // Линейный цвет RGB.
+    pub color: [f32; 3],
+    // Сила света: освещенность в люксах для направленного света, сила в канделах для остальных.
+    pub intensity: f32,
+    // Расстояние, дальше которого свет не действует. Без него свет убывает бесконечно.
+    pub range: Option<f32>,
+}
+
+// Преобразование узла относительно родителя: сначала масштаб, затем поворот, затем перенос.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Transform {
+    pub const IDENTITY: Transform = Transform {
+        translation: Vec3::ZERO,
+        rotation: Quat::IDENTITY,
+        scale: Vec3::ONE,
+    };
+
+    pub fn from_translation(translation: Vec3) -> Self {
+        Transform {
+            translation,
+            ..Self::IDENTITY
+        }
+    }
+
+    pub fn matrix(&self) -> Mat4 {
+        Mat4::from_scale_rotation_translation(self.scale, self.rotation, self.translation)
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+struct Node {
+    name: String,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    local: Transform,
+    world: Mat4,
+    // Локальное преобразование изменилось после последнего пересчета мировых матриц.
+    dirty: bool,
+    mesh: Option<(MeshHandle, MaterialId)>,
+    skin: Option<SkinHandle>,
+    camera: Option<CameraProjection>,
+    light: Option<Light>,
+}
+
+// Камера сцены и ее мировая матрица: положение и поворот камеры в мире.
+#[derive(Debug, Clone, Copy)]
+pub struct SceneCamera {
+    pub node: NodeId,
+    pub projection: CameraProjection,
+    pub world: Mat4,
+}
+
+// Источник света сцены и мировая матрица его узла.
+#[derive(Debug, Clone, Copy)]
+pub struct SceneLight {
+    pub light: Light,
+    pub world: Mat4,
+}
+
+// Одна отрисовка, которую дает обход сцены.
+#[derive(Debug, Clone, Copy)]
+pub struct SceneDraw {
+    pub mesh: MeshHandle,
+    pub material: Material,
+    pub world: Mat4,
+    // Палитра суставов для скелетной сетки. Без нее сетка рисуется в позе привязки.
+    pub skin: Option<SkinHandle>,
+}
+
+#[derive(Default)]
+pub struct Scene {
+    nodes: Vec<Node>,
+    materials: Vec<Material>,
+}
+
+impl Scene {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Добавляет узел. Без родителя узел становится корнем сцены.
+    pub fn add_node(&mut self, name: &str, parent: Option<NodeId>, local: Transform) -> NodeId {
+        let id = NodeId(self.nodes.len());
+        if let Some(parent) = parent {
+            self.nodes[parent.0].children.push(id);
+        }
+        self.nodes.push(Node {
+            name: name.to_owned(),
+            parent,
+            children: Vec::new(),
+            local,
+            world: Mat4::IDENTITY,
+            dirty: true,
+            mesh: None,
+            skin: None,
+            camera: None,
+            light: None,
+        });
+        id
+    }
+
+    pub fn add_material(&mut self, material: Material) -> MaterialId {
+        self.materials.push(material);
+        MaterialId(self.materials.len() - 1)
+    }
+
+    pub fn material_mut(&mut self, material: MaterialId) -> &mut Material {
+        &mut self.materials[material.0]
+    }
+
+    // Узел с сеткой рисуется ее материалом. Узлы без сетки только группируют детей.
+    pub fn set_mesh(&mut self, node: NodeId, mesh: MeshHandle, material: MaterialId) {
+        self.nodes[node.0].mesh = Some((mesh, material));
+    }
+
+    // Скелетная сетка узла деформируется палитрой skin.
+    pub fn set_skin(&mut self, node: NodeId, skin: SkinHandle) {
+        self.nodes[node.0].skin = Some(skin);
+    }
+
+    pub fn set_camera(&mut self, node: NodeId, projection: CameraProjection) {
+        self.nodes[node.0].camera = Some(projection);
+    }
+
+    // Источник света светит из узла и двигается вместе с ним. Заменяет прежний источник узла.
+    pub fn set_light(&mut self, node: NodeId, light: Light) {
+        self.nodes[node.0].light = Some(light);
+    }
+
+    pub fn light(&self, node: NodeId) -> Option<Light> {
+        self.nodes[node.0].light
+    }
+
+    // Гасит источник света узла. Сам узел остается в сцене.
+    pub fn remove_light(&mut self, node: NodeId) -> Option<Light> {
+        self.nodes[node.0].light.take()
+    }
+
+    pub fn name(&self, node: NodeId) -> &str {
+        &self.nodes[node.0].name
+    }
+
+    pub fn parent(&self, node: NodeId) -> Option<NodeId> {
+        self.nodes[node.0].parent
+    }
+
+    pub fn children(&self, node: NodeId) -> &[NodeId] {
+        &self.nodes[node.0].children
+    }
+
+    // Первый узел с таким именем.
+    pub fn find(&self, name: &str) -> Option<NodeId> {
+        self.nodes
+            .iter()
+            .position(|node| node.name == name)
+            .map(NodeId)
+    }
+
+    pub fn local_transform(&self, node: NodeId) -> Transform {
+        self.nodes[node.0].local
+    }
+
+    pub fn set_local_transform(&mut self, node: NodeId, local: Transform) {
+        let node = &mut self.nodes[node.0];
+        if node.local != local {
+            node.local = local;
+            node.dirty = true;
+        }
+    }
+
+    // Мировая матрица на момент последнего update_world_matrices.
+    pub fn world_matrix(&self, node: NodeId) -> Mat4 {
+        self.nodes[node.0].world
+    }
+
+    // Пересчитывает мировые матрицы измененных узлов и всех их потомков. Возвращает число пересчитанных узлов.
+    pub fn update_world_matrices(&mut self) -> usize {
+        // Изменился ли узел с этим номером в текущем пересчете. Родитель всегда проверен раньше детей.
+        let mut changed = vec![false; self.nodes.len()];
+        for index in 0..self.nodes.len() {
+            let parent = self.nodes[index].parent;
+            let parent_changed = parent.is_some_and(|parent| changed[parent.0]);
+            if !self.nodes[index].dirty && !parent_changed {
+                continue;
+            }
+            let parent_world = parent.map_or(Mat4::IDENTITY, |parent| self.nodes[parent.0].world);
+            let node = &mut self.nodes[index];
+            node.world = parent_world * node.local.matrix();
+            node.dirty = false;
+            changed[index] = true;
+        }
+        changed.into_iter().filter(|&changed| changed).count()
+    }
+
+    // Все узлы с сетками и их мировые матрицы. Вызывается после update_world_matrices.
+    pub fn draws(&self) -> impl Iterator<Item = SceneDraw> + '_ {
+        self.nodes.iter().filter_map(move |node| {
+            node.mesh.map(|(mesh, material)| SceneDraw {
+                mesh,
+                material: self.materials[material.0],
+                world: node.world,
+                skin: node.skin,
+            })
+        })
+    }
+
+    // Все камеры сцены в порядке добавления узлов. Вызывается после update_world_matrices.
+    pub fn cameras(&self) -> impl Iterator<Item = SceneCamera> + '_ {
+        self.nodes.iter().enumerate().filter_map(|(index, node)| {
+            node.camera.map(|projection| SceneCamera {
+                node: NodeId(index),
+                projection,
+                world: node.world,
+            })
+        })
+    }
+
+    // Все источники света сцены. Вызывается после update_world_matrices.
+    pub fn lights(&self) -> impl Iterator<Item = SceneLight> + '_ {
+        self.nodes.iter().filter_map(|node| {
+            node.light.map(|light| SceneLight {
+                light,
+                world: node.world,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Корень с двумя ветками: root -> arm -> hand и root -> leg.
+    fn skeleton() -> (Scene, [NodeId; 4]) {
+        let mut scene = Scene::new();
+        let root = scene.add_node("root", None, Transform::IDENTITY);
+        let arm = scene.add_node("arm", Some(root), Transform::from_translation(Vec3::X));
+        let hand = scene.add_node("hand", Some(arm), Transform::from_translation(Vec3::X));
+        let leg = scene.add_node("leg", Some(root), Transform::from_translation(-Vec3::Y));
+        (scene, [root, arm, hand, leg])
+    }
+
+    fn position(scene: &Scene, node: NodeId) -> Vec3 {
+        scene.world_matrix(node).w_axis.truncate()
+    }
+
+    #[test]
+    fn parent_change_updates_children() {
+        let (mut scene, [root, arm, hand, leg]) = skeleton();
+        assert_eq!(scene.update_world_matrices(), 4);
+        assert_eq!(position(&scene, hand), Vec3::new(2.0, 0.0, 0.0));
+
+        scene.set_local_transform(root, Transform::from_translation(Vec3::Z));
+        assert_eq!(scene.update_world_matrices(), 4);
+        assert_eq!(position(&scene, arm), Vec3::new(1.0, 0.0, 1.0));
+        assert_eq!(position(&scene, hand), Vec3::new(2.0, 0.0, 1.0));
+        assert_eq!(position(&scene, leg), Vec3::new(0.0, -1.0, 1.0));
+    }
+
+    #[test]
+    fn unchanged_subtrees_are_skipped() {
+        let (mut scene, [_, arm, hand, leg]) = skeleton();
+        scene.update_world_matrices();
+        assert_eq!(scene.update_world_matrices(), 0);
+
+        // Меняется только ветка руки, нога и корень не пересчитываются.
+        scene.set_local_transform(arm, Transform::from_translation(Vec3::Y));
+        assert_eq!(scene.update_world_matrices(), 2);
+        assert_eq!(position(&scene, hand), Vec3::new(1.0, 1.0, 0.0));
+        assert_eq!(position(&scene, leg), Vec3::new(0.0, -1.0, 0.0));
+
+        scene.set_local_transform(hand, Transform::IDENTITY);
+        assert_eq!(scene.update_world_matrices(), 1);
+        assert_eq!(position(&scene, hand), Vec3::Y);
+    }
+
+    #[test]
+    fn equal_transform_does_not_mark_dirty() {
+        let (mut scene, [root, arm, ..]) = skeleton();
+        scene.update_world_matrices();
+        scene.set_local_transform(root, Transform::IDENTITY);
+        scene.set_local_transform(arm, Transform::from_translation(Vec3::X));
+        assert_eq!(scene.update_world_matrices(), 0);
+    }
+}
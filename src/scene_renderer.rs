@@ -0,0 +1,1163 @@
+use ash::vk;
+use glam::{Mat4, Vec3};
+
+use std::ffi::CString;
+
+use crate::instancing::DEPTH_CLEAR_VALUE;
+use crate::memory::{as_bytes, find_memory_type, Buffer};
+use crate::push_constants::PushConstants;
+use crate::render_graph::{
+    ImageDesc, ImageHandle, ImageUsage, ImportedImage, LoadOp, RenderGraph, RenderGraphCache,
+};
+use crate::scene::{
+    LightKind, MeshData, MeshHandle, MeshVertex, SceneDraw, SceneLight, SkinHandle, TextureHandle,
+    TextureImage, VertexJoints,
+};
+
+// Отрисовка графа сцены. Сетки загружаются в видеопамять один раз, а каждый кадр обход сцены дает список
+// отрисовок: сетку, материал и мировую матрицу узла. Матрица вида и проекции и положение камеры общие
+// для кадра и лежат в uniform-буфере, а данные отрисовки передаются через push-константы. Текстура материала
+// привязывается вторым набором дескрипторов, у материалов без текстуры это белая текстура 1x1.
+//
+// Освещение считается в каждом фрагменте по Блинну — Фонгу. Источники света прохода дописываются
+// в storage-буфер кадра, а отрисовка получает их диапазон в push-константах. Если источников нет,
+// сцену освещает один направленный свет по умолчанию.
+//
+// Скелетные сетки рисуются отдельным конвейером. Палитры матриц суставов всех скелетных отрисовок кадра
+// складываются в один storage-буфер кадра, а отрисовка получает начало своей палитры в push-константах.
+
+// Текстуры материалов хранятся в sRGB и при выборке переводятся в линейное пространство.
+const TEXTURE_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+// Сколько текстур может существовать одновременно.
+const MAX_TEXTURES: u32 = 256;
+// Сколько матриц суставов помещается в буфер кадра сначала. Буфер растет, если кадру нужно больше.
+const INITIAL_JOINT_CAPACITY: usize = 64;
+// Сколько источников света помещается в буфер кадра сначала.
+const INITIAL_LIGHT_CAPACITY: usize = 16;
+
+// Значения Light::kind в scene.frag.
+const LIGHT_DIRECTIONAL: u32 = 0;
+const LIGHT_POINT: u32 = 1;
+const LIGHT_SPOT: u32 = 2;
+
+// Uniform-буфер кадра. Раскладка совпадает с блоком FrameUniforms в scene.vert и scene.frag.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct FrameUniforms {
+    view_projection: [f32; 16],
+    // Положение камеры в мире, w не используется.
+    camera_position: [f32; 4],
+}
+
+// Источник света в буфере кадра. Раскладка std430 совпадает со структурой Light в scene.frag.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct GpuLight {
+    position: [f32; 3],
+    // Расстояние, дальше которого свет не действует. Ноль — без предела.
+    range: f32,
+    // Куда светит источник: ось -z его узла.
+    direction: [f32; 3],
+    kind: u32,
+    // Цвет, умноженный на силу света.
+    color: [f32; 3],
+    inner_cone_cos: f32,
+    outer_cone_cos: f32,
+    _padding: [f32; 3],
+}
+
+impl GpuLight {
+    fn new(light: &SceneLight) -> Self {
+        let (kind, inner_cone_cos, outer_cone_cos) = match light.light.kind {
+            LightKind::Directional => (LIGHT_DIRECTIONAL, 1.0, 1.0),
+            LightKind::Point => (LIGHT_POINT, 1.0, 1.0),
+            LightKind::Spot {
+                inner_cone_angle,
+                outer_cone_angle,
+            } => (LIGHT_SPOT, inner_cone_angle.cos(), outer_cone_angle.cos()),
+        };
+        let [r, g, b] = light.light.color;
+        let intensity = light.light.intensity;
+        GpuLight {
+            position: light.world.w_axis.truncate().to_array(),
+            range: light.light.range.unwrap_or(0.0),
+            direction: (-light.world.z_axis.truncate())
+                .normalize_or_zero()
+                .to_array(),
+            kind,
+            color: [r * intensity, g * intensity, b * intensity],
+            inner_cone_cos,
+            outer_cone_cos,
+            _padding: [0.0; 3],
+        }
+    }
+
+    // Свет сверху сбоку для сцен без источников.
+    fn default_light() -> Self {
+        GpuLight {
+            position: [0.0; 3],
+            range: 0.0,
+            direction: (-Vec3::new(0.4, 1.0, 0.6).normalize()).to_array(),
+            kind: LIGHT_DIRECTIONAL,
+            color: [0.8; 3],
+            inner_cone_cos: 1.0,
+            outer_cone_cos: 1.0,
+            _padding: [0.0; 3],
+        }
+    }
+}
+
+// Push-константы scene.vert, scene_skinned.vert и scene.frag.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct DrawPushConstants {
+    model: [f32; 16],
+    base_color: [f32; 4],
+    // Номер первой матрицы палитры отрисовки в буфере суставов кадра.
+    joint_offset: u32,
+    metallic: f32,
+    roughness: f32,
+    // Диапазон источников света прохода в буфере света кадра.
+    light_offset: u32,
+    light_count: u32,
+}
+
+unsafe impl PushConstants for DrawPushConstants {
+    const STAGES: vk::ShaderStageFlags = vk::ShaderStageFlags::from_raw(
+        vk::ShaderStageFlags::VERTEX.as_raw() | vk::ShaderStageFlags::FRAGMENT.as_raw(),
+    );
+}
+
+struct GpuMesh {
+    vertices: Buffer,
+    indices: Buffer,
+    // Суставы и веса вершин, только у скелетных сеток.
+    joints: Option<Buffer>,
+    index_count: u32,
+}
+
+struct TextureData {
+    image: vk::Image,
+    memory: vk::DeviceMemory,
+    view: vk::ImageView,
+    descriptor_set: vk::DescriptorSet,
+    extent: vk::Extent2D,
+}
+
+// Uniform-буфер, буферы матриц суставов и источников света одного кадра в полете и набор дескрипторов,
+// который на них указывает. Промежуточные буферы загрузки текстур освобождаются, когда этот кадр начинается снова.
+struct FrameData {
+    uniforms: Buffer,
+    joints: FrameArray,
+    lights: FrameArray,
+    descriptor_set: vk::DescriptorSet,
+    staging: Vec<Buffer>,
+}
+
+// Все, что нужно для записи одной отрисовки.
+struct DrawCommand {
+    vertex_buffer: vk::Buffer,
+    // Есть только у отрисовок скелетной сетки с палитрой, они рисуются скелетным конвейером.
+    joint_buffer: Option<vk::Buffer>,
+    index_buffer: vk::Buffer,
+    index_count: u32,
+    texture_set: vk::DescriptorSet,
+    push_constants: DrawPushConstants,
+}
+
+pub struct SceneRenderer {
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    pipeline: vk::Pipeline,
+    skinned_pipeline: vk::Pipeline,
+    pipeline_layout: vk::PipelineLayout,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    texture_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    sampler: vk::Sampler,
+    depth_format: vk::Format,
+    meshes: Vec<GpuMesh>,
+    textures: Vec<TextureData>,
+    // Палитры матриц суставов, которые рисуются в следующем add_pass.
+    skins: Vec<Vec<[f32; 16]>>,
+    // Белая текстура для материалов без своей.
+    white_texture: TextureHandle,
+    // Текстуры, созданные после прошлой отрисовки, и буферы с их пикселями.
+    pending_uploads: Vec<(TextureHandle, Buffer)>,
+    frames: Vec<FrameData>,
+}
+
+impl SceneRenderer {
+    pub fn new(
+        device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        graph_cache: &mut RenderGraphCache,
+        color_format: vk::Format,
+        depth_format: vk::Format,
+        frames_in_flight: usize,
+    ) -> Self {
+        let bindings = [
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT)
+                .build(),
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(1)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::VERTEX)
+                .build(),
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(2)
+                .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .build(),
+        ];
+        let descriptor_set_layout_create_info =
+            vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+        let descriptor_set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(&descriptor_set_layout_create_info, None)
+                .expect("Failed to create scene Descriptor Set Layout!")
+        };
+
+        // Текстуры повторяются за пределами [0, 1], как принято в моделях.
+        let sampler_create_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .address_mode_u(vk::SamplerAddressMode::REPEAT)
+            .address_mode_v(vk::SamplerAddressMode::REPEAT)
+            .address_mode_w(vk::SamplerAddressMode::REPEAT);
+        let sampler = unsafe {
+            device
+                .create_sampler(&sampler_create_info, None)
+                .expect("Failed to create scene Sampler!")
+        };
+        let samplers = [sampler];
+        let texture_bindings = [
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .build(),
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(1)
+                .descriptor_type(vk::DescriptorType::SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .immutable_samplers(&samplers)
+                .build(),
+        ];
+        let texture_set_layout_create_info =
+            vk::DescriptorSetLayoutCreateInfo::builder().bindings(&texture_bindings);
+        let texture_set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(&texture_set_layout_create_info, None)
+                .expect("Failed to create scene texture Descriptor Set Layout!")
+        };
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: frames_in_flight as u32,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: 2 * frames_in_flight as u32,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::SAMPLED_IMAGE,
+                descriptor_count: MAX_TEXTURES,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::SAMPLER,
+                descriptor_count: MAX_TEXTURES,
+            },
+        ];
+        let descriptor_pool_create_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(frames_in_flight as u32 + MAX_TEXTURES)
+            .pool_sizes(&pool_sizes);
+        let descriptor_pool = unsafe {
+            device
+                .create_descriptor_pool(&descriptor_pool_create_info, None)
+                .expect("Failed to create scene Descriptor Pool!")
+        };
+
+        let set_layouts = vec![descriptor_set_layout; frames_in_flight];
+        let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&set_layouts);
+        let descriptor_sets = unsafe {
+            device
+                .allocate_descriptor_sets(&descriptor_set_allocate_info)
+                .expect("Failed to allocate scene Descriptor Sets!")
+        };
+        let frames = descriptor_sets
+            .into_iter()
+            .map(|descriptor_set| {
+                let uniforms = Buffer::new(
+                    device,
+                    &memory_properties,
+                    std::mem::size_of::<FrameUniforms>() as vk::DeviceSize,
+                    vk::BufferUsageFlags::UNIFORM_BUFFER,
+                    &[vk::MemoryPropertyFlags::HOST_VISIBLE
+                        | vk::MemoryPropertyFlags::HOST_COHERENT],
+                );
+                let buffer_info = [vk::DescriptorBufferInfo {
+                    buffer: uniforms.buffer,
+                    offset: 0,
+                    range: vk::WHOLE_SIZE,
+                }];
+                let write = vk::WriteDescriptorSet::builder()
+                    .dst_set(descriptor_set)
+                    .dst_binding(0)
+                    .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+                    .buffer_info(&buffer_info);
+                unsafe { device.update_descriptor_sets(std::slice::from_ref(&write), &[]) };
+                let joints = FrameArray::new(
+                    device,
+                    &memory_properties,
+                    descriptor_set,
+                    1,
+                    INITIAL_JOINT_CAPACITY * std::mem::size_of::<[f32; 16]>(),
+                );
+                let lights = FrameArray::new(
+                    device,
+                    &memory_properties,
+                    descriptor_set,
+                    2,
+                    INITIAL_LIGHT_CAPACITY * std::mem::size_of::<GpuLight>(),
+                );
+                FrameData {
+                    uniforms,
+                    joints,
+                    lights,
+                    descriptor_set,
+                    staging: Vec::new(),
+                }
+            })
+            .collect();
+
+        let push_constant_ranges = [DrawPushConstants::range()];
+        let set_layouts = [descriptor_set_layout, texture_set_layout];
+        let pipeline_layout_create_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&set_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+        let pipeline_layout = unsafe {
+            device
+                .create_pipeline_layout(&pipeline_layout_create_info, None)
+                .expect("Failed to create scene Pipeline Layout!")
+        };
+
+        let pipeline = create_pipeline(
+            device,
+            graph_cache,
+            color_format,
+            depth_format,
+            pipeline_layout,
+            false,
+        );
+        let skinned_pipeline = create_pipeline(
+            device,
+            graph_cache,
+            color_format,
+            depth_format,
+            pipeline_layout,
+            true,
+        );
+
+        let mut renderer = SceneRenderer {
+            memory_properties,
+            pipeline,
+            skinned_pipeline,
+            pipeline_layout,
+            descriptor_set_layout,
+            texture_set_layout,
+            descriptor_pool,
+            sampler,
+            depth_format,
+            meshes: Vec::new(),
+            textures: Vec::new(),
+            skins: Vec::new(),
+            white_texture: TextureHandle(0),
+            pending_uploads: Vec::new(),
+            frames,
+        };
+        renderer.white_texture = renderer.create_texture(
+            device,
+            &TextureImage {
+                width: 1,
+                height: 1,
+                pixels: vec![255; 4],
+            },
+        );
+        renderer
+    }
+
+    // Загружает сетку в память, доступную видеокарте. Сетки живут, пока живет SceneRenderer.
+    // Сетка с суставами вершин рисуется скелетной, если у ее отрисовки есть палитра.
+    pub fn create_mesh(&mut self, device: &ash::Device, mesh: &MeshData) -> MeshHandle {
+        let upload =
+            |data: &[u8], usage| Buffer::upload(device, &self.memory_properties, data, usage);
+        let vertices = upload(
+            as_bytes(&mesh.vertices),
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+        );
+        let indices = upload(as_bytes(&mesh.indices), vk::BufferUsageFlags::INDEX_BUFFER);
+        let joints = if mesh.joints.is_empty() {
+            None
+        } else {
+            assert_eq!(
+                mesh.joints.len(),
+                mesh.vertices.len(),
+                "Scene mesh joints do not match its vertices"
+            );
+            Some(upload(
+                as_bytes(&mesh.joints),
+                vk::BufferUsageFlags::VERTEX_BUFFER,
+            ))
+        };
+        self.meshes.push(GpuMesh {
+            vertices,
+            indices,
+            joints,
+            index_count: mesh.indices.len() as u32,
+        });
+        MeshHandle(self.meshes.len() - 1)
+    }
+
+    // Сколько еще текстур можно создать.
+    pub fn remaining_textures(&self) -> usize {
+        MAX_TEXTURES as usize - self.textures.len()
+    }
+
+    // Создает текстуру материала. Пиксели загружаются в видеопамять в ближайшем add_pass, который что-то рисует,
+    // до этого текстуру уже можно назначать материалам.
+    pub fn create_texture(&mut self, device: &ash::Device, image: &TextureImage) -> TextureHandle {
+        assert_eq!(
+            image.pixels.len(),
+            image.width as usize * image.height as usize * 4,
+            "Scene texture size does not match its pixels"
+        );
+        assert!(
+            image.width > 0 && image.height > 0,
+            "Scene texture must not be empty"
+        );
+        assert!(
+            (self.textures.len() as u32) < MAX_TEXTURES,
+            "Too many scene textures"
+        );
+
+        let staging = Buffer::new(
+            device,
+            &self.memory_properties,
+            image.pixels.len() as vk::DeviceSize,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            &[vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT],
+        );
+        unsafe {
+            let data = device
+                .map_memory(staging.memory, 0, staging.size, vk::MemoryMapFlags::empty())
+                .expect("Failed to map scene texture staging memory!");
+            std::ptr::copy_nonoverlapping(
+                image.pixels.as_ptr(),
+                data as *mut u8,
+                image.pixels.len(),
+            );
+            device.unmap_memory(staging.memory);
+        }
+
+        let texture = TextureHandle(self.textures.len());
+        let data = self.create_texture_data(
+            device,
+            vk::Extent2D {
+                width: image.width,
+                height: image.height,
+            },
+        );
+        self.textures.push(data);
+        self.pending_uploads.push((texture, staging));
+        texture
+    }
+
+    // Создает палитру из joint_count единичных матриц суставов.
+    pub fn create_skin(&mut self, joint_count: usize) -> SkinHandle {
+        self.skins
+            .push(vec![Mat4::IDENTITY.to_cols_array(); joint_count]);
+        SkinHandle(self.skins.len() - 1)
+    }
+
+    // Задает матрицы суставов палитры, обычно Skin::joint_matrices после пересчета мировых матриц сцены.
+    // Они попадают в видеопамять в следующем add_pass, который рисует эту палитру.
+    pub fn set_joint_matrices(&mut self, skin: SkinHandle, matrices: &[Mat4]) {
+        let palette = &mut self.skins[skin.0];
+        assert_eq!(
+            palette.len(),
+            matrices.len(),
+            "Joint matrices do not match the skin"
+        );
+        for (target, matrix) in palette.iter_mut().zip(matrices) {
+            *target = matrix.to_cols_array();
+        }
+    }
+
+    // Вызывается раз за кадр после ожидания забора кадра frame_index, до его add_pass. Видеокарта закончила
+    // прошлый кадр в этом слоте, поэтому его промежуточные буферы освобождаются, а палитры суставов
+    // и источники света нового кадра пишутся в буферы кадра с начала.
+    pub fn begin_frame(&mut self, device: &ash::Device, frame_index: usize) {
+        let frame = &mut self.frames[frame_index];
+        for buffer in frame.staging.drain(..) {
+            unsafe { buffer.destroy(device) };
+        }
+        frame.joints.used = 0;
+        frame.lights.used = 0;
+    }
+
+    // Добавляет в граф проход name, рисующий draws с освещением lights поверх target: обход Scene::draws
+    // и Scene::lights после пересчета мировых матриц или списки, собранные из ECS. Вызывается после begin_frame
+    // этого кадра.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_pass(
+        &mut self,
+        device: &ash::Device,
+        graph: &mut RenderGraph,
+        target: ImageHandle,
+        extent: vk::Extent2D,
+        frame_index: usize,
+        name: &str,
+        draws: impl IntoIterator<Item = SceneDraw>,
+        lights: impl IntoIterator<Item = SceneLight>,
+        view_projection: [f32; 16],
+        camera_position: [f32; 3],
+    ) {
+        // Для записи команд нужны только буферы сетки, поэтому обход сцены заканчивается здесь, до прохода.
+        // Палитры скелетных отрисовок сразу дописываются в буфер суставов кадра.
+        let mut commands = Vec::new();
+        for draw in draws {
+            let mesh = &self.meshes[draw.mesh.0];
+            if mesh.index_count == 0 {
+                continue;
+            }
+            let texture = draw
+                .material
+                .base_color_texture
+                .unwrap_or(self.white_texture);
+            let mut command = DrawCommand {
+                vertex_buffer: mesh.vertices.buffer,
+                joint_buffer: None,
+                index_buffer: mesh.indices.buffer,
+                index_count: mesh.index_count,
+                texture_set: self.textures[texture.0].descriptor_set,
+                push_constants: DrawPushConstants {
+                    model: draw.world.to_cols_array(),
+                    base_color: draw.material.base_color,
+                    joint_offset: 0,
+                    metallic: draw.material.metallic,
+                    roughness: draw.material.roughness,
+                    light_offset: 0,
+                    light_count: 0,
+                },
+            };
+            if let (Some(joints), Some(skin)) = (&mesh.joints, draw.skin) {
+                command.joint_buffer = Some(joints.buffer);
+                let frame = &mut self.frames[frame_index];
+                command.push_constants.joint_offset = frame.joints.append(
+                    device,
+                    &self.memory_properties,
+                    frame.descriptor_set,
+                    &self.skins[skin.0],
+                );
+            }
+            commands.push(command);
+        }
+        let mut draws = commands;
+        if draws.is_empty() {
+            return;
+        }
+
+        let mut lights = lights
+            .into_iter()
+            .map(|light| GpuLight::new(&light))
+            .collect::<Vec<_>>();
+        if lights.is_empty() {
+            lights.push(GpuLight::default_light());
+        }
+        let frame = &mut self.frames[frame_index];
+        let light_offset = frame.lights.append(
+            device,
+            &self.memory_properties,
+            frame.descriptor_set,
+            &lights,
+        );
+        for draw in &mut draws {
+            draw.push_constants.light_offset = light_offset;
+            draw.push_constants.light_count = lights.len() as u32;
+        }
+
+        // Новые текстуры загружаются только вместе с проходом, который их читает.
+        let mut texture_handles = Vec::new();
+        for (texture, staging) in std::mem::take(&mut self.pending_uploads) {
+            texture_handles.push(self.upload_texture(graph, texture, &staging));
+            self.frames[frame_index].staging.push(staging);
+        }
+
+        let frame = &self.frames[frame_index];
+        unsafe {
+            let data = device
+                .map_memory(
+                    frame.uniforms.memory,
+                    0,
+                    vk::WHOLE_SIZE,
+                    vk::MemoryMapFlags::empty(),
+                )
+                .expect("Failed to map scene uniform memory!")
+                as *mut FrameUniforms;
+            let [x, y, z] = camera_position;
+            data.write(FrameUniforms {
+                view_projection,
+                camera_position: [x, y, z, 1.0],
+            });
+            device.unmap_memory(frame.uniforms.memory);
+        }
+
+        let depth = graph.create_image(
+            &format!("{} depth", name),
+            ImageDesc {
+                format: self.depth_format,
+                extent,
+            },
+        );
+        let pipeline = self.pipeline;
+        let skinned_pipeline = self.skinned_pipeline;
+        let pipeline_layout = self.pipeline_layout;
+        let descriptor_set = frame.descriptor_set;
+        let mut pass = graph
+            .add_pass(name)
+            .color_attachment(target, LoadOp::Load)
+            .depth_attachment(depth, LoadOp::Clear(DEPTH_CLEAR_VALUE));
+        for handle in texture_handles {
+            pass = pass.read_image(
+                handle,
+                ImageUsage::Sampled(vk::PipelineStageFlags::FRAGMENT_SHADER),
+            );
+        }
+        pass.execute(move |context| unsafe {
+            let device = context.device;
+            let command_buffer = context.command_buffer;
+            let viewport = vk::Viewport {
+                x: 0.0,
+                y: 0.0,
+                width: extent.width as f32,
+                height: extent.height as f32,
+                min_depth: 0.0,
+                max_depth: 1.0,
+            };
+            let scissor = vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent,
+            };
+            device.cmd_set_viewport(command_buffer, 0, std::slice::from_ref(&viewport));
+            device.cmd_set_scissor(command_buffer, 0, std::slice::from_ref(&scissor));
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                pipeline_layout,
+                0,
+                &[descriptor_set],
+                &[],
+            );
+            for draw in &draws {
+                // Раскладка у конвейеров общая, поэтому наборы дескрипторов при смене конвейера остаются привязанными.
+                match draw.joint_buffer {
+                    Some(joint_buffer) => {
+                        device.cmd_bind_pipeline(
+                            command_buffer,
+                            vk::PipelineBindPoint::GRAPHICS,
+                            skinned_pipeline,
+                        );
+                        device.cmd_bind_vertex_buffers(command_buffer, 1, &[joint_buffer], &[0]);
+                    }
+                    None => device.cmd_bind_pipeline(
+                        command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        pipeline,
+                    ),
+                }
+                device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    pipeline_layout,
+                    1,
+                    &[draw.texture_set],
+                    &[],
+                );
+                device.cmd_bind_vertex_buffers(command_buffer, 0, &[draw.vertex_buffer], &[0]);
+                device.cmd_bind_index_buffer(
+                    command_buffer,
+                    draw.index_buffer,
+                    0,
+                    vk::IndexType::UINT32,
+                );
+                draw.push_constants
+                    .push(device, command_buffer, pipeline_layout);
+                device.cmd_draw_indexed(command_buffer, draw.index_count, 1, 0, 0, 0);
+            }
+        });
+    }
+
+    pub unsafe fn destroy(&mut self, device: &ash::Device) {
+        for mesh in self.meshes.drain(..) {
+            mesh.vertices.destroy(device);
+            mesh.indices.destroy(device);
+            if let Some(joints) = mesh.joints {
+                joints.destroy(device);
+            }
+        }
+        for texture in self.textures.drain(..) {
+            device.destroy_image_view(texture.view, None);
+            device.destroy_image(texture.image, None);
+            device.free_memory(texture.memory, None);
+        }
+        for (_, staging) in self.pending_uploads.drain(..) {
+            staging.destroy(device);
+        }
+        for frame in self.frames.drain(..) {
+            frame.uniforms.destroy(device);
+            frame.joints.buffer.destroy(device);
+            frame.lights.buffer.destroy(device);
+            for buffer in frame.staging {
+                buffer.destroy(device);
+            }
+        }
+        device.destroy_pipeline(self.pipeline, None);
+        device.destroy_pipeline(self.skinned_pipeline, None);
+        device.destroy_pipeline_layout(self.pipeline_layout, None);
+        // Наборы дескрипторов освобождаются вместе с пулом.
+        device.destroy_descriptor_pool(self.descriptor_pool, None);
+        device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        device.destroy_descriptor_set_layout(self.texture_set_layout, None);
+        device.destroy_sampler(self.sampler, None);
+    }
+
+    // Копирует пиксели новой текстуры из промежуточного буфера. После копирования текстура
+    // навсегда остается в SHADER_READ_ONLY_OPTIMAL.
+    fn upload_texture(
+        &self,
+        graph: &mut RenderGraph,
+        texture: TextureHandle,
+        staging: &Buffer,
+    ) -> ImageHandle {
+        let data = &self.textures[texture.0];
+        let handle = graph.import_image(
+            "scene texture",
+            ImportedImage {
+                image: data.image,
+                view: data.view,
+                format: TEXTURE_FORMAT,
+                extent: data.extent,
+                initial_layout: vk::ImageLayout::UNDEFINED,
+                initial_stage: vk::PipelineStageFlags::TOP_OF_PIPE,
+                final_layout: Some(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+            },
+        );
+        let staging_buffer = staging.buffer;
+        let extent = data.extent;
+        graph
+            .add_pass("scene texture upload")
+            .write_image(handle, ImageUsage::Transfer)
+            .execute(move |context| {
+                let region = vk::BufferImageCopy::builder()
+                    .image_subresource(vk::ImageSubresourceLayers {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        mip_level: 0,
+                        base_array_layer: 0,
+                        layer_count: 1,
+                    })
+                    .image_extent(vk::Extent3D {
+                        width: extent.width,
+                        height: extent.height,
+                        depth: 1,
+                    });
+                unsafe {
+                    context.device.cmd_copy_buffer_to_image(
+                        context.command_buffer,
+                        staging_buffer,
+                        context.image(handle),
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        std::slice::from_ref(&region),
+                    );
+                }
+            });
+        handle
+    }
+
+    fn create_texture_data(&self, device: &ash::Device, extent: vk::Extent2D) -> TextureData {
+        let image_create_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(TEXTURE_FORMAT)
+            .extent(vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            })
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+
+        unsafe {
+            let image = device
+                .create_image(&image_create_info, None)
+                .expect("Failed to create scene texture!");
+            let requirements = device.get_image_memory_requirements(image);
+            let memory_type_index = find_memory_type(
+                &self.memory_properties,
+                requirements.memory_type_bits,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            )
+            .expect("No suitable memory type for scene texture!");
+            let allocate_info = vk::MemoryAllocateInfo::builder()
+                .allocation_size(requirements.size)
+                .memory_type_index(memory_type_index);
+            let memory = device
+                .allocate_memory(&allocate_info, None)
+                .expect("Failed to allocate scene texture memory!");
+            device
+                .bind_image_memory(image, memory, 0)
+                .expect("Failed to bind scene texture memory!");
+
+            let view_create_info = vk::ImageViewCreateInfo::builder()
+                .image(image)
+                .view_type(vk::ImageViewType::TYPE_2D)
+                .format(TEXTURE_FORMAT)
+                .subresource_range(vk::ImageSubresourceRange {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    base_mip_level: 0,
+                    level_count: 1,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                });
+            let view = device
+                .create_image_view(&view_create_info, None)
+                .expect("Failed to create scene texture view!");
+
+            let set_layouts = [self.texture_set_layout];
+            let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+                .descriptor_pool(self.descriptor_pool)
+                .set_layouts(&set_layouts);
+            let descriptor_set = device
+                .allocate_descriptor_sets(&allocate_info)
+                .expect("Failed to allocate scene texture Descriptor Set!")[0];
+            let image_info = [vk::DescriptorImageInfo {
+                sampler: vk::Sampler::null(),
+                image_view: view,
+                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            }];
+            let write = vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                .image_info(&image_info);
+            device.update_descriptor_sets(std::slice::from_ref(&write), &[]);
+
+            TextureData {
+                image,
+                memory,
+                view,
+                descriptor_set,
+                extent,
+            }
+        }
+    }
+}
+
+// Storage-буфер кадра, в который проходы кадра дописывают данные одного типа подряд.
+struct FrameArray {
+    buffer: Buffer,
+    // Привязка буфера в наборе дескрипторов кадра.
+    binding: u32,
+    // Сколько байт уже записано в этом кадре.
+    used: usize,
+}
+
+impl FrameArray {
+    fn new(
+        device: &ash::Device,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        descriptor_set: vk::DescriptorSet,
+        binding: u32,
+        size: usize,
+    ) -> Self {
+        FrameArray {
+            buffer: create_storage_buffer(device, memory_properties, descriptor_set, binding, size),
+            binding,
+            used: 0,
+        }
+    }
+
+    // Дописывает items и возвращает номер первого из них. Если буфер мал, он заменяется большим
+    // с теми же данными: команды кадра еще не записаны, а прошлое использование кадра уже закончилось.
+    fn append<T: Copy>(
+        &mut self,
+        device: &ash::Device,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        descriptor_set: vk::DescriptorSet,
+        items: &[T],
+    ) -> u32 {
+        let offset = self.used;
+        let required = offset + std::mem::size_of_val(items);
+        if required > self.buffer.size as usize {
+            let buffer = create_storage_buffer(
+                device,
+                memory_properties,
+                descriptor_set,
+                self.binding,
+                required.next_power_of_two(),
+            );
+            unsafe {
+                let source = device
+                    .map_memory(
+                        self.buffer.memory,
+                        0,
+                        vk::WHOLE_SIZE,
+                        vk::MemoryMapFlags::empty(),
+                    )
+                    .expect("Failed to map scene storage memory!");
+                let target = device
+                    .map_memory(
+                        buffer.memory,
+                        0,
+                        vk::WHOLE_SIZE,
+                        vk::MemoryMapFlags::empty(),
+                    )
+                    .expect("Failed to map scene storage memory!");
+                std::ptr::copy_nonoverlapping(source as *const u8, target as *mut u8, offset);
+                device.unmap_memory(buffer.memory);
+                device.unmap_memory(self.buffer.memory);
+                std::mem::replace(&mut self.buffer, buffer).destroy(device);
+            }
+        }
+        unsafe {
+            let data = device
+                .map_memory(
+                    self.buffer.memory,
+                    0,
+                    vk::WHOLE_SIZE,
+                    vk::MemoryMapFlags::empty(),
+                )
+                .expect("Failed to map scene storage memory!") as *mut u8;
+            let bytes = as_bytes(items);
+            std::ptr::copy_nonoverlapping(bytes.as_ptr(), data.add(offset), bytes.len());
+            device.unmap_memory(self.buffer.memory);
+        }
+        self.used = required;
+        (offset / std::mem::size_of::<T>()) as u32
+    }
+}
+
+// Storage-буфер на size байт, привязанный к набору дескрипторов кадра.
+fn create_storage_buffer(
+    device: &ash::Device,
+    memory_properties: &vk::PhysicalDeviceMemoryProperties,
+    descriptor_set: vk::DescriptorSet,
+    binding: u32,
+    size: usize,
+) -> Buffer {
+    let buffer = Buffer::new(
+        device,
+        memory_properties,
+        size as vk::DeviceSize,
+        vk::BufferUsageFlags::STORAGE_BUFFER,
+        &[vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT],
+    );
+    let buffer_info = [vk::DescriptorBufferInfo {
+        buffer: buffer.buffer,
+        offset: 0,
+        range: vk::WHOLE_SIZE,
+    }];
+    let write = vk::WriteDescriptorSet::builder()
+        .dst_set(descriptor_set)
+        .dst_binding(binding)
+        .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+        .buffer_info(&buffer_info);
+    unsafe { device.update_descriptor_sets(std::slice::from_ref(&write), &[]) };
+    buffer
+}
+
+fn create_pipeline(
+    device: &ash::Device,
+    graph_cache: &mut RenderGraphCache,
+    color_format: vk::Format,
+    depth_format: vk::Format,
+    pipeline_layout: vk::PipelineLayout,
+    skinned: bool,
+) -> vk::Pipeline {
+    let create_shader_module = |code: &[u8]| {
+        let shader_module_create_info = vk::ShaderModuleCreateInfo {
+            code_size: code.len(),
+            p_code: code.as_ptr() as *const u32,
+            ..Default::default()
+        };
+        unsafe {
+            device
+                .create_shader_module(&shader_module_create_info, None)
+                .expect("Failed to create scene Shader Module!")
+        }
+    };
+    let vert_shader_module = if skinned {
+        create_shader_module(include_bytes!("spv/scene_skinned_vert.spv"))
+    } else {
+        create_shader_module(include_bytes!("spv/scene_vert.spv"))
+    };
+    let frag_shader_module = create_shader_module(include_bytes!("spv/scene_frag.spv"));
+
+    let main_function_name = CString::new("main").unwrap();
+    let shader_stages = [
+        vk::PipelineShaderStageCreateInfo::builder()
+            .module(vert_shader_module)
+            .name(&main_function_name)
+            .stage(vk::ShaderStageFlags::VERTEX)
+            .build(),
+        vk::PipelineShaderStageCreateInfo::builder()
+            .module(frag_shader_module)
+            .name(&main_function_name)
+            .stage(vk::ShaderStageFlags::FRAGMENT)
+            .build(),
+    ];
+
+    let mut vertex_binding_descriptions = vec![vk::VertexInputBindingDescription {
+        binding: 0,
+        stride: std::mem::size_of::<MeshVertex>() as u32,
+        input_rate: vk::VertexInputRate::VERTEX,
+    }];
+    let mut vertex_attribute_descriptions = vec![
+        vk::VertexInputAttributeDescription {
+            location: 0,
+            binding: 0,
+            format: vk::Format::R32G32B32_SFLOAT,
+            offset: 0,
+        },
+        vk::VertexInputAttributeDescription {
+            location: 1,
+            binding: 0,
+            format: vk::Format::R32G32B32_SFLOAT,
+            offset: 12,
+        },
+        vk::VertexInputAttributeDescription {
+            location: 2,
+            binding: 0,
+            format: vk::Format::R32G32_SFLOAT,
+            offset: 24,
+        },
+    ];
+    // Суставы и веса скелетной сетки лежат в отдельном буфере.
+    if skinned {
+        vertex_binding_descriptions.push(vk::VertexInputBindingDescription {
+            binding: 1,
+            stride: std::mem::size_of::<VertexJoints>() as u32,
+            input_rate: vk::VertexInputRate::VERTEX,
+        });
+        vertex_attribute_descriptions.extend([
+            vk::VertexInputAttributeDescription {
+                location: 3,
+                binding: 1,
+                format: vk::Format::R32G32B32A32_UINT,
+                offset: 0,
+            },
+            vk::VertexInputAttributeDescription {
+                location: 4,
+                binding: 1,
+                format: vk::Format::R32G32B32A32_SFLOAT,
+                offset: 16,
+            },
+        ]);
+    }
+    let vertex_input_state_create_info = vk::PipelineVertexInputStateCreateInfo::builder()
+        .vertex_binding_descriptions(&vertex_binding_descriptions)
+        .vertex_attribute_descriptions(&vertex_attribute_descriptions);
+
+    let input_assembly_state_create_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+
+    let viewport_state_create_info = vk::PipelineViewportStateCreateInfo::builder()
+        .viewport_count(1)
+        .scissor_count(1);
+
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state_create_info =
+        vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+
+    // Как и в instancing, обход против часовой стрелки снаружи остается им и в кадре.
+    let rasterization_state_create_info = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::BACK)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE);
+
+    let multisample_state_create_info = vk::PipelineMultisampleStateCreateInfo::builder()
+        .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+
+    let depth_state_create_info = vk::PipelineDepthStencilStateCreateInfo::builder()
+        .depth_test_enable(true)
+        .depth_write_enable(true)
+        .depth_compare_op(vk::CompareOp::LESS);
+
+    let color_blend_attachment_states = [vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(vk::ColorComponentFlags::RGBA)
+        .blend_enable(false)
+        .build()];
+    let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
+        .attachments(&color_blend_attachment_states);
+
+    let color_attachment_formats = [color_format];
+    let mut pipeline_rendering_create_info = vk::PipelineRenderingCreateInfo::builder()
+        .color_attachment_formats(&color_attachment_formats)
+        .depth_attachment_format(depth_format);
+
+    let mut graphic_pipeline_create_info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(&shader_stages)
+        .vertex_input_state(&vertex_input_state_create_info)
+        .input_assembly_state(&input_assembly_state_create_info)
+        .viewport_state(&viewport_state_create_info)
+        .rasterization_state(&rasterization_state_create_info)
+        .multisample_state(&multisample_state_create_info)
+        .depth_stencil_state(&depth_state_create_info)
+        .color_blend_state(&color_blend_state)
+        .dynamic_state(&dynamic_state_create_info)
+        .layout(pipeline_layout);
+
+    if graph_cache.uses_dynamic_rendering() {
+        graphic_pipeline_create_info =
+            graphic_pipeline_create_info.push_next(&mut pipeline_rendering_create_info);
+    } else {
+        let render_pass = graph_cache.compatible_render_pass(
+            device,
+            &color_attachment_formats,
+            Some(depth_format),
+        );
+        graphic_pipeline_create_info = graphic_pipeline_create_info.render_pass(render_pass);
+    }
+
+    let pipeline = unsafe {
+        device
+            .create_graphics_pipelines(
+                vk::PipelineCache::null(),
+                &[graphic_pipeline_create_info.build()],
+                None,
+            )
+            .expect("Failed to create scene Pipeline!")[0]
+    };
+
+    unsafe {
+        device.destroy_shader_module(vert_shader_module, None);
+        device.destroy_shader_module(frag_shader_module, None);
+    }
+
+    pipeline
+}
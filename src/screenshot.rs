@@ -0,0 +1,269 @@
+use ash::vk;
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::memory::Buffer;
+use crate::render_graph::{BufferUsage, ImageHandle, ImageUsage, RenderGraph};
+
+// Снимок, который видеокарта еще копирует в буфер. Ждать его нельзя, иначе остановится цикл отрисовки,
+// поэтому буфер читается в одном из следующих кадров, когда забор кадра уже просигналил.
+struct PendingScreenshot {
+    buffer: Buffer,
+    fence: vk::Fence,
+    extent: vk::Extent2D,
+    format: vk::Format,
+}
+
+pub struct Screenshots {
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    pending: Vec<PendingScreenshot>,
+}
+
+impl Screenshots {
+    pub fn new(memory_properties: vk::PhysicalDeviceMemoryProperties) -> Self {
+        Screenshots {
+            memory_properties,
+            pending: Vec::new(),
+        }
+    }
+
+    // Формат, который умеем переводить в RGBA8.
+    pub fn is_format_supported(format: vk::Format) -> bool {
+        to_rgba8(format, &mut []).is_ok()
+    }
+
+    // Добавляет в граф проход, копирующий изображение в буфер, доступный процессору.
+    // fence — забор, который просигналит после выполнения этого кадра.
+    pub fn capture(
+        &mut self,
+        device: &ash::Device,
+        graph: &mut RenderGraph,
+        image: ImageHandle,
+        extent: vk::Extent2D,
+        format: vk::Format,
+        fence: vk::Fence,
+    ) {
+        let size = extent.width as vk::DeviceSize * extent.height as vk::DeviceSize * 4;
+        // Процессор будет читать из этой памяти, поэтому лучше всего подходит кэшируемая.
+        let buffer = Buffer::new(
+            device,
+            &self.memory_properties,
+            size,
+            vk::BufferUsageFlags::TRANSFER_DST,
+            &[
+                vk::MemoryPropertyFlags::HOST_VISIBLE
+                    | vk::MemoryPropertyFlags::HOST_COHERENT
+                    | vk::MemoryPropertyFlags::HOST_CACHED,
+                vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            ],
+        );
+
+        let target = graph.import_buffer("screenshot", buffer.buffer, size, true);
+        graph
+            .add_pass("screenshot")
+            .read_image(image, ImageUsage::Transfer)
+            .write_buffer(target, BufferUsage::Transfer)
+            .execute(move |context| {
+                let region = vk::BufferImageCopy::builder()
+                    .image_subresource(vk::ImageSubresourceLayers {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        mip_level: 0,
+                        base_array_layer: 0,
+                        layer_count: 1,
+                    })
+                    .image_extent(vk::Extent3D {
+                        width: extent.width,
+                        height: extent.height,
+                        depth: 1,
+                    });
+                unsafe {
+                    context.device.cmd_copy_image_to_buffer(
+                        context.command_buffer,
+                        context.image(image),
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                        context.buffer(target),
+                        std::slice::from_ref(&region),
+                    );
+                }
+            });
+
+        self.pending.push(PendingScreenshot {
+            buffer,
+            fence,
+            extent,
+            format,
+        });
+    }
+
+    // Вызывается в начале кадра, до сброса забора. Готовые снимки копируются из видеопамяти,
+    // а перевод в RGBA и сжатие в PNG выполняются в отдельном потоке.
+    pub fn poll(&mut self, device: &ash::Device) {
+        let (ready, pending) = self
+            .pending
+            .drain(..)
+            .partition::<Vec<_>, _>(|screenshot| unsafe {
+                device.get_fence_status(screenshot.fence).unwrap_or(false)
+            });
+        self.pending = pending;
+
+        for screenshot in ready {
+            let pixels = unsafe {
+                let data = device
+                    .map_memory(
+                        screenshot.buffer.memory,
+                        0,
+                        screenshot.buffer.size,
+                        vk::MemoryMapFlags::empty(),
+                    )
+                    .expect("Failed to map screenshot memory!");
+                let pixels =
+                    std::slice::from_raw_parts(data as *const u8, screenshot.buffer.size as usize)
+                        .to_vec();
+                device.unmap_memory(screenshot.buffer.memory);
+                screenshot.buffer.destroy(device);
+                pixels
+            };
+
+            let path = format!("screenshot_{}.png", timestamp());
+            let extent = screenshot.extent;
+            let format = screenshot.format;
+            std::thread::spawn(move || match save_png(&path, extent, format, pixels) {
+                Ok(()) => println!("Screenshot saved to {}", path),
+                Err(error) => println!("Failed to save screenshot {}: {}", path, error),
+            });
+        }
+    }
+
+    pub unsafe fn destroy(&mut self, device: &ash::Device) {
+        self.pending
+            .drain(..)
+            .for_each(|screenshot| screenshot.buffer.destroy(device));
+    }
+}
+
+fn save_png(
+    path: &str,
+    extent: vk::Extent2D,
+    format: vk::Format,
+    mut pixels: Vec<u8>,
+) -> Result<(), String> {
+    to_rgba8(format, &mut pixels)?;
+
+    let file = std::fs::File::create(path).map_err(|error| error.to_string())?;
+    let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), extent.width, extent.height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().map_err(|error| error.to_string())?;
+    writer
+        .write_image_data(&pixels)
+        .map_err(|error| error.to_string())
+}
+
+// Переставляет каналы в порядок RGBA с 8 битами на канал. Окно непрозрачное, поэтому альфа всегда 255.
+fn to_rgba8(format: vk::Format, pixels: &mut [u8]) -> Result<(), String> {
+    match format {
+        vk::Format::R8G8B8A8_SRGB | vk::Format::R8G8B8A8_UNORM => {}
+        vk::Format::B8G8R8A8_SRGB | vk::Format::B8G8R8A8_UNORM => pixels
+            .chunks_exact_mut(4)
+            .for_each(|pixel| pixel.swap(0, 2)),
+        // 10 бит на канал упакованы в одно 32-битное слово, оставляем старшие 8 бит каждого канала.
+        vk::Format::A2B10G10R10_UNORM_PACK32 | vk::Format::A2R10G10B10_UNORM_PACK32 => {
+            let red_first = format == vk::Format::A2B10G10R10_UNORM_PACK32;
+            pixels.chunks_exact_mut(4).for_each(|pixel| {
+                let packed = u32::from_le_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]);
+                let low = ((packed >> 2) & 0xff) as u8;
+                let middle = ((packed >> 12) & 0xff) as u8;
+                let high = ((packed >> 22) & 0xff) as u8;
+                let (red, blue) = if red_first { (low, high) } else { (high, low) };
+                pixel.copy_from_slice(&[red, middle, blue, 255]);
+            })
+        }
+        _ => return Err(format!("unsupported surface format {:?}", format)),
+    }
+    pixels.chunks_exact_mut(4).for_each(|pixel| pixel[3] = 255);
+    Ok(())
+}
+
+// Время UTC в виде 20261019_142530_123, чтобы имена снимков сортировались по времени.
+fn timestamp() -> String {
+    let since_epoch = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let seconds = since_epoch.as_secs();
+    let (year, month, day) = civil_from_days((seconds / 86400) as i64);
+    let time_of_day = seconds % 86400;
+    format!(
+        "{:04}{:02}{:02}_{:02}{:02}{:02}_{:03}",
+        year,
+        month,
+        day,
+        time_of_day / 3600,
+        time_of_day / 60 % 60,
+        time_of_day % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+// Переводит количество дней с 1970-01-01 в дату григорианского календаря (алгоритм Говарда Хиннанта).
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_days_to_dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(civil_from_days(10957), (2000, 1, 1));
+        // 2000 високосный, хотя делится на 100, потому что делится на 400.
+        assert_eq!(civil_from_days(11016), (2000, 2, 29));
+        assert_eq!(civil_from_days(19782), (2024, 2, 29));
+        assert_eq!(civil_from_days(19783), (2024, 3, 1));
+        // А 2100 не високосный: за 28 февраля сразу идет 1 марта.
+        assert_eq!(civil_from_days(47540), (2100, 2, 28));
+        assert_eq!(civil_from_days(47541), (2100, 3, 1));
+    }
+
+    #[test]
+    fn converts_packed_10_bit_pixels() {
+        // Красный 1023, зеленый 512, синий 0, альфа 0.
+        let packed = 0x3ff | (0x200 << 10);
+        let mut pixels = u32::to_le_bytes(packed).to_vec();
+        to_rgba8(vk::Format::A2B10G10R10_UNORM_PACK32, &mut pixels).unwrap();
+        assert_eq!(pixels, [255, 128, 0, 255]);
+
+        let mut pixels = u32::to_le_bytes(packed).to_vec();
+        to_rgba8(vk::Format::A2R10G10B10_UNORM_PACK32, &mut pixels).unwrap();
+        assert_eq!(pixels, [0, 128, 255, 255]);
+    }
+
+    #[test]
+    fn swaps_bgra_and_rejects_unknown_formats() {
+        let mut pixels = vec![1, 2, 3, 0, 5, 6, 7, 8];
+        to_rgba8(vk::Format::B8G8R8A8_SRGB, &mut pixels).unwrap();
+        assert_eq!(pixels, [3, 2, 1, 255, 7, 6, 5, 255]);
+        assert!(Screenshots::is_format_supported(
+            vk::Format::A2B10G10R10_UNORM_PACK32
+        ));
+        assert!(!Screenshots::is_format_supported(
+            vk::Format::R16G16B16A16_SFLOAT
+        ));
+    }
+}
@@ -0,0 +1,780 @@
+use ash::vk;
+
+use std::ffi::CString;
+
+use crate::memory::{find_memory_type, Buffer};
+use crate::push_constants::PushConstants;
+use crate::render_graph::{
+    ImageHandle, ImageUsage, ImportedImage, LoadOp, RenderGraph, RenderGraphCache,
+};
+
+// Спрайты собираются за кадр в общий буфер вершин, сортируются по слою и текстуре,
+// и каждая серия спрайтов с одной текстурой рисуется одним вызовом отрисовки.
+
+// Текстуры спрайтов хранятся в sRGB и при выборке переводятся в линейное пространство.
+const TEXTURE_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+// Сколько текстур спрайтов может существовать одновременно.
+const MAX_TEXTURES: u32 = 256;
+
+// Текстура, созданная в SpriteBatch::create_texture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SpriteTexture(usize);
+
+// Один спрайт кадра. Координаты в пикселях, начало в левом верхнем углу, ось y направлена вниз.
+#[derive(Debug, Clone, Copy)]
+pub struct Sprite {
+    pub texture: SpriteTexture,
+    pub position: [f32; 2],
+    pub size: [f32; 2],
+    // Поворот в радианах по часовой стрелке вокруг origin.
+    pub rotation: f32,
+    // Точка спрайта, которая ставится в position: [0, 0] — левый верхний угол, [0.5, 0.5] — центр.
+    pub origin: [f32; 2],
+    // Часть текстуры: u и v левого верхнего и правого нижнего углов.
+    pub uv: [f32; 4],
+    // Линейный цвет RGBA, на который умножается текстура.
+    pub color: [f32; 4],
+    // Спрайты с большим слоем рисуются поверх. Внутри слоя порядок добавления сохраняется только для одной текстуры.
+    pub layer: i32,
+}
+
+impl Sprite {
+    // Спрайт с центром в position, без поворота и оттенка, во всю текстуру.
+    pub fn new(texture: SpriteTexture, position: [f32; 2], size: [f32; 2]) -> Self {
+        Sprite {
+            texture,
+            position,
+            size,
+            rotation: 0.0,
+            origin: [0.5, 0.5],
+            uv: [0.0, 0.0, 1.0, 1.0],
+            color: [1.0, 1.0, 1.0, 1.0],
+            layer: 0,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct SpriteVertex {
+    position: [f32; 2],
+    uv: [f32; 2],
+    color: [f32; 4],
+}
+
+// Push-константы sprite.vert.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct SpritePushConstants {
+    // Ортографическая проекция размером с кадр.
+    projection: [f32; 16],
+}
+
+unsafe impl PushConstants for SpritePushConstants {
+    const STAGES: vk::ShaderStageFlags = vk::ShaderStageFlags::VERTEX;
+}
+
+struct TextureData {
+    image: vk::Image,
+    memory: vk::DeviceMemory,
+    view: vk::ImageView,
+    descriptor_set: vk::DescriptorSet,
+    extent: vk::Extent2D,
+}
+
+// Буферы одного кадра в полете. Промежуточные буферы загрузки текстур освобождаются,
+// когда этот кадр начинается снова и его забор уже просигналил.
+#[derive(Default)]
+struct FrameBuffers {
+    vertices: Option<Buffer>,
+    indices: Option<Buffer>,
+    staging: Vec<Buffer>,
+}
+
+// Серия спрайтов с одной текстурой: часть общего буфера индексов.
+struct Batch {
+    descriptor_set: vk::DescriptorSet,
+    first_index: u32,
+    index_count: u32,
+}
+
+pub struct SpriteBatch {
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    pipeline: vk::Pipeline,
+    pipeline_layout: vk::PipelineLayout,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    sampler: vk::Sampler,
+    textures: Vec<TextureData>,
+    // Текстуры, созданные после прошлого кадра, и буферы с их пикселями.
+    pending_uploads: Vec<(SpriteTexture, Buffer)>,
+    sprites: Vec<Sprite>,
+    frames: Vec<FrameBuffers>,
+}
+
+impl SpriteBatch {
+    pub fn new(
+        device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        graph_cache: &mut RenderGraphCache,
+        color_format: vk::Format,
+        frames_in_flight: usize,
+    ) -> Self {
+        let sampler_create_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE);
+        let sampler = unsafe {
+            device
+                .create_sampler(&sampler_create_info, None)
+                .expect("Failed to create sprite Sampler!")
+        };
+
+        let samplers = [sampler];
+        let bindings = [
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .build(),
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(1)
+                .descriptor_type(vk::DescriptorType::SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .immutable_samplers(&samplers)
+                .build(),
+        ];
+        let descriptor_set_layout_create_info =
+            vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+        let descriptor_set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(&descriptor_set_layout_create_info, None)
+                .expect("Failed to create sprite Descriptor Set Layout!")
+        };
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::SAMPLED_IMAGE,
+                descriptor_count: MAX_TEXTURES,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::SAMPLER,
+                descriptor_count: MAX_TEXTURES,
+            },
+        ];
+        let descriptor_pool_create_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(MAX_TEXTURES)
+            .pool_sizes(&pool_sizes);
+        let descriptor_pool = unsafe {
+            device
+                .create_descriptor_pool(&descriptor_pool_create_info, None)
+                .expect("Failed to create sprite Descriptor Pool!")
+        };
+
+        // Матрица проекции передается через push-константы.
+        let push_constant_ranges = [SpritePushConstants::range()];
+        let set_layouts = [descriptor_set_layout];
+        let pipeline_layout_create_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&set_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+        let pipeline_layout = unsafe {
+            device
+                .create_pipeline_layout(&pipeline_layout_create_info, None)
+                .expect("Failed to create sprite Pipeline Layout!")
+        };
+
+        let pipeline = create_pipeline(device, graph_cache, color_format, pipeline_layout);
+
+        SpriteBatch {
+            memory_properties,
+            pipeline,
+            pipeline_layout,
+            descriptor_set_layout,
+            descriptor_pool,
+            sampler,
+            textures: Vec::new(),
+            pending_uploads: Vec::new(),
+            sprites: Vec::new(),
+            frames: (0..frames_in_flight)
+                .map(|_| FrameBuffers::default())
+                .collect(),
+        }
+    }
+
+    // Создает текстуру из пикселей RGBA в sRGB, по 4 байта на пиксель. Пиксели загружаются
+    // в видеопамять в следующем add_pass, до этого спрайты с текстурой уже можно добавлять.
+    pub fn create_texture(
+        &mut self,
+        device: &ash::Device,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> SpriteTexture {
+        assert_eq!(
+            pixels.len(),
+            (width * height * 4) as usize,
+            "Sprite texture size does not match its pixels"
+        );
+        assert!(
+            (self.textures.len() as u32) < MAX_TEXTURES,
+            "Too many sprite textures"
+        );
+
+        let staging = Buffer::new(
+            device,
+            &self.memory_properties,
+            pixels.len() as vk::DeviceSize,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            &[vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT],
+        );
+        unsafe {
+            let data = device
+                .map_memory(staging.memory, 0, staging.size, vk::MemoryMapFlags::empty())
+                .expect("Failed to map sprite staging memory!");
+            std::ptr::copy_nonoverlapping(pixels.as_ptr(), data as *mut u8, pixels.len());
+            device.unmap_memory(staging.memory);
+        }
+
+        let texture = SpriteTexture(self.textures.len());
+        let data = self.create_texture_data(device, vk::Extent2D { width, height });
+        self.textures.push(data);
+        self.pending_uploads.push((texture, staging));
+        texture
+    }
+
+    // Добавляет спрайт в очередь кадра.
+    pub fn draw(&mut self, sprite: Sprite) {
+        self.sprites.push(sprite);
+    }
+
+    // Добавляет в граф загрузку новых текстур и проход, рисующий все спрайты кадра поверх target.
+    // Вызывается после ожидания забора кадра frame_index.
+    pub fn add_pass(
+        &mut self,
+        device: &ash::Device,
+        graph: &mut RenderGraph,
+        target: ImageHandle,
+        extent: vk::Extent2D,
+        frame_index: usize,
+    ) {
+        for buffer in self.frames[frame_index].staging.drain(..) {
+            unsafe { buffer.destroy(device) };
+        }
+
+        let mut texture_handles = Vec::new();
+        for (texture, staging) in std::mem::take(&mut self.pending_uploads) {
+            texture_handles.push(self.upload_texture(graph, texture, &staging));
+            self.frames[frame_index].staging.push(staging);
+        }
+
+        let mut sprites = std::mem::take(&mut self.sprites);
+        if sprites.is_empty() {
+            return;
+        }
+        // Сортировка устойчивая: спрайты одного слоя с одной текстурой рисуются в порядке добавления.
+        sprites.sort_by_key(|sprite| (sprite.layer, sprite.texture));
+
+        let vertex_buffer = self.frame_buffer(
+            device,
+            frame_index,
+            false,
+            (sprites.len() * 4 * std::mem::size_of::<SpriteVertex>()) as vk::DeviceSize,
+        );
+        let index_buffer = self.frame_buffer(
+            device,
+            frame_index,
+            true,
+            (sprites.len() * 6 * std::mem::size_of::<u32>()) as vk::DeviceSize,
+        );
+
+        let mut batches: Vec<Batch> = Vec::new();
+        unsafe {
+            let vertices = device
+                .map_memory(
+                    vertex_buffer.1,
+                    0,
+                    vk::WHOLE_SIZE,
+                    vk::MemoryMapFlags::empty(),
+                )
+                .expect("Failed to map sprite vertex memory!")
+                as *mut SpriteVertex;
+            let indices = device
+                .map_memory(
+                    index_buffer.1,
+                    0,
+                    vk::WHOLE_SIZE,
+                    vk::MemoryMapFlags::empty(),
+                )
+                .expect("Failed to map sprite index memory!") as *mut u32;
+
+            let mut previous_texture = None;
+            for (number, sprite) in sprites.iter().enumerate() {
+                let quad = sprite_vertices(sprite);
+                std::ptr::copy_nonoverlapping(quad.as_ptr(), vertices.add(number * 4), 4);
+                let first_vertex = (number * 4) as u32;
+                let quad_indices = [0, 1, 2, 0, 2, 3].map(|index| first_vertex + index);
+                std::ptr::copy_nonoverlapping(quad_indices.as_ptr(), indices.add(number * 6), 6);
+
+                // Новая серия начинается, когда меняется текстура. Слои отсортированы раньше текстур,
+                // поэтому одна текстура на разных слоях через другой слой дает разные серии.
+                if previous_texture == Some(sprite.texture) {
+                    batches.last_mut().unwrap().index_count += 6;
+                } else {
+                    batches.push(Batch {
+                        descriptor_set: self.textures[sprite.texture.0].descriptor_set,
+                        first_index: (number * 6) as u32,
+                        index_count: 6,
+                    });
+                    previous_texture = Some(sprite.texture);
+                }
+            }
+
+            device.unmap_memory(vertex_buffer.1);
+            device.unmap_memory(index_buffer.1);
+        }
+
+        let pipeline = self.pipeline;
+        let pipeline_layout = self.pipeline_layout;
+        let push_constants = SpritePushConstants {
+            projection: orthographic(extent.width as f32, extent.height as f32),
+        };
+        let mut pass = graph
+            .add_pass("sprites")
+            .color_attachment(target, LoadOp::Load);
+        // Текстуры, загруженные в этом кадре, читаются только после завершения копирования.
+        for handle in texture_handles {
+            pass = pass.read_image(
+                handle,
+                ImageUsage::Sampled(vk::PipelineStageFlags::FRAGMENT_SHADER),
+            );
+        }
+        pass.execute(move |context| unsafe {
+            let device = context.device;
+            let command_buffer = context.command_buffer;
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+            let viewport = vk::Viewport {
+                x: 0.0,
+                y: 0.0,
+                width: extent.width as f32,
+                height: extent.height as f32,
+                min_depth: 0.0,
+                max_depth: 1.0,
+            };
+            let scissor = vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent,
+            };
+            device.cmd_set_viewport(command_buffer, 0, std::slice::from_ref(&viewport));
+            device.cmd_set_scissor(command_buffer, 0, std::slice::from_ref(&scissor));
+            device.cmd_bind_vertex_buffers(command_buffer, 0, &[vertex_buffer.0], &[0]);
+            device.cmd_bind_index_buffer(command_buffer, index_buffer.0, 0, vk::IndexType::UINT32);
+            push_constants.push(device, command_buffer, pipeline_layout);
+
+            for batch in &batches {
+                device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    pipeline_layout,
+                    0,
+                    &[batch.descriptor_set],
+                    &[],
+                );
+                device.cmd_draw_indexed(
+                    command_buffer,
+                    batch.index_count,
+                    1,
+                    batch.first_index,
+                    0,
+                    0,
+                );
+            }
+        });
+    }
+
+    // Копирует пиксели новой текстуры из промежуточного буфера. После копирования текстура
+    // навсегда остается в SHADER_READ_ONLY_OPTIMAL.
+    fn upload_texture(
+        &self,
+        graph: &mut RenderGraph,
+        texture: SpriteTexture,
+        staging: &Buffer,
+    ) -> ImageHandle {
+        let data = &self.textures[texture.0];
+        let handle = graph.import_image(
+            "sprite texture",
+            ImportedImage {
+                image: data.image,
+                view: data.view,
+                format: TEXTURE_FORMAT,
+                extent: data.extent,
+                initial_layout: vk::ImageLayout::UNDEFINED,
+                initial_stage: vk::PipelineStageFlags::TOP_OF_PIPE,
+                final_layout: Some(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+            },
+        );
+        let staging_buffer = staging.buffer;
+        let extent = data.extent;
+        graph
+            .add_pass("sprite upload")
+            .write_image(handle, ImageUsage::Transfer)
+            .execute(move |context| {
+                let region = vk::BufferImageCopy::builder()
+                    .image_subresource(vk::ImageSubresourceLayers {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        mip_level: 0,
+                        base_array_layer: 0,
+                        layer_count: 1,
+                    })
+                    .image_extent(vk::Extent3D {
+                        width: extent.width,
+                        height: extent.height,
+                        depth: 1,
+                    });
+                unsafe {
+                    context.device.cmd_copy_buffer_to_image(
+                        context.command_buffer,
+                        staging_buffer,
+                        context.image(handle),
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        std::slice::from_ref(&region),
+                    );
+                }
+            });
+        handle
+    }
+
+    // Возвращает буфер вершин или индексов кадра не меньше size байт. Старый буфер мог использоваться
+    // другими кадрами в полете только через этот же кадр, а его забор уже дождались, поэтому удаляется сразу.
+    fn frame_buffer(
+        &mut self,
+        device: &ash::Device,
+        frame_index: usize,
+        indices: bool,
+        size: vk::DeviceSize,
+    ) -> (vk::Buffer, vk::DeviceMemory) {
+        let frame = &mut self.frames[frame_index];
+        let (slot, usage) = if indices {
+            (&mut frame.indices, vk::BufferUsageFlags::INDEX_BUFFER)
+        } else {
+            (&mut frame.vertices, vk::BufferUsageFlags::VERTEX_BUFFER)
+        };
+        if slot.as_ref().is_none_or(|buffer| buffer.size < size) {
+            if let Some(buffer) = slot.take() {
+                unsafe { buffer.destroy(device) };
+            }
+            *slot = Some(Buffer::new(
+                device,
+                &self.memory_properties,
+                size.next_power_of_two(),
+                usage,
+                &[vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT],
+            ));
+        }
+        let buffer = slot.as_ref().unwrap();
+        (buffer.buffer, buffer.memory)
+    }
+
+    fn create_texture_data(&self, device: &ash::Device, extent: vk::Extent2D) -> TextureData {
+        let image_create_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(TEXTURE_FORMAT)
+            .extent(vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            })
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+
+        unsafe {
+            let image = device
+                .create_image(&image_create_info, None)
+                .expect("Failed to create sprite texture!");
+            let requirements = device.get_image_memory_requirements(image);
+            let memory_type_index = find_memory_type(
+                &self.memory_properties,
+                requirements.memory_type_bits,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            )
+            .expect("No suitable memory type for sprite texture!");
+            let allocate_info = vk::MemoryAllocateInfo::builder()
+                .allocation_size(requirements.size)
+                .memory_type_index(memory_type_index);
+            let memory = device
+                .allocate_memory(&allocate_info, None)
+                .expect("Failed to allocate sprite texture memory!");
+            device
+                .bind_image_memory(image, memory, 0)
+                .expect("Failed to bind sprite texture memory!");
+
+            let view_create_info = vk::ImageViewCreateInfo::builder()
+                .image(image)
+                .view_type(vk::ImageViewType::TYPE_2D)
+                .format(TEXTURE_FORMAT)
+                .subresource_range(vk::ImageSubresourceRange {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    base_mip_level: 0,
+                    level_count: 1,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                });
+            let view = device
+                .create_image_view(&view_create_info, None)
+                .expect("Failed to create sprite texture view!");
+
+            let set_layouts = [self.descriptor_set_layout];
+            let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+                .descriptor_pool(self.descriptor_pool)
+                .set_layouts(&set_layouts);
+            let descriptor_set = device
+                .allocate_descriptor_sets(&allocate_info)
+                .expect("Failed to allocate sprite Descriptor Set!")[0];
+            let image_info = [vk::DescriptorImageInfo {
+                sampler: vk::Sampler::null(),
+                image_view: view,
+                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            }];
+            let write = vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                .image_info(&image_info);
+            device.update_descriptor_sets(std::slice::from_ref(&write), &[]);
+
+            TextureData {
+                image,
+                memory,
+                view,
+                descriptor_set,
+                extent,
+            }
+        }
+    }
+
+    pub unsafe fn destroy(&mut self, device: &ash::Device) {
+        for (_, staging) in self.pending_uploads.drain(..) {
+            staging.destroy(device);
+        }
+        for texture in self.textures.drain(..) {
+            device.destroy_image_view(texture.view, None);
+            device.destroy_image(texture.image, None);
+            device.free_memory(texture.memory, None);
+        }
+        for frame in &mut self.frames {
+            let buffers = frame
+                .vertices
+                .take()
+                .into_iter()
+                .chain(frame.indices.take());
+            for buffer in buffers.chain(frame.staging.drain(..)) {
+                buffer.destroy(device);
+            }
+        }
+        device.destroy_pipeline(self.pipeline, None);
+        device.destroy_pipeline_layout(self.pipeline_layout, None);
+        // Наборы дескрипторов освобождаются вместе с пулом.
+        device.destroy_descriptor_pool(self.descriptor_pool, None);
+        device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        device.destroy_sampler(self.sampler, None);
+    }
+}
+
+// Углы спрайта по часовой стрелке, начиная с левого верхнего, повернутые вокруг origin.
+fn sprite_vertices(sprite: &Sprite) -> [SpriteVertex; 4] {
+    let (sin, cos) = sprite.rotation.sin_cos();
+    let [u0, v0, u1, v1] = sprite.uv;
+    let corners = [
+        (0.0, 0.0, u0, v0),
+        (1.0, 0.0, u1, v0),
+        (1.0, 1.0, u1, v1),
+        (0.0, 1.0, u0, v1),
+    ];
+    corners.map(|(x, y, u, v)| {
+        let local_x = (x - sprite.origin[0]) * sprite.size[0];
+        let local_y = (y - sprite.origin[1]) * sprite.size[1];
+        SpriteVertex {
+            position: [
+                sprite.position[0] + local_x * cos - local_y * sin,
+                sprite.position[1] + local_x * sin + local_y * cos,
+            ],
+            uv: [u, v],
+            color: sprite.color,
+        }
+    })
+}
+
+// Ортографическая проекция из пикселей кадра в координаты отсечения, по столбцам.
+// В Vulkan ось y в координатах отсечения направлена вниз, как и в пикселях, поэтому переворачивать ее не нужно.
+pub fn orthographic(width: f32, height: f32) -> [f32; 16] {
+    [
+        2.0 / width,
+        0.0,
+        0.0,
+        0.0,
+        0.0,
+        2.0 / height,
+        0.0,
+        0.0,
+        0.0,
+        0.0,
+        1.0,
+        0.0,
+        -1.0,
+        -1.0,
+        0.0,
+        1.0,
+    ]
+}
+
+fn create_pipeline(
+    device: &ash::Device,
+    graph_cache: &mut RenderGraphCache,
+    color_format: vk::Format,
+    pipeline_layout: vk::PipelineLayout,
+) -> vk::Pipeline {
+    let create_shader_module = |code: &[u8]| {
+        let shader_module_create_info = vk::ShaderModuleCreateInfo {
+            code_size: code.len(),
+            p_code: code.as_ptr() as *const u32,
+            ..Default::default()
+        };
+        unsafe {
+            device
+                .create_shader_module(&shader_module_create_info, None)
+                .expect("Failed to create sprite Shader Module!")
+        }
+    };
+    let vert_shader_module = create_shader_module(include_bytes!("spv/sprite_vert.spv"));
+    let frag_shader_module = create_shader_module(include_bytes!("spv/sprite_frag.spv"));
+
+    let main_function_name = CString::new("main").unwrap();
+    let shader_stages = [
+        vk::PipelineShaderStageCreateInfo::builder()
+            .module(vert_shader_module)
+            .name(&main_function_name)
+            .stage(vk::ShaderStageFlags::VERTEX)
+            .build(),
+        vk::PipelineShaderStageCreateInfo::builder()
+            .module(frag_shader_module)
+            .name(&main_function_name)
+            .stage(vk::ShaderStageFlags::FRAGMENT)
+            .build(),
+    ];
+
+    let vertex_binding_descriptions = [vk::VertexInputBindingDescription {
+        binding: 0,
+        stride: std::mem::size_of::<SpriteVertex>() as u32,
+        input_rate: vk::VertexInputRate::VERTEX,
+    }];
+    let vertex_attribute_descriptions = [
+        vk::VertexInputAttributeDescription {
+            location: 0,
+            binding: 0,
+            format: vk::Format::R32G32_SFLOAT,
+            offset: 0,
+        },
+        vk::VertexInputAttributeDescription {
+            location: 1,
+            binding: 0,
+            format: vk::Format::R32G32_SFLOAT,
+            offset: 8,
+        },
+        vk::VertexInputAttributeDescription {
+            location: 2,
+            binding: 0,
+            format: vk::Format::R32G32B32A32_SFLOAT,
+            offset: 16,
+        },
+    ];
+    let vertex_input_state_create_info = vk::PipelineVertexInputStateCreateInfo::builder()
+        .vertex_binding_descriptions(&vertex_binding_descriptions)
+        .vertex_attribute_descriptions(&vertex_attribute_descriptions);
+
+    let input_assembly_state_create_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+
+    let viewport_state_create_info = vk::PipelineViewportStateCreateInfo::builder()
+        .viewport_count(1)
+        .scissor_count(1);
+
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state_create_info =
+        vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+
+    // Поворот и отражение меняют направление обхода, поэтому отсечение граней выключено.
+    let rasterization_state_create_info = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::CLOCKWISE);
+
+    let multisample_state_create_info = vk::PipelineMultisampleStateCreateInfo::builder()
+        .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+
+    let depth_state_create_info = vk::PipelineDepthStencilStateCreateInfo::default();
+
+    // Обычное смешивание по альфе: спрайты рисуются по порядку слоев поверх уже нарисованного.
+    let color_blend_attachment_states = [vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(vk::ColorComponentFlags::RGBA)
+        .blend_enable(true)
+        .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+        .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .color_blend_op(vk::BlendOp::ADD)
+        .src_alpha_blend_factor(vk::BlendFactor::ONE)
+        .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .alpha_blend_op(vk::BlendOp::ADD)
+        .build()];
+    let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
+        .attachments(&color_blend_attachment_states);
+
+    let color_attachment_formats = [color_format];
+    let mut pipeline_rendering_create_info = vk::PipelineRenderingCreateInfo::builder()
+        .color_attachment_formats(&color_attachment_formats);
+
+    let mut graphic_pipeline_create_info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(&shader_stages)
+        .vertex_input_state(&vertex_input_state_create_info)
+        .input_assembly_state(&input_assembly_state_create_info)
+        .viewport_state(&viewport_state_create_info)
+        .rasterization_state(&rasterization_state_create_info)
+        .multisample_state(&multisample_state_create_info)
+        .depth_stencil_state(&depth_state_create_info)
+        .color_blend_state(&color_blend_state)
+        .dynamic_state(&dynamic_state_create_info)
+        .layout(pipeline_layout);
+
+    if graph_cache.uses_dynamic_rendering() {
+        graphic_pipeline_create_info =
+            graphic_pipeline_create_info.push_next(&mut pipeline_rendering_create_info);
+    } else {
+        let render_pass =
+            graph_cache.compatible_render_pass(device, &color_attachment_formats, None);
+        graphic_pipeline_create_info = graphic_pipeline_create_info.render_pass(render_pass);
+    }
+
+    let pipeline = unsafe {
+        device
+            .create_graphics_pipelines(
+                vk::PipelineCache::null(),
+                &[graphic_pipeline_create_info.build()],
+                None,
+            )
+            .expect("Failed to create sprite Pipeline!")[0]
+    };
+
+    unsafe {
+        device.destroy_shader_module(vert_shader_module, None);
+        device.destroy_shader_module(frag_shader_module, None);
+    }
+
+    pipeline
+}
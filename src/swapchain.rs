@@ -0,0 +1,185 @@
+use ash::extensions::khr::{Surface, Swapchain};
+use ash::vk;
+
+// Все объекты, которые зависят от размера поверхности окна. При изменении размера окна
+// их необходимо пересоздать, а вот конвейер, благодаря динамическому состоянию viewport/scissor, остается прежним.
+pub struct SwapchainBundle {
+    pub swapchain: vk::SwapchainKHR,
+    pub surface_format: vk::SurfaceFormatKHR,
+    pub surface_resolution: vk::Extent2D,
+    pub image_usage: vk::ImageUsageFlags,
+    pub present_mode: vk::PresentModeKHR,
+    // Запрошенная вертикальная синхронизация. Без нее present_mode все равно может оказаться FIFO,
+    // если поверхность не поддерживает других режимов.
+    pub vsync: bool,
+    pub images: Vec<vk::Image>,
+    pub image_views: Vec<vk::ImageView>,
+}
+
+impl SwapchainBundle {
+    // old_swapchain передается драйверу, чтобы он мог переиспользовать ресурсы старой цепочки обмена.
+    // Без vsync выбирается режим показа без ожидания вертикальной синхронизации, если поверхность его поддерживает.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &ash::Device,
+        surface_loader: &Surface,
+        swapchain_loader: &Swapchain,
+        p_device: vk::PhysicalDevice,
+        surface: vk::SurfaceKHR,
+        window_size: winit::dpi::PhysicalSize<u32>,
+        old_swapchain: vk::SwapchainKHR,
+        vsync: bool,
+    ) -> Self {
+        // Получаем информацию о поверхности нашего окна.
+        let surface_capabilities =
+            unsafe { surface_loader.get_physical_device_surface_capabilities(p_device, surface) }
+                .unwrap();
+
+        // Количество изображений в цепочке обмена.
+        let image_count = if surface_capabilities.max_image_count > 0
+            && surface_capabilities.min_image_count + 1 > surface_capabilities.max_image_count
+        {
+            surface_capabilities.max_image_count
+        } else {
+            surface_capabilities.min_image_count + 1
+        };
+
+        // Формат изображения. Важно указать формат, поддерживаемый поверностию нашего окна.
+        let surface_format = {
+            let formats_support =
+                unsafe { surface_loader.get_physical_device_surface_formats(p_device, surface) }
+                    .expect("Failed to query for surface formats.");
+
+            formats_support
+                .iter()
+                .find_map(|format_support| {
+                    if format_support.format == vk::Format::B8G8R8A8_SRGB
+                        && format_support.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
+                    {
+                        return Some(*format_support);
+                    }
+                    None
+                })
+                .unwrap_or_else(|| *formats_support.first().unwrap())
+        };
+
+        // Получаем размер поверхности. Некоторые оконные системы (например Wayland) сообщают u32::MAX,
+        // предоставляя нам выбрать размер самостоятельно, в этом случае берем размер окна.
+        let surface_resolution = if surface_capabilities.current_extent.width == u32::MAX {
+            vk::Extent2D {
+                width: window_size.width.clamp(
+                    surface_capabilities.min_image_extent.width,
+                    surface_capabilities.max_image_extent.width,
+                ),
+                height: window_size.height.clamp(
+                    surface_capabilities.min_image_extent.height,
+                    surface_capabilities.max_image_extent.height,
+                ),
+            }
+        } else {
+            surface_capabilities.current_extent
+        };
+
+        // Кроме рисования, изображения цепочки обмена могут быть источником копирования (например для снимков экрана),
+        // если поверхность это поддерживает.
+        let image_usage = vk::ImageUsageFlags::COLOR_ATTACHMENT
+            | (surface_capabilities.supported_usage_flags & vk::ImageUsageFlags::TRANSFER_SRC);
+
+        let pre_transform = vk::SurfaceTransformFlagsKHR::IDENTITY;
+
+        // Описываем в как будут подаваться наши изображения из очереди на поверхность.
+        // FIFO поддерживается всегда. MAILBOX не ждет синхронизации и не допускает разрывов изображения,
+        // IMMEDIATE показывает кадр сразу, возможны разрывы.
+        let present_mode = if vsync {
+            vk::PresentModeKHR::FIFO
+        } else {
+            let present_modes = unsafe {
+                surface_loader.get_physical_device_surface_present_modes(p_device, surface)
+            }
+            .unwrap_or_default();
+            [vk::PresentModeKHR::MAILBOX, vk::PresentModeKHR::IMMEDIATE]
+                .iter()
+                .copied()
+                .find(|mode| present_modes.contains(mode))
+                .unwrap_or(vk::PresentModeKHR::FIFO)
+        };
+
+        let swapchain_create_info = vk::SwapchainCreateInfoKHR::builder()
+            .surface(surface)
+            .min_image_count(image_count)
+            .image_color_space(surface_format.color_space)
+            .image_format(surface_format.format)
+            .image_extent(surface_resolution)
+            .image_usage(image_usage)
+            .image_sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .pre_transform(pre_transform)
+            .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
+            .present_mode(present_mode)
+            .clipped(true)
+            .image_array_layers(1)
+            .old_swapchain(old_swapchain);
+
+        let swapchain = unsafe {
+            swapchain_loader
+                .create_swapchain(&swapchain_create_info, None)
+                .expect("Error create swapchain")
+        };
+
+        let images = unsafe {
+            swapchain_loader
+                .get_swapchain_images(swapchain)
+                .expect("Failed to get Swapchain Images.")
+        };
+
+        //Чтобы использовать что-либо VkImage, в том числе в цепочке подкачки, в конвейере рендеринга,
+        //мы должны создать VkImageViewобъект. Просмотр изображения - это буквально взгляд в изображение.
+        //В нем описывается, как получить доступ к изображению и к какой части изображения получить доступ,
+        //например, следует ли рассматривать его как текстуру глубины 2D текстуры без каких-либо уровней mipmapping.
+        let image_views = images
+            .iter()
+            .map(|&image| {
+                let imageview_create_info = vk::ImageViewCreateInfo::builder()
+                    .view_type(vk::ImageViewType::TYPE_2D)
+                    .format(surface_format.format)
+                    .components(vk::ComponentMapping {
+                        r: vk::ComponentSwizzle::IDENTITY,
+                        g: vk::ComponentSwizzle::IDENTITY,
+                        b: vk::ComponentSwizzle::IDENTITY,
+                        a: vk::ComponentSwizzle::IDENTITY,
+                    })
+                    .subresource_range(vk::ImageSubresourceRange {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        base_mip_level: 0,
+                        level_count: 1,
+                        base_array_layer: 0,
+                        layer_count: 1,
+                    })
+                    .image(image);
+                unsafe {
+                    device
+                        .create_image_view(&imageview_create_info, None)
+                        .expect("Failed to create Image View!")
+                }
+            })
+            .collect::<Vec<_>>();
+
+        SwapchainBundle {
+            swapchain,
+            surface_format,
+            surface_resolution,
+            image_usage,
+            present_mode,
+            vsync,
+            images,
+            image_views,
+        }
+    }
+
+    // Уничтожает image view и саму цепочку обмена. Изображения принадлежат цепочке обмена и удаляются вместе с ней.
+    pub unsafe fn destroy(&self, device: &ash::Device, swapchain_loader: &Swapchain) {
+        self.image_views
+            .iter()
+            .for_each(|&image_view| device.destroy_image_view(image_view, None));
+        swapchain_loader.destroy_swapchain(self.swapchain, None);
+    }
+}
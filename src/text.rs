@@ -0,0 +1,924 @@
+use ash::vk;
+
+use ab_glyph::{Font, FontArc, GlyphId, PxScale, ScaleFont};
+
+use std::collections::HashMap;
+use std::ffi::CString;
+
+use crate::memory::{find_memory_type, Buffer};
+use crate::push_constants::PushConstants;
+use crate::render_graph::{
+    ImageHandle, ImageUsage, ImportedImage, LoadOp, RenderGraph, RenderGraphCache,
+};
+
+// Текст рисуется без интерфейса: символы шрифта TTF растеризуются в атлас, а каждая строка превращается
+// в набор прямоугольников, которые за кадр рисуются одним вызовом отрисовки.
+
+const ATLAS_SIZE: u32 = 1024;
+const ATLAS_FORMAT: vk::Format = vk::Format::R8_UNORM;
+// Размер, в котором растеризуются символы SDF. Из него они масштабируются до любого размера.
+const SDF_BASE_SIZE: f32 = 48.0;
+// На сколько пикселей вокруг контура хранится расстояние в SDF.
+const SDF_SPREAD: f32 = 6.0;
+
+// Растровый символ четкий только в том размере, в котором растеризован, зато дешев.
+// SDF хранит расстояние до контура и одинаково четко масштабируется в любую сторону.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextMode {
+    Bitmap,
+    Sdf,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TextStyle {
+    // Высота строки в пикселях.
+    pub size: f32,
+    // Линейный цвет RGBA.
+    pub color: [f32; 4],
+    pub mode: TextMode,
+}
+
+// Символ в атласе: прямоугольник в текстуре и его смещение от точки на базовой линии.
+#[derive(Debug, Clone, Copy)]
+struct AtlasGlyph {
+    uv_min: [f32; 2],
+    uv_max: [f32; 2],
+    offset: [f32; 2],
+    size: [f32; 2],
+}
+
+// Растровые символы хранятся отдельно для каждого размера, SDF — один раз.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum GlyphKey {
+    Bitmap(GlyphId, u32),
+    Sdf(GlyphId),
+}
+
+// Атлас на процессоре. Символы укладываются полками: слева направо, пока помещаются, затем новая полка.
+struct GlyphAtlas {
+    pixels: Vec<u8>,
+    glyphs: HashMap<GlyphKey, Option<AtlasGlyph>>,
+    shelf_x: u32,
+    shelf_y: u32,
+    shelf_height: u32,
+    // Область, измененная с последней загрузки в видеопамять: min_x, min_y, max_x, max_y.
+    dirty: Option<[u32; 4]>,
+    full: bool,
+}
+
+impl GlyphAtlas {
+    fn new() -> Self {
+        GlyphAtlas {
+            pixels: vec![0; (ATLAS_SIZE * ATLAS_SIZE) as usize],
+            glyphs: HashMap::new(),
+            shelf_x: 0,
+            shelf_y: 0,
+            shelf_height: 0,
+            dirty: None,
+            full: false,
+        }
+    }
+
+    // Ищет место под прямоугольник с отступом в один пиксель, чтобы соседние символы не смешивались при фильтрации.
+    fn allocate(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        if self.shelf_x + width + 1 > ATLAS_SIZE {
+            self.shelf_x = 0;
+            self.shelf_y += self.shelf_height + 1;
+            self.shelf_height = 0;
+        }
+        if width + 1 > ATLAS_SIZE || self.shelf_y + height + 1 > ATLAS_SIZE {
+            if !self.full {
+                println!("Glyph atlas is full, new glyphs will not be drawn");
+                self.full = true;
+            }
+            return None;
+        }
+        let position = (self.shelf_x + 1, self.shelf_y + 1);
+        self.shelf_x += width + 1;
+        self.shelf_height = self.shelf_height.max(height + 1);
+        Some(position)
+    }
+
+    // Копирует растеризованный символ в атлас и запоминает измененную область.
+    fn insert(&mut self, width: u32, height: u32, pixels: &[u8]) -> Option<[u32; 2]> {
+        let (x, y) = self.allocate(width, height)?;
+        for row in 0..height {
+            let source = (row * width) as usize;
+            let target = ((y + row) * ATLAS_SIZE + x) as usize;
+            self.pixels[target..target + width as usize]
+                .copy_from_slice(&pixels[source..source + width as usize]);
+        }
+        let rect = [x, y, x + width, y + height];
+        self.dirty = Some(match self.dirty {
+            Some(dirty) => [
+                dirty[0].min(rect[0]),
+                dirty[1].min(rect[1]),
+                dirty[2].max(rect[2]),
+                dirty[3].max(rect[3]),
+            ],
+            None => rect,
+        });
+        Some([x, y])
+    }
+
+    fn glyph(&mut self, font: &FontArc, key: GlyphKey) -> Option<AtlasGlyph> {
+        if let Some(glyph) = self.glyphs.get(&key) {
+            return *glyph;
+        }
+        let glyph = match key {
+            GlyphKey::Bitmap(id, size) => self.rasterize_bitmap(font, id, size as f32),
+            GlyphKey::Sdf(id) => self.rasterize_sdf(font, id),
+        };
+        // Пробелы и символы без контура тоже запоминаем, чтобы не растеризовать их каждый кадр.
+        self.glyphs.insert(key, glyph);
+        glyph
+    }
+
+    fn rasterize_bitmap(&mut self, font: &FontArc, id: GlyphId, size: f32) -> Option<AtlasGlyph> {
+        let outline = font.outline_glyph(id.with_scale(PxScale::from(size)))?;
+        let bounds = outline.px_bounds();
+        let width = bounds.width() as u32;
+        let height = bounds.height() as u32;
+        if width == 0 || height == 0 {
+            return None;
+        }
+        let mut pixels = vec![0u8; (width * height) as usize];
+        outline.draw(|x, y, coverage| {
+            pixels[(y * width + x) as usize] = (coverage.clamp(0.0, 1.0) * 255.0) as u8;
+        });
+        let [x, y] = self.insert(width, height, &pixels)?;
+        Some(atlas_glyph(
+            x,
+            y,
+            width,
+            height,
+            [bounds.min.x, bounds.min.y],
+        ))
+    }
+
+    // Расстояние считается перебором соседей в пределах SDF_SPREAD по растровому покрытию символа.
+    // Символ растеризуется один раз, поэтому простого перебора достаточно.
+    fn rasterize_sdf(&mut self, font: &FontArc, id: GlyphId) -> Option<AtlasGlyph> {
+        let outline = font.outline_glyph(id.with_scale(PxScale::from(SDF_BASE_SIZE)))?;
+        let bounds = outline.px_bounds();
+        let spread = SDF_SPREAD as i32;
+        let inner_width = bounds.width() as i32;
+        let inner_height = bounds.height() as i32;
+        let width = inner_width + 2 * spread;
+        let height = inner_height + 2 * spread;
+
+        let mut inside = vec![false; (width * height) as usize];
+        outline.draw(|x, y, coverage| {
+            let index = (y as i32 + spread) * width + x as i32 + spread;
+            inside[index as usize] = coverage >= 0.5;
+        });
+
+        let mut pixels = vec![0u8; (width * height) as usize];
+        for y in 0..height {
+            for x in 0..width {
+                let is_inside = inside[(y * width + x) as usize];
+                let mut nearest = SDF_SPREAD * SDF_SPREAD;
+                for dy in -spread..=spread {
+                    for dx in -spread..=spread {
+                        let (sx, sy) = (x + dx, y + dy);
+                        let other = sx >= 0
+                            && sy >= 0
+                            && sx < width
+                            && sy < height
+                            && inside[(sy * width + sx) as usize];
+                        if other != is_inside {
+                            nearest = nearest.min((dx * dx + dy * dy) as f32);
+                        }
+                    }
+                }
+                let distance = nearest.sqrt() * if is_inside { 1.0 } else { -1.0 };
+                let value = 0.5 + distance / (2.0 * SDF_SPREAD);
+                pixels[(y * width + x) as usize] = (value.clamp(0.0, 1.0) * 255.0) as u8;
+            }
+        }
+
+        let [x, y] = self.insert(width as u32, height as u32, &pixels)?;
+        Some(atlas_glyph(
+            x,
+            y,
+            width as u32,
+            height as u32,
+            [bounds.min.x - SDF_SPREAD, bounds.min.y - SDF_SPREAD],
+        ))
+    }
+}
+
+fn atlas_glyph(x: u32, y: u32, width: u32, height: u32, offset: [f32; 2]) -> AtlasGlyph {
+    let atlas_size = ATLAS_SIZE as f32;
+    AtlasGlyph {
+        uv_min: [x as f32 / atlas_size, y as f32 / atlas_size],
+        uv_max: [
+            (x + width) as f32 / atlas_size,
+            (y + height) as f32 / atlas_size,
+        ],
+        offset,
+        size: [width as f32, height as f32],
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct TextVertex {
+    position: [f32; 2],
+    uv: [f32; 2],
+    color: [f32; 4],
+    sdf: f32,
+}
+
+// Push-константы text.vert.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct TextPushConstants {
+    // Размер кадра в пикселях.
+    screen_size: [f32; 2],
+}
+
+unsafe impl PushConstants for TextPushConstants {
+    const STAGES: vk::ShaderStageFlags = vk::ShaderStageFlags::VERTEX;
+}
+
+// Буферы одного кадра в полете. Промежуточный буфер загрузки атласа освобождается,
+// когда этот кадр начинается снова и его забор уже просигналил.
+#[derive(Default)]
+struct FrameBuffers {
+    vertices: Option<Buffer>,
+    staging: Option<Buffer>,
+}
+
+pub struct TextRenderer {
+    font: FontArc,
+    atlas: GlyphAtlas,
+    vertices: Vec<TextVertex>,
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    atlas_image: vk::Image,
+    atlas_memory: vk::DeviceMemory,
+    atlas_view: vk::ImageView,
+    // Атлас хотя бы раз загружен и находится в SHADER_READ_ONLY_OPTIMAL.
+    atlas_initialized: bool,
+    sampler: vk::Sampler,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    descriptor_set: vk::DescriptorSet,
+    pipeline_layout: vk::PipelineLayout,
+    pipeline: vk::Pipeline,
+    frames: Vec<FrameBuffers>,
+    // Сколько физических пикселей в одной логической единице.
+    scale_factor: f32,
+}
+
+impl TextRenderer {
+    // font_data — содержимое файла TTF или OTF. Возвращает None, если шрифт не удалось прочитать.
+    pub fn new(
+        device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        graph_cache: &mut RenderGraphCache,
+        color_format: vk::Format,
+        frames_in_flight: usize,
+        font_data: Vec<u8>,
+    ) -> Option<Self> {
+        let font = FontArc::try_from_vec(font_data).ok()?;
+
+        let (atlas_image, atlas_memory, atlas_view) = create_atlas(device, &memory_properties);
+
+        let sampler_create_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE);
+        let sampler = unsafe {
+            device
+                .create_sampler(&sampler_create_info, None)
+                .expect("Failed to create text Sampler!")
+        };
+
+        let samplers = [sampler];
+        let bindings = [
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .build(),
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(1)
+                .descriptor_type(vk::DescriptorType::SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .immutable_samplers(&samplers)
+                .build(),
+        ];
+        let descriptor_set_layout_create_info =
+            vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+        let descriptor_set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(&descriptor_set_layout_create_info, None)
+                .expect("Failed to create text Descriptor Set Layout!")
+        };
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::SAMPLED_IMAGE,
+                descriptor_count: 1,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::SAMPLER,
+                descriptor_count: 1,
+            },
+        ];
+        let descriptor_pool_create_info = vk::DescriptorPoolCreateInfo::builder()
+            .max_sets(1)
+            .pool_sizes(&pool_sizes);
+        let descriptor_pool = unsafe {
+            device
+                .create_descriptor_pool(&descriptor_pool_create_info, None)
+                .expect("Failed to create text Descriptor Pool!")
+        };
+
+        let set_layouts = [descriptor_set_layout];
+        let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+            .descriptor_pool(descriptor_pool)
+            .set_layouts(&set_layouts);
+        let descriptor_set = unsafe {
+            device
+                .allocate_descriptor_sets(&allocate_info)
+                .expect("Failed to allocate text Descriptor Set!")[0]
+        };
+        let image_info = [vk::DescriptorImageInfo {
+            sampler: vk::Sampler::null(),
+            image_view: atlas_view,
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        }];
+        let write = vk::WriteDescriptorSet::builder()
+            .dst_set(descriptor_set)
+            .dst_binding(0)
+            .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+            .image_info(&image_info);
+        unsafe { device.update_descriptor_sets(std::slice::from_ref(&write), &[]) };
+
+        let push_constant_ranges = [TextPushConstants::range()];
+        let pipeline_layout_create_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&set_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+        let pipeline_layout = unsafe {
+            device
+                .create_pipeline_layout(&pipeline_layout_create_info, None)
+                .expect("Failed to create text Pipeline Layout!")
+        };
+
+        let pipeline = create_pipeline(device, graph_cache, color_format, pipeline_layout);
+
+        Some(TextRenderer {
+            font,
+            atlas: GlyphAtlas::new(),
+            vertices: Vec::new(),
+            memory_properties,
+            atlas_image,
+            atlas_memory,
+            atlas_view,
+            atlas_initialized: false,
+            sampler,
+            descriptor_set_layout,
+            descriptor_pool,
+            descriptor_set,
+            pipeline_layout,
+            pipeline,
+            frames: (0..frames_in_flight)
+                .map(|_| FrameBuffers::default())
+                .collect(),
+            scale_factor: 1.0,
+        })
+    }
+
+    // Позиции и размеры текста задаются в логических единицах, как и у интерфейса, а символы растрируются
+    // в физических пикселях. Поэтому на экране с масштабом 2 текст занимает то же место и остается четким.
+    pub fn set_scale_factor(&mut self, scale_factor: f32) {
+        self.scale_factor = scale_factor;
+    }
+
+    // Ширина и высота строки в логических единицах без отрисовки.
+    pub fn measure(&self, text: &str, size: f32) -> [f32; 2] {
+        let size = size * self.scale_factor;
+        let mut bounds = [0.0f32, 0.0f32];
+        self.layout(text, [0.0, 0.0], size, |_, caret| {
+            bounds[0] = bounds[0].max(caret[0]);
+            bounds[1] = bounds[1].max(caret[1]);
+        });
+        let font = self.font.as_scaled(PxScale::from(size));
+        [
+            bounds[0] / self.scale_factor,
+            (bounds[1] + font.height()) / self.scale_factor,
+        ]
+    }
+
+    // Добавляет строку в очередь кадра. position — левый верхний угол первой строки в логических единицах.
+    // Строки разделяются символом '\n'.
+    pub fn queue(&mut self, text: &str, position: [f32; 2], style: TextStyle) {
+        // Дальше все считается в физических пикселях.
+        let size = style.size * self.scale_factor;
+        let position = [
+            position[0] * self.scale_factor,
+            position[1] * self.scale_factor,
+        ];
+        let mut glyphs = Vec::new();
+        self.layout(text, position, size, |id, caret| glyphs.push((id, caret)));
+
+        for (id, caret) in glyphs {
+            let (key, scale) = match style.mode {
+                TextMode::Bitmap => (GlyphKey::Bitmap(id, size.round() as u32), 1.0),
+                TextMode::Sdf => (GlyphKey::Sdf(id), size / SDF_BASE_SIZE),
+            };
+            let glyph = match self.atlas.glyph(&self.font, key) {
+                Some(glyph) => glyph,
+                None => continue,
+            };
+            // Растровый символ ставим ровно на пиксели, иначе фильтрация его размоет.
+            let origin = match style.mode {
+                TextMode::Bitmap => [caret[0].round(), caret[1].round()],
+                TextMode::Sdf => caret,
+            };
+            let min = [
+                origin[0] + glyph.offset[0] * scale,
+                origin[1] + glyph.offset[1] * scale,
+            ];
+            let max = [
+                min[0] + glyph.size[0] * scale,
+                min[1] + glyph.size[1] * scale,
+            ];
+            let sdf = if style.mode == TextMode::Sdf {
+                1.0
+            } else {
+                0.0
+            };
+            let vertex = |x: usize, y: usize| TextVertex {
+                position: [[min[0], max[0]][x], [min[1], max[1]][y]],
+                uv: [
+                    [glyph.uv_min[0], glyph.uv_max[0]][x],
+                    [glyph.uv_min[1], glyph.uv_max[1]][y],
+                ],
+                color: style.color,
+                sdf,
+            };
+            self.vertices.extend_from_slice(&[
+                vertex(0, 0),
+                vertex(1, 0),
+                vertex(1, 1),
+                vertex(0, 0),
+                vertex(1, 1),
+                vertex(0, 1),
+            ]);
+        }
+    }
+
+    // Раскладка строки: для каждого символа вызывает emit с его идентификатором и точкой на базовой линии.
+    fn layout(
+        &self,
+        text: &str,
+        position: [f32; 2],
+        size: f32,
+        mut emit: impl FnMut(GlyphId, [f32; 2]),
+    ) {
+        let font = self.font.as_scaled(PxScale::from(size));
+        let line_height = font.height() + font.line_gap();
+        let mut caret = [position[0], position[1] + font.ascent()];
+        let mut previous: Option<GlyphId> = None;
+        for character in text.chars() {
+            if character == '\n' {
+                caret = [position[0], caret[1] + line_height];
+                previous = None;
+                continue;
+            }
+            if character.is_control() {
+                continue;
+            }
+            let id = font.glyph_id(character);
+            if let Some(previous) = previous {
+                caret[0] += font.kern(previous, id);
+            }
+            emit(id, caret);
+            caret[0] += font.h_advance(id);
+            previous = Some(id);
+        }
+    }
+
+    // Добавляет в граф загрузку новых символов атласа и один проход, рисующий весь текст кадра поверх target.
+    // Вызывается после ожидания забора кадра frame_index.
+    pub fn add_passes(
+        &mut self,
+        device: &ash::Device,
+        graph: &mut RenderGraph,
+        target: ImageHandle,
+        extent: vk::Extent2D,
+        frame_index: usize,
+    ) {
+        let memory_properties = self.memory_properties;
+        let frame = &mut self.frames[frame_index];
+        if let Some(staging) = frame.staging.take() {
+            unsafe { staging.destroy(device) };
+        }
+
+        let vertices = std::mem::take(&mut self.vertices);
+        if vertices.is_empty() && self.atlas.dirty.is_none() {
+            return;
+        }
+
+        let atlas = graph.import_image(
+            "glyph atlas",
+            ImportedImage {
+                image: self.atlas_image,
+                view: self.atlas_view,
+                format: ATLAS_FORMAT,
+                extent: vk::Extent2D {
+                    width: ATLAS_SIZE,
+                    height: ATLAS_SIZE,
+                },
+                initial_layout: if self.atlas_initialized {
+                    vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL
+                } else {
+                    vk::ImageLayout::UNDEFINED
+                },
+                initial_stage: vk::PipelineStageFlags::FRAGMENT_SHADER,
+                final_layout: Some(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+            },
+        );
+
+        if let Some([min_x, min_y, max_x, max_y]) = self.atlas.dirty.take() {
+            // Первая загрузка переводит атлас из UNDEFINED, поэтому копируется целиком.
+            let [min_x, min_y, max_x, max_y] = if self.atlas_initialized {
+                [min_x, min_y, max_x, max_y]
+            } else {
+                [0, 0, ATLAS_SIZE, ATLAS_SIZE]
+            };
+            let width = max_x - min_x;
+            let height = max_y - min_y;
+            let staging = Buffer::new(
+                device,
+                &memory_properties,
+                (width * height) as vk::DeviceSize,
+                vk::BufferUsageFlags::TRANSFER_SRC,
+                &[vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT],
+            );
+            unsafe {
+                let data = device
+                    .map_memory(staging.memory, 0, staging.size, vk::MemoryMapFlags::empty())
+                    .expect("Failed to map glyph staging memory!")
+                    as *mut u8;
+                for row in 0..height {
+                    let source = ((min_y + row) * ATLAS_SIZE + min_x) as usize;
+                    std::ptr::copy_nonoverlapping(
+                        self.atlas.pixels[source..].as_ptr(),
+                        data.add((row * width) as usize),
+                        width as usize,
+                    );
+                }
+                device.unmap_memory(staging.memory);
+            }
+
+            let staging_buffer = staging.buffer;
+            graph
+                .add_pass("glyph atlas upload")
+                .write_image(atlas, ImageUsage::Transfer)
+                .execute(move |context| {
+                    let region = vk::BufferImageCopy::builder()
+                        .image_subresource(vk::ImageSubresourceLayers {
+                            aspect_mask: vk::ImageAspectFlags::COLOR,
+                            mip_level: 0,
+                            base_array_layer: 0,
+                            layer_count: 1,
+                        })
+                        .image_offset(vk::Offset3D {
+                            x: min_x as i32,
+                            y: min_y as i32,
+                            z: 0,
+                        })
+                        .image_extent(vk::Extent3D {
+                            width,
+                            height,
+                            depth: 1,
+                        });
+                    unsafe {
+                        context.device.cmd_copy_buffer_to_image(
+                            context.command_buffer,
+                            staging_buffer,
+                            context.image(atlas),
+                            vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                            std::slice::from_ref(&region),
+                        );
+                    }
+                });
+            self.frames[frame_index].staging = Some(staging);
+            self.atlas_initialized = true;
+        }
+
+        if vertices.is_empty() {
+            return;
+        }
+
+        let size = std::mem::size_of_val(vertices.as_slice()) as vk::DeviceSize;
+        let frame = &mut self.frames[frame_index];
+        if frame
+            .vertices
+            .as_ref()
+            .is_none_or(|buffer| buffer.size < size)
+        {
+            if let Some(buffer) = frame.vertices.take() {
+                unsafe { buffer.destroy(device) };
+            }
+            frame.vertices = Some(Buffer::new(
+                device,
+                &memory_properties,
+                size.next_power_of_two(),
+                vk::BufferUsageFlags::VERTEX_BUFFER,
+                &[vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT],
+            ));
+        }
+        let vertex_buffer = frame.vertices.as_ref().unwrap();
+        unsafe {
+            let data = device
+                .map_memory(vertex_buffer.memory, 0, size, vk::MemoryMapFlags::empty())
+                .expect("Failed to map text vertex memory!");
+            std::ptr::copy_nonoverlapping(
+                vertices.as_ptr(),
+                data as *mut TextVertex,
+                vertices.len(),
+            );
+            device.unmap_memory(vertex_buffer.memory);
+        }
+
+        let vertex_buffer = vertex_buffer.buffer;
+        let vertex_count = vertices.len() as u32;
+        let pipeline = self.pipeline;
+        let pipeline_layout = self.pipeline_layout;
+        let descriptor_set = self.descriptor_set;
+        let screen_size = [extent.width as f32, extent.height as f32];
+        graph
+            .add_pass("text")
+            .color_attachment(target, LoadOp::Load)
+            .read_image(
+                atlas,
+                ImageUsage::Sampled(vk::PipelineStageFlags::FRAGMENT_SHADER),
+            )
+            .execute(move |context| unsafe {
+                let device = context.device;
+                let command_buffer = context.command_buffer;
+                device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+                let viewport = vk::Viewport {
+                    x: 0.0,
+                    y: 0.0,
+                    width: screen_size[0],
+                    height: screen_size[1],
+                    min_depth: 0.0,
+                    max_depth: 1.0,
+                };
+                let scissor = vk::Rect2D {
+                    offset: vk::Offset2D { x: 0, y: 0 },
+                    extent,
+                };
+                device.cmd_set_viewport(command_buffer, 0, std::slice::from_ref(&viewport));
+                device.cmd_set_scissor(command_buffer, 0, std::slice::from_ref(&scissor));
+                device.cmd_bind_descriptor_sets(
+                    command_buffer,
+                    vk::PipelineBindPoint::GRAPHICS,
+                    pipeline_layout,
+                    0,
+                    &[descriptor_set],
+                    &[],
+                );
+                device.cmd_bind_vertex_buffers(command_buffer, 0, &[vertex_buffer], &[0]);
+                TextPushConstants { screen_size }.push(device, command_buffer, pipeline_layout);
+                // Весь текст кадра — один вызов отрисовки.
+                device.cmd_draw(command_buffer, vertex_count, 1, 0, 0);
+            });
+    }
+
+    pub unsafe fn destroy(&mut self, device: &ash::Device) {
+        for frame in &mut self.frames {
+            if let Some(buffer) = frame.vertices.take() {
+                buffer.destroy(device);
+            }
+            if let Some(buffer) = frame.staging.take() {
+                buffer.destroy(device);
+            }
+        }
+        device.destroy_pipeline(self.pipeline, None);
+        device.destroy_pipeline_layout(self.pipeline_layout, None);
+        device.destroy_descriptor_pool(self.descriptor_pool, None);
+        device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        device.destroy_sampler(self.sampler, None);
+        device.destroy_image_view(self.atlas_view, None);
+        device.destroy_image(self.atlas_image, None);
+        device.free_memory(self.atlas_memory, None);
+    }
+}
+
+fn create_atlas(
+    device: &ash::Device,
+    memory_properties: &vk::PhysicalDeviceMemoryProperties,
+) -> (vk::Image, vk::DeviceMemory, vk::ImageView) {
+    let image_create_info = vk::ImageCreateInfo::builder()
+        .image_type(vk::ImageType::TYPE_2D)
+        .format(ATLAS_FORMAT)
+        .extent(vk::Extent3D {
+            width: ATLAS_SIZE,
+            height: ATLAS_SIZE,
+            depth: 1,
+        })
+        .mip_levels(1)
+        .array_layers(1)
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .tiling(vk::ImageTiling::OPTIMAL)
+        .usage(vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST)
+        .sharing_mode(vk::SharingMode::EXCLUSIVE)
+        .initial_layout(vk::ImageLayout::UNDEFINED);
+
+    unsafe {
+        let image = device
+            .create_image(&image_create_info, None)
+            .expect("Failed to create glyph atlas!");
+        let requirements = device.get_image_memory_requirements(image);
+        let memory_type_index = find_memory_type(
+            memory_properties,
+            requirements.memory_type_bits,
+            vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        )
+        .expect("No suitable memory type for glyph atlas!");
+        let allocate_info = vk::MemoryAllocateInfo::builder()
+            .allocation_size(requirements.size)
+            .memory_type_index(memory_type_index);
+        let memory = device
+            .allocate_memory(&allocate_info, None)
+            .expect("Failed to allocate glyph atlas memory!");
+        device
+            .bind_image_memory(image, memory, 0)
+            .expect("Failed to bind glyph atlas memory!");
+
+        let view_create_info = vk::ImageViewCreateInfo::builder()
+            .image(image)
+            .view_type(vk::ImageViewType::TYPE_2D)
+            .format(ATLAS_FORMAT)
+            .subresource_range(vk::ImageSubresourceRange {
+                aspect_mask: vk::ImageAspectFlags::COLOR,
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            });
+        let view = device
+            .create_image_view(&view_create_info, None)
+            .expect("Failed to create glyph atlas view!");
+        (image, memory, view)
+    }
+}
+
+fn create_pipeline(
+    device: &ash::Device,
+    graph_cache: &mut RenderGraphCache,
+    color_format: vk::Format,
+    pipeline_layout: vk::PipelineLayout,
+) -> vk::Pipeline {
+    let create_shader_module = |code: &[u8]| {
+        let shader_module_create_info = vk::ShaderModuleCreateInfo {
+            code_size: code.len(),
+            p_code: code.as_ptr() as *const u32,
+            ..Default::default()
+        };
+        unsafe {
+            device
+                .create_shader_module(&shader_module_create_info, None)
+                .expect("Failed to create text Shader Module!")
+        }
+    };
+    let vert_shader_module = create_shader_module(include_bytes!("spv/text_vert.spv"));
+    let frag_shader_module = create_shader_module(include_bytes!("spv/text_frag.spv"));
+
+    let main_function_name = CString::new("main").unwrap();
+    let shader_stages = [
+        vk::PipelineShaderStageCreateInfo::builder()
+            .module(vert_shader_module)
+            .name(&main_function_name)
+            .stage(vk::ShaderStageFlags::VERTEX)
+            .build(),
+        vk::PipelineShaderStageCreateInfo::builder()
+            .module(frag_shader_module)
+            .name(&main_function_name)
+            .stage(vk::ShaderStageFlags::FRAGMENT)
+            .build(),
+    ];
+
+    let vertex_binding_descriptions = [vk::VertexInputBindingDescription {
+        binding: 0,
+        stride: std::mem::size_of::<TextVertex>() as u32,
+        input_rate: vk::VertexInputRate::VERTEX,
+    }];
+    let vertex_attribute_descriptions = [
+        vk::VertexInputAttributeDescription {
+            location: 0,
+            binding: 0,
+            format: vk::Format::R32G32_SFLOAT,
+            offset: 0,
+        },
+        vk::VertexInputAttributeDescription {
+            location: 1,
+            binding: 0,
+            format: vk::Format::R32G32_SFLOAT,
+            offset: 8,
+        },
+        vk::VertexInputAttributeDescription {
+            location: 2,
+            binding: 0,
+            format: vk::Format::R32G32B32A32_SFLOAT,
+            offset: 16,
+        },
+        vk::VertexInputAttributeDescription {
+            location: 3,
+            binding: 0,
+            format: vk::Format::R32_SFLOAT,
+            offset: 32,
+        },
+    ];
+    let vertex_input_state_create_info = vk::PipelineVertexInputStateCreateInfo::builder()
+        .vertex_binding_descriptions(&vertex_binding_descriptions)
+        .vertex_attribute_descriptions(&vertex_attribute_descriptions);
+
+    let input_assembly_state_create_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+
+    let viewport_state_create_info = vk::PipelineViewportStateCreateInfo::builder()
+        .viewport_count(1)
+        .scissor_count(1);
+
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state_create_info =
+        vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+
+    let rasterization_state_create_info = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::CLOCKWISE);
+
+    let multisample_state_create_info = vk::PipelineMultisampleStateCreateInfo::builder()
+        .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+
+    let depth_state_create_info = vk::PipelineDepthStencilStateCreateInfo::default();
+
+    let color_blend_attachment_states = [vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(vk::ColorComponentFlags::RGBA)
+        .blend_enable(true)
+        .src_color_blend_factor(vk::BlendFactor::SRC_ALPHA)
+        .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .color_blend_op(vk::BlendOp::ADD)
+        .src_alpha_blend_factor(vk::BlendFactor::ONE)
+        .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .alpha_blend_op(vk::BlendOp::ADD)
+        .build()];
+    let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
+        .attachments(&color_blend_attachment_states);
+
+    let color_attachment_formats = [color_format];
+    let mut pipeline_rendering_create_info = vk::PipelineRenderingCreateInfo::builder()
+        .color_attachment_formats(&color_attachment_formats);
+
+    let mut graphic_pipeline_create_info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(&shader_stages)
+        .vertex_input_state(&vertex_input_state_create_info)
+        .input_assembly_state(&input_assembly_state_create_info)
+        .viewport_state(&viewport_state_create_info)
+        .rasterization_state(&rasterization_state_create_info)
+        .multisample_state(&multisample_state_create_info)
+        .depth_stencil_state(&depth_state_create_info)
+        .color_blend_state(&color_blend_state)
+        .dynamic_state(&dynamic_state_create_info)
+        .layout(pipeline_layout);
+
+    if graph_cache.uses_dynamic_rendering() {
+        graphic_pipeline_create_info =
+            graphic_pipeline_create_info.push_next(&mut pipeline_rendering_create_info);
+    } else {
+        let render_pass =
+            graph_cache.compatible_render_pass(device, &color_attachment_formats, None);
+        graphic_pipeline_create_info = graphic_pipeline_create_info.render_pass(render_pass);
+    }
+
+    let pipeline = unsafe {
+        device
+            .create_graphics_pipelines(
+                vk::PipelineCache::null(),
+                &[graphic_pipeline_create_info.build()],
+                None,
+            )
+            .expect("Failed to create text Pipeline!")[0]
+    };
+
+    unsafe {
+        device.destroy_shader_module(vert_shader_module, None);
+        device.destroy_shader_module(frag_shader_module, None);
+    }
+
+    pipeline
+}
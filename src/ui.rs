@@ -0,0 +1,983 @@
+use ash::vk;
+
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::time::Instant;
+
+use winit::event::{
+    ElementState, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent,
+};
+
+use crate::memory::{find_memory_type, Buffer};
+use crate::push_constants::PushConstants;
+use crate::render_graph::{
+    ImageHandle, ImageUsage, ImportedImage, LoadOp, RenderGraph, RenderGraphCache,
+};
+
+// Интерфейс рисуется библиотекой egui в режиме immediate mode: каждый кадр заново описываем окна и виджеты,
+// а egui возвращает треугольники и изменения текстур, которые остается нарисовать поверх сцены.
+
+// Формат текстур интерфейса. egui отдает цвета в sRGB, поэтому при выборке они сразу переводятся в линейное пространство.
+const TEXTURE_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+// Сколько текстур интерфейса может существовать одновременно.
+const MAX_TEXTURES: u32 = 64;
+// Сколько точек прокручивает одна строка колеса мыши.
+const POINTS_PER_SCROLL_LINE: f32 = 50.0;
+
+// Собирает события winit в формат egui между кадрами.
+pub struct UiInput {
+    events: Vec<egui::Event>,
+    modifiers: egui::Modifiers,
+    pointer_position: egui::Pos2,
+    max_texture_side: usize,
+    start: Instant,
+    // Время интерфейса при воспроизведении записи: сумма записанных времен кадров вместо часов.
+    recorded_time: Option<f64>,
+}
+
+impl UiInput {
+    // max_texture_side — максимальный размер текстуры, берется из лимитов устройства.
+    pub fn new(max_texture_side: usize) -> Self {
+        UiInput {
+            events: Vec::new(),
+            modifiers: egui::Modifiers::default(),
+            pointer_position: egui::Pos2::ZERO,
+            max_texture_side,
+            start: Instant::now(),
+            recorded_time: None,
+        }
+    }
+
+    // Переводит интерфейс на время записи и продвигает его на записанное время кадра. Тогда анимации
+    // и двойные щелчки при воспроизведении срабатывают в те же кадры, что и при записи.
+    pub fn advance_recorded_time(&mut self, delta_time: f32) {
+        *self.recorded_time.get_or_insert(0.0) += delta_time as f64;
+    }
+
+    // Передает событие интерфейсу. Возвращает true, если интерфейс забрал событие себе
+    // (курсор над окном интерфейса или в фокусе поле ввода), тогда приложению его обрабатывать не нужно.
+    pub fn handle_event(
+        &mut self,
+        context: &egui::Context,
+        event: &WindowEvent,
+        pixels_per_point: f32,
+    ) -> bool {
+        match event {
+            WindowEvent::CursorMoved { position, .. } => {
+                self.pointer_position = egui::pos2(
+                    position.x as f32 / pixels_per_point,
+                    position.y as f32 / pixels_per_point,
+                );
+                self.events
+                    .push(egui::Event::PointerMoved(self.pointer_position));
+                false
+            }
+            WindowEvent::CursorLeft { .. } => {
+                self.events.push(egui::Event::PointerGone);
+                false
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                let button = match button {
+                    MouseButton::Left => egui::PointerButton::Primary,
+                    MouseButton::Right => egui::PointerButton::Secondary,
+                    MouseButton::Middle => egui::PointerButton::Middle,
+                    MouseButton::Other(_) => return false,
+                };
+                self.events.push(egui::Event::PointerButton {
+                    pos: self.pointer_position,
+                    button,
+                    pressed: *state == ElementState::Pressed,
+                    modifiers: self.modifiers,
+                });
+                context.wants_pointer_input()
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                let delta = match delta {
+                    MouseScrollDelta::LineDelta(x, y) => {
+                        egui::vec2(*x, *y) * POINTS_PER_SCROLL_LINE
+                    }
+                    MouseScrollDelta::PixelDelta(delta) => {
+                        egui::vec2(delta.x as f32, delta.y as f32) / pixels_per_point
+                    }
+                };
+                self.events.push(egui::Event::Scroll(delta));
+                context.wants_pointer_input()
+            }
+            WindowEvent::ModifiersChanged(state) => {
+                self.modifiers = egui::Modifiers {
+                    alt: state.alt(),
+                    ctrl: state.ctrl(),
+                    shift: state.shift(),
+                    mac_cmd: cfg!(target_os = "macos") && state.logo(),
+                    command: if cfg!(target_os = "macos") {
+                        state.logo()
+                    } else {
+                        state.ctrl()
+                    },
+                };
+                false
+            }
+            WindowEvent::ReceivedCharacter(character) => {
+                if character.is_control() {
+                    return false;
+                }
+                self.events.push(egui::Event::Text(character.to_string()));
+                context.wants_keyboard_input()
+            }
+            WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        virtual_keycode: Some(keycode),
+                        state,
+                        ..
+                    },
+                ..
+            } => {
+                if let Some(key) = translate_key(*keycode) {
+                    self.events.push(egui::Event::Key {
+                        key,
+                        pressed: *state == ElementState::Pressed,
+                        modifiers: self.modifiers,
+                    });
+                }
+                context.wants_keyboard_input()
+            }
+            _ => false,
+        }
+    }
+
+    // Забирает накопленные события для нового кадра интерфейса. screen_size — размер кадра в пикселях.
+    pub fn take(&mut self, screen_size: vk::Extent2D, pixels_per_point: f32) -> egui::RawInput {
+        egui::RawInput {
+            screen_rect: Some(egui::Rect::from_min_size(
+                egui::Pos2::ZERO,
+                egui::vec2(
+                    screen_size.width as f32 / pixels_per_point,
+                    screen_size.height as f32 / pixels_per_point,
+                ),
+            )),
+            pixels_per_point: Some(pixels_per_point),
+            max_texture_side: Some(self.max_texture_side),
+            time: Some(
+                self.recorded_time
+                    .unwrap_or_else(|| self.start.elapsed().as_secs_f64()),
+            ),
+            modifiers: self.modifiers,
+            events: std::mem::take(&mut self.events),
+            ..Default::default()
+        }
+    }
+}
+
+fn translate_key(keycode: VirtualKeyCode) -> Option<egui::Key> {
+    let key = match keycode {
+        VirtualKeyCode::Down => egui::Key::ArrowDown,
+        VirtualKeyCode::Left => egui::Key::ArrowLeft,
+        VirtualKeyCode::Right => egui::Key::ArrowRight,
+        VirtualKeyCode::Up => egui::Key::ArrowUp,
+        VirtualKeyCode::Escape => egui::Key::Escape,
+        VirtualKeyCode::Tab => egui::Key::Tab,
+        VirtualKeyCode::Back => egui::Key::Backspace,
+        VirtualKeyCode::Return => egui::Key::Enter,
+        VirtualKeyCode::Space => egui::Key::Space,
+        VirtualKeyCode::Insert => egui::Key::Insert,
+        VirtualKeyCode::Delete => egui::Key::Delete,
+        VirtualKeyCode::Home => egui::Key::Home,
+        VirtualKeyCode::End => egui::Key::End,
+        VirtualKeyCode::PageUp => egui::Key::PageUp,
+        VirtualKeyCode::PageDown => egui::Key::PageDown,
+        // Буквы, которые egui использует в сочетаниях клавиш для редактирования текста.
+        VirtualKeyCode::A => egui::Key::A,
+        VirtualKeyCode::K => egui::Key::K,
+        VirtualKeyCode::U => egui::Key::U,
+        VirtualKeyCode::W => egui::Key::W,
+        VirtualKeyCode::Z => egui::Key::Z,
+        _ => return None,
+    };
+    Some(key)
+}
+
+struct UiTexture {
+    image: vk::Image,
+    memory: vk::DeviceMemory,
+    view: vk::ImageView,
+    descriptor_set: vk::DescriptorSet,
+    extent: vk::Extent2D,
+}
+
+// Ресурс, который еще может читать видеокарта из кадров в полете. Удаляется, когда все они завершатся.
+enum Garbage {
+    Texture(UiTexture),
+    Buffer(Buffer),
+}
+
+// Буферы вершин и индексов одного кадра в полете. Растут по мере необходимости.
+#[derive(Default)]
+struct FrameBuffers {
+    vertices: Option<Buffer>,
+    indices: Option<Buffer>,
+}
+
+// Push-константы ui.vert.
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct UiPushConstants {
+    // Размер экрана в точках.
+    screen_size: [f32; 2],
+}
+
+unsafe impl PushConstants for UiPushConstants {
+    const STAGES: vk::ShaderStageFlags = vk::ShaderStageFlags::VERTEX;
+}
+
+// Одна команда отрисовки: часть общего буфера индексов со своей текстурой и прямоугольником отсечения.
+struct DrawCommand {
+    scissor: vk::Rect2D,
+    descriptor_set: vk::DescriptorSet,
+    first_index: u32,
+    index_count: u32,
+    vertex_offset: i32,
+}
+
+pub struct UiRenderer {
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+    pipeline: vk::Pipeline,
+    pipeline_layout: vk::PipelineLayout,
+    descriptor_set_layout: vk::DescriptorSetLayout,
+    descriptor_pool: vk::DescriptorPool,
+    sampler: vk::Sampler,
+    textures: HashMap<egui::TextureId, UiTexture>,
+    frames: Vec<FrameBuffers>,
+    garbage: Vec<(u64, Garbage)>,
+    frame_number: u64,
+}
+
+impl UiRenderer {
+    // color_format — формат изображения, поверх которого рисуется интерфейс.
+    pub fn new(
+        device: &ash::Device,
+        memory_properties: vk::PhysicalDeviceMemoryProperties,
+        graph_cache: &mut RenderGraphCache,
+        color_format: vk::Format,
+        frames_in_flight: usize,
+    ) -> Self {
+        // Текстуры интерфейса растягиваются и сжимаются, поэтому нужна линейная фильтрация.
+        let sampler_create_info = vk::SamplerCreateInfo::builder()
+            .mag_filter(vk::Filter::LINEAR)
+            .min_filter(vk::Filter::LINEAR)
+            .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+            .address_mode_u(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_v(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .address_mode_w(vk::SamplerAddressMode::CLAMP_TO_EDGE)
+            .max_lod(vk::LOD_CLAMP_NONE);
+        let sampler = unsafe {
+            device
+                .create_sampler(&sampler_create_info, None)
+                .expect("Failed to create UI Sampler!")
+        };
+
+        // Сэмплер один на все текстуры, поэтому он неизменяемый и задается прямо в раскладке набора дескрипторов.
+        let samplers = [sampler];
+        let bindings = [
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(0)
+                .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .build(),
+            vk::DescriptorSetLayoutBinding::builder()
+                .binding(1)
+                .descriptor_type(vk::DescriptorType::SAMPLER)
+                .descriptor_count(1)
+                .stage_flags(vk::ShaderStageFlags::FRAGMENT)
+                .immutable_samplers(&samplers)
+                .build(),
+        ];
+        let descriptor_set_layout_create_info =
+            vk::DescriptorSetLayoutCreateInfo::builder().bindings(&bindings);
+        let descriptor_set_layout = unsafe {
+            device
+                .create_descriptor_set_layout(&descriptor_set_layout_create_info, None)
+                .expect("Failed to create UI Descriptor Set Layout!")
+        };
+
+        let pool_sizes = [
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::SAMPLED_IMAGE,
+                descriptor_count: MAX_TEXTURES,
+            },
+            vk::DescriptorPoolSize {
+                ty: vk::DescriptorType::SAMPLER,
+                descriptor_count: MAX_TEXTURES,
+            },
+        ];
+        // Текстуры создаются и удаляются по ходу работы, поэтому наборы нужно освобождать по одному.
+        let descriptor_pool_create_info = vk::DescriptorPoolCreateInfo::builder()
+            .flags(vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET)
+            .max_sets(MAX_TEXTURES)
+            .pool_sizes(&pool_sizes);
+        let descriptor_pool = unsafe {
+            device
+                .create_descriptor_pool(&descriptor_pool_create_info, None)
+                .expect("Failed to create UI Descriptor Pool!")
+        };
+
+        // Размер экрана в точках передается через push-константы.
+        let push_constant_ranges = [UiPushConstants::range()];
+        let set_layouts = [descriptor_set_layout];
+        let pipeline_layout_create_info = vk::PipelineLayoutCreateInfo::builder()
+            .set_layouts(&set_layouts)
+            .push_constant_ranges(&push_constant_ranges);
+        let pipeline_layout = unsafe {
+            device
+                .create_pipeline_layout(&pipeline_layout_create_info, None)
+                .expect("Failed to create UI Pipeline Layout!")
+        };
+
+        let pipeline = create_pipeline(device, graph_cache, color_format, pipeline_layout);
+
+        UiRenderer {
+            memory_properties,
+            pipeline,
+            pipeline_layout,
+            descriptor_set_layout,
+            descriptor_pool,
+            sampler,
+            textures: HashMap::new(),
+            frames: (0..frames_in_flight)
+                .map(|_| FrameBuffers::default())
+                .collect(),
+            garbage: Vec::new(),
+            frame_number: 0,
+        }
+    }
+
+    // Добавляет в граф загрузку измененных текстур и проход, рисующий интерфейс поверх target.
+    // Вызывается после ожидания забора кадра frame_index.
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_passes(
+        &mut self,
+        device: &ash::Device,
+        graph: &mut RenderGraph,
+        target: ImageHandle,
+        extent: vk::Extent2D,
+        frame_index: usize,
+        pixels_per_point: f32,
+        textures_delta: egui::TexturesDelta,
+        meshes: Vec<egui::ClippedMesh>,
+    ) {
+        self.collect_garbage(device);
+
+        let mut texture_handles = HashMap::new();
+        for (id, delta) in textures_delta.set {
+            let handle = self.upload_texture(device, graph, id, delta);
+            texture_handles.insert(id, handle);
+        }
+
+        let meshes = meshes
+            .into_iter()
+            .filter(|egui::ClippedMesh(_, mesh)| {
+                !mesh.indices.is_empty() && self.textures.contains_key(&mesh.texture_id)
+            })
+            .collect::<Vec<_>>();
+
+        if !meshes.is_empty() {
+            let vertex_count = meshes
+                .iter()
+                .map(|mesh| mesh.1.vertices.len())
+                .sum::<usize>();
+            let index_count = meshes
+                .iter()
+                .map(|mesh| mesh.1.indices.len())
+                .sum::<usize>();
+            let vertex_buffer = self.frame_buffer(
+                device,
+                frame_index,
+                false,
+                (vertex_count * std::mem::size_of::<egui::epaint::Vertex>()) as vk::DeviceSize,
+            );
+            let index_buffer = self.frame_buffer(
+                device,
+                frame_index,
+                true,
+                (index_count * std::mem::size_of::<u32>()) as vk::DeviceSize,
+            );
+
+            let mut commands = Vec::with_capacity(meshes.len());
+            unsafe {
+                let vertices = device
+                    .map_memory(
+                        vertex_buffer.1,
+                        0,
+                        vk::WHOLE_SIZE,
+                        vk::MemoryMapFlags::empty(),
+                    )
+                    .expect("Failed to map UI vertex memory!")
+                    as *mut egui::epaint::Vertex;
+                let indices = device
+                    .map_memory(
+                        index_buffer.1,
+                        0,
+                        vk::WHOLE_SIZE,
+                        vk::MemoryMapFlags::empty(),
+                    )
+                    .expect("Failed to map UI index memory!")
+                    as *mut u32;
+
+                let mut first_vertex = 0;
+                let mut first_index = 0;
+                for egui::ClippedMesh(clip_rect, mesh) in &meshes {
+                    std::ptr::copy_nonoverlapping(
+                        mesh.vertices.as_ptr(),
+                        vertices.add(first_vertex),
+                        mesh.vertices.len(),
+                    );
+                    std::ptr::copy_nonoverlapping(
+                        mesh.indices.as_ptr(),
+                        indices.add(first_index),
+                        mesh.indices.len(),
+                    );
+
+                    if let Some(scissor) = clip_to_scissor(*clip_rect, extent, pixels_per_point) {
+                        commands.push(DrawCommand {
+                            scissor,
+                            descriptor_set: self.textures[&mesh.texture_id].descriptor_set,
+                            first_index: first_index as u32,
+                            index_count: mesh.indices.len() as u32,
+                            vertex_offset: first_vertex as i32,
+                        });
+                    }
+                    first_vertex += mesh.vertices.len();
+                    first_index += mesh.indices.len();
+                }
+
+                device.unmap_memory(vertex_buffer.1);
+                device.unmap_memory(index_buffer.1);
+            }
+
+            let pipeline = self.pipeline;
+            let pipeline_layout = self.pipeline_layout;
+            let screen_size = [
+                extent.width as f32 / pixels_per_point,
+                extent.height as f32 / pixels_per_point,
+            ];
+            let mut pass = graph.add_pass("ui").color_attachment(target, LoadOp::Load);
+            // Текстуры, загруженные в этом кадре, читаются только после завершения копирования.
+            for handle in texture_handles.values() {
+                pass = pass.read_image(
+                    *handle,
+                    ImageUsage::Sampled(vk::PipelineStageFlags::FRAGMENT_SHADER),
+                );
+            }
+            pass.execute(move |context| unsafe {
+                let device = context.device;
+                let command_buffer = context.command_buffer;
+                device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, pipeline);
+                let viewport = vk::Viewport {
+                    x: 0.0,
+                    y: 0.0,
+                    width: extent.width as f32,
+                    height: extent.height as f32,
+                    min_depth: 0.0,
+                    max_depth: 1.0,
+                };
+                device.cmd_set_viewport(command_buffer, 0, std::slice::from_ref(&viewport));
+                device.cmd_bind_vertex_buffers(command_buffer, 0, &[vertex_buffer.0], &[0]);
+                device.cmd_bind_index_buffer(
+                    command_buffer,
+                    index_buffer.0,
+                    0,
+                    vk::IndexType::UINT32,
+                );
+                UiPushConstants { screen_size }.push(device, command_buffer, pipeline_layout);
+
+                for command in &commands {
+                    device.cmd_set_scissor(
+                        command_buffer,
+                        0,
+                        std::slice::from_ref(&command.scissor),
+                    );
+                    device.cmd_bind_descriptor_sets(
+                        command_buffer,
+                        vk::PipelineBindPoint::GRAPHICS,
+                        pipeline_layout,
+                        0,
+                        &[command.descriptor_set],
+                        &[],
+                    );
+                    device.cmd_draw_indexed(
+                        command_buffer,
+                        command.index_count,
+                        1,
+                        command.first_index,
+                        command.vertex_offset,
+                        0,
+                    );
+                }
+            });
+        }
+
+        // Удаленные текстуры могли использоваться в этом кадре, поэтому они уходят в очередь на удаление.
+        for id in textures_delta.free {
+            if let Some(texture) = self.textures.remove(&id) {
+                self.garbage
+                    .push((self.frame_number, Garbage::Texture(texture)));
+            }
+        }
+    }
+
+    // Освобождает ресурсы, которые видеокарта уже точно не использует: с момента их замены
+    // прошло столько кадров, сколько их бывает в полете.
+    fn collect_garbage(&mut self, device: &ash::Device) {
+        self.frame_number += 1;
+        let frame_number = self.frame_number;
+        let frames_in_flight = self.frames.len() as u64;
+        let descriptor_pool = self.descriptor_pool;
+        self.garbage.retain(|(frame, garbage)| {
+            if frame_number - frame < frames_in_flight {
+                return true;
+            }
+            unsafe { destroy_garbage(device, descriptor_pool, garbage) };
+            false
+        });
+    }
+
+    // Возвращает буфер вершин или индексов кадра не меньше size байт. Старый буфер мог использоваться
+    // другими кадрами в полете только через этот же кадр, а его забор уже дождались, поэтому удаляется сразу.
+    fn frame_buffer(
+        &mut self,
+        device: &ash::Device,
+        frame_index: usize,
+        indices: bool,
+        size: vk::DeviceSize,
+    ) -> (vk::Buffer, vk::DeviceMemory) {
+        let frame = &mut self.frames[frame_index];
+        let (slot, usage) = if indices {
+            (&mut frame.indices, vk::BufferUsageFlags::INDEX_BUFFER)
+        } else {
+            (&mut frame.vertices, vk::BufferUsageFlags::VERTEX_BUFFER)
+        };
+        if slot.as_ref().is_none_or(|buffer| buffer.size < size) {
+            if let Some(buffer) = slot.take() {
+                unsafe { buffer.destroy(device) };
+            }
+            *slot = Some(Buffer::new(
+                device,
+                &self.memory_properties,
+                size.next_power_of_two(),
+                usage,
+                &[vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT],
+            ));
+        }
+        let buffer = slot.as_ref().unwrap();
+        (buffer.buffer, buffer.memory)
+    }
+
+    // Копирует изменение текстуры через промежуточный буфер. Новая текстура создается целиком,
+    // а частичное изменение (например, новые символы в атласе шрифта) дописывается в существующую.
+    fn upload_texture(
+        &mut self,
+        device: &ash::Device,
+        graph: &mut RenderGraph,
+        id: egui::TextureId,
+        delta: egui::epaint::ImageDelta,
+    ) -> ImageHandle {
+        let [width, height] = delta.image.size();
+        let pixels: Vec<u8> = match &delta.image {
+            egui::ImageData::Color(image) => image
+                .pixels
+                .iter()
+                .flat_map(|color| color.to_array())
+                .collect(),
+            egui::ImageData::Alpha(image) => image
+                .srgba_pixels(1.0)
+                .flat_map(|color| color.to_array())
+                .collect(),
+        };
+
+        let staging = Buffer::new(
+            device,
+            &self.memory_properties,
+            pixels.len() as vk::DeviceSize,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            &[vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT],
+        );
+        unsafe {
+            let data = device
+                .map_memory(staging.memory, 0, staging.size, vk::MemoryMapFlags::empty())
+                .expect("Failed to map UI staging memory!");
+            std::ptr::copy_nonoverlapping(pixels.as_ptr(), data as *mut u8, pixels.len());
+            device.unmap_memory(staging.memory);
+        }
+
+        let (offset, initial_layout, initial_stage) = match delta.pos {
+            Some([x, y]) => (
+                vk::Offset3D {
+                    x: x as i32,
+                    y: y as i32,
+                    z: 0,
+                },
+                vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+            ),
+            None => {
+                let extent = vk::Extent2D {
+                    width: width as u32,
+                    height: height as u32,
+                };
+                let texture = self.create_texture(device, extent);
+                if let Some(old) = self.textures.insert(id, texture) {
+                    self.garbage
+                        .push((self.frame_number, Garbage::Texture(old)));
+                }
+                (
+                    vk::Offset3D::default(),
+                    vk::ImageLayout::UNDEFINED,
+                    vk::PipelineStageFlags::TOP_OF_PIPE,
+                )
+            }
+        };
+        let texture = &self.textures[&id];
+
+        let handle = graph.import_image(
+            "ui texture",
+            ImportedImage {
+                image: texture.image,
+                view: texture.view,
+                format: TEXTURE_FORMAT,
+                extent: texture.extent,
+                initial_layout,
+                initial_stage,
+                // Между кадрами текстура всегда остается готовой для чтения в шейдере.
+                final_layout: Some(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+            },
+        );
+        let staging_buffer = staging.buffer;
+        graph
+            .add_pass("ui upload")
+            .write_image(handle, ImageUsage::Transfer)
+            .execute(move |context| {
+                let region = vk::BufferImageCopy::builder()
+                    .image_subresource(vk::ImageSubresourceLayers {
+                        aspect_mask: vk::ImageAspectFlags::COLOR,
+                        mip_level: 0,
+                        base_array_layer: 0,
+                        layer_count: 1,
+                    })
+                    .image_offset(offset)
+                    .image_extent(vk::Extent3D {
+                        width: width as u32,
+                        height: height as u32,
+                        depth: 1,
+                    });
+                unsafe {
+                    context.device.cmd_copy_buffer_to_image(
+                        context.command_buffer,
+                        staging_buffer,
+                        context.image(handle),
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        std::slice::from_ref(&region),
+                    );
+                }
+            });
+        self.garbage
+            .push((self.frame_number, Garbage::Buffer(staging)));
+        handle
+    }
+
+    fn create_texture(&self, device: &ash::Device, extent: vk::Extent2D) -> UiTexture {
+        let image_create_info = vk::ImageCreateInfo::builder()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(TEXTURE_FORMAT)
+            .extent(vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            })
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+
+        unsafe {
+            let image = device
+                .create_image(&image_create_info, None)
+                .expect("Failed to create UI texture!");
+            let requirements = device.get_image_memory_requirements(image);
+            let memory_type_index = find_memory_type(
+                &self.memory_properties,
+                requirements.memory_type_bits,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+            )
+            .expect("No suitable memory type for UI texture!");
+            let allocate_info = vk::MemoryAllocateInfo::builder()
+                .allocation_size(requirements.size)
+                .memory_type_index(memory_type_index);
+            let memory = device
+                .allocate_memory(&allocate_info, None)
+                .expect("Failed to allocate UI texture memory!");
+            device
+                .bind_image_memory(image, memory, 0)
+                .expect("Failed to bind UI texture memory!");
+
+            let view_create_info = vk::ImageViewCreateInfo::builder()
+                .image(image)
+                .view_type(vk::ImageViewType::TYPE_2D)
+                .format(TEXTURE_FORMAT)
+                .subresource_range(vk::ImageSubresourceRange {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    base_mip_level: 0,
+                    level_count: 1,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                });
+            let view = device
+                .create_image_view(&view_create_info, None)
+                .expect("Failed to create UI texture view!");
+
+            let set_layouts = [self.descriptor_set_layout];
+            let allocate_info = vk::DescriptorSetAllocateInfo::builder()
+                .descriptor_pool(self.descriptor_pool)
+                .set_layouts(&set_layouts);
+            let descriptor_set = device
+                .allocate_descriptor_sets(&allocate_info)
+                .expect("Failed to allocate UI Descriptor Set!")[0];
+            let image_info = [vk::DescriptorImageInfo {
+                sampler: vk::Sampler::null(),
+                image_view: view,
+                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            }];
+            let write = vk::WriteDescriptorSet::builder()
+                .dst_set(descriptor_set)
+                .dst_binding(0)
+                .descriptor_type(vk::DescriptorType::SAMPLED_IMAGE)
+                .image_info(&image_info);
+            device.update_descriptor_sets(std::slice::from_ref(&write), &[]);
+
+            UiTexture {
+                image,
+                memory,
+                view,
+                descriptor_set,
+                extent,
+            }
+        }
+    }
+
+    pub unsafe fn destroy(&mut self, device: &ash::Device) {
+        for (_, garbage) in self.garbage.drain(..) {
+            destroy_garbage(device, self.descriptor_pool, &garbage);
+        }
+        for (_, texture) in self.textures.drain() {
+            destroy_garbage(device, self.descriptor_pool, &Garbage::Texture(texture));
+        }
+        for frame in &mut self.frames {
+            frame
+                .vertices
+                .take()
+                .iter()
+                .for_each(|buffer| buffer.destroy(device));
+            frame
+                .indices
+                .take()
+                .iter()
+                .for_each(|buffer| buffer.destroy(device));
+        }
+        device.destroy_pipeline(self.pipeline, None);
+        device.destroy_pipeline_layout(self.pipeline_layout, None);
+        device.destroy_descriptor_pool(self.descriptor_pool, None);
+        device.destroy_descriptor_set_layout(self.descriptor_set_layout, None);
+        device.destroy_sampler(self.sampler, None);
+    }
+}
+
+unsafe fn destroy_garbage(
+    device: &ash::Device,
+    descriptor_pool: vk::DescriptorPool,
+    garbage: &Garbage,
+) {
+    match garbage {
+        Garbage::Texture(texture) => {
+            device
+                .free_descriptor_sets(descriptor_pool, &[texture.descriptor_set])
+                .expect("Failed to free UI Descriptor Set!");
+            device.destroy_image_view(texture.view, None);
+            device.destroy_image(texture.image, None);
+            device.free_memory(texture.memory, None);
+        }
+        Garbage::Buffer(buffer) => buffer.destroy(device),
+    }
+}
+
+// Переводит прямоугольник отсечения egui из точек в пиксели кадра. Пустой прямоугольник рисовать не нужно.
+fn clip_to_scissor(
+    clip_rect: egui::Rect,
+    extent: vk::Extent2D,
+    pixels_per_point: f32,
+) -> Option<vk::Rect2D> {
+    let min_x = (clip_rect.min.x * pixels_per_point).round().max(0.0) as u32;
+    let min_y = (clip_rect.min.y * pixels_per_point).round().max(0.0) as u32;
+    let max_x = ((clip_rect.max.x * pixels_per_point).round().max(0.0) as u32).min(extent.width);
+    let max_y = ((clip_rect.max.y * pixels_per_point).round().max(0.0) as u32).min(extent.height);
+    if min_x >= max_x || min_y >= max_y {
+        return None;
+    }
+    Some(vk::Rect2D {
+        offset: vk::Offset2D {
+            x: min_x as i32,
+            y: min_y as i32,
+        },
+        extent: vk::Extent2D {
+            width: max_x - min_x,
+            height: max_y - min_y,
+        },
+    })
+}
+
+fn create_pipeline(
+    device: &ash::Device,
+    graph_cache: &mut RenderGraphCache,
+    color_format: vk::Format,
+    pipeline_layout: vk::PipelineLayout,
+) -> vk::Pipeline {
+    let create_shader_module = |code: &[u8]| {
+        let shader_module_create_info = vk::ShaderModuleCreateInfo {
+            code_size: code.len(),
+            p_code: code.as_ptr() as *const u32,
+            ..Default::default()
+        };
+        unsafe {
+            device
+                .create_shader_module(&shader_module_create_info, None)
+                .expect("Failed to create UI Shader Module!")
+        }
+    };
+    let vert_shader_module = create_shader_module(include_bytes!("spv/ui_vert.spv"));
+    let frag_shader_module = create_shader_module(include_bytes!("spv/ui_frag.spv"));
+
+    let main_function_name = CString::new("main").unwrap();
+    let shader_stages = [
+        vk::PipelineShaderStageCreateInfo::builder()
+            .module(vert_shader_module)
+            .name(&main_function_name)
+            .stage(vk::ShaderStageFlags::VERTEX)
+            .build(),
+        vk::PipelineShaderStageCreateInfo::builder()
+            .module(frag_shader_module)
+            .name(&main_function_name)
+            .stage(vk::ShaderStageFlags::FRAGMENT)
+            .build(),
+    ];
+
+    // Вершина egui: позиция и текстурные координаты по два float и цвет из четырех байт.
+    let vertex_binding_descriptions = [vk::VertexInputBindingDescription {
+        binding: 0,
+        stride: std::mem::size_of::<egui::epaint::Vertex>() as u32,
+        input_rate: vk::VertexInputRate::VERTEX,
+    }];
+    let vertex_attribute_descriptions = [
+        vk::VertexInputAttributeDescription {
+            location: 0,
+            binding: 0,
+            format: vk::Format::R32G32_SFLOAT,
+            offset: 0,
+        },
+        vk::VertexInputAttributeDescription {
+            location: 1,
+            binding: 0,
+            format: vk::Format::R32G32_SFLOAT,
+            offset: 8,
+        },
+        vk::VertexInputAttributeDescription {
+            location: 2,
+            binding: 0,
+            format: vk::Format::R8G8B8A8_UNORM,
+            offset: 16,
+        },
+    ];
+    let vertex_input_state_create_info = vk::PipelineVertexInputStateCreateInfo::builder()
+        .vertex_binding_descriptions(&vertex_binding_descriptions)
+        .vertex_attribute_descriptions(&vertex_attribute_descriptions);
+
+    let input_assembly_state_create_info = vk::PipelineInputAssemblyStateCreateInfo::builder()
+        .topology(vk::PrimitiveTopology::TRIANGLE_LIST);
+
+    let viewport_state_create_info = vk::PipelineViewportStateCreateInfo::builder()
+        .viewport_count(1)
+        .scissor_count(1);
+
+    let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+    let dynamic_state_create_info =
+        vk::PipelineDynamicStateCreateInfo::builder().dynamic_states(&dynamic_states);
+
+    // egui не следит за порядком обхода вершин, поэтому отбраковка граней выключена.
+    let rasterization_state_create_info = vk::PipelineRasterizationStateCreateInfo::builder()
+        .polygon_mode(vk::PolygonMode::FILL)
+        .line_width(1.0)
+        .cull_mode(vk::CullModeFlags::NONE)
+        .front_face(vk::FrontFace::COUNTER_CLOCKWISE);
+
+    let multisample_state_create_info = vk::PipelineMultisampleStateCreateInfo::builder()
+        .rasterization_samples(vk::SampleCountFlags::TYPE_1);
+
+    let depth_state_create_info = vk::PipelineDepthStencilStateCreateInfo::default();
+
+    // Цвета egui уже умножены на альфу (premultiplied alpha).
+    let color_blend_attachment_states = [vk::PipelineColorBlendAttachmentState::builder()
+        .color_write_mask(vk::ColorComponentFlags::RGBA)
+        .blend_enable(true)
+        .src_color_blend_factor(vk::BlendFactor::ONE)
+        .dst_color_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+        .color_blend_op(vk::BlendOp::ADD)
+        .src_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_DST_ALPHA)
+        .dst_alpha_blend_factor(vk::BlendFactor::ONE)
+        .alpha_blend_op(vk::BlendOp::ADD)
+        .build()];
+    let color_blend_state = vk::PipelineColorBlendStateCreateInfo::builder()
+        .attachments(&color_blend_attachment_states);
+
+    let color_attachment_formats = [color_format];
+    let mut pipeline_rendering_create_info = vk::PipelineRenderingCreateInfo::builder()
+        .color_attachment_formats(&color_attachment_formats);
+
+    let mut graphic_pipeline_create_info = vk::GraphicsPipelineCreateInfo::builder()
+        .stages(&shader_stages)
+        .vertex_input_state(&vertex_input_state_create_info)
+        .input_assembly_state(&input_assembly_state_create_info)
+        .viewport_state(&viewport_state_create_info)
+        .rasterization_state(&rasterization_state_create_info)
+        .multisample_state(&multisample_state_create_info)
+        .depth_stencil_state(&depth_state_create_info)
+        .color_blend_state(&color_blend_state)
+        .dynamic_state(&dynamic_state_create_info)
+        .layout(pipeline_layout);
+
+    if graph_cache.uses_dynamic_rendering() {
+        graphic_pipeline_create_info =
+            graphic_pipeline_create_info.push_next(&mut pipeline_rendering_create_info);
+    } else {
+        let render_pass =
+            graph_cache.compatible_render_pass(device, &color_attachment_formats, None);
+        graphic_pipeline_create_info = graphic_pipeline_create_info.render_pass(render_pass);
+    }
+
+    let pipeline = unsafe {
+        device
+            .create_graphics_pipelines(
+                vk::PipelineCache::null(),
+                &[graphic_pipeline_create_info.build()],
+                None,
+            )
+            .expect("Failed to create UI Pipeline!")[0]
+    };
+
+    unsafe {
+        device.destroy_shader_module(vert_shader_module, None);
+        device.destroy_shader_module(frag_shader_module, None);
+    }
+
+    pipeline
+}
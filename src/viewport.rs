@@ -0,0 +1,144 @@
+use ash::vk;
+
+// Раскладка областей просмотра внутри одного кадра. Для split-screen сцена рисуется несколько раз,
+// каждый раз со своей парой viewport/scissor, которые задаются динамически через cmd_set_viewport/cmd_set_scissor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViewportLayout {
+    // Одна область на весь кадр.
+    Single,
+    // Две области: левая и правая половины.
+    SplitVertical,
+    // Две области: верхняя и нижняя половины.
+    SplitHorizontal,
+    // Четыре области 2x2.
+    Quad,
+}
+
+impl ViewportLayout {
+    // Следующая раскладка, используется для переключения по клавише.
+    pub fn next(self) -> Self {
+        match self {
+            ViewportLayout::Single => ViewportLayout::SplitVertical,
+            ViewportLayout::SplitVertical => ViewportLayout::SplitHorizontal,
+            ViewportLayout::SplitHorizontal => ViewportLayout::Quad,
+            ViewportLayout::Quad => ViewportLayout::Single,
+        }
+    }
+
+    // Количество столбцов и строк сетки областей.
+    fn grid(self) -> (u32, u32) {
+        match self {
+            ViewportLayout::Single => (1, 1),
+            ViewportLayout::SplitVertical => (2, 1),
+            ViewportLayout::SplitHorizontal => (1, 2),
+            ViewportLayout::Quad => (2, 2),
+        }
+    }
+
+    // Вычисляет области просмотра и прямоугольники-ножницы для текущего размера кадра.
+    // Если кадр меньше сетки, часть ячеек получается пустой. Vulkan не допускает viewport нулевого размера,
+    // поэтому такие области пропускаются.
+    pub fn regions(self, extent: vk::Extent2D) -> Vec<(vk::Viewport, vk::Rect2D)> {
+        let (columns, rows) = self.grid();
+        let cell_width = extent.width / columns;
+        let cell_height = extent.height / rows;
+
+        (0..rows)
+            .flat_map(|row| (0..columns).map(move |column| (column, row)))
+            .map(|(column, row)| {
+                let x = column * cell_width;
+                let y = row * cell_height;
+                // Последний столбец/строка забирают остаток от деления, чтобы не оставалось пустых пикселей.
+                let width = if column + 1 == columns {
+                    extent.width - x
+                } else {
+                    cell_width
+                };
+                let height = if row + 1 == rows {
+                    extent.height - y
+                } else {
+                    cell_height
+                };
+
+                let viewport = vk::Viewport {
+                    x: x as f32,
+                    y: y as f32,
+                    width: width as f32,
+                    height: height as f32,
+                    min_depth: 0.0,
+                    max_depth: 1.0,
+                };
+                let scissor = vk::Rect2D {
+                    offset: vk::Offset2D {
+                        x: x as i32,
+                        y: y as i32,
+                    },
+                    extent: vk::Extent2D { width, height },
+                };
+                (viewport, scissor)
+            })
+            .filter(|(_, scissor)| scissor.extent.width > 0 && scissor.extent.height > 0)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LAYOUTS: [ViewportLayout; 4] = [
+        ViewportLayout::Single,
+        ViewportLayout::SplitVertical,
+        ViewportLayout::SplitHorizontal,
+        ViewportLayout::Quad,
+    ];
+
+    // Каждый пиксель кадра покрыт ровно одной областью, а viewport совпадает со своими ножницами.
+    fn assert_tiles(layout: ViewportLayout, extent: vk::Extent2D) {
+        let mut coverage = vec![0u32; (extent.width * extent.height) as usize];
+        for (viewport, scissor) in layout.regions(extent) {
+            assert!(scissor.extent.width > 0 && scissor.extent.height > 0);
+            assert_eq!(viewport.x, scissor.offset.x as f32);
+            assert_eq!(viewport.y, scissor.offset.y as f32);
+            assert_eq!(viewport.width, scissor.extent.width as f32);
+            assert_eq!(viewport.height, scissor.extent.height as f32);
+            for y in scissor.offset.y as u32..scissor.offset.y as u32 + scissor.extent.height {
+                for x in scissor.offset.x as u32..scissor.offset.x as u32 + scissor.extent.width {
+                    coverage[(y * extent.width + x) as usize] += 1;
+                }
+            }
+        }
+        assert!(
+            coverage.iter().all(|&count| count == 1),
+            "{:?} does not tile {:?}",
+            layout,
+            extent
+        );
+    }
+
+    #[test]
+    fn regions_tile_extent_with_remainder() {
+        for layout in LAYOUTS {
+            for &(width, height) in &[(800, 600), (801, 601), (3, 5)] {
+                assert_tiles(layout, vk::Extent2D { width, height });
+            }
+        }
+    }
+
+    #[test]
+    fn regions_skip_empty_cells() {
+        let extent = vk::Extent2D {
+            width: 1,
+            height: 1,
+        };
+        for layout in LAYOUTS {
+            assert_tiles(layout, extent);
+            assert_eq!(layout.regions(extent).len(), 1);
+        }
+        let regions = ViewportLayout::Quad.regions(vk::Extent2D {
+            width: 1,
+            height: 4,
+        });
+        assert_eq!(regions.len(), 2);
+    }
+}
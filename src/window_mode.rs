@@ -0,0 +1,172 @@
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+use winit::monitor::{MonitorHandle, VideoMode};
+use winit::window::{Fullscreen, Window};
+
+// Режим окна. В полноэкранном режиме без рамки окно просто накрывает монитор в его текущем видеорежиме,
+// а в исключительном монитор переключается в выбранный видеорежим.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowMode {
+    Windowed,
+    Borderless,
+    Exclusive,
+}
+
+// Переключает режимы окна и помнит положение и размер окна, чтобы вернуть их после полноэкранного режима.
+// Размер кадра при этом меняется, поэтому после переключения цепочку обмена нужно пересоздать.
+pub struct WindowModeController {
+    mode: WindowMode,
+    // Режим, в который переключает toggle из оконного.
+    pub fullscreen_mode: WindowMode,
+    // Номер монитора из available_monitors. None — монитор, на котором сейчас окно.
+    monitor_index: Option<usize>,
+    // Желаемый видеорежим для исключительного режима: ширина, высота и частота. None — лучший режим монитора.
+    video_mode: Option<(u32, u32, Option<u16>)>,
+    windowed_position: Option<PhysicalPosition<i32>>,
+    windowed_size: Option<PhysicalSize<u32>>,
+}
+
+impl WindowModeController {
+    pub fn new(fullscreen_mode: WindowMode) -> Self {
+        WindowModeController {
+            mode: WindowMode::Windowed,
+            fullscreen_mode,
+            monitor_index: None,
+            video_mode: None,
+            windowed_position: None,
+            windowed_size: None,
+        }
+    }
+
+    pub fn mode(&self) -> WindowMode {
+        self.mode
+    }
+
+    pub fn set_monitor(&mut self, monitor_index: Option<usize>) {
+        self.monitor_index = monitor_index;
+    }
+
+    // Разбирает видеорежим вида "1920x1080" или "1920x1080@60".
+    pub fn set_video_mode(&mut self, text: &str) -> Result<(), String> {
+        let error = || {
+            format!(
+                "Invalid video mode \"{}\", expected WIDTHxHEIGHT[@HZ]",
+                text
+            )
+        };
+        let (size, refresh_rate) = match text.split_once('@') {
+            Some((size, refresh_rate)) => (
+                size,
+                Some(refresh_rate.parse::<u16>().map_err(|_| error())?),
+            ),
+            None => (text, None),
+        };
+        let (width, height) = size.split_once('x').ok_or_else(error)?;
+        let width = width.parse::<u32>().map_err(|_| error())?;
+        let height = height.parse::<u32>().map_err(|_| error())?;
+        self.video_mode = Some((width, height, refresh_rate));
+        Ok(())
+    }
+
+    // Оконный режим или выбранный полноэкранный.
+    pub fn toggle(&mut self, window: &Window) {
+        let mode = if self.mode == WindowMode::Windowed {
+            self.fullscreen_mode
+        } else {
+            WindowMode::Windowed
+        };
+        self.set_mode(window, mode);
+    }
+
+    pub fn set_mode(&mut self, window: &Window, mode: WindowMode) {
+        if mode == self.mode {
+            return;
+        }
+        // Геометрию запоминаем только при уходе из оконного режима: между полноэкранными режимами она уже чужая.
+        if self.mode == WindowMode::Windowed {
+            self.windowed_position = window.outer_position().ok();
+            self.windowed_size = Some(window.inner_size());
+        }
+
+        match mode {
+            WindowMode::Windowed => {
+                window.set_fullscreen(None);
+                if let Some(size) = self.windowed_size {
+                    window.set_inner_size(size);
+                }
+                if let Some(position) = self.windowed_position {
+                    window.set_outer_position(position);
+                }
+            }
+            WindowMode::Borderless => {
+                window.set_fullscreen(Some(Fullscreen::Borderless(self.monitor(window))));
+            }
+            WindowMode::Exclusive => {
+                let video_mode = self
+                    .monitor(window)
+                    .and_then(|monitor| self.choose_video_mode(&monitor));
+                match video_mode {
+                    Some(video_mode) => {
+                        println!("Exclusive fullscreen: {}", video_mode);
+                        window.set_fullscreen(Some(Fullscreen::Exclusive(video_mode)));
+                    }
+                    // Некоторые системы (например, Wayland) не сообщают видеорежимы, тогда остается режим без рамки.
+                    None => {
+                        println!("No video modes available, falling back to borderless fullscreen");
+                        window.set_fullscreen(Some(Fullscreen::Borderless(self.monitor(window))));
+                    }
+                }
+            }
+        }
+        self.mode = mode;
+    }
+
+    fn monitor(&self, window: &Window) -> Option<MonitorHandle> {
+        match self.monitor_index {
+            Some(index) => {
+                let monitor = window.available_monitors().nth(index);
+                if monitor.is_none() {
+                    println!("Monitor {} not found, using the current one", index);
+                }
+                monitor.or_else(|| window.current_monitor())
+            }
+            None => window.current_monitor(),
+        }
+    }
+
+    // Видеорежим монитора, ближайший к желаемому: сначала по размеру, затем по частоте.
+    // Без желаемого режима берется самый большой и быстрый.
+    fn choose_video_mode(&self, monitor: &MonitorHandle) -> Option<VideoMode> {
+        match self.video_mode {
+            Some((width, height, refresh_rate)) => monitor.video_modes().min_by_key(|mode| {
+                let size = mode.size();
+                let size_difference = (size.width as i64 - width as i64).abs()
+                    + (size.height as i64 - height as i64).abs();
+                let refresh_difference = refresh_rate.map_or(0, |refresh_rate| {
+                    (mode.refresh_rate() as i64 - refresh_rate as i64).abs()
+                });
+                (
+                    size_difference,
+                    refresh_difference,
+                    -(mode.bit_depth() as i64),
+                )
+            }),
+            None => monitor.video_modes().max(),
+        }
+    }
+}
+
+// Список мониторов и их видеорежимов для выбора аргументами --monitor и --video-mode.
+pub fn print_monitors(window: &Window) {
+    for (index, monitor) in window.available_monitors().enumerate() {
+        println!(
+            "Monitor {}: {} {}x{}",
+            index,
+            monitor.name().unwrap_or_default(),
+            monitor.size().width,
+            monitor.size().height
+        );
+        for video_mode in monitor.video_modes() {
+            println!("    {}", video_mode);
+        }
+    }
+}
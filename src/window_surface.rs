@@ -0,0 +1,130 @@
+use ash::extensions::khr::{Surface, Swapchain};
+use ash::vk;
+
+use winit::window::{Window, WindowId};
+
+use crate::swapchain::SwapchainBundle;
+
+// Окно вместе со своей поверхностью и цепочкой обмена. Экземпляр, устройство, конвейеры и остальные ресурсы
+// общие для всех окон, а поверхность у каждого окна своя, поэтому и поддержку показа нужно проверять для каждой.
+pub struct WindowSurface {
+    pub window: Window,
+    pub surface: vk::SurfaceKHR,
+    pub swapchain_bundle: SwapchainBundle,
+    // Выставляется, когда размер окна изменился или цепочка обмена устарела.
+    pub swapchain_dirty: bool,
+}
+
+impl WindowSurface {
+    // Окно, поверхность которого уже создана. Так создается главное окно: по его поверхности выбирается устройство.
+    pub fn new(
+        window: Window,
+        surface: vk::SurfaceKHR,
+        device: &ash::Device,
+        surface_loader: &Surface,
+        swapchain_loader: &Swapchain,
+        p_device: vk::PhysicalDevice,
+        vsync: bool,
+    ) -> Self {
+        let swapchain_bundle = SwapchainBundle::new(
+            device,
+            surface_loader,
+            swapchain_loader,
+            p_device,
+            surface,
+            window.inner_size(),
+            vk::SwapchainKHR::null(),
+            vsync,
+        );
+        WindowSurface {
+            window,
+            surface,
+            swapchain_bundle,
+            swapchain_dirty: false,
+        }
+    }
+
+    // Дополнительное окно уже существующего устройства. Его поверхность может оказаться недоступной
+    // для очереди показа (например, окно на мониторе другой видеокарты), тогда возвращается ошибка.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create(
+        entry: &ash::Entry,
+        instance: &ash::Instance,
+        window: Window,
+        device: &ash::Device,
+        surface_loader: &Surface,
+        swapchain_loader: &Swapchain,
+        p_device: vk::PhysicalDevice,
+        present_family_index: u32,
+        vsync: bool,
+    ) -> Result<Self, String> {
+        let surface = unsafe { ash_window::create_surface(entry, instance, &window, None) }
+            .map_err(|error| format!("Failed to create surface: {}", error))?;
+        let is_present_support = unsafe {
+            surface_loader.get_physical_device_surface_support(
+                p_device,
+                present_family_index,
+                surface,
+            )
+        }
+        .unwrap_or(false);
+        if !is_present_support {
+            unsafe { surface_loader.destroy_surface(surface, None) };
+            return Err("The present queue can not present to this window".to_owned());
+        }
+        Ok(Self::new(
+            window,
+            surface,
+            device,
+            surface_loader,
+            swapchain_loader,
+            p_device,
+            vsync,
+        ))
+    }
+
+    pub fn id(&self) -> WindowId {
+        self.window.id()
+    }
+
+    // Свернутое окно имеет нулевой размер, для него цепочку обмена создать нельзя.
+    pub fn is_minimized(&self) -> bool {
+        let size = self.window.inner_size();
+        size.width == 0 || size.height == 0
+    }
+
+    // Видеокарта не должна использовать старую цепочку обмена, вызывающий код сначала дожидается устройства.
+    pub unsafe fn recreate_swapchain(
+        &mut self,
+        device: &ash::Device,
+        surface_loader: &Surface,
+        swapchain_loader: &Swapchain,
+        p_device: vk::PhysicalDevice,
+        vsync: bool,
+    ) {
+        let new_swapchain_bundle = SwapchainBundle::new(
+            device,
+            surface_loader,
+            swapchain_loader,
+            p_device,
+            self.surface,
+            self.window.inner_size(),
+            self.swapchain_bundle.swapchain,
+            vsync,
+        );
+        self.swapchain_bundle.destroy(device, swapchain_loader);
+        self.swapchain_bundle = new_swapchain_bundle;
+        self.swapchain_dirty = false;
+    }
+
+    // Цепочка обмена уничтожается раньше поверхности, для которой она создана.
+    pub unsafe fn destroy(
+        &self,
+        device: &ash::Device,
+        surface_loader: &Surface,
+        swapchain_loader: &Swapchain,
+    ) {
+        self.swapchain_bundle.destroy(device, swapchain_loader);
+        surface_loader.destroy_surface(self.surface, None);
+    }
+}